@@ -49,5 +49,9 @@ pub enum Commands {
     SendApReq {
         #[arg(long)]
         server_address: std::net::SocketAddr,
+
+        /// Service whose ticket to send, e.g. `HTTP/localhost`, the last one got by default
+        #[arg(long)]
+        target_principal: Option<String>,
     },
 }
@@ -3,6 +3,7 @@ use config::{Config, ConfigError};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use clap::ValueEnum;
+use kerberos::client::ccache::FileCredentialCache;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, ValueEnum, Copy, Clone)]
 pub enum TransportType {
@@ -17,6 +18,8 @@ pub struct AppConfig {
     pub address: SocketAddr,
     pub key: Option<String>,
     pub cache_location: Option<PathBuf>,
+    /// MIT credential cache the tickets are kept in, `krb5cc` in `cache_location` by default
+    pub ccache: Option<PathBuf>,
     pub transport_type: Option<TransportType>,
 }
 
@@ -31,4 +34,18 @@ impl AppConfig {
         let cfg = cfg.try_deserialize()?;
         Ok(cfg)
     }
+
+    pub fn cache_location(&self) -> PathBuf {
+        self.cache_location
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("./"))
+    }
+
+    pub fn credential_cache(&self) -> FileCredentialCache {
+        FileCredentialCache::new(
+            self.ccache
+                .clone()
+                .unwrap_or_else(|| self.cache_location().join("krb5cc")),
+        )
+    }
 }
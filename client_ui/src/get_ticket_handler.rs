@@ -2,6 +2,7 @@ use crate::config::TransportType;
 use config::ConfigError;
 use derive_builder::Builder;
 use kerberos::client::as_exchange::{prepare_as_request, receive_as_response};
+use kerberos::client::ccache::{Credential, CredentialCache, FileCredentialCache, KdcOffset};
 use kerberos::client::client_env::ClientEnv;
 use kerberos::client::client_env_error::ClientEnvError;
 use kerberos::client::tgs_exchange::{prepare_tgs_request, receive_tgs_response};
@@ -9,7 +10,7 @@ use kerberos::cryptographic_hash::CryptographicHash;
 use kerberos::cryptography::Cryptography;
 use kerberos_infra::client::{Sendable, TcpClient, UdpClient};
 use messages::basic_types::{
-    EncryptionKey, KerberosFlags, KerberosString, KerberosTime, OctetString,
+    EncryptionKey, KerberosFlags, KerberosString, KerberosTime, OctetString, PrincipalName,
 };
 use messages::flags::KdcOptionsFlag;
 use messages::{AsRep, Decode, EncAsRepPart, EncTgsRepPart, Encode, KrbErrorMsg, TgsRep};
@@ -33,6 +34,7 @@ pub struct GetTicketHandler {
     pub tgs_sender: SocketAddr,
     pub key: Option<String>,
     pub cache_location: PathBuf,
+    pub ccache: FileCredentialCache,
     pub transport_type: TransportType,
     pub ticket_lifetime: Option<humantime::Duration>,
     pub ticket_renew_time: Option<humantime::Timestamp>,
@@ -60,21 +62,18 @@ impl GetTicketHandler {
         Ok(())
     }
 
-    fn open_file_and_read(
-        &self,
-        // folder_name: Option<&str>,
-        name: &str,
-    ) -> std::io::Result<Vec<u8>> {
-        let mut loc = self.cache_location.clone();
-        // match folder_name {
-        //     None => {}
-        //     Some(folder) => {
-        //         loc.push(folder);
-        //         fs::create_dir_all(loc.clone())?;
-        //     }
-        // }
-        loc.push(name);
-        fs::read(loc)
+    fn get_tgt(&self) -> Result<Credential, ClientEnvError> {
+        self.ccache.retrieve_tgt()?.ok_or(ClientEnvError {
+            message: "No ticket-granting ticket in the credential cache".to_string(),
+        })
+    }
+
+    fn get_service_ticket(&self) -> Result<Credential, ClientEnvError> {
+        self.ccache
+            .retrieve(&self.get_server_principal()?, &self.get_server_realm()?)?
+            .ok_or(ClientEnvError {
+                message: "No ticket for the server in the credential cache".to_string(),
+            })
     }
 
     pub async fn handle(&self) -> Result<(), ConfigError> {
@@ -131,16 +130,7 @@ impl GetTicketHandler {
         let ok = receive_tgs_response(&tgs_req, &tgs_rep, self);
         match ok {
             Ok(_) => {
-                println!(
-                    "Successfully got ticket for {}",
-                    tgs_rep
-                        .ticket()
-                        .sname()
-                        .name_string()
-                        .first()
-                        .unwrap()
-                        .as_str()
-                );
+                println!("Successfully got ticket for {}", tgs_rep.ticket().sname());
                 Ok(())
             }
             Err(e) => {
@@ -172,6 +162,17 @@ impl ClientEnv for GetTicketHandler {
         }))
     }
 
+    /// The target principal may name a service on a host, e.g. `HTTP/localhost`
+    fn get_server_principal(&self) -> Result<PrincipalName, ClientEnvError> {
+        self.server_name
+            .as_deref()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|e| ClientEnvError {
+                message: format!("Failed to get server name: {}", e),
+            })
+    }
+
     fn get_server_realm(&self) -> Result<KerberosString, ClientEnvError> {
         KerberosString::new(self.server_realm.as_ref().unwrap().as_bytes()).or(Err(
             ClientEnvError {
@@ -216,45 +217,28 @@ impl ClientEnv for GetTicketHandler {
 
     fn set_clock_diff(
         &self,
-        _diff: Duration,
-        _is_client_earlier: bool,
+        diff: Duration,
+        is_client_earlier: bool,
     ) -> Result<(), ClientEnvError> {
-        todo!()
+        Ok(self
+            .ccache
+            .set_kdc_offset(KdcOffset::new(diff, is_client_earlier))?)
     }
 
+    /// A new initial ticket starts the cache over, as `kinit` does
     fn save_as_reply(&self, data: &AsRep, data_part: &EncAsRepPart) -> Result<(), ClientEnvError> {
-        self.open_file_and_write(
-            "as_rep_enc_part",
-            // data_part.authtime().timestamp().to_string().as_str(),
-            &data_part.to_der().unwrap(),
-        )
-        .map_err(|_| ClientEnvError {
-            message: "failed to save as_rep_enc_part".to_string(),
-        })?;
-        self.open_file_and_write(
-            "as_rep",
-            // data_part.authtime().timestamp().to_string().as_str(),
-            &data.to_der().unwrap(),
-        )
-        .map_err(|_| ClientEnvError {
-            message: "failed to save as_rep".to_string(),
-        })
+        self.ccache.initialize(data.cname(), data.crealm())?;
+        Ok(self
+            .ccache
+            .store(Credential::from_kdc_reply(data, data_part))?)
     }
 
     fn get_as_reply(&self) -> Result<AsRep, ClientEnvError> {
-        self.open_file_and_read("as_rep")
-            .map(|b| AsRep::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_tgt().map(|tgt| tgt.as_reply().0)
     }
 
     fn get_as_reply_enc_part(&self) -> Result<EncAsRepPart, ClientEnvError> {
-        self.open_file_and_read("as_rep_enc_part")
-            .map(|b| EncAsRepPart::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_tgt().map(|tgt| tgt.as_reply().1)
     }
 
     fn save_tgs_reply(
@@ -262,38 +246,17 @@ impl ClientEnv for GetTicketHandler {
         data: &TgsRep,
         data_part: &EncTgsRepPart,
     ) -> Result<(), ClientEnvError> {
-        self.open_file_and_write(
-            "tgs_rep_enc_part",
-            // data_part.authtime().timestamp().to_string().as_str(),
-            &data_part.to_der().unwrap(),
-        )
-        .map_err(|_| ClientEnvError {
-            message: "".to_string(),
-        })?;
-        self.open_file_and_write(
-            "tgs_rep",
-            // data_part.authtime().timestamp().to_string().as_str(),
-            &data.to_der().unwrap(),
-        )
-        .map_err(|_| ClientEnvError {
-            message: "".to_string(),
-        })
+        Ok(self
+            .ccache
+            .store(Credential::from_kdc_reply(data, data_part))?)
     }
 
     fn get_tgs_reply(&self) -> Result<TgsRep, ClientEnvError> {
-        self.open_file_and_read("tgs_rep")
-            .map(|b| TgsRep::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_service_ticket().map(|ticket| ticket.tgs_reply().0)
     }
 
     fn get_tgs_reply_enc_part(&self) -> Result<EncTgsRepPart, ClientEnvError> {
-        self.open_file_and_read("tgs_rep_enc_part")
-            .map(|b| EncTgsRepPart::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_service_ticket().map(|ticket| ticket.tgs_reply().1)
     }
 
     fn save_subkey(&self, key: EncryptionKey) -> Result<(), ClientEnvError> {
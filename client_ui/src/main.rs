@@ -8,12 +8,11 @@ use client_ui::get_ticket_handler::GetTicketHandlerBuilder;
 use client_ui::list_ticket_handler::ListTicketHandler;
 use client_ui::send_ap_req_handler::SendApReqHandler;
 use config::ConfigError;
-use kerberos::client::ap_exchange::prepare_ap_request;
-use kerberos::client::client_env::ClientEnv;
-use messages::{ApRep, Decode, EncApRepPart, Encode};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use kerberos::gssapi::{init_spnego_context, ContextFlag, ContextFlags};
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
 use reqwest::Url;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
 
 #[tokio::main]
 async fn main() {
@@ -35,10 +34,21 @@ async fn main() {
     };
     match args.command {
         ListTicket => {
-            let handler = ListTicketHandler::default();
-            let tickets = handler.list_tickets();
-            for ticket in tickets {
-                println!("Ticket from {:?} - {:?}", ticket.sname(), ticket.realm());
+            let handler = ListTicketHandler::new(config.credential_cache());
+            match handler.list_tickets() {
+                Ok(tickets) => {
+                    for ticket in tickets {
+                        println!(
+                            "Ticket for {}@{} - expires {}",
+                            ticket.server,
+                            ticket.srealm.as_str(),
+                            humantime::format_rfc3339(
+                                UNIX_EPOCH + ticket.endtime.to_unix_duration()
+                            )
+                        );
+                    }
+                }
+                Err(e) => println!("{}", e),
             }
         }
         GetTicket {
@@ -55,7 +65,8 @@ async fn main() {
             tgs_server_address,
         } => {
             let client = GetTicketHandlerBuilder::default()
-                .cache_location(config.cache_location.unwrap_or_else(|| PathBuf::from("./")))
+                .cache_location(config.cache_location())
+                .ccache(config.credential_cache())
                 .renewable(renewable)
                 .as_sender(as_server_address)
                 .tgs_sender(tgs_server_address)
@@ -73,60 +84,52 @@ async fn main() {
                 .unwrap();
             client.handle().await.unwrap();
         }
-        Commands::SendApReq { server_address } => {
+        Commands::SendApReq {
+            server_address,
+            target_principal,
+        } => {
+            let target_principal = match target_principal.map(|p| p.parse()).transpose() {
+                Ok(target_principal) => target_principal,
+                Err(e) => {
+                    println!("Invalid target principal: {}", e);
+                    return;
+                }
+            };
             let client = SendApReqHandler {
                 name: config.name.clone(),
                 realm: config.realm.clone(),
-                cache_location: config.cache_location.unwrap_or_else(|| PathBuf::from("./")),
+                cache_location: config.cache_location(),
+                ccache: config.credential_cache(),
+                target_principal,
             };
-            let req = prepare_ap_request(&client, false, None)
-                .unwrap()
-                .to_der()
-                .unwrap();
+            let flags = ContextFlags::default().with(ContextFlag::Mutual);
+            let (mut context, token) = init_spnego_context(&client, flags, None).unwrap();
             let http_client = reqwest::Client::new();
-            let mut data = HashMap::new();
-            data.insert("ticket", hex::encode(req));
             let res = http_client
-                .post(Url::parse(&format!("http://{}/authenticate", server_address)).unwrap())
-                .json(&data)
+                .get(
+                    Url::parse(&format!("http://{}/users/{}", server_address, config.name))
+                        .unwrap(),
+                )
+                .header(AUTHORIZATION, format!("Negotiate {}", STANDARD.encode(token)))
                 .send()
                 .await
                 .unwrap();
 
-            let res = res.bytes().await.unwrap();
-            match ApRep::from_der(&res) {
-                Ok(ap_rep) => {
-                    let tgs_rep = client.get_tgs_reply_enc_part().unwrap();
-                    let session_key = tgs_rep.key().keyvalue().as_bytes();
-                    let ap_rep_decrypted = EncApRepPart::from_der(
-                        client
-                            .get_crypto(*client.get_tgs_reply_enc_part().unwrap().key().keytype())
-                            .unwrap()
-                            .decrypt(ap_rep.enc_part().cipher().as_ref(), session_key)
-                            .unwrap()
-                            .as_slice(),
-                    )
-                    .unwrap();
-                    let seq_number = *ap_rep_decrypted.seq_number().unwrap();
-                    let res = http_client
-                        .get(
-                            Url::parse(&format!("http://{}/users/{}", server_address, config.name))
-                                .unwrap(),
-                        )
-                        .query(&[
-                            ("realm", "MYREALM.COM"),
-                            ("sequence", seq_number.to_string().as_str()),
-                        ])
-                        .send()
-                        .await
-                        .unwrap();
+            // The server authenticates to the client in the token completing the negotiation
+            let completion = res
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Negotiate "))
+                .and_then(|token| STANDARD.decode(token).ok());
+            match completion.map(|token| context.continue_spnego_context(&client, &token)) {
+                Some(Ok(())) => {
                     let res = res.text().await.unwrap();
                     let res_json: serde_json::Value = serde_json::from_str(&res).unwrap();
                     println!("{}", serde_json::to_string_pretty(&res_json).unwrap());
                 }
-                Err(e) => {
-                    println!("Failed to parse AP_REP: {:?}", e);
-                }
+                Some(Err(e)) => println!("Failed to authenticate the server: {}", e),
+                None => println!("Server did not authenticate: {}", res.status()),
             }
         }
     }
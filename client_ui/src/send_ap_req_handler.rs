@@ -1,9 +1,10 @@
+use kerberos::client::ccache::{Credential, CredentialCache, FileCredentialCache};
 use kerberos::client::client_env::ClientEnv;
 use kerberos::client::client_env_error::ClientEnvError;
 use kerberos::cryptographic_hash::CryptographicHash;
 use kerberos::cryptography::Cryptography;
-use messages::basic_types::{EncryptionKey, KerberosFlags, KerberosString};
-use messages::{AsRep, Decode, EncAsRepPart, EncTgsRepPart, TgsRep};
+use messages::basic_types::{EncryptionKey, KerberosFlags, KerberosString, PrincipalName};
+use messages::{AsRep, EncAsRepPart, EncTgsRepPart, Encode, TgsRep};
 use std::fs;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -12,24 +13,37 @@ pub struct SendApReqHandler {
     pub name: String,
     pub realm: String,
     pub cache_location: PathBuf,
+    pub ccache: FileCredentialCache,
+    /// Server whose ticket to send, the last one got when `None`
+    pub target_principal: Option<PrincipalName>,
 }
 
 impl SendApReqHandler {
-    fn open_file_and_read(
-        &self,
-        // folder_name: Option<&str>,
-        name: &str,
-    ) -> std::io::Result<Vec<u8>> {
+    fn get_tgt(&self) -> Result<Credential, ClientEnvError> {
+        self.ccache.retrieve_tgt()?.ok_or(ClientEnvError {
+            message: "No ticket-granting ticket in the credential cache".to_string(),
+        })
+    }
+
+    fn get_service_ticket(&self) -> Result<Credential, ClientEnvError> {
+        let ticket = match &self.target_principal {
+            Some(server) => self.ccache.retrieve(server, &self.get_client_realm()?)?,
+            None => self
+                .ccache
+                .credentials()?
+                .into_iter()
+                .rev()
+                .find(|c| !c.is_tgt()),
+        };
+        ticket.ok_or(ClientEnvError {
+            message: "No ticket for the server in the credential cache".to_string(),
+        })
+    }
+
+    fn open_file_and_write(&self, name: &str, data: &[u8]) -> std::io::Result<()> {
         let mut loc = self.cache_location.clone();
-        // match folder_name {
-        //     None => {}
-        //     Some(folder) => {
-        //         loc.push(folder);
-        //         fs::create_dir_all(loc.clone())?;
-        //     }
-        // }
         loc.push(name);
-        fs::read(loc)
+        fs::write(loc, data)
     }
 }
 
@@ -100,19 +114,11 @@ impl ClientEnv for SendApReqHandler {
     }
 
     fn get_as_reply(&self) -> Result<AsRep, ClientEnvError> {
-        self.open_file_and_read("as_rep")
-            .map(|b| AsRep::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_tgt().map(|tgt| tgt.as_reply().0)
     }
 
     fn get_as_reply_enc_part(&self) -> Result<EncAsRepPart, ClientEnvError> {
-        self.open_file_and_read("as_rep_enc_part")
-            .map(|b| EncAsRepPart::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_tgt().map(|tgt| tgt.as_reply().1)
     }
 
     fn save_tgs_reply(
@@ -125,28 +131,24 @@ impl ClientEnv for SendApReqHandler {
     }
 
     fn get_tgs_reply(&self) -> Result<TgsRep, ClientEnvError> {
-        self.open_file_and_read("tgs_rep")
-            .map(|b| TgsRep::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_service_ticket().map(|ticket| ticket.tgs_reply().0)
     }
 
     fn get_tgs_reply_enc_part(&self) -> Result<EncTgsRepPart, ClientEnvError> {
-        self.open_file_and_read("tgs_rep_enc_part")
-            .map(|b| EncTgsRepPart::from_der(&b).unwrap())
-            .map_err(|_| ClientEnvError {
-                message: "Failed to read file".to_string(),
-            })
+        self.get_service_ticket().map(|ticket| ticket.tgs_reply().1)
     }
 
-    fn save_subkey(&self, _key: EncryptionKey) -> Result<(), ClientEnvError> {
-        #[allow(unreachable_code)]
-        !unimplemented!()
+    fn save_subkey(&self, key: EncryptionKey) -> Result<(), ClientEnvError> {
+        self.open_file_and_write("subkey", &key.to_der().unwrap())
+            .map_err(|_| ClientEnvError {
+                message: "Failed to write subkey".to_string(),
+            })
     }
 
-    fn save_seq_number(&self, _seq_num: u32) -> Result<(), ClientEnvError> {
-        #[allow(unreachable_code)]
-        !unimplemented!()
+    fn save_seq_number(&self, seq_num: u32) -> Result<(), ClientEnvError> {
+        self.open_file_and_write("seq_number", &seq_num.to_der().unwrap())
+            .map_err(|_| ClientEnvError {
+                message: "Failed to write sequence number".to_string(),
+            })
     }
 }
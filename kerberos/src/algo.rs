@@ -28,7 +28,9 @@ impl Cryptography for AesGcm {
         let key = aes_gcm::Key::<Aes256>::from_slice(key);
         let nonce = aes_gcm::Nonce::from([0xff; 12]);
         let cipher = aes_gcm::Aes256Gcm::new(&key);
-        Ok(cipher.encrypt(&nonce, data).unwrap().to_vec())
+        cipher
+            .encrypt(&nonce, data)
+            .map_err(|_| CryptographyError::EncryptError)
     }
 
     fn decrypt(&self, data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptographyError> {
@@ -38,7 +40,10 @@ impl Cryptography for AesGcm {
         let key = aes_gcm::Key::<Aes256>::from_slice(key);
         let nonce = aes_gcm::Nonce::from([0xff; 12]);
         let cipher = aes_gcm::Aes256Gcm::new(&key);
-        Ok(cipher.decrypt(&nonce, data).unwrap().to_vec())
+        // Data encrypted with another key or modified fails to authenticate
+        cipher
+            .decrypt(&nonce, data)
+            .map_err(|_| CryptographyError::DecryptError)
     }
 
     fn generate_key(&self) -> Result<Vec<u8>, CryptographyError> {
@@ -103,4 +108,22 @@ mod tests {
         assert_eq!(digested.len(), 160 / 8);
         assert_ne!(data, digested);
     }
+
+    #[test]
+    fn test_keyed_hash() {
+        // https://www.rfc-editor.org/rfc/rfc2202#section-3, test case 2
+        let hasher = Sha1::new();
+        let digested = hasher.keyed_digest(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            digested,
+            [
+                0xef, 0xfc, 0xdf, 0x6a, 0xe5, 0xeb, 0x2f, 0xa2, 0xd2, 0x74, 0x16, 0xd5, 0xf1, 0x84,
+                0xdf, 0x9c, 0x25, 0x9a, 0x7c, 0x79
+            ]
+        );
+        assert_ne!(
+            hasher.keyed_digest(b"Jeff", b"what do ya want for nothing?"),
+            digested
+        );
+    }
 }
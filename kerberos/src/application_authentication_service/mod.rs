@@ -1,11 +1,17 @@
 #[cfg(test)]
 mod tests;
 
+use crate::algo::Sha1;
 use crate::application_authentication_service::ServerError::ProtocolError;
+use crate::authorization_data;
+use crate::cryptographic_hash::CryptographicHash;
 use crate::cryptography::Cryptography;
+use crate::pac::Pac;
 use crate::service_traits::{
-    ApReplayCache, ApReplayEntry, ClientAddressStorage, UserSessionEntry, UserSessionStorage,
+    ApReplayCache, ApReplayEntry, ClientAddressStorage, ServiceKeyStore, UserSessionEntry,
+    UserSessionStorage,
 };
+use crate::transit_policy::TransitPolicy;
 use chrono::Local;
 use derive_builder::Builder;
 use messages::basic_types::{
@@ -17,38 +23,57 @@ use messages::{
     ApRep, ApReq, Authenticator, Ecode, EncApRepPart, EncTicketPart, Encode,
 };
 use messages::{Decode, KrbErrorMsg, KrbErrorMsgBuilder};
+use rand::{thread_rng, Rng};
 use std::time::Duration;
 
 #[derive(Builder)]
 #[builder(pattern = "owned", setter(strip_option))]
-pub struct ApplicationAuthenticationService<'a, C, S, CAS>
+pub struct ApplicationAuthenticationService<'a, C, S, CAS, K>
 where
     C: ApReplayCache,
     S: UserSessionStorage,
     CAS: ClientAddressStorage,
+    K: ServiceKeyStore,
 {
     realm: Realm,
     sname: PrincipalName,
-    service_key: EncryptionKey,
+    /// Keys of the services tickets are accepted for, e.g. a `Keytab`
+    key_store: &'a K,
     accept_empty_address_ticket: bool,
     ticket_allowable_clock_skew: Duration,
+    #[builder(default)]
+    transit_policy: TransitPolicy,
     address_storage: &'a CAS,
     replay_cache: &'a C,
     crypto: Vec<Box<dyn Cryptography + Send + Sync>>,
+    /// Checksums the AD-KDC-ISSUED containers and PACs of tickets may be signed with
+    #[builder(default)]
+    supported_checksum: Vec<Box<dyn CryptographicHash + Send + Sync>>,
     session_storage: &'a S,
 }
 
+/// Outcome of an AP-REQ the client was authenticated by
+#[derive(Debug)]
+pub struct AcceptedApReq {
+    /// Reply to send, when the client required mutual authentication
+    pub reply: Option<ApRep>,
+    pub authenticator: Authenticator,
+    /// Session negotiated with the client, as kept in the session storage
+    pub session: UserSessionEntry,
+}
+
 #[derive(Debug)]
 pub enum ServerError {
     ProtocolError(Box<KrbErrorMsg>),
     Internal,
 }
 
-impl<'a, C, S, CAS> ApplicationAuthenticationService<'a, C, S, CAS>
+impl<'a, C, S, CAS, K> ApplicationAuthenticationService<'a, C, S, CAS, K>
 where
     CAS: ClientAddressStorage,
     C: ApReplayCache,
     S: UserSessionStorage,
+    K: ServiceKeyStore,
 {
     fn verify_msg_type(&self, msg_type: &u8) -> Result<(), Ecode> {
         match msg_type {
@@ -97,20 +122,62 @@ where
             .to_owned()
     }
 
-    fn get_key_for_decrypt(
+    /// Key the ticket is encrypted in, telling apart a service this server does not run from a
+    /// key version it no longer has
+    async fn get_key_for_decrypt(
         &self,
-        _sname: &PrincipalName,
-        _realm: &Realm,
+        sname: &PrincipalName,
+        realm: &Realm,
         etype: Int32,
-        _knvo: Option<UInt32>,
+        kvno: Option<UInt32>,
     ) -> Result<EncryptionKey, Ecode> {
-        // TODO: Check for key in session key cache first
-        let key = self.service_key.keyvalue().as_bytes();
-        Ok(EncryptionKey::new(etype, OctetString::new(key).unwrap()))
+        if !self.crypto.iter().any(|crypto| crypto.get_etype() == etype) {
+            return Err(Ecode::KDC_ERR_ETYPE_NOSUPP);
+        }
+        match self.key_store.get_key(sname, realm, etype, kvno).await {
+            Some(key) => Ok(key),
+            None if kvno.is_some()
+                && self
+                    .key_store
+                    .get_key(sname, realm, etype, None)
+                    .await
+                    .is_some() =>
+            {
+                Err(Ecode::KRB_AP_ERR_BADKEYVER)
+            }
+            None => Err(Ecode::KRB_AP_ERR_NOKEY),
+        }
+    }
+
+    /// Encryption of the given type, among the supported ones
+    pub(crate) fn get_crypto(&self, etype: Int32) -> Option<&(dyn Cryptography + Send + Sync)> {
+        self.crypto
+            .iter()
+            .find(|crypto| crypto.get_etype() == etype)
+            .map(|crypto| crypto.as_ref())
     }
 
-    pub async fn handle_krb_ap_req(&self, ap_req: ApReq) -> Result<ApRep, ServerError> {
-        let replay_cache = self.replay_cache;
+    /// Checksum of the given type, among the supported ones
+    pub(crate) fn get_checksum(
+        &self,
+        checksum_type: Int32,
+    ) -> Option<&(dyn CryptographicHash + Send + Sync)> {
+        self.supported_checksum
+            .iter()
+            .find(|hash| hash.get_checksum_type() == checksum_type)
+            .map(|hash| hash.as_ref())
+    }
+
+    /// Authenticate the client of `ap_req`, replying only if it requires mutual authentication
+    pub async fn handle_krb_ap_req(&self, ap_req: ApReq) -> Result<Option<ApRep>, ServerError> {
+        self.accept_ap_req(ap_req)
+            .await
+            .map(|accepted| accepted.reply)
+    }
+
+    /// Authenticate the client of `ap_req` as `handle_krb_ap_req` does, giving back its
+    /// authenticator and the session negotiated along with the reply
+    pub async fn accept_ap_req(&self, ap_req: ApReq) -> Result<AcceptedApReq, ServerError> {
         let crypto = &self.crypto;
         let error_msg = std::sync::Mutex::new(self.default_error_builder());
 
@@ -124,6 +191,14 @@ where
             .and(self.verify_key(ap_req.ticket().tkt_vno()))
             .map_err(&mut build_protocol_error)?;
 
+        // A server may run several services, the errors are on behalf of the one addressed
+        {
+            error_msg
+                .lock()
+                .unwrap()
+                .sname(ap_req.ticket().sname().to_owned());
+        }
+
         let key = self
             .get_key_for_decrypt(
                 ap_req.ticket().sname(),
@@ -131,6 +206,7 @@ where
                 *ap_req.ticket().enc_part().etype(),
                 ap_req.ticket().enc_part().kvno().copied(),
             )
+            .await
             .map_err(&mut build_protocol_error)?;
 
         let decrypted_ticket = crypto
@@ -184,20 +260,26 @@ where
                 .cname(authenticator.cname().to_owned());
         }
 
-        // if self
-        //     .replay_cache
-        //     .contain(&ApReplayEntry {
-        //         ctime: authenticator.ctime().to_owned(),
-        //         cusec: authenticator.cusec().to_owned(),
-        //         cname: authenticator.cname().to_owned(),
-        //         crealm: authenticator.crealm().to_owned(),
-        //     })
-        //     .await
-        //     // .inspect_err(|e| println!("{e:?}"))
-        //     .map_err(|_| panic!("cc"))?
-        // {
-        //     return Err(build_protocol_error(Ecode::KRB_AP_ERR_REPEAT));
-        // }
+        // The hash tells apart authenticators sent by the same client within a microsecond
+        let replay_entry = ApReplayEntry {
+            ctime: authenticator.ctime().to_owned(),
+            cusec: authenticator.cusec().to_owned(),
+            cname: authenticator.cname().to_owned(),
+            crealm: authenticator.crealm().to_owned(),
+            sname: ap_req.ticket().sname().to_owned(),
+            authenticator_hash: OctetString::new(
+                Sha1.digest(ap_req.authenticator().cipher().as_bytes()),
+            )
+            .map_err(|_| ServerError::Internal)?,
+        };
+        if self
+            .replay_cache
+            .contain(&replay_entry)
+            .await
+            .map_err(|_| ServerError::Internal)?
+        {
+            return Err(build_protocol_error(Ecode::KRB_AP_ERR_REPEAT));
+        }
 
         if !self.accept_empty_address_ticket {
             if let Some(addr) = decrypted_ticket.caddr() {
@@ -207,6 +289,46 @@ where
             }
         }
 
+        // Unless the KDC vouches for the path, checking it is up to the application server,
+        // https://www.rfc-editor.org/rfc/rfc4120#section-2.7
+        let transited = decrypted_ticket
+            .transited()
+            .realms(decrypted_ticket.crealm(), &self.realm)
+            .map_err(|_| build_protocol_error(Ecode::KDC_ERR_TRTYPE_NOSUPP))?;
+        if !decrypted_ticket
+            .flags()
+            .is_set(TicketFlag::TRANSITED_POLICY_CHECKED as usize)
+            && !self.transit_policy.is_path_allowed(
+                decrypted_ticket.crealm(),
+                &self.realm,
+                &transited,
+            )
+        {
+            return Err(build_protocol_error(Ecode::KRB_AP_PATH_NOT_ACCEPTED));
+        }
+
+        // The elements the KDC vouches for are only trusted once their checksum is verified
+        let authorization_data = match decrypted_ticket.authorization_data() {
+            Some(auth_data) => authorization_data::ticket_elements(
+                &self.supported_checksum,
+                decrypted_ticket.key(),
+                auth_data,
+            )
+            .map_err(&mut build_protocol_error)?,
+            None => vec![],
+        };
+
+        // A PAC must be signed for this service, and issued to the client of the ticket
+        if let Some(pac) =
+            Pac::from_elements(&authorization_data).map_err(&mut build_protocol_error)?
+        {
+            pac.verify_server_signature(&self.supported_checksum, &key)
+                .and_then(|_| {
+                    pac.verify_client(decrypted_ticket.cname(), decrypted_ticket.authtime())
+                })
+                .map_err(&mut build_protocol_error)?;
+        }
+
         let ticket_time = decrypted_ticket
             .starttime()
             .unwrap_or(decrypted_ticket.authtime());
@@ -228,66 +350,94 @@ where
             return Err(build_protocol_error(Ecode::KRB_AP_ERR_TKT_EXPIRED));
         }
 
-        replay_cache
-            .store(&ApReplayEntry {
-                ctime: authenticator.ctime().to_owned(),
-                cusec: authenticator.cusec().to_owned(),
-                cname: authenticator.cname().to_owned(),
-                crealm: authenticator.crealm().to_owned(),
-            })
+        // An authenticator stamped up to the skew ahead of us is refused as skewed once twice
+        // the skew has elapsed, https://www.rfc-editor.org/rfc/rfc4120#section-3.2.3
+        self.replay_cache
+            .store(&replay_entry, self.ticket_allowable_clock_skew * 2)
             .await
-            // .inspect_err(|e| println!("{e:?}"))
-            .map_err(|_| panic!("cc"))?;
-
-        let rep_authenticator = EncApRepPart::new(
-            authenticator.ctime(),
-            authenticator.cusec(),
-            None,
-            authenticator.seq_number().map(|num| num as u32),
-        )
-        .to_der()
-        .inspect_err(|e| println!("{e:?}"))
-        .map_err(|_| panic!("cc"))?;
-
-        let encrypted = crypto
-            .first()
-            .expect("At least one crypto should be available")
-            .encrypt(
-                &rep_authenticator,
-                decrypted_ticket.key().keyvalue().as_bytes(),
+            .map_err(|_| ServerError::Internal)?;
+
+        let sequence_number = authenticator.seq_number().ok_or(ProtocolError(Box::new(
+            error_msg
+                .lock()
+                .unwrap()
+                .error_code(Ecode::KRB_ERR_GENERIC)
+                .e_data(
+                    OctetString::new("Sequence number must be provided".to_string().as_bytes())
+                        .unwrap(),
+                )
+                .build()
+                .unwrap(),
+        )))?;
+
+        let session_key = decrypted_ticket.key();
+        let session_crypto = crypto
+            .iter()
+            .find(|crypto| crypto.get_etype() == *session_key.keytype())
+            .ok_or(build_protocol_error(Ecode::KDC_ERR_ETYPE_NOSUPP))?;
+
+        // The client only expects a reply, and the context it negotiates, when it asked for one,
+        // https://www.rfc-editor.org/rfc/rfc4120#section-3.2.4
+        let mut reply = None;
+        let (mut subkey, mut server_sequence_number) = (None, None);
+        if ap_req.ap_options().mutual_required() {
+            let key = session_crypto
+                .generate_key()
+                .map_err(|_| ServerError::Internal)?;
+            let key = EncryptionKey::new(
+                *session_key.keytype(),
+                OctetString::new(key).map_err(|_| ServerError::Internal)?,
+            );
+            // Kept within 31 bits for the peers reading it as a signed integer
+            let initial_sequence_number = thread_rng().gen::<u32>() & 0x7FFF_FFFF;
+
+            let enc_part = EncApRepPart::new(
+                authenticator.ctime(),
+                authenticator.cusec(),
+                key.clone(),
+                initial_sequence_number,
             )
-            .inspect_err(|e| println!("{e:?}"))
-            .map_err(|_| panic!("cc"))?;
+            .to_der()
+            .map_err(|_| ServerError::Internal)?;
+            let encrypted = session_crypto
+                .encrypt(&enc_part, session_key.keyvalue().as_bytes())
+                .map_err(|_| ServerError::Internal)?;
+
+            reply = Some(ApRep::new(EncryptedData::new(
+                *session_key.keytype(),
+                None,
+                OctetString::new(encrypted).map_err(|_| ServerError::Internal)?,
+            )));
+            subkey = Some(key);
+            server_sequence_number = Some(initial_sequence_number);
+        }
 
+        let session = UserSessionEntry {
+            cname: authenticator.cname().to_owned(),
+            crealm: authenticator.crealm().to_owned(),
+            session_key: session_key.to_owned(),
+            sequence_number,
+            transited,
+            authorization_data,
+            subkey,
+            server_sequence_number,
+            flags: decrypted_ticket.flags().clone(),
+            authtime: decrypted_ticket.authtime(),
+            starttime: decrypted_ticket
+                .starttime()
+                .unwrap_or(decrypted_ticket.authtime()),
+            endtime: decrypted_ticket.endtime(),
+            renew_till: decrypted_ticket.renew_till(),
+        };
         self.session_storage
-            .store_session(&UserSessionEntry {
-                cname: authenticator.cname().to_owned(),
-                crealm: authenticator.crealm().to_owned(),
-                session_key: decrypted_ticket.key().to_owned(),
-                sequence_number: authenticator.seq_number().ok_or(ProtocolError(Box::new(
-                    error_msg
-                        .lock()
-                        .unwrap()
-                        .error_code(Ecode::KRB_ERR_GENERIC)
-                        .e_data(
-                            OctetString::new(
-                                "Sequence number must be provided".to_string().as_bytes(),
-                            )
-                            .unwrap(),
-                        )
-                        .build()
-                        .unwrap(),
-                )))?,
-            })
+            .store_session(&session)
             .await
-            // .inspect_err(|e| println!("{e:?}"))
-            .map_err(|_| panic!("cc"))?;
-
-        Ok(ApRep::new(EncryptedData::new(
-            *ap_req.ticket().enc_part().etype(),
-            ap_req.ticket().enc_part().kvno().copied(),
-            OctetString::new(encrypted)
-                .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_MODIFIED))?,
-        )))
+            .map_err(|_| ServerError::Internal)?;
+
+        Ok(AcceptedApReq {
+            reply,
+            authenticator,
+            session,
+        })
     }
 }
@@ -1,19 +1,26 @@
+use crate::application_authentication_service::ServerError;
 use crate::application_authentication_service::{
     ApplicationAuthenticationService, ApplicationAuthenticationServiceBuilder,
 };
+use crate::authorization_data::{
+    issue_claims, sign_kdc_issued, AuthorizationElement, PrincipalClaims, AD_PRINCIPAL_CLAIMS,
+};
 use crate::cryptography::Cryptography;
-use crate::service_traits::{ApReplayCache, ClientAddressStorage};
+use crate::keytab::{Keytab, KeytabEntry};
+use crate::pac::Pac;
+use crate::service_traits::{ApReplayCache, ClientAddressStorage, UserSessionStorage};
 use crate::tests_common::mocked::{
-    MockedApReplayCache, MockedClientAddressStorage, MockedCrypto, MockedUserSessionStorage,
+    MockedApReplayCache, MockedClientAddressStorage, MockedCrypto, MockedHasher,
+    MockedUserSessionStorage,
 };
 use messages::basic_types::{
-    EncryptedData, EncryptionKey, HostAddresses, KerberosString, KerberosTime, NameTypes,
-    OctetString, PrincipalName, Realm,
+    ADEntry, AuthorizationData, AuthorizationDataTypes, EncryptedData, EncryptionKey,
+    HostAddresses, KerberosString, KerberosTime, NameTypes, OctetString, PrincipalName, Realm,
 };
 use messages::flags::TicketFlag;
 use messages::{
-    APOptions, ApReq, AuthenticatorBuilder, EncTicketPart, Encode, Ticket, TicketFlags,
-    TransitedEncoding,
+    APOptions, ApReq, AuthenticatorBuilder, Decode, Ecode, EncApRepPart, EncTicketPart, Encode,
+    Ticket, TicketFlags, TransitedEncoding,
 };
 use std::sync::LazyLock;
 use std::time::Duration;
@@ -59,6 +66,16 @@ static CLIENT_NAME: LazyLock<PrincipalName> = LazyLock::new(|| {
 
 static CLIENT_REALM: LazyLock<Realm> = LazyLock::new(|| Realm::try_from("EXAMPLE.COM").unwrap());
 
+static SERVER_KEYTAB: LazyLock<Keytab> = LazyLock::new(|| {
+    Keytab::new(vec![KeytabEntry {
+        principal: SERVER_NAME.clone(),
+        realm: SERVER_REALM.clone(),
+        timestamp: KerberosTime::now(),
+        kvno: 1,
+        key: SERVER_KEY.clone(),
+    }])
+});
+
 struct TicketConfig {
     authtime: KerberosTime,
     starttime: KerberosTime,
@@ -69,6 +86,7 @@ struct TicketConfig {
     cname: PrincipalName,
     crealm: Realm,
     caddr: HostAddresses,
+    authorization_data: Option<AuthorizationData>,
 }
 
 impl Default for TicketConfig {
@@ -83,6 +101,7 @@ impl Default for TicketConfig {
             cname: CLIENT_NAME.clone(),
             crealm: CLIENT_REALM.clone(),
             caddr: vec![],
+            authorization_data: None,
         }
     }
 }
@@ -96,6 +115,27 @@ fn create_ap_service<'a>(
     MockedApReplayCache,
     MockedUserSessionStorage,
     MockedClientAddressStorage,
+    Keytab,
+> {
+    create_ap_service_with_keytab(
+        ap_replay_cache,
+        address_storage,
+        session_storage,
+        &SERVER_KEYTAB,
+    )
+}
+
+fn create_ap_service_with_keytab<'a>(
+    ap_replay_cache: &'a MockedApReplayCache,
+    address_storage: &'a MockedClientAddressStorage,
+    session_storage: &'a MockedUserSessionStorage,
+    keytab: &'a Keytab,
+) -> ApplicationAuthenticationService<
+    'a,
+    MockedApReplayCache,
+    MockedUserSessionStorage,
+    MockedClientAddressStorage,
+    Keytab,
 > {
     ApplicationAuthenticationServiceBuilder::default()
         .realm(SERVER_REALM.clone())
@@ -104,15 +144,20 @@ fn create_ap_service<'a>(
         .ticket_allowable_clock_skew(Duration::from_secs(60 * 5))
         .replay_cache(ap_replay_cache)
         .crypto(vec![Box::new(MockedCrypto)])
+        .supported_checksum(vec![Box::new(MockedHasher)])
         .session_storage(session_storage)
-        .service_key(SERVER_KEY.to_owned())
+        .key_store(keytab)
         .address_storage(address_storage)
         .build()
         .unwrap()
 }
 
 fn make_ticket(config: &TicketConfig) -> Ticket {
-    let enc_ticket = EncTicketPart::builder()
+    let mut enc_ticket = EncTicketPart::builder();
+    if let Some(authorization_data) = &config.authorization_data {
+        enc_ticket.authorization_data(authorization_data.clone());
+    }
+    let enc_ticket = enc_ticket
         .transited(TransitedEncoding::new(1, OctetString::new(vec![]).unwrap()))
         .key(config.key.clone())
         .cname(config.cname.clone())
@@ -293,3 +338,382 @@ async fn test_ticket_time() {
         .await
         .expect_err("Should return error, the ticket is expired");
 }
+
+fn make_ap_req(ticket: Ticket) -> ApReq {
+    make_ap_req_at(ticket, KerberosTime::now(), 1)
+}
+
+fn make_ap_req_at(ticket: Ticket, ctime: KerberosTime, seq_number: i32) -> ApReq {
+    let authenticator = AuthenticatorBuilder::default()
+        .ctime(ctime)
+        .cusec(0)
+        .crealm(CLIENT_REALM.clone())
+        .cname(CLIENT_NAME.clone())
+        .seq_number(seq_number)
+        .build()
+        .unwrap()
+        .to_der()
+        .unwrap();
+    let encrypted_authenticator = MockedCrypto
+        .encrypt(&authenticator, SESSION_KEY.keyvalue().as_bytes())
+        .unwrap();
+    let authenticator = EncryptedData::new(
+        *SESSION_KEY.keytype(),
+        None,
+        OctetString::new(encrypted_authenticator).unwrap(),
+    );
+    ApReq::new(APOptions::new(false, false), ticket, authenticator)
+}
+
+fn make_ad_entry(ad_type: i32, data: &[u8]) -> ADEntry {
+    ADEntry::new(ad_type, OctetString::new(data).unwrap())
+}
+
+#[tokio::test]
+async fn test_authorization_data_is_exposed_to_the_application() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let kdc_issued = sign_kdc_issued(
+        &[Box::new(MockedHasher)],
+        &SESSION_KEY,
+        vec![make_ad_entry(-1, b"admins")],
+        &SERVER_REALM,
+        &SERVER_NAME,
+    )
+    .unwrap();
+    let if_relevant = make_ad_entry(
+        AuthorizationDataTypes::IfRelevant as i32,
+        &vec![make_ad_entry(-2, b"hint")].to_der().unwrap(),
+    );
+    let ticket = make_ticket(&TicketConfig {
+        authorization_data: Some(vec![kdc_issued, if_relevant]),
+        ..TicketConfig::default()
+    });
+
+    auth_service
+        .handle_krb_ap_req(make_ap_req(ticket))
+        .await
+        .expect("Ticket with authorization data should be accepted");
+
+    let session = session_storage
+        .get_session(&CLIENT_NAME, &CLIENT_REALM)
+        .await
+        .unwrap()
+        .expect("Session should be stored");
+    assert_eq!(
+        session.authorization_data,
+        vec![
+            AuthorizationElement {
+                entry: make_ad_entry(-1, b"admins"),
+                kdc_issued: true,
+            },
+            AuthorizationElement {
+                entry: make_ad_entry(-2, b"hint"),
+                kdc_issued: false,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_kdc_issued_authorization_data_signed_for_another_ticket_is_rejected() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let kdc_issued = sign_kdc_issued(
+        &[Box::new(MockedHasher)],
+        &CLIENT_KEY,
+        vec![make_ad_entry(-1, b"admins")],
+        &SERVER_REALM,
+        &SERVER_NAME,
+    )
+    .unwrap();
+    let ticket = make_ticket(&TicketConfig {
+        authorization_data: Some(vec![kdc_issued]),
+        ..TicketConfig::default()
+    });
+
+    match auth_service.handle_krb_ap_req(make_ap_req(ticket)).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_MODIFIED)
+        }
+        other => panic!("Ticket should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_only_claims_issued_by_the_kdc_are_trusted() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let claims = |group: &str| PrincipalClaims {
+        groups: vec![KerberosString::new(group).unwrap()],
+        roles: vec![],
+    };
+    // The client may put elements of its own outside of the KDC-issued container
+    let forged = make_ad_entry(AD_PRINCIPAL_CLAIMS, &claims("admins").to_der().unwrap());
+    let mut authorization_data = vec![forged];
+    authorization_data.extend(
+        issue_claims(
+            &[Box::new(MockedHasher)],
+            &SESSION_KEY,
+            &claims("users"),
+            &SERVER_REALM,
+            &SERVER_NAME,
+        )
+        .unwrap(),
+    );
+    let ticket = make_ticket(&TicketConfig {
+        authorization_data: Some(authorization_data),
+        ..TicketConfig::default()
+    });
+
+    auth_service
+        .handle_krb_ap_req(make_ap_req(ticket))
+        .await
+        .expect("Ticket with claims should be accepted");
+
+    let session = session_storage
+        .get_session(&CLIENT_NAME, &CLIENT_REALM)
+        .await
+        .unwrap()
+        .expect("Session should be stored");
+    let claims = PrincipalClaims::from_elements(&session.authorization_data)
+        .unwrap()
+        .expect("Claims should be found");
+    assert!(claims.is_member_of("users"));
+    assert!(!claims.is_member_of("admins"));
+}
+
+/// Ticket of CLIENT carrying a PAC of `cname`, signed for the service of key `server_key`
+fn make_ticket_with_pac(cname: &str, server_key: &EncryptionKey) -> Ticket {
+    let authtime = KerberosTime::now();
+    let pac = Pac::issue(
+        &[Box::new(MockedHasher)],
+        server_key,
+        &CLIENT_KEY,
+        &PrincipalName::new(
+            NameTypes::NtPrincipal,
+            vec![KerberosString::new(cname).unwrap()],
+        )
+        .unwrap(),
+        &CLIENT_REALM,
+        authtime,
+        &SERVER_NAME,
+    )
+    .unwrap();
+    make_ticket(&TicketConfig {
+        authtime,
+        authorization_data: Some(vec![pac.to_authorization_data().unwrap()]),
+        ..TicketConfig::default()
+    })
+}
+
+#[tokio::test]
+async fn test_pac_signed_for_the_service_is_accepted() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let ticket = make_ticket_with_pac("CLIENT", &SERVER_KEY);
+
+    auth_service
+        .handle_krb_ap_req(make_ap_req(ticket))
+        .await
+        .expect("Ticket with a PAC should be accepted");
+}
+
+#[tokio::test]
+async fn test_pac_signed_for_another_service_or_client_is_rejected() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    for ticket in [
+        make_ticket_with_pac("CLIENT", &SESSION_KEY),
+        make_ticket_with_pac("OTHER", &SERVER_KEY),
+    ] {
+        match auth_service.handle_krb_ap_req(make_ap_req(ticket)).await {
+            Err(ServerError::ProtocolError(err)) => {
+                assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_MODIFIED)
+            }
+            other => panic!("Ticket should be rejected, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_replayed_authenticator_is_rejected() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let ap_req = make_ap_req(make_ticket(&TicketConfig::default()));
+
+    auth_service
+        .handle_krb_ap_req(ap_req.clone())
+        .await
+        .expect("First authenticator should be accepted");
+    match auth_service.handle_krb_ap_req(ap_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_REPEAT)
+        }
+        other => panic!("Replayed authenticator should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_authenticators_sent_within_the_same_microsecond_are_not_replays() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let ctime = KerberosTime::now();
+    for seq_number in [1, 2] {
+        auth_service
+            .handle_krb_ap_req(make_ap_req_at(
+                make_ticket(&TicketConfig::default()),
+                ctime,
+                seq_number,
+            ))
+            .await
+            .expect("Authenticators with distinct ciphertexts should be accepted");
+    }
+}
+
+#[tokio::test]
+async fn test_mutual_authentication_negotiates_a_subkey_and_sequence_number() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+
+    let ctime = KerberosTime::now();
+    let ap_req = make_ap_req_at(make_ticket(&TicketConfig::default()), ctime, 1);
+    let ap_req = ApReq::new(
+        APOptions::new(false, true),
+        ap_req.ticket().clone(),
+        ap_req.authenticator().clone(),
+    );
+
+    let ap_rep = auth_service
+        .handle_krb_ap_req(ap_req)
+        .await
+        .unwrap()
+        .expect("Server should reply when mutual authentication is required");
+    assert_eq!(ap_rep.enc_part().etype(), SESSION_KEY.keytype());
+    let enc_part = EncApRepPart::from_der(
+        &MockedCrypto
+            .decrypt(
+                ap_rep.enc_part().cipher().as_bytes(),
+                SESSION_KEY.keyvalue().as_bytes(),
+            )
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(enc_part.ctime(), &ctime);
+
+    let session = session_storage
+        .get_session(&CLIENT_NAME, &CLIENT_REALM)
+        .await
+        .unwrap()
+        .expect("Session should be stored");
+    assert_eq!(session.sequence_number, 1);
+    assert!(enc_part.subkey().is_some());
+    assert_eq!(enc_part.subkey(), session.subkey.as_ref());
+    assert_eq!(enc_part.seq_number(), session.server_sequence_number.as_ref());
+}
+
+fn make_ticket_for(sname: &PrincipalName, key: &EncryptionKey, kvno: Option<u32>) -> Ticket {
+    let ticket = make_ticket(&TicketConfig::default());
+    let enc_part = MockedCrypto
+        .decrypt(
+            ticket.enc_part().cipher().as_bytes(),
+            SERVER_KEY.keyvalue().as_bytes(),
+        )
+        .unwrap();
+    let enc_part = MockedCrypto
+        .encrypt(&enc_part, key.keyvalue().as_bytes())
+        .unwrap();
+    Ticket::new(
+        SERVER_REALM.clone(),
+        sname.clone(),
+        EncryptedData::new(1, kvno, OctetString::new(enc_part).unwrap()),
+    )
+}
+
+#[tokio::test]
+async fn test_tickets_of_every_service_and_key_version_of_the_keytab_are_accepted() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let other_service = "HTTP/web01.corp.example".parse::<PrincipalName>().unwrap();
+    let rotated_key = EncryptionKey::new(1, OctetString::new(vec![0x4; 16]).unwrap());
+    let mut keytab = SERVER_KEYTAB.clone();
+    for (principal, kvno, key) in [
+        (SERVER_NAME.clone(), 2, rotated_key.clone()),
+        (other_service.clone(), 1, SERVER_KEY.clone()),
+    ] {
+        keytab.add(KeytabEntry {
+            principal,
+            realm: SERVER_REALM.clone(),
+            timestamp: KerberosTime::now(),
+            kvno,
+            key,
+        });
+    }
+    let auth_service =
+        create_ap_service_with_keytab(&cache, &address_storage, &session_storage, &keytab);
+
+    // Tickets issued before the rotation remain valid until they expire
+    for (seq_number, ticket) in [
+        make_ticket_for(&SERVER_NAME, &SERVER_KEY, Some(1)),
+        make_ticket_for(&SERVER_NAME, &rotated_key, Some(2)),
+        make_ticket_for(&SERVER_NAME, &rotated_key, None),
+        make_ticket_for(&other_service, &SERVER_KEY, None),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        auth_service
+            .handle_krb_ap_req(make_ap_req_at(ticket, KerberosTime::now(), seq_number as i32))
+            .await
+            .expect("Ticket encrypted in a key of the keytab should be accepted");
+    }
+}
+
+#[tokio::test]
+async fn test_tickets_without_key_in_the_keytab_are_rejected() {
+    let cache = MockedApReplayCache::new();
+    let address_storage = MockedClientAddressStorage::new();
+    let session_storage = MockedUserSessionStorage::new();
+    let auth_service = create_ap_service(&cache, &address_storage, &session_storage);
+    let other_service = "HTTP/web01.corp.example".parse::<PrincipalName>().unwrap();
+
+    for (ticket, error_code) in [
+        (
+            make_ticket_for(&other_service, &SERVER_KEY, None),
+            Ecode::KRB_AP_ERR_NOKEY,
+        ),
+        (
+            make_ticket_for(&SERVER_NAME, &SERVER_KEY, Some(2)),
+            Ecode::KRB_AP_ERR_BADKEYVER,
+        ),
+    ] {
+        match auth_service.handle_krb_ap_req(make_ap_req(ticket)).await {
+            Err(ServerError::ProtocolError(err)) => assert_eq!(err.error_code(), error_code),
+            other => panic!("Ticket should be rejected, got {:?}", other),
+        }
+    }
+}
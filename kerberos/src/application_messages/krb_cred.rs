@@ -0,0 +1,62 @@
+use super::{current_time, MessageContext};
+use crate::algo::Sha1;
+use crate::client::ccache::Credential;
+use crate::cryptographic_hash::CryptographicHash;
+use messages::{Decode, Ecode, EncKrbCredPart, KrbCred};
+use std::time::Duration;
+
+/// msg-type of KRB-CRED, https://www.rfc-editor.org/rfc/rfc4120#section-5.8.1
+const KRB_CRED_MSG_TYPE: i32 = 22;
+
+impl MessageContext {
+    /// Credentials the other end delegated in a KRB-CRED, e.g. a forwarded TGT, once decrypted
+    /// and checked it is recent and not replayed, https://www.rfc-editor.org/rfc/rfc4120#section-3.6.2
+    pub fn read_krb_cred(&mut self, message: &KrbCred) -> Result<Vec<Credential>, Ecode> {
+        self.read_krb_cred_at(message, current_time()?)
+    }
+
+    pub(super) fn read_krb_cred_at(
+        &mut self,
+        message: &KrbCred,
+        now: Duration,
+    ) -> Result<Vec<Credential>, Ecode> {
+        if *message.pvno() != 5 {
+            return Err(Ecode::KRB_AP_ERR_BADVERSION);
+        }
+        if *message.msg_type() != KRB_CRED_MSG_TYPE {
+            return Err(Ecode::KRB_AP_ERR_MSG_TYPE);
+        }
+        let enc_part = message.enc_part();
+        if enc_part.etype() != self.key.keytype() {
+            return Err(Ecode::KDC_ERR_ETYPE_NOSUPP);
+        }
+        let cipher = enc_part.cipher().as_bytes();
+        let enc_krb_cred_part = self
+            .get_crypto()?
+            .decrypt(cipher, self.key.keyvalue().as_bytes())
+            .ok()
+            .and_then(|data| EncKrbCredPart::from_der(&data).ok())
+            .ok_or(Ecode::KRB_AP_ERR_BAD_INTEGRITY)?;
+
+        self.verify_addresses(enc_krb_cred_part.s_address(), enc_krb_cred_part.r_address())?;
+        // Unlike KRB-SAFE and KRB-PRIV, KRB-CRED is protected by its timestamp only
+        let (timestamp, usec) = enc_krb_cred_part
+            .timestamp()
+            .zip(enc_krb_cred_part.usec())
+            .ok_or(Ecode::KRB_AP_ERR_SKEW)?;
+        self.verify_timestamp(timestamp, usec, Sha1::new().digest(cipher), now)?;
+
+        let ticket_info = enc_krb_cred_part.ticket_info();
+        if ticket_info.len() != message.tickets().len() {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        ticket_info
+            .iter()
+            .zip(message.tickets())
+            .map(|(info, ticket)| {
+                Credential::from_krb_cred_info(info, ticket.clone())
+                    .ok_or(Ecode::KRB_AP_ERR_MODIFIED)
+            })
+            .collect()
+    }
+}
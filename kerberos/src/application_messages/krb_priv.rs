@@ -0,0 +1,87 @@
+use super::{current_time, Freshness, MessageContext};
+use crate::algo::Sha1;
+use crate::cryptographic_hash::CryptographicHash;
+use messages::basic_types::{EncryptedData, OctetString};
+use messages::{Decode, Ecode, EncKrbPrivPart, Encode, KrbPriv};
+use std::time::Duration;
+
+/// msg-type of KRB-PRIV, https://www.rfc-editor.org/rfc/rfc4120#section-5.7.1
+const KRB_PRIV_MSG_TYPE: i32 = 21;
+
+impl MessageContext {
+    /// KRB-PRIV carrying `user_data` encrypted to the other end, which can tell whether it
+    /// was modified, https://www.rfc-editor.org/rfc/rfc4120#section-3.5.1
+    pub fn seal(&mut self, user_data: &[u8]) -> Result<KrbPriv, Ecode> {
+        self.seal_at(user_data, current_time()?)
+    }
+
+    pub(super) fn seal_at(&mut self, user_data: &[u8], now: Duration) -> Result<KrbPriv, Ecode> {
+        let (timestamp, usec, seq_number) = match self.next_freshness(now)? {
+            Freshness::Timestamp(timestamp, usec) => (Some(timestamp), Some(usec), None),
+            Freshness::SeqNumber(seq_number) => (None, None, Some(seq_number)),
+        };
+        let enc_part = EncKrbPrivPart::new(
+            OctetString::new(user_data).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+            timestamp,
+            usec,
+            seq_number,
+            self.local_address.clone(),
+            self.remote_address.clone(),
+        );
+        let cipher = self
+            .get_crypto()?
+            .encrypt(
+                &enc_part.to_der().map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+                self.key.keyvalue().as_bytes(),
+            )
+            .map_err(|_| Ecode::KRB_ERR_GENERIC)?;
+        Ok(KrbPriv::new(
+            5,
+            KRB_PRIV_MSG_TYPE,
+            EncryptedData::new(
+                *self.key.keytype(),
+                None,
+                OctetString::new(cipher).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+            ),
+        ))
+    }
+
+    /// User data of a KRB-PRIV sent by the other end, once decrypted and checked it is
+    /// neither replayed nor out of order, https://www.rfc-editor.org/rfc/rfc4120#section-3.5.2
+    pub fn unseal(&mut self, message: &KrbPriv) -> Result<Vec<u8>, Ecode> {
+        self.unseal_at(message, current_time()?)
+    }
+
+    pub(super) fn unseal_at(&mut self, message: &KrbPriv, now: Duration) -> Result<Vec<u8>, Ecode> {
+        if *message.pvno() != 5 {
+            return Err(Ecode::KRB_AP_ERR_BADVERSION);
+        }
+        if *message.msg_type() != KRB_PRIV_MSG_TYPE {
+            return Err(Ecode::KRB_AP_ERR_MSG_TYPE);
+        }
+        let enc_part = message.enc_part();
+        if enc_part.etype() != self.key.keytype() {
+            return Err(Ecode::KDC_ERR_ETYPE_NOSUPP);
+        }
+        let cipher = enc_part.cipher().as_bytes();
+        let enc_krb_priv_part = self
+            .get_crypto()?
+            .decrypt(cipher, self.key.keyvalue().as_bytes())
+            .ok()
+            .and_then(|data| EncKrbPrivPart::from_der(&data).ok())
+            .ok_or(Ecode::KRB_AP_ERR_BAD_INTEGRITY)?;
+
+        self.verify_addresses(
+            Some(enc_krb_priv_part.s_address()),
+            enc_krb_priv_part.r_address(),
+        )?;
+        self.verify_freshness(
+            enc_krb_priv_part.timestamp(),
+            enc_krb_priv_part.usec(),
+            enc_krb_priv_part.seq_number(),
+            Sha1::new().digest(cipher),
+            now,
+        )?;
+        Ok(enc_krb_priv_part.user_data().as_bytes().to_vec())
+    }
+}
@@ -0,0 +1,71 @@
+use super::{current_time, Freshness, MessageContext};
+use messages::basic_types::OctetString;
+use messages::{Ecode, Encode, KrbSafe, KrbSafeBody};
+use std::time::Duration;
+
+/// msg-type of KRB-SAFE, https://www.rfc-editor.org/rfc/rfc4120#section-5.6.1
+const KRB_SAFE_MSG_TYPE: u8 = 20;
+
+impl MessageContext {
+    /// KRB-SAFE carrying `user_data` to the other end, which can tell whether it was
+    /// modified, https://www.rfc-editor.org/rfc/rfc4120#section-3.4.1
+    pub fn make_krb_safe(&mut self, user_data: &[u8]) -> Result<KrbSafe, Ecode> {
+        self.make_krb_safe_at(user_data, current_time()?)
+    }
+
+    pub(super) fn make_krb_safe_at(
+        &mut self,
+        user_data: &[u8],
+        now: Duration,
+    ) -> Result<KrbSafe, Ecode> {
+        let (timestamp, usec, seq_number) = match self.next_freshness(now)? {
+            Freshness::Timestamp(timestamp, usec) => (Some(timestamp), Some(usec), None),
+            Freshness::SeqNumber(seq_number) => (None, None, Some(seq_number)),
+        };
+        let body = KrbSafeBody::new(
+            OctetString::new(user_data).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+            timestamp,
+            usec,
+            seq_number,
+            self.local_address.clone(),
+            self.remote_address.clone(),
+        );
+        let cksum = self.make_checksum(&body.to_der().map_err(|_| Ecode::KRB_ERR_GENERIC)?)?;
+        Ok(KrbSafe::new(body, cksum))
+    }
+
+    /// User data of a KRB-SAFE sent by the other end, once checked it was not modified nor
+    /// replayed, https://www.rfc-editor.org/rfc/rfc4120#section-3.4.2
+    pub fn read_krb_safe(&mut self, message: &KrbSafe) -> Result<Vec<u8>, Ecode> {
+        self.read_krb_safe_at(message, current_time()?)
+    }
+
+    pub(super) fn read_krb_safe_at(
+        &mut self,
+        message: &KrbSafe,
+        now: Duration,
+    ) -> Result<Vec<u8>, Ecode> {
+        if message.pvno() != 5 {
+            return Err(Ecode::KRB_AP_ERR_BADVERSION);
+        }
+        if message.msg_type() != KRB_SAFE_MSG_TYPE {
+            return Err(Ecode::KRB_AP_ERR_MSG_TYPE);
+        }
+        let body = message.safe_body();
+        self.verify_addresses(Some(body.s_address()), body.r_address())?;
+        // The checksum is verified first so that forged messages leave the sequence numbers
+        // and the replay cache untouched
+        self.verify_checksum(
+            message.cksum(),
+            &body.to_der().map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)?,
+        )?;
+        self.verify_freshness(
+            body.timestamp(),
+            body.usec(),
+            body.seq_number(),
+            message.cksum().checksum().as_bytes().to_vec(),
+            now,
+        )?;
+        Ok(body.user_data().as_bytes().to_vec())
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests;
+
+mod krb_cred;
+mod krb_priv;
+mod krb_safe;
+
+use crate::cryptographic_hash::CryptographicHash;
+use crate::cryptography::Cryptography;
+use derive_builder::Builder;
+use messages::basic_types::{
+    Checksum, EncryptionKey, HostAddress, KerberosTime, Microseconds, OctetString, UInt32,
+};
+use messages::Ecode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One end of the KRB-SAFE, KRB-PRIV and KRB-CRED messages an application exchanges once the client is
+/// authenticated, https://www.rfc-editor.org/rfc/rfc4120#section-3.4
+#[derive(Builder)]
+#[builder(pattern = "owned", setter(strip_option))]
+pub struct MessageContext {
+    /// Subkey negotiated through mutual authentication, or else the session key of the ticket
+    key: EncryptionKey,
+    local_address: HostAddress,
+    /// Address of the other end, which received messages must come from when set
+    #[builder(default)]
+    remote_address: Option<HostAddress>,
+    /// Sequence number of the next message sent, which are timestamped instead when `None`
+    #[builder(default)]
+    local_seq_number: Option<UInt32>,
+    /// Sequence number of the next message expected, received messages being timestamped
+    /// instead when `None`
+    #[builder(default)]
+    remote_seq_number: Option<UInt32>,
+    #[builder(default = "Duration::from_secs(5 * 60)")]
+    clock_skew: Duration,
+    /// Checksums KRB-SAFE messages may be protected with, the first being used for the ones
+    /// sent
+    #[builder(default)]
+    supported_checksum: Vec<Box<dyn CryptographicHash + Send + Sync>>,
+    /// Encryption of KRB-PRIV and KRB-CRED messages, the one of the type of `key` being used
+    #[builder(default)]
+    crypto: Vec<Box<dyn Cryptography + Send + Sync>>,
+    /// Timestamped messages received within the clock skew, to tell replays apart
+    #[builder(setter(skip))]
+    received: Vec<ReceivedMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReceivedMessage {
+    timestamp: KerberosTime,
+    usec: Microseconds,
+    /// Digest of the protected part of the message, telling apart the ones sent within the
+    /// same microsecond
+    digest: Vec<u8>,
+}
+
+/// Replay protection of a message, either a timestamp or a sequence number
+enum Freshness {
+    Timestamp(KerberosTime, Microseconds),
+    SeqNumber(UInt32),
+}
+
+impl MessageContext {
+    pub fn builder() -> MessageContextBuilder {
+        MessageContextBuilder::default()
+    }
+
+    /// Protection of the next message sent: its sequence number, which is then moved on, or
+    /// the time it is sent at
+    fn next_freshness(&mut self, now: Duration) -> Result<Freshness, Ecode> {
+        match self.local_seq_number.as_mut() {
+            Some(seq_number) => {
+                let current = *seq_number;
+                *seq_number = seq_number.wrapping_add(1);
+                Ok(Freshness::SeqNumber(current))
+            }
+            None => Ok(Freshness::Timestamp(
+                KerberosTime::from_unix_duration(Duration::from_secs(now.as_secs()))
+                    .map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+                now.subsec_micros() as Microseconds,
+            )),
+        }
+    }
+
+    /// Check the sender and recipient of a received message, when they are known. A message
+    /// claiming to come from this end is one it sent, reflected back to it
+    fn verify_addresses(
+        &self,
+        s_address: Option<&HostAddress>,
+        r_address: Option<&HostAddress>,
+    ) -> Result<(), Ecode> {
+        if s_address == Some(&self.local_address) {
+            return Err(Ecode::KRB_AP_ERR_BADDIRECTION);
+        }
+        if s_address
+            .zip(self.remote_address.as_ref())
+            .is_some_and(|(sender, remote)| sender != remote)
+            || r_address.is_some_and(|local| local != &self.local_address)
+        {
+            return Err(Ecode::KRB_AP_ERR_BADADDR);
+        }
+        Ok(())
+    }
+
+    /// Check a received message is the next one expected, or is recent and was not received
+    /// already, then remember it
+    fn verify_freshness(
+        &mut self,
+        timestamp: Option<&KerberosTime>,
+        usec: Option<&Microseconds>,
+        seq_number: Option<&UInt32>,
+        digest: Vec<u8>,
+        now: Duration,
+    ) -> Result<(), Ecode> {
+        if let Some(expected) = self.remote_seq_number.as_mut() {
+            if seq_number != Some(expected) {
+                return Err(Ecode::KRB_AP_ERR_BADORDER);
+            }
+            *expected = expected.wrapping_add(1);
+            return Ok(());
+        }
+        let (timestamp, usec) = timestamp.zip(usec).ok_or(Ecode::KRB_AP_ERR_MODIFIED)?;
+        self.verify_timestamp(timestamp, usec, digest, now)
+    }
+
+    /// Check a received message is recent and was not received already, then remember it
+    fn verify_timestamp(
+        &mut self,
+        timestamp: &KerberosTime,
+        usec: &Microseconds,
+        digest: Vec<u8>,
+        now: Duration,
+    ) -> Result<(), Ecode> {
+        let now = KerberosTime::from_unix_duration(Duration::from_secs(now.as_secs()))
+            .map_err(|_| Ecode::KRB_ERR_GENERIC)?;
+        if timestamp.abs_diff(&now) > self.clock_skew {
+            return Err(Ecode::KRB_AP_ERR_SKEW);
+        }
+        // Messages older than the clock skew are rejected as such, so need not be remembered
+        let clock_skew = self.clock_skew;
+        self.received
+            .retain(|message| message.timestamp.abs_diff(&now) <= clock_skew);
+        let message = ReceivedMessage {
+            timestamp: *timestamp,
+            usec: *usec,
+            digest,
+        };
+        if self.received.contains(&message) {
+            return Err(Ecode::KRB_AP_ERR_REPEAT);
+        }
+        self.received.push(message);
+        Ok(())
+    }
+
+    /// Checksum of `data` keyed with the key of the exchange, of the first supported type
+    fn make_checksum(&self, data: &[u8]) -> Result<Checksum, Ecode> {
+        let hash = self
+            .supported_checksum
+            .first()
+            .ok_or(Ecode::KDC_ERR_SUMTYPE_NOSUPP)?;
+        Ok(Checksum::new(
+            hash.get_checksum_type(),
+            OctetString::new(hash.keyed_digest(self.key.keyvalue().as_bytes(), data))
+                .map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+        ))
+    }
+
+    /// Check `cksum` was computed over `data` with the key of the exchange
+    fn verify_checksum(&self, cksum: &Checksum, data: &[u8]) -> Result<(), Ecode> {
+        let hash = self
+            .supported_checksum
+            .iter()
+            .find(|hash| hash.get_checksum_type() == *cksum.cksumtype())
+            .ok_or(Ecode::KRB_AP_ERR_INAPP_CKSUM)?;
+        if hash.keyed_digest(self.key.keyvalue().as_bytes(), data) != cksum.checksum().as_bytes() {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        Ok(())
+    }
+
+    /// Encryption of the type of the key of the exchange
+    fn get_crypto(&self) -> Result<&(dyn Cryptography + Send + Sync), Ecode> {
+        self.crypto
+            .iter()
+            .find(|crypto| crypto.get_etype() == *self.key.keytype())
+            .map(|crypto| crypto.as_ref())
+            .ok_or(Ecode::KDC_ERR_ETYPE_NOSUPP)
+    }
+}
+
+fn current_time() -> Result<Duration, Ecode> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Ecode::KRB_ERR_GENERIC)
+}
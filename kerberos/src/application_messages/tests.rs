@@ -0,0 +1,304 @@
+use crate::application_messages::MessageContext;
+use crate::cryptography::Cryptography;
+use crate::{AesGcm, Sha1};
+use messages::basic_types::{
+    AddressTypes, Checksum, EncryptedData, EncryptionKey, HostAddress, KerberosString,
+    KerberosTime, NameTypes, OctetString, PrincipalName, Realm,
+};
+use messages::{
+    Ecode, EncKrbCredPart, Encode, KrbCred, KrbCredInfo, KrbPriv, KrbSafe, KrbSafeBody, Ticket,
+};
+use std::time::Duration;
+
+const NOW: Duration = Duration::from_secs(1_700_000_000);
+
+fn key(byte: u8) -> EncryptionKey {
+    EncryptionKey::new(1, OctetString::new([byte; 32]).unwrap())
+}
+
+fn address(last: u8) -> HostAddress {
+    HostAddress::new(
+        AddressTypes::Ipv4,
+        OctetString::new([10, 0, 0, last]).unwrap(),
+    )
+    .unwrap()
+}
+
+/// Client at 10.0.0.1 and server at 10.0.0.2 of a session of key `key`, using sequence
+/// numbers starting at `seq_numbers` if any
+fn ends(key: EncryptionKey, seq_numbers: Option<(u32, u32)>) -> (MessageContext, MessageContext) {
+    let end = |local, remote, seq_numbers: Option<(u32, u32)>| {
+        let mut builder = MessageContext::builder()
+            .key(key.clone())
+            .local_address(address(local))
+            .remote_address(address(remote))
+            .supported_checksum(vec![Box::new(Sha1::new())])
+            .crypto(vec![Box::new(AesGcm::new())]);
+        if let Some((sent, received)) = seq_numbers {
+            builder = builder.local_seq_number(sent).remote_seq_number(received);
+        }
+        builder.build().unwrap()
+    };
+    (
+        end(1, 2, seq_numbers),
+        end(2, 1, seq_numbers.map(|(client, server)| (server, client))),
+    )
+}
+
+#[test]
+fn timestamped_krb_safe_should_be_read_once() {
+    let (mut client, mut server) = ends(key(1), None);
+
+    let message = client.make_krb_safe_at(b"transfer 10", NOW).unwrap();
+    assert!(message.safe_body().timestamp().is_some());
+    assert_eq!(
+        server.read_krb_safe_at(&message, NOW + Duration::from_secs(1)),
+        Ok(b"transfer 10".to_vec())
+    );
+    assert_eq!(
+        server.read_krb_safe_at(&message, NOW + Duration::from_secs(2)),
+        Err(Ecode::KRB_AP_ERR_REPEAT)
+    );
+
+    let later = client
+        .make_krb_safe_at(b"transfer 10", NOW + Duration::from_micros(1))
+        .unwrap();
+    assert_eq!(
+        server.read_krb_safe_at(&later, NOW + Duration::from_secs(2)),
+        Ok(b"transfer 10".to_vec())
+    );
+}
+
+#[test]
+fn krb_safe_outside_the_clock_skew_should_be_rejected() {
+    let (mut client, mut server) = ends(key(1), None);
+
+    let message = client.make_krb_safe_at(b"hello", NOW).unwrap();
+    assert_eq!(
+        server.read_krb_safe_at(&message, NOW + Duration::from_secs(6 * 60)),
+        Err(Ecode::KRB_AP_ERR_SKEW)
+    );
+}
+
+#[test]
+fn krb_safe_should_follow_the_sequence_numbers_of_the_session() {
+    let (mut client, mut server) = ends(key(1), Some((u32::MAX, 7)));
+
+    let first = client.make_krb_safe_at(b"first", NOW).unwrap();
+    let second = client.make_krb_safe_at(b"second", NOW).unwrap();
+    assert_eq!(first.safe_body().seq_number(), Some(&u32::MAX));
+    assert_eq!(second.safe_body().seq_number(), Some(&0));
+    assert_eq!(
+        server.read_krb_safe_at(&second, NOW),
+        Err(Ecode::KRB_AP_ERR_BADORDER)
+    );
+    assert_eq!(server.read_krb_safe_at(&first, NOW), Ok(b"first".to_vec()));
+    assert_eq!(
+        server.read_krb_safe_at(&second, NOW),
+        Ok(b"second".to_vec())
+    );
+    assert_eq!(
+        server.read_krb_safe_at(&second, NOW),
+        Err(Ecode::KRB_AP_ERR_BADORDER)
+    );
+
+    let reply = server.make_krb_safe_at(b"reply", NOW).unwrap();
+    assert_eq!(reply.safe_body().seq_number(), Some(&7));
+    assert_eq!(client.read_krb_safe_at(&reply, NOW), Ok(b"reply".to_vec()));
+}
+
+#[test]
+fn modified_krb_safe_should_be_rejected() {
+    let (mut client, mut server) = ends(key(1), Some((1, 1)));
+    let message = client.make_krb_safe_at(b"transfer 10", NOW).unwrap();
+    let body = message.safe_body();
+
+    let modified = KrbSafe::new(
+        KrbSafeBody::new(
+            OctetString::new(b"transfer 99").unwrap(),
+            None,
+            None,
+            body.seq_number().copied(),
+            body.s_address().clone(),
+            body.r_address().cloned(),
+        ),
+        message.cksum().clone(),
+    );
+    assert_eq!(
+        server.read_krb_safe_at(&modified, NOW),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+
+    let unkeyed = KrbSafe::new(
+        body.clone(),
+        Checksum::new(99, message.cksum().checksum().clone()),
+    );
+    assert_eq!(
+        server.read_krb_safe_at(&unkeyed, NOW),
+        Err(Ecode::KRB_AP_ERR_INAPP_CKSUM)
+    );
+
+    let (_, mut other_session) = ends(key(2), Some((1, 1)));
+    assert_eq!(
+        other_session.read_krb_safe_at(&message, NOW),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+    // Rejected messages do not move the sequence number on
+    assert_eq!(
+        server.read_krb_safe_at(&message, NOW),
+        Ok(b"transfer 10".to_vec())
+    );
+}
+
+#[test]
+fn krb_safe_from_another_address_should_be_rejected() {
+    let (mut client, _) = ends(key(1), None);
+    let mut server = MessageContext::builder()
+        .key(key(1))
+        .local_address(address(2))
+        .remote_address(address(3))
+        .supported_checksum(vec![Box::new(Sha1::new())])
+        .build()
+        .unwrap();
+
+    let message = client.make_krb_safe_at(b"hello", NOW).unwrap();
+    assert_eq!(
+        server.read_krb_safe_at(&message, NOW),
+        Err(Ecode::KRB_AP_ERR_BADADDR)
+    );
+}
+
+#[test]
+fn sealed_messages_should_be_unsealed_in_order() {
+    let (mut client, mut server) = ends(key(1), Some((10, 20)));
+
+    let first = client.seal_at(b"first", NOW).unwrap();
+    let second = client.seal_at(b"second", NOW).unwrap();
+    assert_eq!(*first.msg_type(), 21);
+    assert!(!first
+        .enc_part()
+        .cipher()
+        .as_bytes()
+        .windows(5)
+        .any(|w| w == b"first"));
+    assert_eq!(
+        server.unseal_at(&second, NOW),
+        Err(Ecode::KRB_AP_ERR_BADORDER)
+    );
+    assert_eq!(server.unseal_at(&first, NOW), Ok(b"first".to_vec()));
+    assert_eq!(server.unseal_at(&second, NOW), Ok(b"second".to_vec()));
+    assert_eq!(
+        server.unseal_at(&first, NOW),
+        Err(Ecode::KRB_AP_ERR_BADORDER)
+    );
+
+    let reply = server.seal_at(b"reply", NOW).unwrap();
+    assert_eq!(client.unseal_at(&reply, NOW), Ok(b"reply".to_vec()));
+}
+
+#[test]
+fn timestamped_sealed_message_should_be_unsealed_once() {
+    let (mut client, mut server) = ends(key(1), None);
+
+    let message = client.seal_at(b"hello", NOW).unwrap();
+    assert_eq!(server.unseal_at(&message, NOW), Ok(b"hello".to_vec()));
+    assert_eq!(
+        server.unseal_at(&message, NOW),
+        Err(Ecode::KRB_AP_ERR_REPEAT)
+    );
+}
+
+#[test]
+fn sealed_message_should_only_be_unsealed_by_the_other_end() {
+    let (mut client, mut server) = ends(key(1), Some((1, 1)));
+    let message = client.seal_at(b"hello", NOW).unwrap();
+
+    let mut cipher = message.enc_part().cipher().as_bytes().to_vec();
+    cipher[0] ^= 1;
+    let modified = KrbPriv::new(
+        5,
+        21,
+        EncryptedData::new(1, None, OctetString::new(cipher).unwrap()),
+    );
+    assert_eq!(
+        server.unseal_at(&modified, NOW),
+        Err(Ecode::KRB_AP_ERR_BAD_INTEGRITY)
+    );
+
+    let (_, mut other_session) = ends(key(2), Some((1, 1)));
+    assert_eq!(
+        other_session.unseal_at(&message, NOW),
+        Err(Ecode::KRB_AP_ERR_BAD_INTEGRITY)
+    );
+    // A message sent back to its sender must not pass for one of the other end
+    assert_eq!(
+        client.unseal_at(&message, NOW),
+        Err(Ecode::KRB_AP_ERR_BADDIRECTION)
+    );
+    assert_eq!(server.unseal_at(&message, NOW), Ok(b"hello".to_vec()));
+}
+
+/// KRB-CRED forwarding a TGT of alice, encrypted in `key` and sent at `sent_at` if timestamped
+fn krb_cred(key: &EncryptionKey, sent_at: Option<Duration>) -> KrbCred {
+    let realm = Realm::new("EXAMPLE.COM").unwrap();
+    let info = KrbCredInfo::new(
+        EncryptionKey::new(1, OctetString::new([7; 32]).unwrap()),
+        realm.clone(),
+        PrincipalName::new(
+            NameTypes::NtPrincipal,
+            [KerberosString::new("alice").unwrap()],
+        )
+        .unwrap(),
+        None,
+        None,
+        None,
+        KerberosTime::from_unix_duration(NOW + Duration::from_secs(3600)).unwrap(),
+        None,
+        None,
+        None,
+        None,
+    );
+    let enc_part = EncKrbCredPart::new(
+        vec![info],
+        None,
+        sent_at.map(|sent_at| KerberosTime::from_unix_duration(sent_at).unwrap()),
+        sent_at.map(|sent_at| sent_at.subsec_micros() as i32),
+        None,
+        None,
+    );
+    let cipher = AesGcm::new()
+        .encrypt(&enc_part.to_der().unwrap(), key.keyvalue().as_bytes())
+        .unwrap();
+    KrbCred::new(
+        vec![Ticket::new(
+            realm.clone(),
+            PrincipalName::krbtgt(&realm),
+            EncryptedData::new(1, 2, OctetString::new(b"tgt-cipher").unwrap()),
+        )],
+        EncryptedData::new(1, None, OctetString::new(cipher).unwrap()),
+    )
+}
+
+#[test]
+fn krb_cred_should_be_read_once_with_the_session_key() {
+    let (_, mut server) = ends(key(1), Some((1, 1)));
+
+    let message = krb_cred(&key(1), Some(NOW));
+    let credentials = server.read_krb_cred_at(&message, NOW).unwrap();
+    assert_eq!(credentials.len(), 1);
+    assert!(credentials[0].is_tgt());
+    assert_eq!(credentials[0].ticket, message.tickets()[0]);
+    assert_eq!(
+        server.read_krb_cred_at(&message, NOW),
+        Err(Ecode::KRB_AP_ERR_REPEAT)
+    );
+
+    assert_eq!(
+        server.read_krb_cred_at(&krb_cred(&key(2), Some(NOW)), NOW),
+        Err(Ecode::KRB_AP_ERR_BAD_INTEGRITY)
+    );
+    assert_eq!(
+        server.read_krb_cred_at(&krb_cred(&key(1), None), NOW),
+        Err(Ecode::KRB_AP_ERR_SKEW)
+    );
+}
@@ -1,16 +1,19 @@
 use crate::authentication_service::ServerError::ProtocolError;
+use crate::authorization_data::{self, PrincipalClaims};
+use crate::cryptographic_hash::CryptographicHash;
 use crate::cryptography::Cryptography;
-use crate::service_traits::{PrincipalDatabase, PrincipalDatabaseRecord};
+use crate::pac::Pac;
+use crate::service_traits::{PrincipalAttribute, PrincipalDatabase, PrincipalDatabaseRecord};
 use chrono::Local;
-use derive_builder::Builder;
+use derive_builder::{Builder, UninitializedFieldError};
 use messages::basic_types::{
     EncryptedData, EncryptionKey, Int32, KerberosFlagsBuilder, KerberosTime, OctetString,
-    PrincipalName, Realm, SequenceOf,
+    PaDataTypes, PaEncTimestamp, PaEncTsEnc, PrincipalName, Realm, SequenceOf,
 };
 use messages::flags::{KdcOptionsFlag, TicketFlag};
 use messages::{
-    AsRep, AsReq, Ecode, EncAsRepPart, EncKdcRepPartBuilder, EncTicketPart, Encode, KrbErrorMsg,
-    KrbErrorMsgBuilder, Ticket, TicketFlags, TransitedEncoding,
+    AsRep, AsReq, Decode, Ecode, EncAsRepPart, EncKdcRepPartBuilder, EncTicketPart, Encode,
+    KrbErrorMsg, KrbErrorMsgBuilder, Ticket, TicketFlags, TransitedEncoding,
 };
 use std::ops::RangeInclusive;
 use std::time::Duration;
@@ -31,11 +34,23 @@ pub struct AuthenticationService<'a, P>
 where
     P: PrincipalDatabase + Sync + Send,
 {
+    /// Whether every client must pre-authenticate, rather than only the ones whose
+    /// principal requires it
     require_pre_authenticate: bool,
     supported_crypto_systems: Vec<Box<dyn Cryptography + Send + Sync>>,
+    /// Checksums signing the claims issued into tickets, the first one being used
+    #[builder(default)]
+    supported_checksum: Vec<Box<dyn CryptographicHash + Send + Sync>>,
+    /// Whether tickets carry a PAC, signed with the key of `sname`
+    #[builder(default)]
+    issue_pac: bool,
     principal_db: &'a P,
-    realm: Realm,
+    /// Name of the ticket-granting service, `krbtgt/<realm>` unless set
+    #[builder(
+        default = "PrincipalName::krbtgt(self.realm.as_ref().ok_or(UninitializedFieldError::new(\"realm\"))?)"
+    )]
     sname: PrincipalName,
+    realm: Realm,
 }
 
 pub type Result<T> = std::result::Result<T, ServerError>;
@@ -117,16 +132,28 @@ where
             .get_server(as_req)
             .await
             .ok_or(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN))?;
-        let client_key = client.key;
-        println!("Client key: {:?}", client_key);
-        let server_key = server.key;
+        if client.attributes.has(PrincipalAttribute::DisallowAllTix) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_CLIENT_REVOKED));
+        }
+        if server.attributes.has(PrincipalAttribute::DisallowAllTix) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN));
+        }
+        // The server only accepts user-to-user tickets, which cannot be issued by the AS
+        if server.attributes.has(PrincipalAttribute::DisallowSvr) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_MUST_USE_USER2USER));
+        }
 
-        if self.require_pre_authenticate {
-            if as_req.padata().is_none() {
-                return Err(build_protocol_error(Ecode::KDC_ERR_PREAUTH_REQUIRED));
-            }
-            todo!("Pre-auth is not yet implemented")
+        let pre_authenticated = self
+            .verify_enc_timestamp(as_req, &client.key)
+            .map_err(&mut build_protocol_error)?;
+        if !pre_authenticated
+            && (self.require_pre_authenticate
+                || client.attributes.has(PrincipalAttribute::RequiresPreAuth))
+        {
+            return Err(build_protocol_error(Ecode::KDC_ERR_PREAUTH_REQUIRED));
         }
+        let client_key = client.key.clone();
+        let server_key = server.key.clone();
         self.verify_encryption_type(as_req)?;
 
         let use_crypto_system = self
@@ -150,7 +177,12 @@ where
         let selected_client_key = client_key;
         let kdc_options = as_req.req_body().kdc_options();
 
-        let mut ticket_flags = self.generate_ticket_flags(as_req).unwrap();
+        let mut ticket_flags = self
+            .generate_ticket_flags(as_req, &client, &server)
+            .unwrap();
+        if pre_authenticated {
+            ticket_flags.set(TicketFlag::PRE_AUTHENT as usize);
+        }
 
         let mut ticket = EncTicketPart::builder();
         if let Some(addr) = as_req.req_body().addresses() {
@@ -165,6 +197,9 @@ where
         let mut starttime = None;
 
         if kdc_options.is_set(KdcOptionsFlag::POSTDATED as usize) {
+            if disallowed_by_either(&client, &server, PrincipalAttribute::DisallowPostdated) {
+                return Err(build_protocol_error(Ecode::KDC_ERR_CANNOT_POSTDATE));
+            }
             if self.against_postdate_policy(as_req.req_body().from()) {
                 return Err(build_protocol_error(Ecode::KDC_ERR_POLICY));
             }
@@ -185,18 +220,23 @@ where
         .expect("Won't fail");
         ticket.endtime(endtime);
 
-        let rtime = if kdc_options.is_set(KdcOptionsFlag::RENEWABLE_OK as usize)
+        // A renewable ticket is asked for with RENEWABLE, until the requested renew-till time
+        // or with no limit, or with RENEWABLE_OK when the requested end time cannot be granted
+        let rtime = if kdc_options.is_set(KdcOptionsFlag::RENEWABLE as usize) {
+            Some(as_req.req_body().rtime().copied().unwrap_or(KerberosTime::zero()))
+        } else if kdc_options.is_set(KdcOptionsFlag::RENEWABLE_OK as usize)
             && &endtime < as_req.req_body().till()
         {
-            Some(as_req.req_body().till())
+            Some(*as_req.req_body().till())
         } else {
             None
         }
+        .filter(|_| !disallowed_by_either(&client, &server, PrincipalAttribute::DisallowRenewable))
         .map(|t| {
-            if t == &KerberosTime::zero() {
+            if t == KerberosTime::zero() {
                 KerberosTime::infinity()
             } else {
-                KerberosTime::zero()
+                t
             }
         });
 
@@ -217,18 +257,57 @@ where
 
         ticket.starttime(starttime.unwrap_or(kdc_time));
 
+        let cname = as_req
+            .req_body()
+            .cname()
+            .ok_or_else(|| todo!("What should be here???"))?;
+
+        let mut authorization_data = vec![];
+        if self.issue_pac {
+            let kdc_key = self
+                .principal_db
+                .get_principal(&self.sname, &self.realm)
+                .await
+                .ok_or(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN))?
+                .key;
+            let pac = Pac::issue(
+                &self.supported_checksum,
+                &server_key,
+                &kdc_key,
+                cname,
+                as_req.req_body().realm(),
+                kdc_time,
+                &self.sname,
+            )
+            .and_then(|pac| pac.to_authorization_data())
+            .map_err(&mut build_protocol_error)?;
+            authorization_data.push(pac);
+        }
+
+        // The groups of the client are issued into the ticket, so that services can make
+        // authorization decisions without looking them up
+        let claims =
+            PrincipalClaims::from_groups(&self.principal_db.get_groups(cname, &self.realm).await);
+        authorization_data.extend(
+            authorization_data::issue_claims(
+                &self.supported_checksum,
+                &session_key,
+                &claims,
+                &self.realm,
+                &self.sname,
+            )
+            .map_err(&mut build_protocol_error)?,
+        );
+        if !authorization_data.is_empty() {
+            ticket.authorization_data(authorization_data);
+        }
+
         let ticket = ticket
             .flags(ticket_flags.build().unwrap())
-            .cname(
-                as_req
-                    .req_body()
-                    .cname()
-                    .ok_or_else(|| todo!("What should be here???"))?
-                    .clone(),
-            )
+            .cname(cname.clone())
             .crealm(as_req.req_body().realm().clone())
             .key(session_key.clone())
-            .transited(TransitedEncoding::empty(0))
+            .transited(TransitedEncoding::empty(TransitedEncoding::DOMAIN_X500_COMPRESS))
             .authtime(kdc_time)
             .build()
             .unwrap();
@@ -294,14 +373,59 @@ where
         Ok(())
     }
 
+    /// Verify the PA-ENC-TIMESTAMP of the request, the current time encrypted with the
+    /// client's key, telling whether the client sent one,
+    /// https://www.rfc-editor.org/rfc/rfc4120#section-5.2.7.2
+    fn verify_enc_timestamp(
+        &self,
+        as_req: &AsReq,
+        client_key: &EncryptionKey,
+    ) -> std::result::Result<bool, Ecode> {
+        let Some(padata) = as_req.padata().and_then(|padata| {
+            padata
+                .iter()
+                .find(|padata| *padata.padata_type() == PaDataTypes::PaEncTimestamp as i32)
+        }) else {
+            return Ok(false);
+        };
+        let enc_timestamp = PaEncTimestamp::from_der(padata.padata_value().as_bytes())
+            .map_err(|_| Ecode::KDC_ERR_PREAUTH_FAILED)?;
+        if enc_timestamp.etype() != client_key.keytype() {
+            return Err(Ecode::KDC_ERR_ETYPE_NOSUPP);
+        }
+        let data = self
+            .get_supported_crypto_systems()
+            .iter()
+            .find(|crypto| crypto.get_etype() == *client_key.keytype())
+            .ok_or(Ecode::KDC_ERR_ETYPE_NOSUPP)?
+            .decrypt(
+                enc_timestamp.cipher().as_bytes(),
+                client_key.keyvalue().as_bytes(),
+            )
+            .map_err(|_| Ecode::KDC_ERR_PREAUTH_FAILED)?;
+        let timestamp = PaEncTsEnc::from_der(&data).map_err(|_| Ecode::KDC_ERR_PREAUTH_FAILED)?;
+        if !self
+            .get_acceptable_clock_skew()
+            .contains(&timestamp.pa_timestamp())
+        {
+            return Err(Ecode::KRB_AP_ERR_SKEW);
+        }
+        Ok(true)
+    }
+
     // TODO: implement this correctly
     fn generate_ticket_flags(
         &self,
         as_req: &AsReq,
+        client: &PrincipalDatabaseRecord,
+        server: &PrincipalDatabaseRecord,
     ) -> std::result::Result<KerberosFlagsBuilder, Ecode> {
         let kdc_options = as_req.req_body().kdc_options();
         let mut ticket_flag = TicketFlags::builder();
-        if kdc_options.is_set(KdcOptionsFlag::FORWARDABLE as usize) {
+        // Capabilities the client or the server is not allowed are left out of the ticket
+        if kdc_options.is_set(KdcOptionsFlag::FORWARDABLE as usize)
+            && !disallowed_by_either(client, server, PrincipalAttribute::DisallowForwardable)
+        {
             ticket_flag.set(TicketFlag::FORWARDABLE as usize);
         }
 
@@ -309,10 +433,16 @@ where
             ticket_flag.set(TicketFlag::FORWARDED as usize);
         }
 
-        if kdc_options.is_set(KdcOptionsFlag::PROXIABLE as usize) {
+        if kdc_options.is_set(KdcOptionsFlag::PROXIABLE as usize)
+            && !disallowed_by_either(client, server, PrincipalAttribute::DisallowProxiable)
+        {
             ticket_flag.set(TicketFlag::PROXIABLE as usize);
         }
 
+        if server.attributes.has(PrincipalAttribute::OkAsDelegate) {
+            ticket_flag.set(TicketFlag::OK_AS_DELEGATE as usize);
+        }
+
         if kdc_options.is_set(KdcOptionsFlag::ALLOW_POSTDATE as usize) {
             ticket_flag.set(TicketFlag::PROXY as usize);
         }
@@ -331,3 +461,11 @@ where
         false
     }
 }
+
+fn disallowed_by_either(
+    client: &PrincipalDatabaseRecord,
+    server: &PrincipalDatabaseRecord,
+    attribute: PrincipalAttribute,
+) -> bool {
+    client.attributes.has(attribute) || server.attributes.has(attribute)
+}
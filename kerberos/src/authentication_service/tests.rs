@@ -1,10 +1,22 @@
 use crate::authentication_service::{AuthenticationService, AuthenticationServiceBuilder};
-use crate::service_traits::{PrincipalDatabase, PrincipalDatabaseRecord};
-use crate::tests_common::mocked::{MockedCrypto, MockedPrincipalDb};
+use crate::authorization_data::{ticket_elements, PrincipalClaims};
+use crate::cryptography::Cryptography;
+use crate::pac::Pac;
+use crate::authentication_service::ServerError;
+use crate::service_traits::{
+    PrincipalAttribute, PrincipalAttributes, PrincipalDatabase, PrincipalDatabaseRecord,
+    PrincipalGroup,
+};
+use crate::tests_common::mocked::{MockedCrypto, MockedHasher, MockedPrincipalDb};
 use lazy_static::lazy_static;
-use messages::basic_types::{EncryptionKey, HostAddresses, KerberosFlags, KerberosFlagsBuilder, KerberosString, KerberosTime, NameTypes, OctetString, PrincipalName, Realm};
-use messages::flags::KdcOptionsFlag;
-use messages::{AsReq, KdcReq, KdcReqBodyBuilder, TicketFlags};
+use messages::basic_types::{
+    EncryptedData, EncryptionKey, HostAddresses, KerberosFlags, KerberosFlagsBuilder,
+    KerberosString, KerberosTime, NameTypes, OctetString, PaData, PaDataTypes, PaEncTsEnc,
+    PrincipalName, Realm, SequenceOf,
+};
+use messages::flags::{KdcOptionsFlag, TicketFlag};
+use messages::{AsRep, AsReq, Decode, Ecode, EncTicketPart, Encode, KdcReq, KdcReqBodyBuilder,
+    TicketFlags};
 use std::time::Duration;
 
 lazy_static! {
@@ -21,6 +33,10 @@ lazy_static! {
         1,
         OctetString::new([0x1; 16]).unwrap()
     );
+    static ref CLIENT_KEY: EncryptionKey = EncryptionKey::new(
+        1,
+        OctetString::new(vec![0xa; 16]).unwrap(), // Mocked key
+    );
 }
 
 struct KdcConfig {
@@ -30,6 +46,9 @@ struct KdcConfig {
     sname: PrincipalName,
     realm: Realm,
     postdate: bool,
+    /// Bits of the other options to set, e.g. `KdcOptionsFlag::FORWARDABLE as usize`
+    kdc_options: Vec<usize>,
+    padata: Option<SequenceOf<PaData>>,
 }
 
 impl Default for KdcConfig {
@@ -41,6 +60,8 @@ impl Default for KdcConfig {
             sname: SERVER_NAME.clone(),
             realm: REALM.clone(),
             postdate: false,
+            kdc_options: vec![],
+            padata: None,
         }
     }
 }
@@ -50,6 +71,9 @@ fn make_as_req(cfg: &KdcConfig) -> AsReq {
     if  cfg.postdate {
         flags.set(KdcOptionsFlag::ALLOW_POSTDATE as usize);
     }
+    for option in &cfg.kdc_options {
+        flags.set(*option);
+    }
     let kdc_req_body = KdcReqBodyBuilder::default()
         .kdc_options(flags.build().unwrap())
         .cname(cfg.cname.clone())
@@ -62,12 +86,19 @@ fn make_as_req(cfg: &KdcConfig) -> AsReq {
         .unwrap();
 
     AsReq::new(
-        None,
+        cfg.padata.clone(),
         kdc_req_body
     )
 }
 
 fn make_principal_db() -> MockedPrincipalDb {
+    make_principal_db_with(PrincipalAttributes::default(), PrincipalAttributes::default())
+}
+
+fn make_principal_db_with(
+    client_attributes: PrincipalAttributes,
+    server_attributes: PrincipalAttributes,
+) -> MockedPrincipalDb {
     let principal_database = MockedPrincipalDb::new();
     principal_database.add_principal(
         CLIENT_NAME.clone(),
@@ -75,12 +106,11 @@ fn make_principal_db() -> MockedPrincipalDb {
         PrincipalDatabaseRecord {
             max_renewable_life: Duration::from_secs(3600 * 24),
             max_lifetime: Duration::from_secs(3600 * 24),
-            key: EncryptionKey::new(
-                1,
-                OctetString::new(vec![0xa; 16]).unwrap(), // Mocked key
-            ),
+            key: CLIENT_KEY.clone(),
             p_kvno: None,
             supported_encryption_types: vec![1, 3, 23, 18],
+            allowed_to_delegate_to: vec![],
+            attributes: client_attributes,
         },
     );
     principal_database.add_principal(
@@ -92,6 +122,8 @@ fn make_principal_db() -> MockedPrincipalDb {
             key: SERVER_KEY.clone(),
             p_kvno: None,
             supported_encryption_types: vec![1, 3, 23, 18],
+            allowed_to_delegate_to: vec![],
+            attributes: server_attributes,
         },
     );
     principal_database
@@ -101,6 +133,7 @@ fn get_as_service<P: PrincipalDatabase + Sync + Send>(db: &P) -> AuthenticationS
     let crypto = MockedCrypto;
     AuthenticationServiceBuilder::default()
         .supported_crypto_systems(vec![Box::new(crypto)])
+        .supported_checksum(vec![Box::new(MockedHasher)])
         .principal_db(db)
         .realm(REALM.clone())
         .require_pre_authenticate(false)
@@ -154,4 +187,260 @@ async fn test_postdate() {
         )
         .await.expect_err("Should fail due to invalid postdate");
 
-}
\ No newline at end of file
+}
+
+fn make_group(name: &str, roles: &[&str]) -> PrincipalGroup {
+    PrincipalGroup {
+        name: KerberosString::new(name).unwrap(),
+        roles: roles.iter().map(|role| KerberosString::new(role).unwrap()).collect(),
+    }
+}
+
+fn decrypt_ticket(as_rep: &AsRep) -> EncTicketPart {
+    let data = MockedCrypto
+        .decrypt(
+            as_rep.ticket().enc_part().cipher().as_bytes(),
+            SERVER_KEY.keyvalue().as_bytes(),
+        )
+        .unwrap();
+    EncTicketPart::from_der(&data).unwrap()
+}
+
+#[tokio::test]
+async fn groups_of_the_client_should_be_issued_as_claims_into_the_ticket() {
+    let principal_db = make_principal_db();
+    principal_db.add_group(CLIENT_NAME.clone(), REALM.clone(), make_group("admins", &["admin"]));
+    principal_db.add_group(
+        CLIENT_NAME.clone(),
+        REALM.clone(),
+        make_group("staff", &["read", "admin"]),
+    );
+    let auth_service = get_as_service(&principal_db);
+
+    let as_rep = auth_service
+        .handle_krb_as_req(&make_as_req(&KdcConfig::default()))
+        .await
+        .unwrap();
+
+    let ticket = decrypt_ticket(&as_rep);
+    let elements = ticket_elements(
+        &[Box::new(MockedHasher)],
+        ticket.key(),
+        ticket.authorization_data().unwrap(),
+    )
+    .unwrap();
+    let claims = PrincipalClaims::from_elements(&elements).unwrap().unwrap();
+    assert!(claims.is_member_of("admins"));
+    assert!(claims.is_member_of("staff"));
+    assert!(!claims.is_member_of("guests"));
+    assert_eq!(
+        claims.roles,
+        vec![KerberosString::new("admin").unwrap(), KerberosString::new("read").unwrap()]
+    );
+}
+
+#[tokio::test]
+async fn ticket_should_have_no_authorization_data_when_the_client_has_no_group() {
+    let principal_db = make_principal_db();
+    let auth_service = get_as_service(&principal_db);
+
+    let as_rep = auth_service
+        .handle_krb_as_req(&make_as_req(&KdcConfig::default()))
+        .await
+        .unwrap();
+
+    assert!(decrypt_ticket(&as_rep).authorization_data().is_none());
+}
+
+#[tokio::test]
+async fn ticket_should_carry_a_pac_signed_for_the_server_when_enabled() {
+    let principal_db = make_principal_db();
+    let auth_service = AuthenticationServiceBuilder::default()
+        .supported_crypto_systems(vec![Box::new(MockedCrypto)])
+        .supported_checksum(vec![Box::new(MockedHasher)])
+        .issue_pac(true)
+        .principal_db(&principal_db)
+        .realm(REALM.clone())
+        .require_pre_authenticate(false)
+        .sname(SERVER_NAME.clone())
+        .build()
+        .unwrap();
+
+    let as_rep = auth_service
+        .handle_krb_as_req(&make_as_req(&KdcConfig::default()))
+        .await
+        .unwrap();
+
+    let ticket = decrypt_ticket(&as_rep);
+    let elements = ticket_elements(
+        &[Box::new(MockedHasher)],
+        ticket.key(),
+        ticket.authorization_data().unwrap(),
+    )
+    .unwrap();
+    let pac = Pac::from_elements(&elements).unwrap().unwrap();
+    assert!(pac.verify_server_signature(&[Box::new(MockedHasher)], &SERVER_KEY).is_ok());
+    assert!(pac.verify_kdc_signature(&[Box::new(MockedHasher)], &SERVER_KEY).is_ok());
+    assert!(pac.verify_client(&CLIENT_NAME, ticket.authtime()).is_ok());
+    assert_eq!(pac.logon_info().unwrap().unwrap().effective_name, "CLIENT");
+}
+
+fn make_enc_timestamp(key: &EncryptionKey, timestamp: KerberosTime) -> SequenceOf<PaData> {
+    let data = PaEncTsEnc::new(timestamp, None).to_der().unwrap();
+    let cipher = MockedCrypto.encrypt(&data, key.keyvalue().as_bytes()).unwrap();
+    let enc_timestamp = EncryptedData::new(1, None, OctetString::new(cipher).unwrap());
+    vec![PaData::new(
+        PaDataTypes::PaEncTimestamp as i32,
+        OctetString::new(enc_timestamp.to_der().unwrap()).unwrap(),
+    )]
+}
+
+fn assert_protocol_error(result: Result<AsRep, ServerError>, ecode: Ecode) {
+    match result {
+        Err(ServerError::ProtocolError(err)) => assert_eq!(err.error_code(), ecode),
+        other => panic!("Request should be rejected with {:?}, got {:?}", ecode, other),
+    }
+}
+
+#[tokio::test]
+async fn client_requiring_pre_authentication_should_send_an_encrypted_timestamp() {
+    let principal_db = make_principal_db_with(
+        PrincipalAttributes::default().with(PrincipalAttribute::RequiresPreAuth),
+        PrincipalAttributes::default(),
+    );
+    let auth_service = get_as_service(&principal_db);
+
+    let result = auth_service.handle_krb_as_req(&make_as_req(&KdcConfig::default())).await;
+    assert_protocol_error(result, Ecode::KDC_ERR_PREAUTH_REQUIRED);
+
+    let as_req = make_as_req(&KdcConfig {
+        padata: Some(make_enc_timestamp(&SERVER_KEY, KerberosTime::now())),
+        ..KdcConfig::default()
+    });
+    let result = auth_service.handle_krb_as_req(&as_req).await;
+    assert_protocol_error(result, Ecode::KDC_ERR_PREAUTH_FAILED);
+
+    let as_req = make_as_req(&KdcConfig {
+        padata: Some(make_enc_timestamp(
+            &CLIENT_KEY,
+            KerberosTime::now() - Duration::from_secs(3600),
+        )),
+        ..KdcConfig::default()
+    });
+    let result = auth_service.handle_krb_as_req(&as_req).await;
+    assert_protocol_error(result, Ecode::KRB_AP_ERR_SKEW);
+
+    let as_req = make_as_req(&KdcConfig {
+        padata: Some(make_enc_timestamp(&CLIENT_KEY, KerberosTime::now())),
+        ..KdcConfig::default()
+    });
+    let as_rep = auth_service.handle_krb_as_req(&as_req).await.unwrap();
+    assert!(decrypt_ticket(&as_rep).flags().is_set(TicketFlag::PRE_AUTHENT as usize));
+}
+
+#[tokio::test]
+async fn ticket_should_not_be_pre_authenticated_without_encrypted_timestamp() {
+    let principal_db = make_principal_db();
+    let auth_service = get_as_service(&principal_db);
+
+    let as_rep = auth_service
+        .handle_krb_as_req(&make_as_req(&KdcConfig::default()))
+        .await
+        .unwrap();
+
+    assert!(!decrypt_ticket(&as_rep).flags().is_set(TicketFlag::PRE_AUTHENT as usize));
+}
+
+#[tokio::test]
+async fn capabilities_disallowed_to_the_client_or_the_server_should_be_left_out() {
+    for renewable in [KdcOptionsFlag::RENEWABLE_OK, KdcOptionsFlag::RENEWABLE] {
+        request_capabilities_of_principals_disallowing_them(vec![
+            KdcOptionsFlag::FORWARDABLE as usize,
+            KdcOptionsFlag::PROXIABLE as usize,
+            renewable as usize,
+        ])
+        .await;
+    }
+}
+
+async fn request_capabilities_of_principals_disallowing_them(kdc_options: Vec<usize>) {
+    let disallowed = PrincipalAttributes::default()
+        .with(PrincipalAttribute::DisallowForwardable)
+        .with(PrincipalAttribute::DisallowProxiable)
+        .with(PrincipalAttribute::DisallowRenewable);
+    let testcases = [
+        (PrincipalAttributes::default(), PrincipalAttributes::default(), true),
+        (disallowed, PrincipalAttributes::default(), false),
+        (PrincipalAttributes::default(), disallowed, false),
+    ];
+
+    for (client_attributes, server_attributes, allowed) in testcases {
+        let principal_db = make_principal_db_with(client_attributes, server_attributes);
+        let auth_service = get_as_service(&principal_db);
+        let as_req = make_as_req(&KdcConfig {
+            till: KerberosTime::now() + Duration::from_secs(3600 * 48),
+            kdc_options: kdc_options.clone(),
+            ..KdcConfig::default()
+        });
+
+        let as_rep = auth_service.handle_krb_as_req(&as_req).await.unwrap();
+
+        let ticket = decrypt_ticket(&as_rep);
+        for flag in [TicketFlag::FORWARDABLE, TicketFlag::PROXIABLE, TicketFlag::RENEWABLE] {
+            assert_eq!(ticket.flags().is_set(flag as usize), allowed);
+        }
+    }
+}
+
+#[tokio::test]
+async fn postdated_ticket_should_be_refused_when_disallowed() {
+    let principal_db = make_principal_db_with(
+        PrincipalAttributes::default().with(PrincipalAttribute::DisallowPostdated),
+        PrincipalAttributes::default(),
+    );
+    let auth_service = get_as_service(&principal_db);
+    let as_req = make_as_req(&KdcConfig {
+        kdc_options: vec![KdcOptionsFlag::POSTDATED as usize],
+        ..KdcConfig::default()
+    });
+
+    let result = auth_service.handle_krb_as_req(&as_req).await;
+
+    assert_protocol_error(result, Ecode::KDC_ERR_CANNOT_POSTDATE);
+}
+
+#[tokio::test]
+async fn ticket_to_server_ok_as_delegate_should_say_so() {
+    let principal_db = make_principal_db_with(
+        PrincipalAttributes::default(),
+        PrincipalAttributes::default().with(PrincipalAttribute::OkAsDelegate),
+    );
+    let auth_service = get_as_service(&principal_db);
+
+    let as_rep = auth_service
+        .handle_krb_as_req(&make_as_req(&KdcConfig::default()))
+        .await
+        .unwrap();
+
+    assert!(decrypt_ticket(&as_rep).flags().is_set(TicketFlag::OK_AS_DELEGATE as usize));
+}
+
+#[tokio::test]
+async fn locked_out_principals_should_get_no_ticket() {
+    let locked = PrincipalAttributes::default().with(PrincipalAttribute::DisallowAllTix);
+    let user_to_user_only = PrincipalAttributes::default().with(PrincipalAttribute::DisallowSvr);
+    let testcases = [
+        (locked, PrincipalAttributes::default(), Ecode::KDC_ERR_CLIENT_REVOKED),
+        (PrincipalAttributes::default(), locked, Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN),
+        (PrincipalAttributes::default(), user_to_user_only, Ecode::KDC_ERR_MUST_USE_USER2USER),
+    ];
+
+    for (client_attributes, server_attributes, ecode) in testcases {
+        let principal_db = make_principal_db_with(client_attributes, server_attributes);
+        let auth_service = get_as_service(&principal_db);
+
+        let result = auth_service.handle_krb_as_req(&make_as_req(&KdcConfig::default())).await;
+
+        assert_protocol_error(result, ecode);
+    }
+}
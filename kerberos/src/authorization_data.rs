@@ -0,0 +1,289 @@
+use crate::cryptographic_hash::CryptographicHash;
+use crate::pac::AD_WIN2K_PAC;
+use crate::service_traits::PrincipalGroup;
+use der::Sequence;
+use messages::basic_types::{
+    ADEntry, ADRegisteredEntry, AdAndOr, AdKdcIssued, AuthorizationData, AuthorizationDataTypes,
+    Checksum, EncryptionKey, Int32, KerberosString, OctetString, PrincipalName, Realm, SequenceOf,
+};
+use messages::{Decode, Ecode, Encode};
+
+pub type SupportedChecksums = [Box<dyn CryptographicHash + Send + Sync>];
+
+/// Element of the authorization data of a ticket, once the containers around it are opened
+#[derive(Clone, Debug, PartialEq, Eq, Sequence)]
+pub struct AuthorizationElement {
+    pub entry: ADEntry,
+    /// Whether the element was found in an AD-KDC-ISSUED container whose checksum was verified
+    pub kdc_issued: bool,
+}
+
+/// ad-type of the group and role claims of a principal, negative values being reserved for
+/// local use, https://www.rfc-editor.org/rfc/rfc4120#section-7.5.4
+pub const AD_PRINCIPAL_CLAIMS: Int32 = -1024;
+
+/// Groups a principal belongs to and the roles they grant, issued by the KDC as the
+/// `AD_PRINCIPAL_CLAIMS` element of an AD-KDC-ISSUED container
+#[derive(Clone, Debug, Default, PartialEq, Eq, Sequence)]
+pub struct PrincipalClaims {
+    #[asn1(context_specific = "0")]
+    pub groups: SequenceOf<KerberosString>,
+    #[asn1(context_specific = "1")]
+    pub roles: SequenceOf<KerberosString>,
+}
+
+impl PrincipalClaims {
+    pub fn from_groups(groups: &[PrincipalGroup]) -> Self {
+        let mut claims = Self::default();
+        for group in groups {
+            claims.groups.push(group.name.clone());
+            for role in &group.roles {
+                if !claims.roles.contains(role) {
+                    claims.roles.push(role.clone());
+                }
+            }
+        }
+        claims
+    }
+
+    /// Claims of the ticket the `elements` come from. Only the ones issued by the KDC are
+    /// trusted, claims the client put in the ticket itself are ignored
+    pub fn from_elements(elements: &[AuthorizationElement]) -> Result<Option<Self>, Ecode> {
+        elements
+            .iter()
+            .find(|element| element.kdc_issued && *element.entry.ad_type() == AD_PRINCIPAL_CLAIMS)
+            .map(|element| {
+                Self::from_der(element.entry.ad_data().as_bytes())
+                    .map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)
+            })
+            .transpose()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.roles.is_empty()
+    }
+
+    pub fn is_member_of(&self, group: &str) -> bool {
+        self.groups.iter().any(|name| name.as_str() == group)
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|name| name.as_str() == role)
+    }
+}
+
+/// Authorization data carrying `claims` in a ticket of session key `key`: an AD-IF-RELEVANT
+/// container around the AD-KDC-ISSUED one, so that services which do not know the claims
+/// may ignore them
+pub fn issue_claims(
+    checksums: &SupportedChecksums,
+    key: &EncryptionKey,
+    claims: &PrincipalClaims,
+    i_realm: &Realm,
+    i_sname: &PrincipalName,
+) -> Result<AuthorizationData, Ecode> {
+    if claims.is_empty() {
+        return Ok(vec![]);
+    }
+    let claims = ADEntry::new(
+        AD_PRINCIPAL_CLAIMS,
+        OctetString::new(claims.to_der().map_err(|_| Ecode::KRB_ERR_GENERIC)?)
+            .map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+    );
+    let kdc_issued = sign_kdc_issued(checksums, key, vec![claims], i_realm, i_sname)?;
+    Ok(vec![make_entry(
+        AuthorizationDataTypes::IfRelevant,
+        &vec![kdc_issued],
+    )?])
+}
+
+/// Checksum over the elements of an AD-KDC-ISSUED container, keyed with the session key of
+/// the ticket holding it, https://www.rfc-editor.org/rfc/rfc4120#section-5.2.6.2
+fn kdc_issued_checksum(
+    hash: &dyn CryptographicHash,
+    key: &EncryptionKey,
+    elements: &AuthorizationData,
+) -> Result<Checksum, Ecode> {
+    let data = elements.to_der().map_err(|_| Ecode::KRB_ERR_GENERIC)?;
+    let checksum = hash.keyed_digest(key.keyvalue().as_bytes(), &data);
+    Ok(Checksum::new(
+        hash.get_checksum_type(),
+        OctetString::new(checksum).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+    ))
+}
+
+/// Wrap `elements` in an AD-KDC-ISSUED container for the ticket of session key `key`,
+/// signed by the KDC `i_sname` of `i_realm`
+pub fn sign_kdc_issued(
+    checksums: &SupportedChecksums,
+    key: &EncryptionKey,
+    elements: AuthorizationData,
+    i_realm: &Realm,
+    i_sname: &PrincipalName,
+) -> Result<ADEntry, Ecode> {
+    let hash = checksums.first().ok_or(Ecode::KDC_ERR_SUMTYPE_NOSUPP)?;
+    let checksum = kdc_issued_checksum(hash.as_ref(), key, &elements)?;
+    let container = AdKdcIssued::new(
+        checksum,
+        Some(i_realm.clone()),
+        Some(i_sname.clone()),
+        elements,
+    );
+    make_entry(AuthorizationDataTypes::KdcIssued, &container)
+}
+
+pub fn verify_kdc_issued(
+    checksums: &SupportedChecksums,
+    key: &EncryptionKey,
+    container: &AdKdcIssued,
+) -> Result<(), Ecode> {
+    let hash = checksums
+        .iter()
+        .find(|hash| hash.get_checksum_type() == *container.ad_checksum().cksumtype())
+        .ok_or(Ecode::KDC_ERR_SUMTYPE_NOSUPP)?;
+    if &kdc_issued_checksum(hash.as_ref(), key, container.elements())? != container.ad_checksum() {
+        return Err(Ecode::KRB_AP_ERR_MODIFIED);
+    }
+    Ok(())
+}
+
+pub(crate) fn make_entry(
+    ad_type: AuthorizationDataTypes,
+    value: &impl Encode,
+) -> Result<ADEntry, Ecode> {
+    let data = value.to_der().map_err(|_| Ecode::KRB_ERR_GENERIC)?;
+    Ok(ADEntry::new(
+        ad_type as i32,
+        OctetString::new(data).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+    ))
+}
+
+/// Decode the containers defined by RFC 4120, other elements are left to their consumer
+fn upgrade(entry: &ADEntry) -> Result<Option<ADRegisteredEntry>, Ecode> {
+    if AuthorizationDataTypes::try_from(*entry.ad_type()).is_err() {
+        return Ok(None);
+    }
+    ADRegisteredEntry::upgrade(entry)
+        .map(Some)
+        .map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)
+}
+
+/// Whether the KDC knows how to handle an element of type `ad_type`
+fn is_understood_by_kdc(ad_type: i32) -> bool {
+    AuthorizationDataTypes::try_from(ad_type).is_ok()
+}
+
+/// Every element of an AD-MANDATORY-FOR-KDC container must be understood by the KDC,
+/// which rejects the request otherwise,
+/// https://www.rfc-editor.org/rfc/rfc4120#section-5.2.6.4
+fn check_mandatory_for_kdc(elements: &AuthorizationData) -> Result<(), Ecode> {
+    if elements
+        .iter()
+        .all(|entry| is_understood_by_kdc(*entry.ad_type()))
+    {
+        Ok(())
+    } else {
+        Err(Ecode::KDC_ERR_POLICY)
+    }
+}
+
+/// Authorization data of the TGT to copy into a ticket of session key `new_key`. AD-KDC-ISSUED
+/// containers are checked with the TGT session key `tgt_key`, then signed again by this KDC
+/// for the new ticket
+pub fn copy_from_tgt(
+    checksums: &SupportedChecksums,
+    tgt_key: &EncryptionKey,
+    new_key: &EncryptionKey,
+    authorization_data: &AuthorizationData,
+    realm: &Realm,
+    sname: &PrincipalName,
+) -> Result<AuthorizationData, Ecode> {
+    authorization_data
+        .iter()
+        .map(|entry| {
+            let reissue = |elements: &AuthorizationData| {
+                copy_from_tgt(checksums, tgt_key, new_key, elements, realm, sname)
+            };
+            match upgrade(entry)? {
+                Some(ADRegisteredEntry::KdcIssued(container)) => {
+                    verify_kdc_issued(checksums, tgt_key, &container)?;
+                    let elements = reissue(container.elements())?;
+                    sign_kdc_issued(checksums, new_key, elements, realm, sname)
+                }
+                Some(ADRegisteredEntry::IfRelevant(elements)) => {
+                    make_entry(AuthorizationDataTypes::IfRelevant, &reissue(&elements)?)
+                }
+                Some(ADRegisteredEntry::AndOr(container)) => {
+                    let elements = reissue(container.elements())?;
+                    let container = AdAndOr::new(*container.condition_count(), elements);
+                    make_entry(AuthorizationDataTypes::AndOr, &container)
+                }
+                Some(ADRegisteredEntry::MandatoryForKdc(elements)) => {
+                    check_mandatory_for_kdc(&elements)?;
+                    make_entry(
+                        AuthorizationDataTypes::MandatoryForKdc,
+                        &reissue(&elements)?,
+                    )
+                }
+                None => Ok(entry.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Check the authorization data a client asks to be put in its ticket: only the KDC may issue
+/// AD-KDC-ISSUED containers and PACs
+pub fn check_requested(authorization_data: &AuthorizationData) -> Result<(), Ecode> {
+    authorization_data
+        .iter()
+        .try_for_each(|entry| match upgrade(entry)? {
+            None if *entry.ad_type() == AD_WIN2K_PAC => Err(Ecode::KDC_ERR_POLICY),
+            Some(ADRegisteredEntry::KdcIssued(_)) => Err(Ecode::KDC_ERR_POLICY),
+            Some(ADRegisteredEntry::IfRelevant(elements)) => check_requested(&elements),
+            Some(ADRegisteredEntry::AndOr(container)) => check_requested(container.elements()),
+            Some(ADRegisteredEntry::MandatoryForKdc(elements)) => {
+                check_mandatory_for_kdc(&elements)?;
+                check_requested(&elements)
+            }
+            None => Ok(()),
+        })
+}
+
+/// Elements of the authorization data of a ticket of session key `key`, for the service it
+/// was issued to. The checksums of AD-KDC-ISSUED containers are verified, AD-AND-OR
+/// containers are left for the service to evaluate
+pub fn ticket_elements(
+    checksums: &SupportedChecksums,
+    key: &EncryptionKey,
+    authorization_data: &AuthorizationData,
+) -> Result<Vec<AuthorizationElement>, Ecode> {
+    let mut elements = vec![];
+    collect_elements(checksums, key, authorization_data, false, &mut elements)?;
+    Ok(elements)
+}
+
+fn collect_elements(
+    checksums: &SupportedChecksums,
+    key: &EncryptionKey,
+    authorization_data: &AuthorizationData,
+    kdc_issued: bool,
+    elements: &mut Vec<AuthorizationElement>,
+) -> Result<(), Ecode> {
+    for entry in authorization_data {
+        match upgrade(entry)? {
+            Some(ADRegisteredEntry::KdcIssued(container)) => {
+                verify_kdc_issued(checksums, key, &container)?;
+                collect_elements(checksums, key, container.elements(), true, elements)?;
+            }
+            Some(ADRegisteredEntry::IfRelevant(inner))
+            | Some(ADRegisteredEntry::MandatoryForKdc(inner)) => {
+                collect_elements(checksums, key, &inner, kdc_issued, elements)?;
+            }
+            Some(ADRegisteredEntry::AndOr(_)) | None => elements.push(AuthorizationElement {
+                entry: entry.clone(),
+                kdc_issued,
+            }),
+        }
+    }
+    Ok(())
+}
@@ -1,29 +1,73 @@
+use crate::client::ccache::Credential;
 use crate::client::client_env::ClientEnv;
 use crate::client::client_error::ClientError;
-use crate::cryptography::Cryptography;
 use messages::basic_types::{
-    Checksum, EncryptedData, KerberosTime, Microseconds, NameTypes, OctetString, PrincipalName,
+    Checksum, EncryptedData, EncryptionKey, KerberosTime, Microseconds, OctetString,
 };
 use messages::{
-    APOptions, ApRep, ApReq, Authenticator, AuthenticatorBuilder, Decode, EncApRepPart, Encode,
+    APOptions, ApRep, ApReq, Authenticator, AuthenticatorBuilder, Decode, EncApRepPart,
+    EncKrbCredPart, Encode, KrbCred, Ticket,
 };
 use rand::{thread_rng, Rng};
+use std::time::Duration;
 
 pub fn prepare_ap_request(
     client_env: &impl ClientEnv,
     mutual_required: bool,
     cksum_material: Option<Vec<u8>>,
 ) -> Result<ApReq, ClientError> {
+    let cksum = material_checksum(client_env, cksum_material)?;
+    build_ap_request(client_env, mutual_required, cksum).map(|(ap_req, _)| ap_req)
+}
+
+/// AP-REQ requiring mutual authentication, along with its authenticator which the reply of the
+/// server is checked against by `receive_ap_reply`
+pub fn prepare_mutual_ap_request(
+    client_env: &impl ClientEnv,
+    cksum_material: Option<Vec<u8>>,
+) -> Result<(ApReq, Authenticator), ClientError> {
+    let cksum = material_checksum(client_env, cksum_material)?;
+    build_ap_request(client_env, true, cksum)
+}
+
+/// AP-REQ whose authenticator carries `cksum` as is, for the protocols defining their own,
+/// e.g. the GSS-API checksum of https://www.rfc-editor.org/rfc/rfc4121#section-4.1.1
+pub fn prepare_ap_request_with_checksum(
+    client_env: &impl ClientEnv,
+    mutual_required: bool,
+    cksum: Checksum,
+) -> Result<(ApReq, Authenticator), ClientError> {
+    build_ap_request(client_env, mutual_required, Some(cksum))
+}
+
+fn material_checksum(
+    client_env: &impl ClientEnv,
+    cksum_material: Option<Vec<u8>>,
+) -> Result<Option<Checksum>, ClientError> {
+    let Some(cksum_material) = cksum_material else {
+        return Ok(None);
+    };
+    let crypto_hash = client_env.get_checksum_hash(1)?;
+    Ok(Some(Checksum::new(
+        1,
+        OctetString::new(crypto_hash.digest(cksum_material.as_slice()))
+            .or(Err(ClientError::EncodeError))?,
+    )))
+}
+
+fn build_ap_request(
+    client_env: &impl ClientEnv,
+    mutual_required: bool,
+    cksum: Option<Checksum>,
+) -> Result<(ApReq, Authenticator), ClientError> {
     let options = APOptions::new(true, mutual_required);
     let tgs_rep = client_env.get_tgs_reply()?;
 
-    let cname = PrincipalName::new(NameTypes::NtPrincipal, vec![client_env.get_client_name()?])
-        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+    let cname = client_env.get_client_principal()?;
     let crealm = client_env.get_client_realm()?;
     let ctime = KerberosTime::from_unix_duration(client_env.get_current_time()?)
         .map_err(|e| ClientError::GenericError(e.to_string()))?;
     let cusec = client_env.get_current_time()?.subsec_micros();
-    let crypto_hash = client_env.get_checksum_hash(1)?;
     let mut authenticator = AuthenticatorBuilder::default();
     let mut rand = thread_rng();
     authenticator
@@ -32,12 +76,7 @@ pub fn prepare_ap_request(
         .crealm(crealm)
         .ctime(ctime)
         .cusec(Microseconds::try_from(cusec).expect("Invalid microseconds"));
-    if let Some(cksum_material) = cksum_material {
-        let cksum = Checksum::new(
-            1,
-            OctetString::new(crypto_hash.digest(cksum_material.as_slice()))
-                .or(Err(ClientError::EncodeError))?,
-        );
+    if let Some(cksum) = cksum {
         authenticator.cksum(cksum);
     };
     let authenticator = authenticator.build()?;
@@ -61,7 +100,7 @@ pub fn prepare_ap_request(
     );
     let ap_req = ApReq::new(options, tgs_rep.ticket().clone(), enc_authenticator);
 
-    Ok(ap_req)
+    Ok((ap_req, authenticator))
 }
 
 pub fn prepare_pa_data(
@@ -69,11 +108,29 @@ pub fn prepare_pa_data(
     mutual_required: bool,
     cksum_material: Option<Vec<u8>>,
 ) -> Result<ApReq, ClientError> {
-    let options = APOptions::new(true, mutual_required);
     let as_rep = client_env.get_as_reply()?;
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    prepare_pa_data_with_tgt(
+        client_env,
+        mutual_required,
+        cksum_material,
+        as_rep.ticket(),
+        enc_part.key(),
+    )
+}
 
-    let cname = PrincipalName::new(NameTypes::NtPrincipal, vec![client_env.get_client_name()?])
-        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+/// Authentication header for a TGS request made with `tgt`, which is not necessarily
+/// the one obtained from the AS, e.g. a cross-realm TGT
+pub(crate) fn prepare_pa_data_with_tgt(
+    client_env: &impl ClientEnv,
+    mutual_required: bool,
+    cksum_material: Option<Vec<u8>>,
+    tgt: &Ticket,
+    session_key: &EncryptionKey,
+) -> Result<ApReq, ClientError> {
+    let options = APOptions::new(true, mutual_required);
+
+    let cname = client_env.get_client_principal()?;
     let crealm = client_env.get_client_realm()?;
     let ctime = KerberosTime::from_unix_duration(client_env.get_current_time()?)
         .map_err(|e| ClientError::GenericError(e.to_string()))?;
@@ -90,63 +147,113 @@ pub fn prepare_pa_data(
     if let Some(cksum_material) = cksum_material {
         let cksum = Checksum::new(
             1,
-            OctetString::new(crypto_hash.digest(cksum_material.as_slice()))
-                .or(Err(ClientError::EncodeError))?,
+            OctetString::new(
+                crypto_hash
+                    .keyed_digest(session_key.keyvalue().as_bytes(), cksum_material.as_slice()),
+            )
+            .or(Err(ClientError::EncodeError))?,
         );
         authenticator.cksum(cksum);
     };
     let authenticator = authenticator.build()?;
 
     let encoded_authenticator = authenticator.to_der().or(Err(ClientError::EncodeError))?;
-    let enc_part = client_env.get_as_reply_enc_part()?;
-    let cryptography = client_env.get_crypto(*enc_part.key().keytype())?;
-    let encrypted_authenticator = cryptography.encrypt(
-        &encoded_authenticator,
-        client_env
-            .get_as_reply_enc_part()
-            .unwrap()
-            .key()
-            .keyvalue()
-            .as_ref(),
-    )?;
+    let cryptography = client_env.get_crypto(*session_key.keytype())?;
+    let encrypted_authenticator =
+        cryptography.encrypt(&encoded_authenticator, session_key.keyvalue().as_ref())?;
     let enc_authenticator = EncryptedData::new(
-        *enc_part.key().keytype(),
+        *session_key.keytype(),
         1,
         OctetString::new(encrypted_authenticator).or(Err(ClientError::EncodeError))?,
     );
-    let ap_req = ApReq::new(options, as_rep.ticket().clone(), enc_authenticator);
+    let ap_req = ApReq::new(options, tgt.clone(), enc_authenticator);
 
     Ok(ap_req)
 }
 
+/// Check the reply of the server to `authenticator`, then keep the subkey and initial sequence
+/// number it negotiated, https://www.rfc-editor.org/rfc/rfc4120#section-3.2.5
 pub fn receive_ap_reply(
     client_env: &impl ClientEnv,
-    cryptography: &impl Cryptography,
     ap_rep: ApRep,
     authenticator: Authenticator,
 ) -> Result<(), ClientError> {
+    let ap_rep_part = verify_ap_reply(client_env, &ap_rep, &authenticator)?;
+    if let Some(subkey) = ap_rep_part.subkey() {
+        client_env.save_subkey(subkey.clone())?;
+    }
+    if let Some(seq_number) = ap_rep_part.seq_number() {
+        client_env.save_seq_number(*seq_number)?;
+    }
+    Ok(())
+}
+
+/// Content of the reply of the server to `authenticator`, once checked it answers it
+pub fn verify_ap_reply(
+    client_env: &impl ClientEnv,
+    ap_rep: &ApRep,
+    authenticator: &Authenticator,
+) -> Result<EncApRepPart, ClientError> {
     let binding = client_env.get_tgs_reply_enc_part()?;
     let session_key = binding.key();
-    let ap_rep_part = EncApRepPart::from_der(
-        cryptography
-            .decrypt(
-                ap_rep.enc_part().cipher().as_ref(),
-                session_key.keyvalue().as_ref(),
-            )?
-            .as_ref(),
-    )
-    .or(Err(ClientError::DecodeError))?;
+    if ap_rep.enc_part().etype() != session_key.keytype() {
+        return Err(ClientError::MutualAuthenticationFailed);
+    }
+    let cryptography = client_env.get_crypto(*session_key.keytype())?;
+    let ap_rep_part = cryptography
+        .decrypt(
+            ap_rep.enc_part().cipher().as_ref(),
+            session_key.keyvalue().as_ref(),
+        )
+        .map_err(|_| ClientError::MutualAuthenticationFailed)
+        .and_then(|d| EncApRepPart::from_der(&d).or(Err(ClientError::DecodeError)))?;
 
     if &authenticator.ctime() != ap_rep_part.ctime()
         || &authenticator.cusec() != ap_rep_part.cusec()
     {
         return Err(ClientError::MutualAuthenticationFailed);
     }
-    if ap_rep_part.subkey().is_some() {
-        client_env.save_subkey(ap_rep_part.subkey().unwrap().clone())?;
-    }
-    if ap_rep_part.seq_number().is_some() {
-        client_env.save_seq_number(*ap_rep_part.seq_number().unwrap())?;
-    }
-    Ok(())
+    Ok(ap_rep_part)
+}
+
+/// KRB-CRED delegating `credentials`, e.g. a TGT from `receive_forwarded_tgt`, to the server of
+/// the last ticket obtained. It is encrypted in the session key of that ticket, which the server
+/// reads it with, https://www.rfc-editor.org/rfc/rfc4120#section-3.6.1
+pub fn prepare_krb_cred(
+    client_env: &impl ClientEnv,
+    credentials: &[Credential],
+) -> Result<KrbCred, ClientError> {
+    let now = client_env.get_current_time()?;
+    let timestamp = KerberosTime::from_unix_duration(Duration::from_secs(now.as_secs()))
+        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+    let enc_part = EncKrbCredPart::new(
+        credentials
+            .iter()
+            .map(Credential::krb_cred_info)
+            .collect::<Vec<_>>(),
+        None,
+        timestamp,
+        now.subsec_micros() as Microseconds,
+        None,
+        None,
+    );
+
+    let binding = client_env.get_tgs_reply_enc_part()?;
+    let session_key = binding.key();
+    let cryptography = client_env.get_crypto(*session_key.keytype())?;
+    let cipher = cryptography.encrypt(
+        &enc_part.to_der().or(Err(ClientError::EncodeError))?,
+        session_key.keyvalue().as_ref(),
+    )?;
+    Ok(KrbCred::new(
+        credentials
+            .iter()
+            .map(|credential| credential.ticket.clone())
+            .collect::<Vec<_>>(),
+        EncryptedData::new(
+            *session_key.keytype(),
+            None,
+            OctetString::new(cipher).or(Err(ClientError::EncodeError))?,
+        ),
+    ))
 }
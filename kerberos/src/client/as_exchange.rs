@@ -2,7 +2,7 @@ use crate::client::client_env::ClientEnv;
 use crate::client::client_error::ClientError;
 use crate::client::kdc_exchange::{receive_kdc_rep, KdcExchangeType};
 use crate::client::util::generate_nonce;
-use messages::basic_types::{KerberosTime, NameTypes, PrincipalName};
+use messages::basic_types::{KerberosTime, PrincipalName};
 use messages::flags::KdcOptionsFlag::{POSTDATED, RENEWABLE};
 use messages::{AsRep, AsReq, Decode, EncAsRepPart, KdcReqBodyBuilder, KrbErrorMsg};
 use std::time::Duration;
@@ -13,13 +13,10 @@ pub fn prepare_as_request(
     starttime: Option<KerberosTime>,
     renewal_time: Option<KerberosTime>,
 ) -> Result<AsReq, ClientError> {
-    let client_name = client_env.get_client_name()?;
-    let cname = PrincipalName::new(NameTypes::NtPrincipal, vec![client_name])
-        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+    let cname = client_env.get_client_principal()?;
     let server_realm = client_env.get_server_realm()?;
-    let server_name = client_env.get_server_name()?;
-    let sname = PrincipalName::new(NameTypes::NtPrincipal, vec![server_name])
-        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+    // The initial ticket is a TGT, from which the tickets to services are then obtained
+    let sname = PrincipalName::krbtgt(&server_realm);
     let nonce = generate_nonce();
     let current_time = client_env.get_current_time()?;
     let duration = ticket_lifetime.unwrap_or(Duration::new(60 * 60 * 24, 0));
@@ -0,0 +1,366 @@
+// MIT credential cache file format, version 0x0504, as written by `kinit`: a version, a
+// header of tagged fields, the default principal, then credentials until the end of the
+// file. Settings of the cache are credentials of the `X-CACHECONF:` realm whose ticket holds
+// the value. Integers are big-endian,
+// https://web.mit.edu/kerberos/krb5-latest/doc/formats/ccache_file_format.html
+
+use std::fmt::{Display, Formatter};
+
+use messages::basic_types::{
+    ADEntry, AddressTypes, EncryptionKey, HostAddress, KerberosString, KerberosTime, NameTypes,
+    OctetString, PrincipalName, Realm,
+};
+use messages::{Decode, Encode, Ticket, TicketFlags};
+
+use super::{Ccache, ConfigEntry, Credential, KdcOffset};
+use crate::file_format::{Reader, Truncated};
+
+pub(super) const CCACHE_VERSION: u16 = 0x0504;
+
+/// Header tag of the offset of the KDC clock
+const DELTA_TIME_TAG: u16 = 1;
+
+/// Realm of the credentials holding the settings of the cache
+pub(super) const CONFIG_REALM: &str = "X-CACHECONF:";
+
+/// First component of the credentials holding the settings of the cache
+pub(super) const CONFIG_SERVICE: &str = "krb5_ccache_conf_data";
+
+/// Why a credential cache could not be read or written
+#[derive(Debug)]
+pub enum CcacheError {
+    Io(std::io::Error),
+    /// Only version 0x0504 caches are supported
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of a field
+    Truncated,
+    /// A realm or name component which is not an IA5 string
+    InvalidString,
+    /// A name type which is not one of RFC4120
+    InvalidNameType(u32),
+    /// An address type which is not one of RFC4120
+    InvalidAddressType(u16),
+    /// A principal with no name component
+    EmptyName,
+    /// A ticket which is not DER
+    InvalidTicket,
+    /// A value which does not fit the size the format allows for it
+    TooLong,
+}
+
+impl Display for CcacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "credential cache could not be accessed: {}", e),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "credential cache version {:#06x} is not supported", v)
+            }
+            Self::Truncated => f.write_str("credential cache is truncated"),
+            Self::InvalidString => f.write_str("credential cache holds a string which is not IA5"),
+            Self::InvalidNameType(t) => {
+                write!(f, "credential cache holds the unknown name type {}", t)
+            }
+            Self::InvalidAddressType(t) => {
+                write!(f, "credential cache holds the unknown address type {}", t)
+            }
+            Self::EmptyName => f.write_str("credential cache holds a principal without name"),
+            Self::InvalidTicket => f.write_str("credential cache holds an invalid ticket"),
+            Self::TooLong => f.write_str("credential is too long"),
+        }
+    }
+}
+
+impl std::error::Error for CcacheError {}
+
+impl From<std::io::Error> for CcacheError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<Truncated> for CcacheError {
+    fn from(_: Truncated) -> Self {
+        Self::Truncated
+    }
+}
+
+type CcacheResult<T> = Result<T, CcacheError>;
+
+/// Fields of a credential cache, whose counted values are prefixed by their 32-bit length
+trait CcacheFields<'a> {
+    fn counted(&mut self) -> CcacheResult<&'a [u8]>;
+    fn counted_string(&mut self) -> CcacheResult<KerberosString>;
+    fn principal(&mut self) -> CcacheResult<(PrincipalName, Realm)>;
+    fn time(&mut self) -> CcacheResult<Option<KerberosTime>>;
+    fn octet_string(&mut self) -> CcacheResult<OctetString>;
+}
+
+impl<'a> CcacheFields<'a> for Reader<'a> {
+    fn counted(&mut self) -> CcacheResult<&'a [u8]> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?)
+    }
+
+    fn counted_string(&mut self) -> CcacheResult<KerberosString> {
+        KerberosString::new(self.counted()?).map_err(|_| CcacheError::InvalidString)
+    }
+
+    fn principal(&mut self) -> CcacheResult<(PrincipalName, Realm)> {
+        let name_type = self.u32()?;
+        let num_components = self.u32()?;
+        let realm = self.counted_string()?;
+        let components = (0..num_components)
+            .map(|_| self.counted_string())
+            .collect::<CcacheResult<Vec<_>>>()?;
+        let name_type = i32::try_from(name_type)
+            .ok()
+            .and_then(|t| NameTypes::try_from(t).ok())
+            .ok_or(CcacheError::InvalidNameType(name_type))?;
+        let name = PrincipalName::new(name_type, components).map_err(|_| CcacheError::EmptyName)?;
+        Ok((name, realm))
+    }
+
+    fn time(&mut self) -> CcacheResult<Option<KerberosTime>> {
+        // Times are unsigned 32-bit, zero standing for a time the KDC did not give
+        Ok(match self.u32()? {
+            0 => None,
+            seconds => Some(
+                KerberosTime::from_timestamp(seconds as u64)
+                    .expect("A 32-bit timestamp is a valid time"),
+            ),
+        })
+    }
+
+    fn octet_string(&mut self) -> CcacheResult<OctetString> {
+        OctetString::new(self.counted()?).map_err(|_| CcacheError::TooLong)
+    }
+}
+
+/// Cache held in `data`
+pub(super) fn read_ccache(data: &[u8]) -> CcacheResult<Ccache> {
+    let mut reader = Reader::new(data);
+    let version = reader.u16()?;
+    if version != CCACHE_VERSION {
+        return Err(CcacheError::UnsupportedVersion(version));
+    }
+
+    let header_len = reader.u16()? as usize;
+    let mut header = Reader::new(reader.take(header_len)?);
+    let mut kdc_offset = None;
+    while !header.is_empty() {
+        let tag = header.u16()?;
+        let len = header.u16()? as usize;
+        let mut field = Reader::new(header.take(len)?);
+        // Tags other than the time offset are for other implementations to know about
+        if tag == DELTA_TIME_TAG {
+            kdc_offset = Some(KdcOffset {
+                seconds: field.i32()?,
+                microseconds: field.i32()?,
+            });
+        }
+    }
+
+    let (principal, realm) = reader.principal()?;
+    let mut ccache = Ccache::new(principal, realm);
+    ccache.kdc_offset = kdc_offset;
+    while !reader.is_empty() {
+        read_credential(&mut reader, &mut ccache)?;
+    }
+    Ok(ccache)
+}
+
+fn read_credential(reader: &mut Reader, ccache: &mut Ccache) -> CcacheResult<()> {
+    let (client, crealm) = reader.principal()?;
+    let (server, srealm) = reader.principal()?;
+    let keytype = reader.u16()?;
+    let key = EncryptionKey::new(keytype as i32, reader.octet_string()?);
+    let authtime = reader.time()?;
+    let starttime = reader.time()?;
+    let endtime = reader.time()?;
+    let renew_till = reader.time()?;
+    let is_skey = reader.u8()? != 0;
+    let flags = flags_from_u32(reader.u32()?);
+    let addresses = (0..reader.u32()?)
+        .map(|_| {
+            let raw_type = reader.u16()?;
+            let addr_type = AddressTypes::try_from(raw_type as i32)
+                .map_err(|_| CcacheError::InvalidAddressType(raw_type))?;
+            HostAddress::new(addr_type, reader.octet_string()?)
+                .map_err(|_| CcacheError::InvalidAddressType(raw_type))
+        })
+        .collect::<CcacheResult<Vec<_>>>()?;
+    let authorization_data = (0..reader.u32()?)
+        .map(|_| {
+            let ad_type = reader.u16()?;
+            Ok(ADEntry::new(ad_type as i32, reader.octet_string()?))
+        })
+        .collect::<CcacheResult<Vec<_>>>()?;
+    let ticket = reader.counted()?;
+    let second_ticket = reader.counted()?;
+
+    if srealm.as_str() == CONFIG_REALM {
+        if let [service, key, principal @ ..] = server.name_string().as_slice() {
+            if service.as_str() == CONFIG_SERVICE {
+                ccache.config.push(ConfigEntry {
+                    principal: principal.first().cloned(),
+                    key: key.clone(),
+                    value: ticket.to_vec(),
+                });
+                return Ok(());
+            }
+        }
+    }
+
+    ccache.credentials.push(Credential {
+        client,
+        crealm,
+        server,
+        srealm,
+        key,
+        authtime: authtime.unwrap_or_else(KerberosTime::zero),
+        starttime,
+        endtime: endtime.unwrap_or_else(KerberosTime::zero),
+        renew_till,
+        is_skey,
+        flags,
+        addresses,
+        authorization_data,
+        ticket: Ticket::from_der(ticket).map_err(|_| CcacheError::InvalidTicket)?,
+        second_ticket: match second_ticket {
+            [] => None,
+            der => Some(Ticket::from_der(der).map_err(|_| CcacheError::InvalidTicket)?),
+        },
+    });
+    Ok(())
+}
+
+/// Ticket flags as MIT keeps them, the first flag of RFC4120 being the highest bit
+fn flags_to_u32(flags: &TicketFlags) -> u32 {
+    (0..32)
+        .filter(|bit| flags.is_set(*bit))
+        .fold(0, |bits, bit| bits | (1 << (31 - bit)))
+}
+
+fn flags_from_u32(bits: u32) -> TicketFlags {
+    let mut flags = TicketFlags::builder();
+    for bit in (0..32).filter(|bit| bits & (1 << (31 - bit)) != 0) {
+        flags.set(bit);
+    }
+    flags.build().expect("32 bits are a valid bit string")
+}
+
+fn put_counted(buffer: &mut Vec<u8>, data: &[u8]) -> CcacheResult<()> {
+    let len = u32::try_from(data.len()).map_err(|_| CcacheError::TooLong)?;
+    buffer.extend_from_slice(&len.to_be_bytes());
+    buffer.extend_from_slice(data);
+    Ok(())
+}
+
+fn put_principal(buffer: &mut Vec<u8>, name: &PrincipalName, realm: &Realm) -> CcacheResult<()> {
+    let components = name.name_string();
+    buffer.extend_from_slice(&(*name.name_type() as u32).to_be_bytes());
+    let num_components = u32::try_from(components.len()).map_err(|_| CcacheError::TooLong)?;
+    buffer.extend_from_slice(&num_components.to_be_bytes());
+    put_counted(buffer, realm.as_str().as_bytes())?;
+    for component in components {
+        put_counted(buffer, component.as_str().as_bytes())?;
+    }
+    Ok(())
+}
+
+fn put_time(buffer: &mut Vec<u8>, time: Option<&KerberosTime>) -> CcacheResult<()> {
+    let seconds = time.map_or(Ok(0), |t| u32::try_from(t.timestamp()));
+    buffer.extend_from_slice(&seconds.map_err(|_| CcacheError::TooLong)?.to_be_bytes());
+    Ok(())
+}
+
+/// `ccache` as a file, its credentials followed by its settings
+pub(super) fn write_ccache(ccache: &Ccache) -> CcacheResult<Vec<u8>> {
+    let mut buffer = CCACHE_VERSION.to_be_bytes().to_vec();
+    match &ccache.kdc_offset {
+        Some(offset) => {
+            buffer.extend_from_slice(&12u16.to_be_bytes());
+            buffer.extend_from_slice(&DELTA_TIME_TAG.to_be_bytes());
+            buffer.extend_from_slice(&8u16.to_be_bytes());
+            buffer.extend_from_slice(&offset.seconds.to_be_bytes());
+            buffer.extend_from_slice(&offset.microseconds.to_be_bytes());
+        }
+        None => buffer.extend_from_slice(&0u16.to_be_bytes()),
+    }
+    put_principal(&mut buffer, &ccache.principal, &ccache.realm)?;
+    for credential in &ccache.credentials {
+        write_credential(&mut buffer, credential)?;
+    }
+    for entry in &ccache.config {
+        write_config_entry(&mut buffer, ccache, entry)?;
+    }
+    Ok(buffer)
+}
+
+fn write_credential(buffer: &mut Vec<u8>, credential: &Credential) -> CcacheResult<()> {
+    put_principal(buffer, &credential.client, &credential.crealm)?;
+    put_principal(buffer, &credential.server, &credential.srealm)?;
+    let keytype = u16::try_from(*credential.key.keytype()).map_err(|_| CcacheError::TooLong)?;
+    buffer.extend_from_slice(&keytype.to_be_bytes());
+    put_counted(buffer, credential.key.keyvalue().as_bytes())?;
+    put_time(buffer, Some(&credential.authtime))?;
+    put_time(buffer, credential.starttime.as_ref())?;
+    put_time(buffer, Some(&credential.endtime))?;
+    put_time(buffer, credential.renew_till.as_ref())?;
+    buffer.push(credential.is_skey as u8);
+    buffer.extend_from_slice(&flags_to_u32(&credential.flags).to_be_bytes());
+
+    let count = u32::try_from(credential.addresses.len()).map_err(|_| CcacheError::TooLong)?;
+    buffer.extend_from_slice(&count.to_be_bytes());
+    for address in &credential.addresses {
+        let addr_type = u16::try_from(*address.addr_type()).map_err(|_| CcacheError::TooLong)?;
+        buffer.extend_from_slice(&addr_type.to_be_bytes());
+        put_counted(buffer, address.address().as_bytes())?;
+    }
+    let count =
+        u32::try_from(credential.authorization_data.len()).map_err(|_| CcacheError::TooLong)?;
+    buffer.extend_from_slice(&count.to_be_bytes());
+    for entry in &credential.authorization_data {
+        let ad_type = u16::try_from(*entry.ad_type()).map_err(|_| CcacheError::TooLong)?;
+        buffer.extend_from_slice(&ad_type.to_be_bytes());
+        put_counted(buffer, entry.ad_data().as_bytes())?;
+    }
+
+    let ticket = credential
+        .ticket
+        .to_der()
+        .map_err(|_| CcacheError::InvalidTicket)?;
+    put_counted(buffer, &ticket)?;
+    let second_ticket = match &credential.second_ticket {
+        Some(ticket) => ticket.to_der().map_err(|_| CcacheError::InvalidTicket)?,
+        None => vec![],
+    };
+    put_counted(buffer, &second_ticket)
+}
+
+fn write_config_entry(
+    buffer: &mut Vec<u8>,
+    ccache: &Ccache,
+    entry: &ConfigEntry,
+) -> CcacheResult<()> {
+    let components = [
+        KerberosString::new(CONFIG_SERVICE).expect("The config service is a valid IA5String"),
+        entry.key.clone(),
+    ]
+    .into_iter()
+    .chain(entry.principal.clone());
+    let server = PrincipalName::new(NameTypes::NtPrincipal, components.collect::<Vec<_>>())
+        .expect("A config entry name has components");
+    let realm = Realm::new(CONFIG_REALM).expect("The config realm is a valid IA5String");
+
+    put_principal(buffer, &ccache.principal, &ccache.realm)?;
+    put_principal(buffer, &server, &realm)?;
+    // Empty key, times, flags, addresses and authorization data
+    buffer.extend_from_slice(&0u16.to_be_bytes());
+    buffer.extend_from_slice(&0u32.to_be_bytes());
+    buffer.extend_from_slice(&[0; 16]);
+    buffer.push(0);
+    buffer.extend_from_slice(&[0; 12]);
+    put_counted(buffer, &entry.value)?;
+    put_counted(buffer, &[])
+}
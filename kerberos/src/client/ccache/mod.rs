@@ -0,0 +1,453 @@
+#[cfg(test)]
+mod tests;
+
+mod file;
+
+pub use file::CcacheError;
+
+use crate::client::client_env_error::ClientEnvError;
+use crate::file_format;
+use messages::basic_types::{
+    AuthorizationData, EncryptedData, EncryptionKey, HostAddresses, KerberosString, KerberosTime,
+    OctetString, PrincipalName, Realm,
+};
+use messages::{
+    AsRep, EncAsRepPart, EncKdcRepPart, EncTgsRepPart, KdcRep, KrbCredInfo, TgsRep, Ticket,
+    TicketFlags,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Ticket along with the session key and times the KDC replied with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub client: PrincipalName,
+    pub crealm: Realm,
+    pub server: PrincipalName,
+    pub srealm: Realm,
+    pub key: EncryptionKey,
+    pub authtime: KerberosTime,
+    pub starttime: Option<KerberosTime>,
+    pub endtime: KerberosTime,
+    pub renew_till: Option<KerberosTime>,
+    /// Whether the ticket is encrypted in the session key of `second_ticket`, user to user
+    pub is_skey: bool,
+    pub flags: TicketFlags,
+    pub addresses: HostAddresses,
+    pub authorization_data: AuthorizationData,
+    pub ticket: Ticket,
+    pub second_ticket: Option<Ticket>,
+}
+
+impl Credential {
+    /// Credential of the ticket of an AS or TGS reply
+    pub fn from_kdc_reply(reply: &KdcRep, enc_part: &EncKdcRepPart) -> Self {
+        Self {
+            client: reply.cname().clone(),
+            crealm: reply.crealm().clone(),
+            server: enc_part.sname().clone(),
+            srealm: enc_part.srealm().clone(),
+            key: enc_part.key().clone(),
+            authtime: *enc_part.authtime(),
+            starttime: enc_part.starttime().copied(),
+            endtime: *enc_part.endtime(),
+            renew_till: enc_part.renew_till().copied(),
+            is_skey: false,
+            flags: enc_part.flags().clone(),
+            addresses: enc_part.caddr().cloned().unwrap_or_default(),
+            authorization_data: vec![],
+            ticket: reply.ticket().clone(),
+            second_ticket: None,
+        }
+    }
+
+    /// Credential of `ticket` as described by the sender of a KRB-CRED, if it names the client.
+    /// Times it leaves out are kept as zero, as in a cache
+    pub fn from_krb_cred_info(info: &KrbCredInfo, ticket: Ticket) -> Option<Self> {
+        let zero = KerberosTime::from_timestamp(0).expect("The epoch is a valid time");
+        Some(Self {
+            client: info.pname()?.clone(),
+            crealm: info.prealm()?.clone(),
+            server: info.sname().unwrap_or(ticket.sname()).clone(),
+            srealm: info.srealm().unwrap_or(ticket.realm()).clone(),
+            key: info.key().clone(),
+            authtime: info.authtime().copied().unwrap_or(zero),
+            starttime: info.starttime().copied(),
+            endtime: info.endtime().copied().unwrap_or(zero),
+            renew_till: info.renew_till().copied(),
+            is_skey: false,
+            flags: match info.flags() {
+                Some(flags) => flags.clone(),
+                None => TicketFlags::builder()
+                    .build()
+                    .expect("No flag set is a valid set of flags"),
+            },
+            addresses: info.caddr().cloned().unwrap_or_default(),
+            authorization_data: vec![],
+            ticket,
+            second_ticket: None,
+        })
+    }
+
+    /// Description of the ticket sent along with it in a KRB-CRED
+    pub fn krb_cred_info(&self) -> KrbCredInfo {
+        KrbCredInfo::new(
+            self.key.clone(),
+            self.crealm.clone(),
+            self.client.clone(),
+            self.flags.clone(),
+            self.authtime,
+            self.starttime,
+            self.endtime,
+            self.renew_till,
+            self.srealm.clone(),
+            self.server.clone(),
+            (!self.addresses.is_empty()).then(|| self.addresses.clone()),
+        )
+    }
+
+    /// AS reply this credential was got from. The cache does not keep the encrypted part of
+    /// the reply, which is left empty, nor the last requests and nonce of its content
+    pub fn as_reply(&self) -> (AsRep, EncAsRepPart) {
+        let enc_part = self.enc_kdc_rep_part();
+        let reply = AsRep::new(
+            None,
+            self.crealm.clone(),
+            self.client.clone(),
+            self.ticket.clone(),
+            self.empty_enc_part(),
+        );
+        (reply, EncAsRepPart::new(enc_part))
+    }
+
+    /// TGS reply this credential was got from, see [`Credential::as_reply`]
+    pub fn tgs_reply(&self) -> (TgsRep, EncTgsRepPart) {
+        let enc_part = self.enc_kdc_rep_part();
+        let reply = TgsRep::new(
+            None,
+            self.crealm.clone(),
+            self.client.clone(),
+            self.ticket.clone(),
+            self.empty_enc_part(),
+        );
+        (reply, EncTgsRepPart::new(enc_part))
+    }
+
+    fn empty_enc_part(&self) -> EncryptedData {
+        EncryptedData::new(
+            *self.key.keytype(),
+            None,
+            OctetString::new([]).expect("An empty string is a valid octet string"),
+        )
+    }
+
+    fn enc_kdc_rep_part(&self) -> EncKdcRepPart {
+        let mut builder = EncKdcRepPart::builder();
+        builder
+            .key(self.key.clone())
+            .last_req(vec![])
+            .nonce(0u32)
+            .flags(self.flags.clone())
+            .authtime(self.authtime)
+            .endtime(self.endtime)
+            .srealm(self.srealm.clone())
+            .sname(self.server.clone());
+        if let Some(starttime) = self.starttime {
+            builder.starttime(starttime);
+        }
+        if let Some(renew_till) = self.renew_till {
+            builder.renew_till(renew_till);
+        }
+        if !self.addresses.is_empty() {
+            builder.caddr(self.addresses.clone());
+        }
+        builder.build().expect("All the required fields are set")
+    }
+
+    /// Whether this is a ticket-granting ticket
+    pub fn is_tgt(&self) -> bool {
+        self.server.krbtgt_realm().is_some()
+    }
+
+    fn is_for(&self, server: &PrincipalName, srealm: &Realm) -> bool {
+        // Services are looked up by their components, whatever the name type the client
+        // chose for them
+        self.server.name_string() == server.name_string() && &self.srealm == srealm
+    }
+}
+
+/// Offset of the KDC clock from the local one, which is added to the local time to get the
+/// time of the KDC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdcOffset {
+    pub seconds: i32,
+    pub microseconds: i32,
+}
+
+impl KdcOffset {
+    /// Offset of a KDC whose clock is `diff` away, ahead of the local one when
+    /// `is_client_earlier`. The microseconds are kept positive, as MIT does
+    pub fn new(diff: Duration, is_client_earlier: bool) -> Self {
+        let micros = diff.as_micros().min(i32::MAX as u128 * 1_000_000) as i64;
+        let micros = if is_client_earlier { micros } else { -micros };
+        Self {
+            seconds: micros.div_euclid(1_000_000) as i32,
+            microseconds: micros.rem_euclid(1_000_000) as i32,
+        }
+    }
+}
+
+/// Setting of a cache, e.g. `fast_avail` for the KDC `principal` supporting FAST
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry {
+    /// Principal the setting is about, `None` for the whole cache
+    pub principal: Option<KerberosString>,
+    pub key: KerberosString,
+    pub value: Vec<u8>,
+}
+
+/// Credentials of a client principal, as MIT `kinit` keeps them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ccache {
+    /// Principal the credentials were got for
+    pub principal: PrincipalName,
+    pub realm: Realm,
+    pub kdc_offset: Option<KdcOffset>,
+    credentials: Vec<Credential>,
+    config: Vec<ConfigEntry>,
+}
+
+impl Ccache {
+    pub fn new(principal: PrincipalName, realm: Realm) -> Self {
+        Self {
+            principal,
+            realm,
+            kdc_offset: None,
+            credentials: vec![],
+            config: vec![],
+        }
+    }
+
+    pub fn credentials(&self) -> &[Credential] {
+        &self.credentials
+    }
+
+    pub fn config_entries(&self) -> &[ConfigEntry] {
+        &self.config
+    }
+
+    /// Cache in the MIT FILE format, version 4
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CcacheError> {
+        file::read_ccache(data)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CcacheError> {
+        file::write_ccache(self)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CcacheError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Write the cache to `path` readable by its owner only, through a temporary file renamed
+    /// over it so that a crash cannot leave the cache truncated
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CcacheError> {
+        let path = path.as_ref();
+        let data = self.to_bytes()?;
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(format!(".tmp{}", std::process::id()));
+        let written = file_format::create_owner_only(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(&data)?;
+                file.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&temp_path, path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        Ok(written?)
+    }
+
+    /// Add `credential`, replacing the one of the same client for the same server if any
+    pub fn store(&mut self, credential: Credential) {
+        self.credentials.retain(|c| {
+            !c.is_for(&credential.server, &credential.srealm)
+                || c.client != credential.client
+                || c.crealm != credential.crealm
+        });
+        self.credentials.push(credential);
+    }
+
+    /// Credential of the default principal for `server`
+    pub fn find(&self, server: &PrincipalName, srealm: &Realm) -> Option<&Credential> {
+        self.credentials.iter().rev().find(|c| {
+            c.is_for(server, srealm) && c.client == self.principal && c.crealm == self.realm
+        })
+    }
+
+    /// Set `key` about `principal`, or about the whole cache, to `value`
+    pub fn set_config(
+        &mut self,
+        principal: Option<&KerberosString>,
+        key: &KerberosString,
+        value: &[u8],
+    ) {
+        self.config
+            .retain(|e| e.principal.as_ref() != principal || &e.key != key);
+        self.config.push(ConfigEntry {
+            principal: principal.cloned(),
+            key: key.clone(),
+            value: value.to_vec(),
+        });
+    }
+
+    pub fn config(
+        &self,
+        principal: Option<&KerberosString>,
+        key: &KerberosString,
+    ) -> Option<&[u8]> {
+        self.config
+            .iter()
+            .find(|e| e.principal.as_ref() == principal && &e.key == key)
+            .map(|e| e.value.as_slice())
+    }
+}
+
+/// Where a client keeps the tickets it got, for the following exchanges and other tools to
+/// use
+pub trait CredentialCache {
+    /// Forget the credentials in the cache, which is now for `principal`, as when it gets a
+    /// new initial ticket
+    fn initialize(&self, principal: &PrincipalName, realm: &Realm) -> Result<(), CcacheError>;
+
+    /// Principal the credentials in the cache were got for
+    fn default_principal(&self) -> Result<(PrincipalName, Realm), CcacheError>;
+
+    /// Add `credential`, replacing the one for the same server if any
+    fn store(&self, credential: Credential) -> Result<(), CcacheError>;
+
+    /// Credential of the default principal for `server`, if the cache holds one
+    fn retrieve(
+        &self,
+        server: &PrincipalName,
+        srealm: &Realm,
+    ) -> Result<Option<Credential>, CcacheError>;
+
+    fn credentials(&self) -> Result<Vec<Credential>, CcacheError>;
+
+    fn set_kdc_offset(&self, offset: KdcOffset) -> Result<(), CcacheError>;
+
+    fn set_config(
+        &self,
+        principal: Option<&KerberosString>,
+        key: &KerberosString,
+        value: &[u8],
+    ) -> Result<(), CcacheError>;
+
+    fn get_config(
+        &self,
+        principal: Option<&KerberosString>,
+        key: &KerberosString,
+    ) -> Result<Option<Vec<u8>>, CcacheError>;
+
+    /// Forget the credentials in the cache for the ones delegated by their client, e.g. the TGT
+    /// of a user forwarded in a KRB-CRED
+    fn store_delegated(&self, credentials: Vec<Credential>) -> Result<(), CcacheError> {
+        let Some(first) = credentials.first() else {
+            return Ok(());
+        };
+        self.initialize(&first.client, &first.crealm)?;
+        for credential in credentials {
+            self.store(credential)?;
+        }
+        Ok(())
+    }
+
+    /// Ticket-granting ticket of the default principal for its own realm
+    fn retrieve_tgt(&self) -> Result<Option<Credential>, CcacheError> {
+        let (_, realm) = self.default_principal()?;
+        self.retrieve(&PrincipalName::krbtgt(&realm), &realm)
+    }
+}
+
+/// Credential cache kept in a file, `FILE:<path>` for MIT tools. The file is read and
+/// written back as a whole on each change
+#[derive(Debug, Clone)]
+pub struct FileCredentialCache {
+    path: PathBuf,
+}
+
+impl FileCredentialCache {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Ccache)) -> Result<(), CcacheError> {
+        let mut ccache = Ccache::load(&self.path)?;
+        change(&mut ccache);
+        ccache.save(&self.path)
+    }
+}
+
+impl CredentialCache for FileCredentialCache {
+    fn initialize(&self, principal: &PrincipalName, realm: &Realm) -> Result<(), CcacheError> {
+        Ccache::new(principal.clone(), realm.clone()).save(&self.path)
+    }
+
+    fn default_principal(&self) -> Result<(PrincipalName, Realm), CcacheError> {
+        let ccache = Ccache::load(&self.path)?;
+        Ok((ccache.principal, ccache.realm))
+    }
+
+    fn store(&self, credential: Credential) -> Result<(), CcacheError> {
+        self.update(|ccache| ccache.store(credential))
+    }
+
+    fn retrieve(
+        &self,
+        server: &PrincipalName,
+        srealm: &Realm,
+    ) -> Result<Option<Credential>, CcacheError> {
+        Ok(Ccache::load(&self.path)?.find(server, srealm).cloned())
+    }
+
+    fn credentials(&self) -> Result<Vec<Credential>, CcacheError> {
+        Ok(Ccache::load(&self.path)?.credentials)
+    }
+
+    fn set_kdc_offset(&self, offset: KdcOffset) -> Result<(), CcacheError> {
+        self.update(|ccache| ccache.kdc_offset = Some(offset))
+    }
+
+    fn set_config(
+        &self,
+        principal: Option<&KerberosString>,
+        key: &KerberosString,
+        value: &[u8],
+    ) -> Result<(), CcacheError> {
+        self.update(|ccache| ccache.set_config(principal, key, value))
+    }
+
+    fn get_config(
+        &self,
+        principal: Option<&KerberosString>,
+        key: &KerberosString,
+    ) -> Result<Option<Vec<u8>>, CcacheError> {
+        Ok(Ccache::load(&self.path)?
+            .config(principal, key)
+            .map(<[u8]>::to_vec))
+    }
+}
+
+impl From<CcacheError> for ClientEnvError {
+    fn from(e: CcacheError) -> Self {
+        ClientEnvError {
+            message: e.to_string(),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::client::ccache::{
+    Ccache, CcacheError, ConfigEntry, Credential, CredentialCache, FileCredentialCache, KdcOffset,
+};
+use messages::basic_types::{
+    ADEntry, AddressTypes, EncryptedData, EncryptionKey, HostAddress, KerberosString, KerberosTime,
+    NameTypes, OctetString, PrincipalName, Realm,
+};
+use messages::flags::TicketFlag;
+use messages::{AsRep, EncKdcRepPart, TgsRep, Ticket, TicketFlags};
+use std::time::Duration;
+
+/// Cache of `alice@EXAMPLE.COM` holding her TGT, a ticket for `HTTP/web01.corp.example` and
+/// the `fast_avail` setting of the KDC, laid out as `kinit` writes it
+const ALICE_CCACHE: &[u8] = include_bytes!("../../../tests/fixtures/ccache/alice.ccache");
+
+fn realm() -> Realm {
+    Realm::new("EXAMPLE.COM").unwrap()
+}
+
+fn alice() -> PrincipalName {
+    PrincipalName::new(
+        NameTypes::NtPrincipal,
+        [KerberosString::new("alice").unwrap()],
+    )
+    .unwrap()
+}
+
+fn http() -> PrincipalName {
+    PrincipalName::new(
+        NameTypes::NtSrvHst,
+        [
+            KerberosString::new("HTTP").unwrap(),
+            KerberosString::new("web01.corp.example").unwrap(),
+        ],
+    )
+    .unwrap()
+}
+
+fn time(seconds: u64) -> KerberosTime {
+    KerberosTime::from_timestamp(seconds).unwrap()
+}
+
+fn ticket(sname: PrincipalName, kvno: u32, cipher: &[u8]) -> Ticket {
+    Ticket::new(
+        realm(),
+        sname,
+        EncryptedData::new(18, kvno, OctetString::new(cipher).unwrap()),
+    )
+}
+
+fn flags(set: &[usize]) -> TicketFlags {
+    let mut flags = TicketFlags::builder();
+    for flag in set {
+        flags.set(*flag);
+    }
+    flags.build().unwrap()
+}
+
+fn tgt() -> Credential {
+    Credential {
+        client: alice(),
+        crealm: realm(),
+        server: PrincipalName::krbtgt(&realm()),
+        srealm: realm(),
+        key: EncryptionKey::new(18, OctetString::new((0..32).collect::<Vec<u8>>()).unwrap()),
+        authtime: time(1_700_000_000),
+        starttime: Some(time(1_700_000_000)),
+        endtime: time(1_700_036_000),
+        renew_till: Some(time(1_700_604_800)),
+        is_skey: false,
+        flags: flags(&[
+            TicketFlag::FORWARDABLE as usize,
+            TicketFlag::RENEWABLE as usize,
+            TicketFlag::INITIAL as usize,
+        ]),
+        addresses: vec![],
+        authorization_data: vec![],
+        ticket: ticket(PrincipalName::krbtgt(&realm()), 2, b"tgt-cipher"),
+        second_ticket: None,
+    }
+}
+
+fn http_ticket() -> Credential {
+    Credential {
+        server: http(),
+        key: EncryptionKey::new(17, OctetString::new([0x40; 16]).unwrap()),
+        starttime: None,
+        renew_till: None,
+        flags: flags(&[TicketFlag::FORWARDABLE as usize]),
+        addresses: vec![HostAddress::new(
+            AddressTypes::Ipv4,
+            OctetString::new([192, 168, 1, 10]).unwrap(),
+        )
+        .unwrap()],
+        authorization_data: vec![ADEntry::new(1, OctetString::new([0x30, 0x00]).unwrap())],
+        ticket: ticket(http(), 5, b"http-cipher"),
+        ..tgt()
+    }
+}
+
+fn fast_avail() -> KerberosString {
+    KerberosString::new("fast_avail").unwrap()
+}
+
+fn kdc() -> KerberosString {
+    KerberosString::new("krbtgt/EXAMPLE.COM@EXAMPLE.COM").unwrap()
+}
+
+#[test]
+fn ccache_file_should_be_read_into_credentials_and_settings() {
+    let ccache = Ccache::from_bytes(ALICE_CCACHE).unwrap();
+
+    assert_eq!(ccache.principal, alice());
+    assert_eq!(ccache.realm, realm());
+    assert_eq!(
+        ccache.kdc_offset,
+        Some(KdcOffset {
+            seconds: -3,
+            microseconds: 500_000
+        })
+    );
+    assert_eq!(ccache.credentials(), &[tgt(), http_ticket()]);
+    assert_eq!(
+        ccache.config_entries(),
+        &[ConfigEntry {
+            principal: Some(kdc()),
+            key: fast_avail(),
+            value: b"yes".to_vec(),
+        }]
+    );
+}
+
+#[test]
+fn ccache_file_should_be_written_back_byte_for_byte() {
+    let ccache = Ccache::from_bytes(ALICE_CCACHE).unwrap();
+
+    assert_eq!(ccache.to_bytes().unwrap(), ALICE_CCACHE);
+}
+
+#[test]
+fn credentials_should_be_found_by_server_whatever_its_name_type() {
+    let ccache = Ccache::from_bytes(ALICE_CCACHE).unwrap();
+
+    let http_principal = PrincipalName::new(NameTypes::NtPrincipal, http().name_string().clone());
+    assert_eq!(
+        ccache.find(&http_principal.unwrap(), &realm()),
+        Some(&http_ticket())
+    );
+    assert_eq!(
+        ccache.find(&http(), &Realm::new("OTHER.COM").unwrap()),
+        None
+    );
+    assert_eq!(
+        ccache.config(Some(&kdc()), &fast_avail()),
+        Some(&b"yes"[..])
+    );
+    assert_eq!(ccache.config(None, &fast_avail()), None);
+}
+
+#[test]
+fn storing_a_credential_should_replace_the_one_for_the_same_server() {
+    let mut ccache = Ccache::new(alice(), realm());
+    ccache.store(tgt());
+    ccache.store(http_ticket());
+
+    let renewed = Credential {
+        endtime: time(1_700_072_000),
+        ..http_ticket()
+    };
+    ccache.store(renewed.clone());
+
+    assert_eq!(ccache.credentials(), &[tgt(), renewed]);
+}
+
+#[test]
+fn credential_should_give_back_the_content_of_its_reply() {
+    let enc_part = EncKdcRepPart::builder()
+        .key(http_ticket().key)
+        .last_req(vec![])
+        .nonce(0u32)
+        .flags(http_ticket().flags)
+        .authtime(time(1_700_000_000))
+        .endtime(time(1_700_036_000))
+        .srealm(realm())
+        .sname(http())
+        .caddr(http_ticket().addresses)
+        .build()
+        .unwrap();
+    let reply = TgsRep::new(
+        None,
+        realm(),
+        alice(),
+        http_ticket().ticket,
+        EncryptedData::new(17, 1, OctetString::new(b"enc-part").unwrap()),
+    );
+
+    let credential = Credential::from_kdc_reply(&reply, &enc_part);
+    let (tgs_rep, enc_tgs_rep_part) = credential.tgs_reply();
+    assert_eq!(tgs_rep.ticket(), reply.ticket());
+    assert_eq!(tgs_rep.cname(), reply.cname());
+    assert_eq!(*enc_tgs_rep_part, enc_part);
+
+    let (as_rep, _): (AsRep, _) = tgt().as_reply();
+    assert!(tgt().is_tgt() && !credential.is_tgt());
+    assert_eq!(as_rep.ticket(), &tgt().ticket);
+}
+
+#[test]
+fn file_cache_should_keep_every_ticket_until_reinitialized() {
+    let path = std::env::temp_dir().join(format!("krb5cc-{}", std::process::id()));
+    let cache = FileCredentialCache::new(&path);
+
+    cache.initialize(&alice(), &realm()).unwrap();
+    cache.store(tgt()).unwrap();
+    cache.store(http_ticket()).unwrap();
+    cache
+        .set_kdc_offset(KdcOffset::new(Duration::from_millis(2_500), false))
+        .unwrap();
+    cache
+        .set_config(Some(&kdc()), &fast_avail(), b"yes")
+        .unwrap();
+    let retrieved = (
+        cache.retrieve_tgt().unwrap(),
+        cache.retrieve(&http(), &realm()).unwrap(),
+        cache.get_config(Some(&kdc()), &fast_avail()).unwrap(),
+        Ccache::load(&path).unwrap(),
+    );
+    cache.initialize(&alice(), &realm()).unwrap();
+    let reinitialized = cache.credentials().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(retrieved.0, Some(tgt()));
+    assert_eq!(retrieved.1, Some(http_ticket()));
+    assert_eq!(retrieved.2, Some(b"yes".to_vec()));
+    assert_eq!(retrieved.3.to_bytes().unwrap(), ALICE_CCACHE);
+    assert!(reinitialized.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn saved_cache_should_only_be_readable_by_its_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("krb5cc-mode-{}", std::process::id()));
+    std::fs::create_dir(&dir).unwrap();
+    let path = dir.join("krb5cc");
+    Ccache::new(alice(), realm()).save(&path).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(mode & 0o777, 0o600);
+    // Nothing but the cache is left behind
+    assert_eq!(files, 1);
+}
+
+#[test]
+fn other_versions_and_truncated_ccaches_should_be_rejected() {
+    assert!(matches!(
+        Ccache::from_bytes(&[0x05, 0x03]),
+        Err(CcacheError::UnsupportedVersion(0x0503))
+    ));
+    assert!(matches!(
+        Ccache::from_bytes(&ALICE_CCACHE[..ALICE_CCACHE.len() - 1]),
+        Err(CcacheError::Truncated)
+    ));
+    assert!(matches!(
+        FileCredentialCache::new("/nonexistent/krb5cc").credentials(),
+        Err(CcacheError::Io(_))
+    ));
+}
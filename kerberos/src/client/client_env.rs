@@ -1,9 +1,11 @@
 use crate::client::client_env_error::ClientEnvError;
 use crate::cryptographic_hash::CryptographicHash;
 use crate::cryptography::Cryptography;
-use messages::basic_types::{EncryptionKey, KerberosFlags, KerberosString, KerberosTime};
+use messages::basic_types::{
+    EncryptionKey, KerberosFlags, KerberosString, NameTypes, PrincipalName,
+};
 use messages::{AsRep, EncAsRepPart, EncTgsRepPart, TgsRep};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub trait ClientEnv {
     fn get_client_name(&self) -> Result<KerberosString, ClientEnvError>;
@@ -14,10 +16,28 @@ pub trait ClientEnv {
 
     fn get_server_realm(&self) -> Result<KerberosString, ClientEnvError>;
 
+    /// Name of the client, the `NtPrincipal` of the single component `get_client_name`
+    /// unless overridden
+    fn get_client_principal(&self) -> Result<PrincipalName, ClientEnvError> {
+        single_component_name(self.get_client_name()?)
+    }
+
+    /// Name of the service tickets are requested for, which may have several components,
+    /// e.g. `HTTP/web01.corp.example` of type `NtSrvHst`. The `NtPrincipal` of the single
+    /// component `get_server_name` unless overridden
+    fn get_server_principal(&self) -> Result<PrincipalName, ClientEnvError> {
+        single_component_name(self.get_server_name()?)
+    }
+
     fn get_kdc_options(&self) -> Result<KerberosFlags, ClientEnvError>;
 
+    /// Time since the Unix epoch, keeping the microseconds the authenticators carry
     fn get_current_time(&self) -> Result<Duration, ClientEnvError> {
-        Ok(KerberosTime::now().to_unix_duration())
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ClientEnvError {
+                message: e.to_string(),
+            })
     }
 
     fn get_supported_etypes(&self) -> Result<Vec<i32>, ClientEnvError>;
@@ -54,3 +74,9 @@ pub trait ClientEnv {
 
     fn save_seq_number(&self, seq_num: u32) -> Result<(), ClientEnvError>;
 }
+
+fn single_component_name(name: KerberosString) -> Result<PrincipalName, ClientEnvError> {
+    PrincipalName::new(NameTypes::NtPrincipal, vec![name]).map_err(|e| ClientEnvError {
+        message: e.to_string(),
+    })
+}
@@ -3,8 +3,11 @@ use crate::client::client_error::ClientError;
 use crate::client::client_error::ClientError::{ClockSkewError, InvalidKdcReq, ResponseModified};
 use crate::client::util::{is_within_clock_skew, is_zero_time};
 use crate::cryptography::Cryptography;
-use messages::basic_types::{EncryptionKey, KerberosTime};
-use messages::flags::KdcOptionsFlag::{RENEWABLE, RENEWABLE_OK};
+use messages::basic_types::{
+    EncryptionKey, KerberosFlags, KerberosTime, PaDataTypes, PaForUser, PrincipalName, Realm,
+};
+use messages::flags::KdcOptionsFlag::{CNAME_IN_ADDL_TKT, RENEWABLE, RENEWABLE_OK};
+use messages::flags::TicketFlag::INITIAL;
 use messages::{Decode, EncAsRepPart, EncKdcRepPart, EncTgsRepPart, KdcRep, KdcReq};
 use std::time::Duration;
 
@@ -13,6 +16,54 @@ pub(crate) enum KdcExchangeType {
     Tgs,
 }
 
+/// Options below INITIAL ask for the ticket flag of the same position, the flags from
+/// there on are set by the KDC on its own (e.g. TRANSITED-POLICY-CHECKED)
+fn flags_match_options(options: &KerberosFlags, flags: &KerberosFlags) -> bool {
+    (0..INITIAL as usize).all(|bit| options.is_set(bit) == flags.is_set(bit))
+}
+
+/// The user a S4U2Self or S4U2Proxy request asks a ticket on behalf of, which the
+/// ticket is issued to instead of the client
+fn impersonated_user(
+    client_env: &impl ClientEnv,
+    kdc_req: &KdcReq,
+) -> Result<Option<(PrincipalName, Realm)>, ClientError> {
+    let pa_for_user = kdc_req.padata().and_then(|padata| {
+        padata
+            .iter()
+            .find(|padata| *padata.padata_type() == PaDataTypes::PaForUser as i32)
+    });
+    if let Some(pa_for_user) = pa_for_user {
+        let pa_for_user = PaForUser::from_der(pa_for_user.padata_value().as_bytes())
+            .or(Err(ClientError::DecodeError))?;
+        return Ok(Some((
+            pa_for_user.user_name().clone(),
+            pa_for_user.user_realm().clone(),
+        )));
+    }
+    if kdc_req
+        .req_body()
+        .kdc_options()
+        .is_set(CNAME_IN_ADDL_TKT as usize)
+    {
+        // The evidence ticket is the one obtained through S4U2Self
+        let evidence_rep = client_env.get_tgs_reply()?;
+        if kdc_req
+            .req_body()
+            .additional_tickets()
+            .and_then(|tickets| tickets.first())
+            != Some(evidence_rep.ticket())
+        {
+            return Err(InvalidKdcReq("Unknown evidence ticket".to_string()));
+        }
+        return Ok(Some((
+            evidence_rep.cname().clone(),
+            evidence_rep.crealm().clone(),
+        )));
+    }
+    Ok(None)
+}
+
 pub fn receive_kdc_rep(
     client_env: &impl ClientEnv,
     cryptography: Box<dyn Cryptography>,
@@ -46,17 +97,34 @@ pub fn receive_kdc_rep(
         .req_body()
         .sname()
         .ok_or(InvalidKdcReq("Request sname not found".to_string()))?;
-    if *req_cname != *kdc_rep.cname()
-        || *kdc_rep.crealm() != *kdc_req.req_body().realm()
-        || req_sname != kdc_rep_part.sname()
-        || kdc_req.req_body().realm() != kdc_rep_part.srealm()
+    // The client keeps the realm it authenticated in across TGS exchanges, even
+    // when the ticket is issued by the KDC of another realm
+    let (expected_cname, expected_crealm) = match exchange_type {
+        KdcExchangeType::As => (req_cname.clone(), kdc_req.req_body().realm().clone()),
+        KdcExchangeType::Tgs => match impersonated_user(client_env, kdc_req)? {
+            Some(user) => user,
+            None => (
+                req_cname.clone(),
+                client_env.get_as_reply()?.crealm().clone(),
+            ),
+        },
+    };
+    // A TGS may answer a request for a service of a foreign realm with a
+    // cross-realm TGT for that realm instead
+    let is_referral = matches!(exchange_type, KdcExchangeType::Tgs)
+        && kdc_rep_part.sname().krbtgt_realm() == Some(kdc_req.req_body().realm());
+    if expected_cname != *kdc_rep.cname()
+        || *kdc_rep.crealm() != expected_crealm
+        || (!is_referral
+            && (req_sname != kdc_rep_part.sname()
+                || kdc_req.req_body().realm() != kdc_rep_part.srealm()))
         || kdc_req.req_body().nonce() != kdc_rep_part.nonce()
         || kdc_req.req_body().addresses() != kdc_rep_part.caddr()
     {
         return Err(ResponseModified);
     }
 
-    if kdc_req.req_body().kdc_options() != kdc_rep_part.flags() {
+    if !flags_match_options(kdc_req.req_body().kdc_options(), kdc_rep_part.flags()) {
         return Err(ResponseModified);
     }
 
@@ -5,4 +5,6 @@ pub mod as_exchange;
 pub mod client_error;
 pub mod tgs_exchange;
 pub mod ap_exchange;
-mod kdc_exchange;
\ No newline at end of file
+pub mod user_to_user;
+mod kdc_exchange;
+pub mod ccache;
\ No newline at end of file
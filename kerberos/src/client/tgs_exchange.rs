@@ -1,4 +1,5 @@
-use crate::client::ap_exchange::prepare_pa_data;
+use crate::client::ap_exchange::prepare_pa_data_with_tgt;
+use crate::client::ccache::Credential;
 use crate::client::client_env::ClientEnv;
 use crate::client::client_env_error::ClientEnvError;
 use crate::client::client_error::ClientError;
@@ -6,21 +7,150 @@ use crate::client::kdc_exchange::{receive_kdc_rep, KdcExchangeType};
 use crate::client::util::generate_nonce;
 use messages::basic_types::PaDataTypes::PaTgsReq;
 use messages::basic_types::{
-    EncryptionKey, KerberosTime, NameTypes, OctetString, PaData, PrincipalName,
+    Checksum, EncryptionKey, KerberosTime, OctetString, PaData, PaDataTypes, PaForUser,
+    PrincipalName, Realm,
 };
+use messages::flags::KdcOptionsFlag;
 use messages::{
-    ApReq, Authenticator, Decode, EncTgsRepPart, Encode, KdcReqBodyBuilder, TgsRep, TgsReq,
+    ApReq, Authenticator, Decode, EncTgsRepPart, Encode, KdcReqBodyBuilder, TgsRep, TgsReq, TgtRep,
+    Ticket,
 };
 use std::time::Duration;
 
+/// What a TGS request asks for besides a ticket of the client to the server
+enum TgsRequestKind<'a> {
+    Service,
+    /// A ticket encrypted in the session key of the server's TGT
+    UserToUser(&'a Ticket),
+    /// A ticket to the client itself, on behalf of the given user
+    S4u2Self(&'a PrincipalName, &'a Realm),
+    /// A ticket to the server, on behalf of the user of the evidence ticket
+    S4u2Proxy(&'a Ticket),
+    /// A TGT of the client for its own realm, which it hands over to another server
+    ForwardedTgt,
+}
+
 pub fn prepare_tgs_request(client_env: &impl ClientEnv) -> Result<TgsReq, ClientError> {
-    let client_name = client_env.get_client_name()?;
-    let server_realm = client_env.get_server_realm()?;
-    let server_name = client_env.get_server_name()?;
-    let sname = PrincipalName::new(NameTypes::NtPrincipal, vec![server_name])
-        .map_err(|e| ClientError::GenericError(e.to_string()))?;
-    let cname = PrincipalName::new(NameTypes::NtPrincipal, vec![client_name])
-        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+    let as_rep = client_env.get_as_reply()?;
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    prepare_tgs_request_with_tgt(
+        client_env,
+        as_rep.ticket(),
+        enc_part.key(),
+        TgsRequestKind::Service,
+    )
+}
+
+/// Request a user-to-user ticket for a server which only holds a TGT. The ticket will be
+/// encrypted in the session key of the server's TGT, as sent in its TGT-REP
+pub fn prepare_user_to_user_tgs_request(
+    client_env: &impl ClientEnv,
+    tgt_rep: &TgtRep,
+) -> Result<TgsReq, ClientError> {
+    let as_rep = client_env.get_as_reply()?;
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    prepare_tgs_request_with_tgt(
+        client_env,
+        as_rep.ticket(),
+        enc_part.key(),
+        TgsRequestKind::UserToUser(tgt_rep.ticket()),
+    )
+}
+
+/// Request, as a service, a ticket to itself on behalf of `user_name` (S4U2Self).
+/// The ticket is forwardable, and so usable with [prepare_s4u2proxy_request], only if
+/// forwardable tickets are asked for and the service is allowed to delegate
+pub fn prepare_s4u2self_request(
+    client_env: &impl ClientEnv,
+    user_name: &PrincipalName,
+    user_realm: &Realm,
+) -> Result<TgsReq, ClientError> {
+    let as_rep = client_env.get_as_reply()?;
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    prepare_tgs_request_with_tgt(
+        client_env,
+        as_rep.ticket(),
+        enc_part.key(),
+        TgsRequestKind::S4u2Self(user_name, user_realm),
+    )
+}
+
+/// Request a ticket to the server on behalf of the user of the ticket obtained
+/// through S4U2Self in the last TGS reply (S4U2Proxy)
+pub fn prepare_s4u2proxy_request(client_env: &impl ClientEnv) -> Result<TgsReq, ClientError> {
+    let as_rep = client_env.get_as_reply()?;
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    let evidence = client_env.get_tgs_reply()?;
+    prepare_tgs_request_with_tgt(
+        client_env,
+        as_rep.ticket(),
+        enc_part.key(),
+        TgsRequestKind::S4u2Proxy(evidence.ticket()),
+    )
+}
+
+/// Request a FORWARDED TGT to hand over to a trusted server, e.g. a job runner acting as the
+/// client, in a KRB-CRED. The TGT from the AS must be forwardable, and the one requested is
+/// too when the KDC options ask for it, so that the server can forward it further
+pub fn prepare_forwarded_tgt_request(client_env: &impl ClientEnv) -> Result<TgsReq, ClientError> {
+    let as_rep = client_env.get_as_reply()?;
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    prepare_tgs_request_with_tgt(
+        client_env,
+        as_rep.ticket(),
+        enc_part.key(),
+        TgsRequestKind::ForwardedTgt,
+    )
+}
+
+/// Check the reply to a request of [prepare_forwarded_tgt_request], giving back the forwarded
+/// TGT. Unlike the reply to other requests, it does not replace the last ticket obtained,
+/// which is the one of the server it is forwarded to
+pub fn receive_forwarded_tgt(
+    tgs_req: &TgsReq,
+    tgs_rep: &TgsRep,
+    client_env: &impl ClientEnv,
+) -> Result<Credential, ClientError> {
+    let enc_tgs_rep_part = verify_tgs_response(tgs_req, tgs_rep, client_env)?;
+    Ok(Credential::from_kdc_reply(tgs_rep, &enc_tgs_rep_part))
+}
+
+/// Request the service ticket from the KDC of the server's realm, using the cross-realm
+/// TGT (`krbtgt/REMOTE@LOCAL`) the local KDC referred the client to in its last TGS reply
+pub fn prepare_cross_realm_tgs_request(client_env: &impl ClientEnv) -> Result<TgsReq, ClientError> {
+    let tgs_rep = client_env.get_tgs_reply()?;
+    let enc_part = client_env.get_tgs_reply_enc_part()?;
+    if tgs_rep.ticket().sname().krbtgt_realm() != Some(&client_env.get_server_realm()?) {
+        return Err(ClientError::PrepareRequestError(
+            "no cross-realm TGT for the server realm".to_string(),
+        ));
+    }
+    prepare_tgs_request_with_tgt(
+        client_env,
+        tgs_rep.ticket(),
+        enc_part.key(),
+        TgsRequestKind::Service,
+    )
+}
+
+fn prepare_tgs_request_with_tgt(
+    client_env: &impl ClientEnv,
+    tgt: &Ticket,
+    session_key: &EncryptionKey,
+    kind: TgsRequestKind,
+) -> Result<TgsReq, ClientError> {
+    let cname = client_env.get_client_principal()?;
+    let (sname, server_realm) = match kind {
+        TgsRequestKind::S4u2Self(..) => (cname.clone(), client_env.get_client_realm()?),
+        TgsRequestKind::ForwardedTgt => {
+            let realm = client_env.get_client_realm()?;
+            (PrincipalName::krbtgt(&realm), realm)
+        }
+        _ => (
+            client_env.get_server_principal()?,
+            client_env.get_server_realm()?,
+        ),
+    };
     let nonce = generate_nonce();
     let current_time = client_env.get_current_time()?;
     let duration = Duration::new(60 * 60 * 24, 0);
@@ -33,19 +163,36 @@ pub fn prepare_tgs_request(client_env: &impl ClientEnv) -> Result<TgsReq, Client
         }));
     }
 
-    let req_body = KdcReqBodyBuilder::default()
+    let mut kdc_options = client_env.get_kdc_options()?.to_builder();
+    let mut req_body = KdcReqBodyBuilder::default();
+    match kind {
+        TgsRequestKind::UserToUser(additional_tgt) => {
+            kdc_options.set(KdcOptionsFlag::ENC_TKT_IN_SKEY as usize);
+            req_body.additional_tickets(vec![additional_tgt.clone()]);
+        }
+        TgsRequestKind::S4u2Proxy(evidence) => {
+            kdc_options.set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize);
+            req_body.additional_tickets(vec![evidence.clone()]);
+        }
+        TgsRequestKind::ForwardedTgt => {
+            kdc_options.set(KdcOptionsFlag::FORWARDED as usize);
+        }
+        _ => {}
+    }
+    let req_body = req_body
         .cname(cname)
         .realm(server_realm)
         .sname(sname)
         .nonce(nonce)
-        .kdc_options(client_env.get_kdc_options()?)
+        .kdc_options(kdc_options.build().or(Err(ClientError::EncodeError))?)
         .till(till)
         .etype(etypes)
         .build()?;
 
     // authentication header
     let encoded_req_body = req_body.to_der().or(Err(ClientError::EncodeError))?;
-    let ap_req = prepare_pa_data(client_env, false, Some(encoded_req_body))?;
+    let ap_req =
+        prepare_pa_data_with_tgt(client_env, false, Some(encoded_req_body), tgt, session_key)?;
     let mut ap_req_buf: Vec<u8> = Vec::new();
     ap_req
         .encode_to_vec(&mut ap_req_buf)
@@ -54,45 +201,62 @@ pub fn prepare_tgs_request(client_env: &impl ClientEnv) -> Result<TgsReq, Client
         PaTgsReq as i32,
         OctetString::new(ap_req_buf).or(Err(ClientError::EncodeError))?,
     );
-    let pa_data = vec![auth_header];
+    let mut pa_data = vec![auth_header];
+    if let TgsRequestKind::S4u2Self(user_name, user_realm) = kind {
+        pa_data.push(prepare_pa_for_user(
+            client_env,
+            user_name,
+            user_realm,
+            session_key,
+        )?);
+    }
 
     let tgs_req = TgsReq::new(pa_data, req_body);
     Ok(tgs_req)
 }
 
+fn prepare_pa_for_user(
+    client_env: &impl ClientEnv,
+    user_name: &PrincipalName,
+    user_realm: &Realm,
+    session_key: &EncryptionKey,
+) -> Result<PaData, ClientError> {
+    let auth_package = Realm::new(PaForUser::AUTH_PACKAGE)
+        .map_err(|e| ClientError::GenericError(e.to_string()))?;
+    let crypto_hash = client_env.get_checksum_hash(1)?;
+    let cksum = Checksum::new(
+        1,
+        OctetString::new(crypto_hash.keyed_digest(
+            session_key.keyvalue().as_bytes(),
+            &PaForUser::checksum_data(user_name, user_realm, &auth_package),
+        ))
+        .or(Err(ClientError::EncodeError))?,
+    );
+    let pa_for_user = PaForUser::new(user_name.clone(), user_realm.clone(), cksum);
+    Ok(PaData::new(
+        PaDataTypes::PaForUser as i32,
+        OctetString::new(pa_for_user.to_der().or(Err(ClientError::EncodeError))?)
+            .or(Err(ClientError::EncodeError))?,
+    ))
+}
+
 pub fn receive_tgs_response(
     tgs_req: &TgsReq,
     tgs_rep: &TgsRep,
     client_env: &impl ClientEnv,
 ) -> Result<(), ClientError> {
+    let enc_tgs_rep_part = verify_tgs_response(tgs_req, tgs_rep, client_env)?;
+    client_env.save_tgs_reply(tgs_rep, &enc_tgs_rep_part)?;
+    Ok(())
+}
+
+fn verify_tgs_response(
+    tgs_req: &TgsReq,
+    tgs_rep: &TgsRep,
+    client_env: &impl ClientEnv,
+) -> Result<EncTgsRepPart, ClientError> {
     let crypto = client_env.get_crypto(*tgs_rep.enc_part().etype())?;
-    let as_rep = client_env.get_as_reply_enc_part()?;
-    let decrypt_key = tgs_rep
-        .padata()
-        .and_then(|padata| {
-            padata
-                .iter()
-                .find(|x| *x.padata_type() == PaTgsReq as i32)
-                .map(|padata| {
-                    let ap_req = ApReq::from_der(padata.padata_value().as_bytes())
-                        .or(Err(ClientError::DecodeError))?;
-                    let decrypted_authenticator = crypto.decrypt(
-                        ap_req.authenticator().cipher().as_bytes(),
-                        client_env
-                            .get_tgs_reply_enc_part()?
-                            .key()
-                            .keyvalue()
-                            .as_ref(),
-                    )?;
-                    let authenticator = Authenticator::from_der(&decrypted_authenticator)
-                        .or(Err(ClientError::DecodeError))?;
-                    match authenticator.subkey() {
-                        None => Ok::<EncryptionKey, ClientError>(as_rep.key().clone()),
-                        Some(key) => Ok(key.clone()),
-                    }
-                })
-        })
-        .unwrap_or(Ok(as_rep.key().clone()))?;
+    let decrypt_key = reply_key(tgs_req, client_env)?;
     let decrypted_kdc_rep_part = crypto.decrypt(
         tgs_rep.enc_part().cipher().as_ref(),
         decrypt_key.keyvalue().as_ref(),
@@ -108,7 +272,38 @@ pub fn receive_tgs_response(
         tgs_rep,
         KdcExchangeType::Tgs,
     )?;
+    Ok(enc_tgs_rep_part)
+}
 
-    client_env.save_tgs_reply(tgs_rep, &enc_tgs_rep_part)?;
-    Ok(())
+/// The reply is encrypted with the subkey of the request's authenticator if there is one,
+/// otherwise with the session key of the TGT the request was made with
+fn reply_key(tgs_req: &TgsReq, client_env: &impl ClientEnv) -> Result<EncryptionKey, ClientError> {
+    let ap_req = tgs_req
+        .padata()
+        .and_then(|padata| padata.iter().find(|x| *x.padata_type() == PaTgsReq as i32))
+        .ok_or(ClientError::InvalidKdcReq(
+            "Request authentication header not found".to_string(),
+        ))
+        .and_then(|padata| {
+            ApReq::from_der(padata.padata_value().as_bytes()).or(Err(ClientError::DecodeError))
+        })?;
+
+    let session_key = if ap_req.ticket() == client_env.get_as_reply()?.ticket() {
+        client_env.get_as_reply_enc_part()?.key().clone()
+    } else if ap_req.ticket() == client_env.get_tgs_reply()?.ticket() {
+        client_env.get_tgs_reply_enc_part()?.key().clone()
+    } else {
+        return Err(ClientError::InvalidKdcReq(
+            "Request was made with an unknown TGT".to_string(),
+        ));
+    };
+
+    let crypto = client_env.get_crypto(*session_key.keytype())?;
+    let decrypted_authenticator = crypto.decrypt(
+        ap_req.authenticator().cipher().as_bytes(),
+        session_key.keyvalue().as_ref(),
+    )?;
+    let authenticator =
+        Authenticator::from_der(&decrypted_authenticator).or(Err(ClientError::DecodeError))?;
+    Ok(authenticator.subkey().cloned().unwrap_or(session_key))
 }
@@ -0,0 +1,50 @@
+use crate::client::client_env::ClientEnv;
+use crate::client::client_error::ClientError;
+use messages::{Decode, EncTicketPart, TgtRep, TgtReq, Ticket};
+
+/// Ask the server for its TGT, so that a user-to-user ticket can be requested for it
+pub fn prepare_tgt_request(client_env: &impl ClientEnv) -> Result<TgtReq, ClientError> {
+    Ok(TgtReq::new(
+        Some(client_env.get_server_principal()?),
+        Some(client_env.get_server_realm()?),
+    ))
+}
+
+/// Answer a TGT-REQ with the TGT obtained from the AS, as the server of a user-to-user exchange
+pub fn prepare_tgt_reply(
+    client_env: &impl ClientEnv,
+    tgt_req: &TgtReq,
+) -> Result<TgtRep, ClientError> {
+    let as_rep = client_env.get_as_reply()?;
+    if tgt_req
+        .server_name()
+        .is_some_and(|name| name != as_rep.cname())
+        || tgt_req
+            .realm()
+            .is_some_and(|realm| realm != as_rep.crealm())
+    {
+        return Err(ClientError::PrepareRequestError(
+            "TGT-REQ is addressed to another principal".to_string(),
+        ));
+    }
+    Ok(TgtRep::new(as_rep.ticket().clone()))
+}
+
+/// Decrypt a user-to-user ticket issued for this principal, which is encrypted in the
+/// session key of the TGT it sent in its TGT-REP
+pub fn decrypt_user_to_user_ticket(
+    client_env: &impl ClientEnv,
+    ticket: &Ticket,
+) -> Result<EncTicketPart, ClientError> {
+    let enc_part = client_env.get_as_reply_enc_part()?;
+    let session_key = enc_part.key();
+    if ticket.enc_part().etype() != session_key.keytype() {
+        return Err(ClientError::DecodeError);
+    }
+    let crypto = client_env.get_crypto(*session_key.keytype())?;
+    let decrypted = crypto.decrypt(
+        ticket.enc_part().cipher().as_bytes(),
+        session_key.keyvalue().as_ref(),
+    )?;
+    EncTicketPart::from_der(&decrypted).or(Err(ClientError::DecodeError))
+}
@@ -1,6 +1,39 @@
 use messages::basic_types::Int32;
 
+/// Block size of the hash functions used as checksums, in bytes
+const HMAC_BLOCK_SIZE: usize = 64;
+
 pub trait CryptographicHash: Send + Sync {
     fn get_checksum_type(&self) -> Int32;
     fn digest(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Checksum which can only be computed knowing `key`, built as an HMAC over `digest`,
+    /// https://www.rfc-editor.org/rfc/rfc2104
+    fn keyed_digest(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut block = if key.len() > HMAC_BLOCK_SIZE {
+            self.digest(key)
+        } else {
+            key.to_vec()
+        };
+        block.resize(HMAC_BLOCK_SIZE, 0);
+        let inner_pad = block.iter().map(|b| b ^ 0x36);
+        let outer_pad = block.iter().map(|b| b ^ 0x5c);
+        let inner = self.digest(&inner_pad.chain(data.iter().copied()).collect::<Vec<_>>());
+        self.digest(&outer_pad.chain(inner).collect::<Vec<_>>())
+    }
+}
+
+/// Checksum borrowed from its owner, see the implementation of `Cryptography` for references
+impl<T: CryptographicHash + ?Sized> CryptographicHash for &T {
+    fn get_checksum_type(&self) -> Int32 {
+        (**self).get_checksum_type()
+    }
+
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        (**self).digest(data)
+    }
+
+    fn keyed_digest(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        (**self).keyed_digest(key, data)
+    }
 }
@@ -9,3 +9,23 @@ pub trait Cryptography: Send + Sync {
 
     fn generate_key(&self) -> Result<Vec<u8>, CryptographyError>;
 }
+
+/// Encryption borrowed from its owner, e.g. a service sharing its own with the contexts it
+/// establishes
+impl<T: Cryptography + ?Sized> Cryptography for &T {
+    fn get_etype(&self) -> i32 {
+        (**self).get_etype()
+    }
+
+    fn encrypt(&self, data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        (**self).encrypt(data, key)
+    }
+
+    fn decrypt(&self, data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        (**self).decrypt(data, key)
+    }
+
+    fn generate_key(&self) -> Result<Vec<u8>, CryptographyError> {
+        (**self).generate_key()
+    }
+}
@@ -0,0 +1,57 @@
+// Helpers shared by the MIT keytab and credential cache file formats, whose integers are
+// big-endian and whose files hold long-term or session keys
+
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+/// The data ends in the middle of a value
+pub(crate) struct Truncated;
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], Truncated> {
+        if self.data.len() < len {
+            return Err(Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Truncated> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Truncated> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Truncated> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, Truncated> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Open `path` for writing, truncating it, and create it readable by its owner only as the
+/// MIT tools do
+pub(crate) fn create_owner_only(path: impl AsRef<Path>) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
@@ -0,0 +1,216 @@
+use crate::application_authentication_service::{ApplicationAuthenticationService, ServerError};
+use crate::client::ap_exchange::{prepare_ap_request_with_checksum, verify_ap_reply};
+use crate::client::client_env::ClientEnv;
+use crate::gssapi::sequence::SequenceWindow;
+use crate::gssapi::token::{
+    frame, gss_checksum, read_gss_checksum, unframe, GSS_CHECKSUM_TYPE, TOK_ID_AP_REP,
+    TOK_ID_AP_REQ, TOK_ID_KRB_ERROR,
+};
+use crate::gssapi::{
+    ChannelBindings, ContextFlag, ContextFlags, GssError, SecurityContext, MIC_CHECKSUM_TYPE,
+};
+use crate::service_traits::{
+    ApReplayCache, ClientAddressStorage, ServiceKeyStore, UserSessionStorage,
+};
+use messages::basic_types::{Checksum, OctetString};
+use messages::{ApRep, ApReq, Decode, Encode, KrbErrorMsg};
+
+/// Context flags the mechanism provides, besides confidentiality and integrity which are always
+/// available
+const SUPPORTED_FLAGS: [ContextFlag; 3] = [
+    ContextFlag::Mutual,
+    ContextFlag::Replay,
+    ContextFlag::Sequence,
+];
+
+fn supported(flags: u32) -> ContextFlags {
+    SUPPORTED_FLAGS
+        .iter()
+        .filter(|flag| flags & **flag as u32 != 0)
+        .fold(ContextFlags::default(), |supported, flag| {
+            supported.with(*flag)
+        })
+        .with(ContextFlag::Conf)
+        .with(ContextFlag::Integ)
+}
+
+/// Start a context with the server of the last ticket the client obtained, giving back the
+/// AP-REQ token to send to it. The context is established at once unless `flags` ask for
+/// mutual authentication, in which case the reply of the acceptor is passed to
+/// [SecurityContext::continue_init_sec_context]
+pub fn init_sec_context(
+    client_env: &impl ClientEnv,
+    flags: ContextFlags,
+    channel_bindings: Option<&ChannelBindings>,
+) -> Result<(SecurityContext<'static>, Vec<u8>), GssError> {
+    let flags = supported(flags.bits());
+    let mutual = flags.has(ContextFlag::Mutual);
+    let bindings_hash = channel_bindings
+        .map(ChannelBindings::hash)
+        .unwrap_or_default();
+    let cksum = Checksum::new(
+        GSS_CHECKSUM_TYPE,
+        OctetString::new(gss_checksum(bindings_hash, flags.bits()))
+            .map_err(|e| GssError::Failure(e.to_string()))?,
+    );
+    let (ap_req, authenticator) = prepare_ap_request_with_checksum(client_env, mutual, cksum)?;
+    let ap_req = ap_req
+        .to_der()
+        .map_err(|e| GssError::Failure(e.to_string()))?;
+
+    let session_key = client_env.get_tgs_reply_enc_part()?.key().clone();
+    // The acceptor numbers its tokens as the initiator does unless it says otherwise
+    let seq_number = authenticator.seq_number().unwrap_or_default() as u32 as u64;
+    let context = SecurityContext {
+        is_initiator: true,
+        flags,
+        peer_name: client_env.get_server_principal()?,
+        peer_realm: client_env.get_server_realm()?,
+        crypto: client_env.get_crypto(*session_key.keytype())?,
+        checksum: client_env.get_checksum_hash(MIC_CHECKSUM_TYPE)?,
+        key: session_key,
+        acceptor_subkey: false,
+        send_seq_number: seq_number,
+        received: SequenceWindow::new(seq_number),
+        pending: mutual.then_some(authenticator),
+        session: None,
+    };
+    Ok((context, frame(TOK_ID_AP_REQ, &ap_req)))
+}
+
+impl SecurityContext<'_> {
+    /// Complete the context of the initiator with the AP-REP token of the acceptor, taking on
+    /// the subkey and sequence number it chose
+    pub fn continue_init_sec_context(
+        &mut self,
+        client_env: &impl ClientEnv,
+        token: &[u8],
+    ) -> Result<(), GssError> {
+        let authenticator = self
+            .pending
+            .as_ref()
+            .ok_or(GssError::Failure("Context already established".to_string()))?;
+        let (tok_id, inner) = unframe(token)?;
+        match tok_id {
+            TOK_ID_AP_REP => {}
+            TOK_ID_KRB_ERROR => return Err(rejection(inner)),
+            _ => return Err(GssError::DefectiveToken),
+        }
+        let ap_rep = ApRep::from_der(inner).or(Err(GssError::DefectiveToken))?;
+        let ap_rep_part = verify_ap_reply(client_env, &ap_rep, authenticator)?;
+
+        if let Some(subkey) = ap_rep_part.subkey() {
+            self.crypto = client_env.get_crypto(*subkey.keytype())?;
+            self.key = subkey.clone();
+            self.acceptor_subkey = true;
+        }
+        if let Some(seq_number) = ap_rep_part.seq_number() {
+            self.received = SequenceWindow::new(*seq_number as u64);
+        }
+        self.pending = None;
+        Ok(())
+    }
+}
+
+/// Accept the context the initiator started with its AP-REQ `token`, giving back the AP-REP
+/// token to reply with when it asked for mutual authentication. When the AP-REQ is rejected,
+/// the KRB-ERROR the error carries is sent back as the token of [error_token]. The initiator
+/// must have bound the context to `channel_bindings` if given
+pub async fn accept_sec_context<'a, C, S, CAS, K>(
+    service: &'a ApplicationAuthenticationService<'_, C, S, CAS, K>,
+    token: &[u8],
+    channel_bindings: Option<&ChannelBindings>,
+) -> Result<(SecurityContext<'a>, Option<Vec<u8>>), GssError>
+where
+    C: ApReplayCache,
+    S: UserSessionStorage,
+    CAS: ClientAddressStorage,
+    K: ServiceKeyStore,
+{
+    let ap_req = read_ap_req(token)?;
+    let accepted = service.accept_ap_req(ap_req).await.map_err(|e| match e {
+        ServerError::ProtocolError(error) => GssError::Rejected(error),
+        ServerError::Internal => GssError::Failure("AP-REQ could not be handled".to_string()),
+    })?;
+
+    let cksum = accepted
+        .authenticator
+        .cksum()
+        .filter(|cksum| *cksum.cksumtype() == GSS_CHECKSUM_TYPE)
+        .ok_or(GssError::DefectiveToken)?;
+    let (bindings_hash, flags) = read_gss_checksum(cksum.checksum().as_bytes())?;
+    if channel_bindings.is_some_and(|bindings| bindings.hash() != bindings_hash) {
+        return Err(GssError::BadBindings);
+    }
+    // The acceptor replies when the AP-REQ requires it, whatever the flags say
+    let flags = match accepted.reply {
+        Some(_) => supported(flags).with(ContextFlag::Mutual),
+        None => supported(flags).without(ContextFlag::Mutual),
+    };
+
+    let session = accepted.session;
+    let (key, acceptor_subkey) = match &session.subkey {
+        Some(subkey) => (subkey.clone(), true),
+        None => (
+            accepted
+                .authenticator
+                .subkey()
+                .unwrap_or(&session.session_key)
+                .clone(),
+            false,
+        ),
+    };
+    let crypto = service
+        .get_crypto(*key.keytype())
+        .ok_or(GssError::Failure("Unsupported encryption type".to_string()))?;
+    let checksum = service
+        .get_checksum(MIC_CHECKSUM_TYPE)
+        .ok_or(GssError::Failure("Unsupported checksum type".to_string()))?;
+    let received = session.sequence_number as u32 as u64;
+    let reply = accepted
+        .reply
+        .map(|ap_rep| ap_rep.to_der().map(|ap_rep| frame(TOK_ID_AP_REP, &ap_rep)))
+        .transpose()
+        .map_err(|e| GssError::Failure(e.to_string()))?;
+
+    let context = SecurityContext {
+        is_initiator: false,
+        flags,
+        peer_name: session.cname.clone(),
+        peer_realm: session.crealm.clone(),
+        key,
+        acceptor_subkey,
+        crypto: Box::new(crypto),
+        checksum: Box::new(checksum),
+        send_seq_number: session.server_sequence_number.map_or(received, u64::from),
+        received: SequenceWindow::new(received),
+        pending: None,
+        session: Some(session),
+    };
+    Ok((context, reply))
+}
+
+/// AP-REQ of the first token of an initiator
+pub(super) fn read_ap_req(token: &[u8]) -> Result<ApReq, GssError> {
+    let (tok_id, inner) = unframe(token)?;
+    if tok_id != TOK_ID_AP_REQ {
+        return Err(GssError::DefectiveToken);
+    }
+    ApReq::from_der(inner).or(Err(GssError::DefectiveToken))
+}
+
+/// Error of the inner token of a KRB-ERROR token
+pub(super) fn rejection(inner: &[u8]) -> GssError {
+    match KrbErrorMsg::from_der(inner) {
+        Ok(error) => GssError::Rejected(Box::new(error)),
+        Err(_) => GssError::DefectiveToken,
+    }
+}
+
+/// Token of the KRB-ERROR an acceptor rejected the AP-REQ of the initiator with
+pub fn error_token(error: &KrbErrorMsg) -> Result<Vec<u8>, GssError> {
+    let error = error
+        .to_der()
+        .map_err(|e| GssError::Failure(e.to_string()))?;
+    Ok(frame(TOK_ID_KRB_ERROR, &error))
+}
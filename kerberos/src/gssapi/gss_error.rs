@@ -0,0 +1,65 @@
+use crate::client::client_env_error::ClientEnvError;
+use crate::client::client_error::ClientError;
+use messages::KrbErrorMsg;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+/// Failure of a GSS-API call, named after the major status it stands for,
+/// https://www.rfc-editor.org/rfc/rfc2743#section-1.2.1.1
+#[derive(Debug)]
+pub enum GssError {
+    /// GSS_S_BAD_MECH, the token is for another mechanism than Kerberos V5
+    BadMech,
+    /// GSS_S_DEFECTIVE_TOKEN
+    DefectiveToken,
+    /// GSS_S_BAD_SIG, the token was modified, sent with another key or reflected to its sender
+    BadSig,
+    /// GSS_S_BAD_BINDINGS, the initiator bound the context to another channel
+    BadBindings,
+    /// GSS_S_NO_CONTEXT, the context is not established yet
+    NoContext,
+    /// GSS_S_DUPLICATE_TOKEN
+    DuplicateToken,
+    /// GSS_S_OLD_TOKEN, the token is too old to tell whether it is a duplicate
+    OldToken,
+    /// GSS_S_UNSEQ_TOKEN, a later token was already received
+    UnseqToken,
+    /// The acceptor rejected the AP-REQ with this error, which is sent back to the initiator
+    Rejected(Box<KrbErrorMsg>),
+    /// The initiator could not authenticate to the acceptor
+    ClientError(ClientError),
+    /// GSS_S_FAILURE
+    Failure(String),
+}
+
+impl Display for GssError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GssError::BadMech => write!(f, "Unsupported mechanism"),
+            GssError::DefectiveToken => write!(f, "Defective token"),
+            GssError::BadSig => write!(f, "Invalid token signature"),
+            GssError::BadBindings => write!(f, "Channel bindings mismatch"),
+            GssError::NoContext => write!(f, "Context not established"),
+            GssError::DuplicateToken => write!(f, "Duplicate token"),
+            GssError::OldToken => write!(f, "Token too old"),
+            GssError::UnseqToken => write!(f, "Token out of sequence"),
+            GssError::Rejected(e) => write!(f, "Rejected by the acceptor: {:?}", e.error_code()),
+            GssError::ClientError(e) => write!(f, "ClientError: {}", e),
+            GssError::Failure(e) => write!(f, "Failure: {}", e),
+        }
+    }
+}
+
+impl Error for GssError {}
+
+impl From<ClientError> for GssError {
+    fn from(e: ClientError) -> Self {
+        GssError::ClientError(e)
+    }
+}
+
+impl From<ClientEnvError> for GssError {
+    fn from(e: ClientEnvError) -> Self {
+        GssError::ClientError(e.into())
+    }
+}
@@ -0,0 +1,165 @@
+//! Kerberos V5 mechanism of the GSS-API, https://www.rfc-editor.org/rfc/rfc4121. The initiator
+//! and the acceptor establish a context through `init_sec_context` and `accept_sec_context`,
+//! exchanging the AP-REQ and AP-REP in framed tokens, then protect their messages with it.
+//! The same exchange can go through SPNEGO, as HTTP Negotiate authentication does.
+
+#[cfg(test)]
+mod tests;
+
+mod context;
+pub mod gss_error;
+mod per_message;
+mod sequence;
+mod spnego;
+mod token;
+
+pub use context::{accept_sec_context, error_token, init_sec_context};
+pub use gss_error::GssError;
+pub use spnego::{
+    accept_spnego_context, init_spnego_context, read_spnego_ap_req, reject_spnego_token,
+};
+pub use token::KRB5_MECH_OID;
+
+use crate::cryptographic_hash::CryptographicHash;
+use crate::cryptography::Cryptography;
+use crate::gssapi::sequence::SequenceWindow;
+use crate::service_traits::UserSessionEntry;
+use md5::{Digest, Md5};
+use messages::basic_types::{EncryptionKey, PrincipalName, Realm};
+use messages::Authenticator;
+
+/// Checksum of the MIC and integrity-only Wrap tokens, keyed with the key of the context.
+/// RFC 4121 derives it from the encryption type of the key, which the encryptions of this
+/// crate leave undefined
+const MIC_CHECKSUM_TYPE: i32 = 1;
+
+/// Service a context provides, valued as its bit of the context flags,
+/// https://www.rfc-editor.org/rfc/rfc2744#section-5.19
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextFlag {
+    /// Credentials delegated to the acceptor, which this mechanism does not do
+    Deleg = 0x1,
+    /// The acceptor authenticates to the initiator too
+    Mutual = 0x2,
+    /// Duplicate tokens are rejected
+    Replay = 0x4,
+    /// Tokens received out of sequence are rejected
+    Sequence = 0x8,
+    Conf = 0x10,
+    Integ = 0x20,
+}
+
+/// Set of the context flags, none being set by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextFlags(u32);
+
+impl ContextFlags {
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn with(self, flag: ContextFlag) -> Self {
+        Self(self.0 | flag as u32)
+    }
+
+    pub fn without(self, flag: ContextFlag) -> Self {
+        Self(self.0 & !(flag as u32))
+    }
+
+    pub fn has(&self, flag: ContextFlag) -> bool {
+        self.0 & flag as u32 != 0
+    }
+}
+
+/// Channel the context is bound to, which both ends must agree on,
+/// https://www.rfc-editor.org/rfc/rfc2744#section-3.11
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelBindings {
+    pub initiator_addrtype: u32,
+    pub initiator_address: Vec<u8>,
+    pub acceptor_addrtype: u32,
+    pub acceptor_address: Vec<u8>,
+    pub application_data: Vec<u8>,
+}
+
+impl ChannelBindings {
+    /// Bindings of the channel `application_data` tells apart, e.g. a TLS one by
+    /// `tls-server-end-point:` and the hash of the certificate of the server,
+    /// https://www.rfc-editor.org/rfc/rfc5929
+    pub fn new(application_data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            application_data: application_data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Hash of the bindings the initiator sends in the checksum of its AP-REQ,
+    /// https://www.rfc-editor.org/rfc/rfc4121#section-4.1.1.2
+    fn hash(&self) -> [u8; 16] {
+        let mut hasher = Md5::new();
+        for (addrtype, address) in [
+            (self.initiator_addrtype, &self.initiator_address),
+            (self.acceptor_addrtype, &self.acceptor_address),
+        ] {
+            hasher.update(addrtype.to_le_bytes());
+            hasher.update((address.len() as u32).to_le_bytes());
+            hasher.update(address);
+        }
+        hasher.update((self.application_data.len() as u32).to_le_bytes());
+        hasher.update(&self.application_data);
+        hasher.finalize().into()
+    }
+}
+
+/// Context established between an initiator and an acceptor, which their messages are
+/// protected with. The acceptor's borrows the encryption and checksums of its service
+pub struct SecurityContext<'a> {
+    is_initiator: bool,
+    flags: ContextFlags,
+    peer_name: PrincipalName,
+    peer_realm: Realm,
+    /// Subkey of the acceptor if it sent one, else the subkey of the initiator if it sent one,
+    /// else the session key of the ticket
+    key: EncryptionKey,
+    acceptor_subkey: bool,
+    crypto: Box<dyn Cryptography + 'a>,
+    checksum: Box<dyn CryptographicHash + 'a>,
+    send_seq_number: u64,
+    received: SequenceWindow,
+    /// Authenticator of the initiator, until the AP-REP it asked for is received
+    pending: Option<Authenticator>,
+    /// Session the acceptor negotiated with the initiator
+    session: Option<UserSessionEntry>,
+}
+
+impl SecurityContext<'_> {
+    /// Whether messages can be protected, which is once the AP-REP is received when the
+    /// initiator asked for mutual authentication, and at once otherwise
+    pub fn is_established(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    pub fn is_initiator(&self) -> bool {
+        self.is_initiator
+    }
+
+    /// Services the context provides, confidentiality and integrity being always available
+    pub fn flags(&self) -> ContextFlags {
+        self.flags
+    }
+
+    /// The acceptor for the initiator, and the authenticated initiator for the acceptor
+    pub fn peer_name(&self) -> (&PrincipalName, &Realm) {
+        (&self.peer_name, &self.peer_realm)
+    }
+
+    /// Session the acceptor negotiated with the initiator, with the authorization data of
+    /// its ticket
+    pub fn session(&self) -> Option<&UserSessionEntry> {
+        self.session.as_ref()
+    }
+}
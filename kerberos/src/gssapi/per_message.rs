@@ -0,0 +1,158 @@
+use crate::gssapi::sequence::Order;
+use crate::gssapi::token::{
+    mic_header, read_header, wrap_header, ACCEPTOR_SUBKEY, HEADER_LEN, SEALED, SENT_BY_ACCEPTOR,
+    TOK_ID_MIC, TOK_ID_WRAP,
+};
+use crate::gssapi::{ContextFlag, GssError, SecurityContext};
+
+impl SecurityContext<'_> {
+    /// MIC token the peer checks `message` against, https://www.rfc-editor.org/rfc/rfc4121#section-4.2.6.1
+    pub fn get_mic(&mut self, message: &[u8]) -> Result<Vec<u8>, GssError> {
+        self.verify_established()?;
+        let header = mic_header(self.token_flags(false), self.next_seq_number());
+        let mut token = header.to_vec();
+        token.extend(self.sign(message, &header));
+        Ok(token)
+    }
+
+    /// Check `token` is the MIC of `message` the peer sent, and that it is neither replayed nor,
+    /// when the context asks for it, out of sequence
+    pub fn verify_mic(&mut self, message: &[u8], token: &[u8]) -> Result<(), GssError> {
+        self.verify_established()?;
+        let (header, cksum) = read_header(token, TOK_ID_MIC)?;
+        self.verify_token_flags(header.flags)?;
+        if self.sign(message, &token[..HEADER_LEN]) != cksum {
+            return Err(GssError::BadSig);
+        }
+        self.verify_seq_number(header.seq_number)
+    }
+
+    /// Wrap token carrying `message`, encrypted when `confidential` and signed otherwise,
+    /// https://www.rfc-editor.org/rfc/rfc4121#section-4.2.6.2
+    pub fn wrap(&mut self, message: &[u8], confidential: bool) -> Result<Vec<u8>, GssError> {
+        self.verify_established()?;
+        let flags = self.token_flags(confidential);
+        let seq_number = self.next_seq_number();
+        let header = wrap_header(flags, 0, 0, seq_number);
+        let mut token;
+        if confidential {
+            // The header is encrypted along with the message, which binds them together
+            token = header.to_vec();
+            let plaintext = [message, &header].concat();
+            token.extend(
+                self.crypto
+                    .encrypt(&plaintext, self.key.keyvalue().as_bytes())
+                    .map_err(|e| GssError::Failure(e.to_string()))?,
+            );
+        } else {
+            // The checksum is computed with the extra count and rotation set to zero
+            let cksum = self.sign(message, &header);
+            token = wrap_header(flags, cksum.len() as u16, 0, seq_number).to_vec();
+            token.extend(message);
+            token.extend(cksum);
+        }
+        Ok(token)
+    }
+
+    /// Message of a Wrap token of the peer, once checked as by `verify_mic`, along with whether
+    /// it was encrypted
+    pub fn unwrap(&mut self, token: &[u8]) -> Result<(Vec<u8>, bool), GssError> {
+        self.verify_established()?;
+        let (header, data) = read_header(token, TOK_ID_WRAP)?;
+        self.verify_token_flags(header.flags)?;
+        let mut data = data.to_vec();
+        // The peer may have rotated the trailer to the front of the data
+        if !data.is_empty() {
+            let rrc = header.rrc as usize % data.len();
+            data.rotate_left(rrc);
+        }
+        let ec = header.ec as usize;
+        let confidential = header.flags & SEALED != 0;
+        let message = if confidential {
+            let plaintext = self
+                .crypto
+                .decrypt(&data, self.key.keyvalue().as_bytes())
+                .or(Err(GssError::BadSig))?;
+            let len = plaintext
+                .len()
+                .checked_sub(HEADER_LEN + ec)
+                .ok_or(GssError::DefectiveToken)?;
+            let expected = wrap_header(header.flags, header.ec, 0, header.seq_number);
+            if plaintext[len + ec..] != expected {
+                return Err(GssError::BadSig);
+            }
+            plaintext[..len].to_vec()
+        } else {
+            let len = data.len().checked_sub(ec).ok_or(GssError::DefectiveToken)?;
+            let (message, cksum) = data.split_at(len);
+            let header = wrap_header(header.flags, 0, 0, header.seq_number);
+            if self.sign(message, &header) != cksum {
+                return Err(GssError::BadSig);
+            }
+            message.to_vec()
+        };
+        self.verify_seq_number(header.seq_number)?;
+        Ok((message, confidential))
+    }
+
+    fn verify_established(&self) -> Result<(), GssError> {
+        if !self.is_established() {
+            return Err(GssError::NoContext);
+        }
+        Ok(())
+    }
+
+    fn next_seq_number(&mut self) -> u64 {
+        let seq_number = self.send_seq_number;
+        self.send_seq_number = seq_number.wrapping_add(1);
+        seq_number
+    }
+
+    fn token_flags(&self, sealed: bool) -> u8 {
+        let mut flags = 0;
+        if !self.is_initiator {
+            flags |= SENT_BY_ACCEPTOR;
+        }
+        if sealed {
+            flags |= SEALED;
+        }
+        if self.acceptor_subkey {
+            flags |= ACCEPTOR_SUBKEY;
+        }
+        flags
+    }
+
+    /// Check a token comes from the peer, and not from this end reflected back to it, and is
+    /// protected with the key of the context
+    fn verify_token_flags(&self, flags: u8) -> Result<(), GssError> {
+        if (flags & SENT_BY_ACCEPTOR != 0) != self.is_initiator
+            || (flags & ACCEPTOR_SUBKEY != 0) != self.acceptor_subkey
+        {
+            return Err(GssError::BadSig);
+        }
+        Ok(())
+    }
+
+    fn sign(&self, message: &[u8], header: &[u8]) -> Vec<u8> {
+        self.checksum
+            .keyed_digest(self.key.keyvalue().as_bytes(), &[message, header].concat())
+    }
+
+    /// Check a token of a valid signature is expected, then remember it. Tokens following
+    /// missing ones are accepted, the missing ones being reported as out of sequence if they
+    /// come later
+    fn verify_seq_number(&mut self, seq_number: u64) -> Result<(), GssError> {
+        let detect_replay =
+            self.flags.has(ContextFlag::Replay) || self.flags.has(ContextFlag::Sequence);
+        match self.received.order(seq_number) {
+            Order::Duplicate if detect_replay => return Err(GssError::DuplicateToken),
+            Order::Old if detect_replay => return Err(GssError::OldToken),
+            Order::Unseq if self.flags.has(ContextFlag::Sequence) => {
+                return Err(GssError::UnseqToken)
+            }
+            _ => {}
+        }
+        self.received.record(seq_number);
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+/// Number of tokens before the next one expected which are remembered
+const WINDOW_SIZE: u64 = 64;
+
+/// Where a received sequence number stands against the ones received before,
+/// https://www.rfc-editor.org/rfc/rfc2743#section-1.2.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Order {
+    /// The next one expected
+    Next,
+    /// Later than the next one expected, some being missing
+    Gap,
+    /// Earlier than the next one expected, and not received yet
+    Unseq,
+    Duplicate,
+    /// Too early to tell whether it was received
+    Old,
+}
+
+/// Sequence numbers of the tokens received, for the window of the last ones before the next
+/// expected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SequenceWindow {
+    next: u64,
+    /// Bit `n` is set when `next - 1 - n` was received
+    received: u64,
+}
+
+impl SequenceWindow {
+    pub(super) fn new(initial: u64) -> Self {
+        Self {
+            next: initial,
+            received: 0,
+        }
+    }
+
+    pub(super) fn order(&self, seq_number: u64) -> Order {
+        let ahead = seq_number.wrapping_sub(self.next);
+        // Sequence numbers wrap, the ones of the upper half before `next` are earlier ones
+        if ahead < 1 << 63 {
+            return if ahead == 0 { Order::Next } else { Order::Gap };
+        }
+        let behind = self.next.wrapping_sub(seq_number);
+        if behind > WINDOW_SIZE {
+            Order::Old
+        } else if self.received & (1 << (behind - 1)) != 0 {
+            Order::Duplicate
+        } else {
+            Order::Unseq
+        }
+    }
+
+    /// Remember `seq_number` was received, moving the window on past it when it is later than
+    /// the ones before
+    pub(super) fn record(&mut self, seq_number: u64) {
+        match self.order(seq_number) {
+            Order::Next | Order::Gap => {
+                let shift = seq_number.wrapping_sub(self.next) + 1;
+                let earlier = if shift >= WINDOW_SIZE {
+                    0
+                } else {
+                    self.received << shift
+                };
+                self.received = earlier | 1;
+                self.next = seq_number.wrapping_add(1);
+            }
+            Order::Unseq => {
+                self.received |= 1 << (self.next.wrapping_sub(seq_number) - 1);
+            }
+            Order::Duplicate | Order::Old => {}
+        }
+    }
+}
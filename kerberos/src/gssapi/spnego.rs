@@ -0,0 +1,140 @@
+use crate::application_authentication_service::ApplicationAuthenticationService;
+use crate::client::client_env::ClientEnv;
+use crate::gssapi::context::{read_ap_req, rejection};
+use crate::gssapi::token::{unframe, TOK_ID_KRB_ERROR};
+use crate::gssapi::{
+    accept_sec_context, error_token, init_sec_context, ChannelBindings, ContextFlags, GssError,
+    SecurityContext,
+};
+use crate::service_traits::{
+    ApReplayCache, ClientAddressStorage, ServiceKeyStore, UserSessionStorage,
+};
+use messages::basic_types::OctetString;
+use messages::{
+    ApReq, Decode, Encode, InitialContextToken, MechType, NegState, NegTokenInit, NegTokenResp,
+    NegotiationToken, KRB5_OID, MS_KRB5_OID, SPNEGO_OID,
+};
+
+/// Whether `mech` is the Kerberos V5 mechanism, under either of its names
+fn is_krb5(mech: &MechType) -> bool {
+    *mech == KRB5_OID || *mech == MS_KRB5_OID
+}
+
+fn octet_string(bytes: Vec<u8>) -> Result<OctetString, GssError> {
+    OctetString::new(bytes).map_err(|e| GssError::Failure(e.to_string()))
+}
+
+fn encode(token: NegotiationToken) -> Result<Vec<u8>, GssError> {
+    token.to_der().map_err(|e| GssError::Failure(e.to_string()))
+}
+
+/// Start a context as `init_sec_context` does, proposing the Kerberos V5 mechanism through
+/// SPNEGO, https://www.rfc-editor.org/rfc/rfc4178. The token of the acceptor is passed to
+/// [SecurityContext::continue_spnego_context]
+pub fn init_spnego_context(
+    client_env: &impl ClientEnv,
+    flags: ContextFlags,
+    channel_bindings: Option<&ChannelBindings>,
+) -> Result<(SecurityContext<'static>, Vec<u8>), GssError> {
+    let (context, token) = init_sec_context(client_env, flags, channel_bindings)?;
+    let neg_token_init = NegTokenInit::new(vec![KRB5_OID], Some(octet_string(token)?), None);
+    let token = InitialContextToken::new(neg_token_init)
+        .to_der()
+        .map_err(|e| GssError::Failure(e.to_string()))?;
+    Ok((context, token))
+}
+
+impl SecurityContext<'_> {
+    /// Complete the context of the initiator with the negotiation token of the acceptor, which
+    /// carries its AP-REP when mutual authentication was asked for
+    pub fn continue_spnego_context(
+        &mut self,
+        client_env: &impl ClientEnv,
+        token: &[u8],
+    ) -> Result<(), GssError> {
+        let NegotiationToken::NegTokenResp(resp) =
+            NegotiationToken::from_der(token).or(Err(GssError::DefectiveToken))?
+        else {
+            return Err(GssError::DefectiveToken);
+        };
+        if resp.supported_mech().is_some_and(|mech| !is_krb5(mech)) {
+            return Err(GssError::BadMech);
+        }
+        match (resp.neg_state(), resp.response_token()) {
+            // The KRB-ERROR of the acceptor, if any, tells why
+            (Some(NegState::Reject), Some(token)) => match unframe(token.as_bytes())? {
+                (TOK_ID_KRB_ERROR, inner) => Err(rejection(inner)),
+                _ => Err(GssError::DefectiveToken),
+            },
+            (Some(NegState::Reject), None) => Err(GssError::BadMech),
+            (Some(NegState::RequestMic), _) => Err(GssError::Failure(
+                "Mechanism list MIC not supported".to_string(),
+            )),
+            (_, Some(token)) => self.continue_init_sec_context(client_env, token.as_bytes()),
+            (_, None) if self.is_established() => Ok(()),
+            (_, None) => Err(GssError::DefectiveToken),
+        }
+    }
+}
+
+/// Accept the context the initiator started with its SPNEGO `token` as `accept_sec_context`
+/// does, giving back the negotiation token to reply with. Only the Kerberos V5 mechanism is
+/// accepted, and only when the initiator prefers it and sent its token at once, so that the
+/// negotiation completes in one round trip and needs no mechanism list MIC
+pub async fn accept_spnego_context<'a, C, S, CAS, K>(
+    service: &'a ApplicationAuthenticationService<'_, C, S, CAS, K>,
+    token: &[u8],
+    channel_bindings: Option<&ChannelBindings>,
+) -> Result<(SecurityContext<'a>, Vec<u8>), GssError>
+where
+    C: ApReplayCache,
+    S: UserSessionStorage,
+    CAS: ClientAddressStorage,
+    K: ServiceKeyStore,
+{
+    let (mech, mech_token) = read_mech_token(token)?;
+    let (context, reply) =
+        accept_sec_context(service, mech_token.as_bytes(), channel_bindings).await?;
+    let resp = NegTokenResp::new(
+        Some(NegState::AcceptCompleted),
+        Some(mech),
+        reply.map(octet_string).transpose()?,
+        None,
+    );
+    Ok((context, encode(NegotiationToken::NegTokenResp(resp))?))
+}
+
+/// AP-REQ of the SPNEGO token of an initiator, e.g. for the acceptor to record where it came
+/// from before accepting it
+pub fn read_spnego_ap_req(token: &[u8]) -> Result<ApReq, GssError> {
+    read_ap_req(read_mech_token(token)?.1.as_bytes())
+}
+
+/// Kerberos V5 mechanism the initiator named first, along with its token
+fn read_mech_token(token: &[u8]) -> Result<(MechType, OctetString), GssError> {
+    let token = InitialContextToken::from_der(token).or(Err(GssError::DefectiveToken))?;
+    if *token.this_mech() != SPNEGO_OID {
+        return Err(GssError::BadMech);
+    }
+    let NegotiationToken::NegTokenInit(init) = token.inner_context_token() else {
+        return Err(GssError::DefectiveToken);
+    };
+    let mech = init
+        .mech_types()
+        .first()
+        .filter(|mech| is_krb5(mech))
+        .ok_or(GssError::BadMech)?;
+    let mech_token = init.mech_token().ok_or(GssError::BadMech)?;
+    Ok((*mech, mech_token.clone()))
+}
+
+/// Negotiation token rejecting the context of the initiator, carrying the KRB-ERROR the
+/// AP-REQ was rejected with if so
+pub fn reject_spnego_token(error: &GssError) -> Result<Vec<u8>, GssError> {
+    let response_token = match error {
+        GssError::Rejected(error) => Some(octet_string(error_token(error)?)?),
+        _ => None,
+    };
+    let resp = NegTokenResp::new(Some(NegState::Reject), None, response_token, None);
+    encode(NegotiationToken::NegTokenResp(resp))
+}
@@ -0,0 +1,165 @@
+use crate::gssapi::sequence::{Order, SequenceWindow};
+use crate::gssapi::token::{frame, unframe, TOK_ID_AP_REQ};
+use crate::gssapi::{ContextFlag, ContextFlags, GssError, SecurityContext};
+use crate::{AesGcm, Sha1};
+use messages::basic_types::{
+    EncryptionKey, KerberosString, NameTypes, OctetString, PrincipalName, Realm,
+};
+
+/// Initiator and acceptor of an established context whose tokens are numbered from `seq_number`
+/// both ways
+fn ends(
+    flags: ContextFlags,
+    seq_number: u64,
+) -> (SecurityContext<'static>, SecurityContext<'static>) {
+    let end = |is_initiator| SecurityContext {
+        is_initiator,
+        flags: flags.with(ContextFlag::Conf).with(ContextFlag::Integ),
+        peer_name: PrincipalName::new(
+            NameTypes::NtPrincipal,
+            [KerberosString::new("peer").unwrap()],
+        )
+        .unwrap(),
+        peer_realm: Realm::new("EXAMPLE.COM").unwrap(),
+        key: EncryptionKey::new(1, OctetString::new([7; 32]).unwrap()),
+        acceptor_subkey: false,
+        crypto: Box::new(AesGcm::new()),
+        checksum: Box::new(Sha1::new()),
+        send_seq_number: seq_number,
+        received: SequenceWindow::new(seq_number),
+        pending: None,
+        session: None,
+    };
+    (end(true), end(false))
+}
+
+fn replay_and_sequence() -> ContextFlags {
+    ContextFlags::default()
+        .with(ContextFlag::Replay)
+        .with(ContextFlag::Sequence)
+}
+
+#[test]
+fn framed_token_should_be_unframed() {
+    let inner = vec![0xab; 300];
+    let token = frame(TOK_ID_AP_REQ, &inner);
+
+    assert_eq!(token[..4], [0x60, 0x82, 0x01, 0x39]);
+    let (tok_id, unframed) = unframe(&token).unwrap();
+    assert_eq!(tok_id, TOK_ID_AP_REQ);
+    assert_eq!(unframed, inner);
+}
+
+#[test]
+fn token_of_another_mechanism_should_be_rejected() {
+    let mut token = frame(TOK_ID_AP_REQ, b"inner");
+    // SPNEGO, 1.3.6.1.5.5.2
+    token[3..11].copy_from_slice(&[0x06, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x02, 0x01]);
+    assert!(matches!(unframe(&token), Err(GssError::BadMech)));
+    assert!(matches!(
+        unframe(&token[..token.len() - 1]),
+        Err(GssError::DefectiveToken)
+    ));
+}
+
+#[test]
+fn sequence_window_should_tell_where_tokens_stand() {
+    let mut window = SequenceWindow::new(u64::MAX - 1);
+    assert_eq!(window.order(u64::MAX - 1), Order::Next);
+    window.record(u64::MAX - 1);
+    // Sequence numbers wrap
+    assert_eq!(window.order(1), Order::Gap);
+    window.record(1);
+
+    assert_eq!(window.order(u64::MAX - 1), Order::Duplicate);
+    assert_eq!(window.order(1), Order::Duplicate);
+    assert_eq!(window.order(0), Order::Unseq);
+    window.record(0);
+    assert_eq!(window.order(0), Order::Duplicate);
+    assert_eq!(window.order(u64::MAX), Order::Unseq);
+    assert_eq!(window.order(2), Order::Next);
+
+    window.record(100);
+    assert_eq!(window.order(37), Order::Unseq);
+    assert_eq!(window.order(36), Order::Old);
+}
+
+#[test]
+fn wrapped_messages_should_be_unwrapped_by_the_peer() {
+    let (mut initiator, mut acceptor) = ends(replay_and_sequence(), 1000);
+
+    let sealed = initiator.wrap(b"secret", true).unwrap();
+    assert!(!sealed.windows(6).any(|w| w == b"secret"));
+    assert_eq!(
+        acceptor.unwrap(&sealed).unwrap(),
+        (b"secret".to_vec(), true)
+    );
+
+    let signed = acceptor.wrap(b"public", false).unwrap();
+    assert_eq!(
+        initiator.unwrap(&signed).unwrap(),
+        (b"public".to_vec(), false)
+    );
+
+    let mic = initiator.get_mic(b"message").unwrap();
+    acceptor.verify_mic(b"message", &mic).unwrap();
+}
+
+#[test]
+fn rotated_wrap_token_should_be_unwrapped() {
+    let (mut initiator, mut acceptor) = ends(ContextFlags::default(), 0);
+
+    let mut token = initiator.wrap(b"rotated", false).unwrap();
+    // Move the 20 bytes of the checksum in front of the message, as a peer may
+    token[16..].rotate_right(20);
+    token[6..8].copy_from_slice(&20u16.to_be_bytes());
+    assert_eq!(
+        acceptor.unwrap(&token).unwrap(),
+        (b"rotated".to_vec(), false)
+    );
+}
+
+#[test]
+fn modified_or_reflected_tokens_should_be_rejected() {
+    let (mut initiator, mut acceptor) = ends(ContextFlags::default(), 0);
+
+    let mic = initiator.get_mic(b"message").unwrap();
+    assert!(matches!(
+        acceptor.verify_mic(b"massage", &mic),
+        Err(GssError::BadSig)
+    ));
+    assert!(matches!(
+        initiator.verify_mic(b"message", &mic),
+        Err(GssError::BadSig)
+    ));
+
+    let mut sealed = initiator.wrap(b"secret", true).unwrap();
+    let last = sealed.len() - 1;
+    sealed[last] ^= 1;
+    assert!(matches!(acceptor.unwrap(&sealed), Err(GssError::BadSig)));
+}
+
+#[test]
+fn tokens_should_be_checked_against_the_sequence_flags() {
+    let (mut initiator, mut acceptor) = ends(replay_and_sequence(), 0);
+    let first = initiator.get_mic(b"first").unwrap();
+    let second = initiator.get_mic(b"second").unwrap();
+
+    acceptor.verify_mic(b"second", &second).unwrap();
+    assert!(matches!(
+        acceptor.verify_mic(b"second", &second),
+        Err(GssError::DuplicateToken)
+    ));
+    assert!(matches!(
+        acceptor.verify_mic(b"first", &first),
+        Err(GssError::UnseqToken)
+    ));
+
+    // Without the flags, tokens are accepted whatever their order
+    let (mut initiator, mut acceptor) = ends(ContextFlags::default(), 0);
+    let first = initiator.get_mic(b"first").unwrap();
+    let second = initiator.get_mic(b"second").unwrap();
+    acceptor.verify_mic(b"second", &second).unwrap();
+    acceptor.verify_mic(b"first", &first).unwrap();
+    acceptor.verify_mic(b"first", &first).unwrap();
+}
@@ -0,0 +1,168 @@
+use crate::gssapi::gss_error::GssError;
+
+/// DER encoding of the object identifier of the Kerberos V5 mechanism, 1.2.840.113554.1.2.2
+pub const KRB5_MECH_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x02];
+
+/// Identifiers of the tokens of the mechanism, https://www.rfc-editor.org/rfc/rfc4121#section-4.1
+pub(super) const TOK_ID_AP_REQ: [u8; 2] = [0x01, 0x00];
+pub(super) const TOK_ID_AP_REP: [u8; 2] = [0x02, 0x00];
+pub(super) const TOK_ID_KRB_ERROR: [u8; 2] = [0x03, 0x00];
+pub(super) const TOK_ID_MIC: [u8; 2] = [0x04, 0x04];
+pub(super) const TOK_ID_WRAP: [u8; 2] = [0x05, 0x04];
+
+/// Type of the checksum of the authenticator of an AP-REQ token, which carries the context
+/// flags and channel bindings, https://www.rfc-editor.org/rfc/rfc4121#section-4.1.1
+pub(super) const GSS_CHECKSUM_TYPE: i32 = 0x8003;
+
+/// Flags of the MIC and Wrap tokens, https://www.rfc-editor.org/rfc/rfc4121#section-4.2.2
+pub(super) const SENT_BY_ACCEPTOR: u8 = 0x01;
+pub(super) const SEALED: u8 = 0x02;
+pub(super) const ACCEPTOR_SUBKEY: u8 = 0x04;
+
+/// Length of the header of the MIC and Wrap tokens
+pub(super) const HEADER_LEN: usize = 16;
+
+/// Token of the context establishment, `inner` framed with the mechanism it is for,
+/// https://www.rfc-editor.org/rfc/rfc2743#section-3.1
+pub(super) fn frame(tok_id: [u8; 2], inner: &[u8]) -> Vec<u8> {
+    let content_len = 2 + KRB5_MECH_OID.len() + tok_id.len() + inner.len();
+    let mut token = vec![0x60];
+    token.extend(der_length(content_len));
+    token.extend([0x06, KRB5_MECH_OID.len() as u8]);
+    token.extend(KRB5_MECH_OID);
+    token.extend(tok_id);
+    token.extend(inner);
+    token
+}
+
+/// Token identifier and inner token of a framed context establishment token
+pub(super) fn unframe(token: &[u8]) -> Result<([u8; 2], &[u8]), GssError> {
+    let (&tag, rest) = token.split_first().ok_or(GssError::DefectiveToken)?;
+    if tag != 0x60 {
+        return Err(GssError::DefectiveToken);
+    }
+    let (content_len, rest) = read_der_length(rest)?;
+    if content_len != rest.len() {
+        return Err(GssError::DefectiveToken);
+    }
+    let (oid, rest) = match rest {
+        [0x06, oid_len, rest @ ..] if rest.len() >= *oid_len as usize => {
+            rest.split_at(*oid_len as usize)
+        }
+        _ => return Err(GssError::DefectiveToken),
+    };
+    if oid != KRB5_MECH_OID {
+        return Err(GssError::BadMech);
+    }
+    match rest {
+        [first, second, inner @ ..] => Ok(([*first, *second], inner)),
+        _ => Err(GssError::DefectiveToken),
+    }
+}
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+    let bytes = len.to_be_bytes();
+    let significant = &bytes[bytes
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or(bytes.len() - 1)..];
+    let mut encoded = vec![0x80 | significant.len() as u8];
+    encoded.extend(significant);
+    encoded
+}
+
+fn read_der_length(data: &[u8]) -> Result<(usize, &[u8]), GssError> {
+    let (&first, rest) = data.split_first().ok_or(GssError::DefectiveToken)?;
+    if first < 0x80 {
+        return Ok((first as usize, rest));
+    }
+    let count = (first & 0x7f) as usize;
+    if count == 0 || count > size_of::<usize>() || rest.len() < count {
+        return Err(GssError::DefectiveToken);
+    }
+    let (bytes, rest) = rest.split_at(count);
+    let len = bytes
+        .iter()
+        .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+    Ok((len, rest))
+}
+
+/// Header of a MIC token, https://www.rfc-editor.org/rfc/rfc4121#section-4.2.6.1
+pub(super) fn mic_header(flags: u8, seq_number: u64) -> [u8; HEADER_LEN] {
+    let mut header = [0xff; HEADER_LEN];
+    header[..2].copy_from_slice(&TOK_ID_MIC);
+    header[2] = flags;
+    header[8..].copy_from_slice(&seq_number.to_be_bytes());
+    header
+}
+
+/// Header of a Wrap token, https://www.rfc-editor.org/rfc/rfc4121#section-4.2.6.2
+pub(super) fn wrap_header(flags: u8, ec: u16, rrc: u16, seq_number: u64) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..2].copy_from_slice(&TOK_ID_WRAP);
+    header[2] = flags;
+    header[3] = 0xff;
+    header[4..6].copy_from_slice(&ec.to_be_bytes());
+    header[6..8].copy_from_slice(&rrc.to_be_bytes());
+    header[8..].copy_from_slice(&seq_number.to_be_bytes());
+    header
+}
+
+/// Fields of the header of a MIC or Wrap token
+pub(super) struct Header {
+    pub flags: u8,
+    /// Extra count of a Wrap token
+    pub ec: u16,
+    /// Right rotation count of a Wrap token
+    pub rrc: u16,
+    pub seq_number: u64,
+}
+
+/// Header of a MIC or Wrap token, once checked it is one of `tok_id`, along with the rest of it
+pub(super) fn read_header(token: &[u8], tok_id: [u8; 2]) -> Result<(Header, &[u8]), GssError> {
+    if token.len() < HEADER_LEN || token[..2] != tok_id {
+        return Err(GssError::DefectiveToken);
+    }
+    let (header, rest) = token.split_at(HEADER_LEN);
+    let u16_at = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]);
+    let header = match tok_id {
+        TOK_ID_MIC if header[3..8].iter().all(|b| *b == 0xff) => Header {
+            flags: header[2],
+            ec: 0,
+            rrc: 0,
+            seq_number: u64::from_be_bytes(header[8..].try_into().expect("8 bytes")),
+        },
+        TOK_ID_WRAP if header[3] == 0xff => Header {
+            flags: header[2],
+            ec: u16_at(4),
+            rrc: u16_at(6),
+            seq_number: u64::from_be_bytes(header[8..].try_into().expect("8 bytes")),
+        },
+        _ => return Err(GssError::DefectiveToken),
+    };
+    Ok((header, rest))
+}
+
+/// Checksum of the authenticator of an AP-REQ token: the hash of the channel bindings, zero
+/// when there are none, and the context flags the initiator asks for
+pub(super) fn gss_checksum(bindings_hash: [u8; 16], flags: u32) -> Vec<u8> {
+    let mut checksum = Vec::with_capacity(24);
+    checksum.extend(16u32.to_le_bytes());
+    checksum.extend(bindings_hash);
+    checksum.extend(flags.to_le_bytes());
+    checksum
+}
+
+/// Hash of the channel bindings and context flags of the checksum of an AP-REQ token. The
+/// credentials the initiator may delegate in it are left out
+pub(super) fn read_gss_checksum(checksum: &[u8]) -> Result<([u8; 16], u32), GssError> {
+    if checksum.len() < 24 || checksum[..4] != 16u32.to_le_bytes() {
+        return Err(GssError::DefectiveToken);
+    }
+    let bindings_hash = checksum[4..20].try_into().expect("16 bytes");
+    let flags = u32::from_le_bytes(checksum[20..24].try_into().expect("4 bytes"));
+    Ok((bindings_hash, flags))
+}
@@ -0,0 +1,164 @@
+// MIT keytab file format, version 0x0502, as written by `ktutil`: a version, then entries
+// each prefixed by their size, a negative size marking a hole left by a removed entry.
+// Integers are big-endian,
+// https://web.mit.edu/kerberos/krb5-latest/doc/formats/keytab_file_format.html
+
+use std::fmt::{Display, Formatter};
+
+use messages::basic_types::{
+    EncryptionKey, KerberosString, KerberosTime, NameTypes, OctetString, PrincipalName,
+};
+
+use super::KeytabEntry;
+use crate::file_format::{Reader, Truncated};
+
+pub(super) const KEYTAB_VERSION: u16 = 0x0502;
+
+/// Why a keytab could not be read or written
+#[derive(Debug)]
+pub enum KeytabError {
+    Io(std::io::Error),
+    /// Only version 0x0502 keytabs are supported
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of an entry
+    Truncated,
+    /// A realm or name component which is not an IA5 string
+    InvalidString,
+    /// A name type which is not one of RFC4120
+    InvalidNameType(u32),
+    /// An entry with no name component
+    EmptyName,
+    /// A value which does not fit the size the format allows for it
+    TooLong,
+}
+
+impl Display for KeytabError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "keytab could not be accessed: {}", e),
+            Self::UnsupportedVersion(v) => write!(f, "keytab version {:#06x} is not supported", v),
+            Self::Truncated => f.write_str("keytab is truncated"),
+            Self::InvalidString => f.write_str("keytab holds a string which is not IA5"),
+            Self::InvalidNameType(t) => write!(f, "keytab holds the unknown name type {}", t),
+            Self::EmptyName => f.write_str("keytab holds an entry without name"),
+            Self::TooLong => f.write_str("keytab entry is too long"),
+        }
+    }
+}
+
+impl std::error::Error for KeytabError {}
+
+impl From<std::io::Error> for KeytabError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<Truncated> for KeytabError {
+    fn from(_: Truncated) -> Self {
+        Self::Truncated
+    }
+}
+
+type KeytabResult<T> = Result<T, KeytabError>;
+
+/// String prefixed by its 16-bit length
+fn counted_string(reader: &mut Reader) -> KeytabResult<KerberosString> {
+    let len = reader.u16()? as usize;
+    KerberosString::new(reader.take(len)?).map_err(|_| KeytabError::InvalidString)
+}
+
+/// Entries of a keytab, skipping the holes
+pub(super) fn read_entries(data: &[u8]) -> KeytabResult<Vec<KeytabEntry>> {
+    let mut reader = Reader::new(data);
+    let version = reader.u16()?;
+    if version != KEYTAB_VERSION {
+        return Err(KeytabError::UnsupportedVersion(version));
+    }
+    let mut entries = vec![];
+    while !reader.is_empty() {
+        let size = reader.i32()?;
+        let record = reader.take(size.unsigned_abs() as usize)?;
+        if size > 0 {
+            entries.push(read_entry(record)?);
+        }
+    }
+    Ok(entries)
+}
+
+fn read_entry(record: &[u8]) -> KeytabResult<KeytabEntry> {
+    let mut reader = Reader::new(record);
+    let num_components = reader.u16()?;
+    let realm = counted_string(&mut reader)?;
+    let components = (0..num_components)
+        .map(|_| counted_string(&mut reader))
+        .collect::<KeytabResult<Vec<_>>>()?;
+    let name_type = reader.u32()?;
+    let timestamp = reader.u32()?;
+    let vno8 = reader.u8()?;
+    let keytype = reader.u16()?;
+    let keylen = reader.u16()? as usize;
+    let key = reader.take(keylen)?;
+    // The 8-bit version only holds the low byte of versions past 255, the 32-bit one
+    // which follows since krb5 1.14 has the whole of it
+    let kvno = match reader.u32() {
+        Ok(vno32) if vno32 != 0 => vno32,
+        _ => vno8 as u32,
+    };
+
+    let name_type = i32::try_from(name_type)
+        .ok()
+        .and_then(|t| NameTypes::try_from(t).ok())
+        .ok_or(KeytabError::InvalidNameType(name_type))?;
+    Ok(KeytabEntry {
+        principal: PrincipalName::new(name_type, components).map_err(|_| KeytabError::EmptyName)?,
+        realm,
+        timestamp: KerberosTime::from_timestamp(timestamp as u64)
+            .expect("A 32-bit timestamp is a valid time"),
+        kvno,
+        key: EncryptionKey::new(
+            keytype as i32,
+            OctetString::new(key).map_err(|_| KeytabError::TooLong)?,
+        ),
+    })
+}
+
+fn put_counted(buffer: &mut Vec<u8>, data: &[u8]) -> KeytabResult<()> {
+    let len = u16::try_from(data.len()).map_err(|_| KeytabError::TooLong)?;
+    buffer.extend_from_slice(&len.to_be_bytes());
+    buffer.extend_from_slice(data);
+    Ok(())
+}
+
+/// Keytab holding `entries`, in the order given
+pub(super) fn write_entries(entries: &[KeytabEntry]) -> KeytabResult<Vec<u8>> {
+    let mut buffer = KEYTAB_VERSION.to_be_bytes().to_vec();
+    for entry in entries {
+        let record = write_entry(entry)?;
+        let size = i32::try_from(record.len()).map_err(|_| KeytabError::TooLong)?;
+        buffer.extend_from_slice(&size.to_be_bytes());
+        buffer.extend_from_slice(&record);
+    }
+    Ok(buffer)
+}
+
+fn write_entry(entry: &KeytabEntry) -> KeytabResult<Vec<u8>> {
+    let components = entry.principal.name_string();
+    let mut record = u16::try_from(components.len())
+        .map_err(|_| KeytabError::TooLong)?
+        .to_be_bytes()
+        .to_vec();
+    put_counted(&mut record, entry.realm.as_str().as_bytes())?;
+    for component in components {
+        put_counted(&mut record, component.as_str().as_bytes())?;
+    }
+    record.extend_from_slice(&(*entry.principal.name_type() as u32).to_be_bytes());
+    let timestamp = u32::try_from(entry.timestamp.timestamp()).map_err(|_| KeytabError::TooLong)?;
+    record.extend_from_slice(&timestamp.to_be_bytes());
+    record.push(entry.kvno as u8);
+    let keytype = u16::try_from(*entry.key.keytype()).map_err(|_| KeytabError::TooLong)?;
+    record.extend_from_slice(&keytype.to_be_bytes());
+    put_counted(&mut record, entry.key.keyvalue().as_bytes())?;
+    record.extend_from_slice(&entry.kvno.to_be_bytes());
+    Ok(record)
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests;
+
+mod file;
+
+pub use file::KeytabError;
+
+use crate::service_traits::ServiceKeyStore;
+use async_trait::async_trait;
+use messages::basic_types::{EncryptionKey, Int32, KerberosTime, PrincipalName, Realm, UInt32};
+use crate::file_format;
+use std::io::Write;
+use std::path::Path;
+
+/// Long-term key of a principal, as kept in a keytab
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeytabEntry {
+    pub principal: PrincipalName,
+    pub realm: Realm,
+    /// Time the key was written to the keytab
+    pub timestamp: KerberosTime,
+    pub kvno: UInt32,
+    pub key: EncryptionKey,
+}
+
+/// Keys of the services a server runs, several versions of a key being kept while it rotates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keytab {
+    entries: Vec<KeytabEntry>,
+}
+
+impl Keytab {
+    pub fn new(entries: Vec<KeytabEntry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[KeytabEntry] {
+        &self.entries
+    }
+
+    /// Keytab in the MIT file format, as written by `ktutil`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, KeytabError> {
+        file::read_entries(data).map(Self::new)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, KeytabError> {
+        file::write_entries(&self.entries)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, KeytabError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Write the keytab to `path`, which is created readable by its owner only as by `ktutil`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), KeytabError> {
+        let data = self.to_bytes()?;
+        file_format::create_owner_only(path)?.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Add `entry`, replacing the key of the same principal, version and type if any
+    pub fn add(&mut self, entry: KeytabEntry) {
+        self.entries.retain(|e| !Self::same_key(e, &entry));
+        self.entries.push(entry);
+    }
+
+    /// Remove the keys of `principal`, of version `kvno` or all of them, returning how many were
+    pub fn remove(
+        &mut self,
+        principal: &PrincipalName,
+        realm: &Realm,
+        kvno: Option<UInt32>,
+    ) -> usize {
+        let count = self.entries.len();
+        self.entries.retain(|e| {
+            e.principal.name_string() != principal.name_string()
+                || &e.realm != realm
+                || kvno.is_some_and(|kvno| e.kvno != kvno)
+        });
+        count - self.entries.len()
+    }
+
+    fn same_key(a: &KeytabEntry, b: &KeytabEntry) -> bool {
+        a.principal.name_string() == b.principal.name_string()
+            && a.realm == b.realm
+            && a.kvno == b.kvno
+            && a.key.keytype() == b.key.keytype()
+    }
+
+    /// Key of `principal` for `etype`, of version `kvno` or the latest one
+    pub fn find(
+        &self,
+        principal: &PrincipalName,
+        realm: &Realm,
+        etype: Int32,
+        kvno: Option<UInt32>,
+    ) -> Option<&KeytabEntry> {
+        self.entries
+            .iter()
+            .filter(|e| {
+                // Services are looked up by their components, whatever the name type the
+                // client chose for them
+                e.principal.name_string() == principal.name_string()
+                    && &e.realm == realm
+                    && *e.key.keytype() == etype
+            })
+            .filter(|e| kvno.is_none_or(|kvno| e.kvno == kvno))
+            .max_by_key(|e| e.kvno)
+    }
+}
+
+#[async_trait]
+impl ServiceKeyStore for Keytab {
+    async fn get_key(
+        &self,
+        sname: &PrincipalName,
+        realm: &Realm,
+        etype: Int32,
+        kvno: Option<UInt32>,
+    ) -> Option<EncryptionKey> {
+        self.find(sname, realm, etype, kvno)
+            .map(|entry| entry.key.clone())
+    }
+}
@@ -0,0 +1,227 @@
+use crate::keytab::{Keytab, KeytabEntry, KeytabError};
+use crate::service_traits::ServiceKeyStore;
+use messages::basic_types::{
+    EncryptionKey, KerberosString, KerberosTime, NameTypes, OctetString, PrincipalName, Realm,
+};
+use std::time::Duration;
+
+fn service(name_type: NameTypes) -> PrincipalName {
+    PrincipalName::new(
+        name_type,
+        [
+            KerberosString::new("HTTP").unwrap(),
+            KerberosString::new("web01.corp.example").unwrap(),
+        ],
+    )
+    .unwrap()
+}
+
+fn realm() -> Realm {
+    Realm::new("EXAMPLE.COM").unwrap()
+}
+
+fn entry(kvno: u32, etype: i32, key: u8) -> KeytabEntry {
+    KeytabEntry {
+        principal: service(NameTypes::NtSrvHst),
+        realm: realm(),
+        timestamp: KerberosTime::from_unix_duration(Duration::from_secs(1_700_000_000)).unwrap(),
+        kvno,
+        key: EncryptionKey::new(etype, OctetString::new(vec![key; 16]).unwrap()),
+    }
+}
+
+#[tokio::test]
+async fn latest_key_should_be_used_when_the_ticket_has_no_version() {
+    let keytab = Keytab::new(vec![entry(1, 1, 1), entry(3, 1, 3), entry(2, 1, 2)]);
+
+    let key = keytab
+        .get_key(&service(NameTypes::NtSrvHst), &realm(), 1, None)
+        .await;
+    assert_eq!(key, Some(entry(3, 1, 3).key));
+}
+
+#[tokio::test]
+async fn key_of_the_version_and_type_of_the_ticket_should_be_used() {
+    let keytab = Keytab::new(vec![entry(1, 1, 1), entry(2, 1, 2), entry(2, 3, 4)]);
+
+    assert_eq!(
+        keytab
+            .get_key(&service(NameTypes::NtSrvHst), &realm(), 1, Some(1))
+            .await,
+        Some(entry(1, 1, 1).key)
+    );
+    assert_eq!(
+        keytab
+            .get_key(&service(NameTypes::NtSrvHst), &realm(), 3, Some(2))
+            .await,
+        Some(entry(2, 3, 4).key)
+    );
+    assert_eq!(
+        keytab
+            .get_key(&service(NameTypes::NtSrvHst), &realm(), 3, Some(1))
+            .await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn service_should_be_found_whatever_its_name_type() {
+    let keytab = Keytab::new(vec![entry(1, 1, 1)]);
+
+    assert!(keytab
+        .get_key(&service(NameTypes::NtPrincipal), &realm(), 1, None)
+        .await
+        .is_some());
+    assert!(keytab
+        .get_key(
+            &service(NameTypes::NtSrvHst),
+            &Realm::new("EXAMPLE.ORG").unwrap(),
+            1,
+            None
+        )
+        .await
+        .is_none());
+}
+
+#[test]
+fn adding_a_key_should_replace_the_one_of_the_same_version_and_type() {
+    let mut keytab = Keytab::new(vec![entry(1, 1, 1), entry(2, 1, 2)]);
+
+    keytab.add(entry(2, 1, 5));
+
+    assert_eq!(keytab.entries(), &[entry(1, 1, 1), entry(2, 1, 5)]);
+
+    let renamed = KeytabEntry {
+        principal: service(NameTypes::NtPrincipal),
+        ..entry(2, 1, 6)
+    };
+    keytab.add(renamed.clone());
+
+    assert_eq!(keytab.entries(), &[entry(1, 1, 1), renamed]);
+}
+
+/// Keytab of `HTTP/web01.corp.example@EXAMPLE.COM` with an aes256 and an aes128 key of version 3,
+/// laid out as `ktutil` writes it
+const HTTP_KEYTAB: &[u8] = include_bytes!("../../tests/fixtures/keytab/http_web01.keytab");
+
+/// Keytab of `host/db01.corp.example@EXAMPLE.COM` with keys of versions 255 and 256, a hole left
+/// by a removed entry, and a key of version 2 written without its 32-bit version
+const ROTATED_KEYTAB: &[u8] = include_bytes!("../../tests/fixtures/keytab/rotated.keytab");
+
+fn host() -> PrincipalName {
+    PrincipalName::new(
+        NameTypes::NtSrvHst,
+        [
+            KerberosString::new("host").unwrap(),
+            KerberosString::new("db01.corp.example").unwrap(),
+        ],
+    )
+    .unwrap()
+}
+
+#[test]
+fn keytab_file_should_be_read_into_entries() {
+    let keytab = Keytab::from_bytes(HTTP_KEYTAB).unwrap();
+
+    let principal = service(NameTypes::NtPrincipal);
+    let timestamp = KerberosTime::from_timestamp(1_700_000_000).unwrap();
+    assert_eq!(
+        keytab.entries(),
+        &[
+            KeytabEntry {
+                principal: principal.clone(),
+                realm: realm(),
+                timestamp,
+                kvno: 3,
+                key: EncryptionKey::new(
+                    18,
+                    OctetString::new((0..32).collect::<Vec<u8>>()).unwrap()
+                ),
+            },
+            KeytabEntry {
+                principal,
+                realm: realm(),
+                timestamp,
+                kvno: 3,
+                key: EncryptionKey::new(
+                    17,
+                    OctetString::new((0x40..0x50).collect::<Vec<u8>>()).unwrap()
+                ),
+            },
+        ]
+    );
+}
+
+#[test]
+fn keytab_file_should_be_written_back_byte_for_byte() {
+    let keytab = Keytab::from_bytes(HTTP_KEYTAB).unwrap();
+
+    assert_eq!(keytab.to_bytes().unwrap(), HTTP_KEYTAB);
+}
+
+#[test]
+fn holes_should_be_skipped_and_versions_past_255_kept() {
+    let keytab = Keytab::from_bytes(ROTATED_KEYTAB).unwrap();
+
+    assert_eq!(
+        keytab.entries().iter().map(|e| e.kvno).collect::<Vec<_>>(),
+        vec![255, 256, 2]
+    );
+    assert!(keytab.entries().iter().all(|e| e.principal == host()));
+    assert_eq!(keytab.find(&host(), &realm(), 18, None).unwrap().kvno, 256);
+}
+
+#[test]
+fn removed_keys_should_no_longer_be_written() {
+    let mut keytab = Keytab::from_bytes(ROTATED_KEYTAB).unwrap();
+
+    assert_eq!(keytab.remove(&host(), &realm(), Some(255)), 1);
+    let keytab = Keytab::from_bytes(&keytab.to_bytes().unwrap()).unwrap();
+    assert_eq!(
+        keytab.entries().iter().map(|e| e.kvno).collect::<Vec<_>>(),
+        vec![256, 2]
+    );
+
+    let mut keytab = keytab;
+    assert_eq!(keytab.remove(&host(), &realm(), None), 2);
+    assert_eq!(keytab.to_bytes().unwrap(), vec![0x05, 0x02]);
+}
+
+#[test]
+fn keytab_should_be_saved_and_loaded() {
+    let path = std::env::temp_dir().join(format!("keytab-{}", std::process::id()));
+    let mut keytab = Keytab::default();
+    keytab.add(entry(1, 18, 1));
+    keytab.add(entry(2, 18, 2));
+
+    keytab.save(&path).unwrap();
+    let loaded = Keytab::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), keytab);
+}
+
+#[cfg(unix)]
+#[test]
+fn saved_keytab_should_only_be_readable_by_its_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("keytab-mode-{}", std::process::id()));
+    Keytab::new(vec![entry(1, 18, 1)]).save(&path).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn other_versions_and_truncated_keytabs_should_be_rejected() {
+    assert!(matches!(
+        Keytab::from_bytes(&[0x05, 0x01]),
+        Err(KeytabError::UnsupportedVersion(0x0501))
+    ));
+    assert!(matches!(
+        Keytab::from_bytes(&HTTP_KEYTAB[..HTTP_KEYTAB.len() - 1]),
+        Err(KeytabError::Truncated)
+    ));
+}
@@ -1,11 +1,18 @@
 pub mod application_authentication_service;
+pub mod application_messages;
+pub mod authorization_data;
 pub mod client;
+pub mod gssapi;
+pub mod keytab;
+pub mod pac;
 pub mod ticket_granting_service;
 pub mod cryptography;
 pub mod cryptography_error;
 pub mod authentication_service;
 pub mod service_traits;
 pub mod cryptographic_hash;
+mod file_format;
+pub mod transit_policy;
 pub mod algo;
 pub use algo::AesGcm;
 pub use algo::Sha1;
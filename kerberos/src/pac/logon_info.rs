@@ -0,0 +1,331 @@
+use crate::pac::ndr::{self, NdrReader, NdrWriter};
+use messages::basic_types::KerberosTime;
+use messages::Ecode;
+
+/// Seconds between 1601-01-01, origin of the Windows time, and the Unix epoch
+const FILETIME_UNIX_EPOCH: u64 = 11_644_473_600;
+
+/// Number of 100-nanosecond intervals since 1601-01-01 (UTC)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileTime(pub u64);
+
+impl FileTime {
+    /// Time which never comes, e.g. the logoff time of an account without restriction
+    pub const NEVER: FileTime = FileTime(0x7fff_ffff_ffff_ffff);
+
+    fn encode(&self, writer: &mut NdrWriter) {
+        writer.write_u32(self.0 as u32);
+        writer.write_u32((self.0 >> 32) as u32);
+    }
+
+    fn decode(reader: &mut NdrReader) -> Result<Self, Ecode> {
+        let low = reader.read_u32()? as u64;
+        let high = reader.read_u32()? as u64;
+        Ok(FileTime(high << 32 | low))
+    }
+}
+
+impl From<KerberosTime> for FileTime {
+    fn from(time: KerberosTime) -> Self {
+        FileTime((time.timestamp() as u64 + FILETIME_UNIX_EPOCH) * 10_000_000)
+    }
+}
+
+/// Security identifier of an account or a domain
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sid {
+    pub revision: u8,
+    pub identifier_authority: [u8; 6],
+    pub sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    /// Conformant structure, the number of sub-authorities coming first
+    fn encode(&self, writer: &mut NdrWriter) {
+        writer.write_u32(self.sub_authorities.len() as u32);
+        writer.write_u8(self.revision);
+        writer.write_u8(self.sub_authorities.len() as u8);
+        writer.write_bytes(&self.identifier_authority);
+        self.sub_authorities
+            .iter()
+            .for_each(|sub_authority| writer.write_u32(*sub_authority));
+    }
+
+    fn decode(reader: &mut NdrReader) -> Result<Self, Ecode> {
+        let count = reader.read_count(4)?;
+        let revision = reader.read_u8()?;
+        if reader.read_u8()? as usize != count {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        let identifier_authority = reader.read_bytes(6)?.try_into().unwrap();
+        let sub_authorities = (0..count)
+            .map(|_| reader.read_u32())
+            .collect::<Result<_, _>>()?;
+        Ok(Sid {
+            revision,
+            identifier_authority,
+            sub_authorities,
+        })
+    }
+}
+
+/// Group of the account's domain, by its relative identifier
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupMembership {
+    pub relative_id: u32,
+    pub attributes: u32,
+}
+
+impl GroupMembership {
+    fn encode_array(groups: &[GroupMembership], writer: &mut NdrWriter) {
+        if groups.is_empty() {
+            return;
+        }
+        writer.write_u32(groups.len() as u32);
+        groups.iter().for_each(|group| {
+            writer.write_u32(group.relative_id);
+            writer.write_u32(group.attributes);
+        });
+    }
+
+    fn decode_array(
+        present: bool,
+        count: u32,
+        reader: &mut NdrReader,
+    ) -> Result<Vec<GroupMembership>, Ecode> {
+        if !present {
+            return Ok(vec![]);
+        }
+        if reader.read_count(8)? != count as usize {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        (0..count)
+            .map(|_| {
+                Ok(GroupMembership {
+                    relative_id: reader.read_u32()?,
+                    attributes: reader.read_u32()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Group of another domain, by its full security identifier
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SidAndAttributes {
+    pub sid: Sid,
+    pub attributes: u32,
+}
+
+/// KERB_VALIDATION_INFO, the logon information of the account a PAC was issued to,
+/// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-pac/69e86ccc-85e3-41b9-b514-7d969cd0ed73
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KerbValidationInfo {
+    pub logon_time: FileTime,
+    pub logoff_time: FileTime,
+    pub kick_off_time: FileTime,
+    pub password_last_set: FileTime,
+    pub password_can_change: FileTime,
+    pub password_must_change: FileTime,
+    pub effective_name: String,
+    pub full_name: String,
+    pub logon_script: String,
+    pub profile_path: String,
+    pub home_directory: String,
+    pub home_directory_drive: String,
+    pub logon_count: u16,
+    pub bad_password_count: u16,
+    pub user_id: u32,
+    pub primary_group_id: u32,
+    pub group_ids: Vec<GroupMembership>,
+    pub user_flags: u32,
+    pub user_session_key: [u8; 16],
+    pub logon_server: String,
+    pub logon_domain_name: String,
+    pub logon_domain_id: Option<Sid>,
+    pub user_account_control: u32,
+    pub sub_auth_status: u32,
+    pub last_successful_i_logon: FileTime,
+    pub last_failed_i_logon: FileTime,
+    pub failed_i_logon_count: u32,
+    pub extra_sids: Vec<SidAndAttributes>,
+    pub resource_group_domain_sid: Option<Sid>,
+    pub resource_group_ids: Vec<GroupMembership>,
+}
+
+impl KerbValidationInfo {
+    /// Serialized as the referent of a top-level unique pointer
+    pub fn to_ndr(&self) -> Vec<u8> {
+        ndr::serialize(|writer| {
+            writer.write_pointer(true);
+            self.encode(writer);
+        })
+    }
+
+    pub fn from_ndr(bytes: &[u8]) -> Result<Self, Ecode> {
+        ndr::deserialize(bytes, |reader| {
+            if !reader.read_pointer()? {
+                return Err(Ecode::KRB_AP_ERR_MODIFIED);
+            }
+            Self::decode(reader)
+        })
+    }
+
+    fn encode(&self, writer: &mut NdrWriter) {
+        let strings = [
+            &self.effective_name,
+            &self.full_name,
+            &self.logon_script,
+            &self.profile_path,
+            &self.home_directory,
+            &self.home_directory_drive,
+        ];
+        [
+            self.logon_time,
+            self.logoff_time,
+            self.kick_off_time,
+            self.password_last_set,
+            self.password_can_change,
+            self.password_must_change,
+        ]
+        .iter()
+        .for_each(|time| time.encode(writer));
+        strings
+            .iter()
+            .for_each(|string| writer.write_unicode_string_header(string));
+        writer.write_u16(self.logon_count);
+        writer.write_u16(self.bad_password_count);
+        writer.write_u32(self.user_id);
+        writer.write_u32(self.primary_group_id);
+        writer.write_u32(self.group_ids.len() as u32);
+        writer.write_pointer(!self.group_ids.is_empty());
+        writer.write_u32(self.user_flags);
+        writer.write_bytes(&self.user_session_key);
+        writer.write_unicode_string_header(&self.logon_server);
+        writer.write_unicode_string_header(&self.logon_domain_name);
+        writer.write_pointer(self.logon_domain_id.is_some());
+        // Reserved1
+        writer.write_u32(0);
+        writer.write_u32(0);
+        writer.write_u32(self.user_account_control);
+        writer.write_u32(self.sub_auth_status);
+        self.last_successful_i_logon.encode(writer);
+        self.last_failed_i_logon.encode(writer);
+        writer.write_u32(self.failed_i_logon_count);
+        // Reserved3
+        writer.write_u32(0);
+        writer.write_u32(self.extra_sids.len() as u32);
+        writer.write_pointer(!self.extra_sids.is_empty());
+        writer.write_pointer(self.resource_group_domain_sid.is_some());
+        writer.write_u32(self.resource_group_ids.len() as u32);
+        writer.write_pointer(!self.resource_group_ids.is_empty());
+
+        // The referents of the pointers follow, in the order of the pointers
+        strings
+            .iter()
+            .for_each(|string| writer.write_unicode_string_body(string));
+        GroupMembership::encode_array(&self.group_ids, writer);
+        writer.write_unicode_string_body(&self.logon_server);
+        writer.write_unicode_string_body(&self.logon_domain_name);
+        if let Some(sid) = &self.logon_domain_id {
+            sid.encode(writer);
+        }
+        if !self.extra_sids.is_empty() {
+            writer.write_u32(self.extra_sids.len() as u32);
+            self.extra_sids.iter().for_each(|extra_sid| {
+                writer.write_pointer(true);
+                writer.write_u32(extra_sid.attributes);
+            });
+            self.extra_sids
+                .iter()
+                .for_each(|extra_sid| extra_sid.sid.encode(writer));
+        }
+        if let Some(sid) = &self.resource_group_domain_sid {
+            sid.encode(writer);
+        }
+        GroupMembership::encode_array(&self.resource_group_ids, writer);
+    }
+
+    fn decode(reader: &mut NdrReader) -> Result<Self, Ecode> {
+        let mut info = KerbValidationInfo {
+            logon_time: FileTime::decode(reader)?,
+            logoff_time: FileTime::decode(reader)?,
+            kick_off_time: FileTime::decode(reader)?,
+            password_last_set: FileTime::decode(reader)?,
+            password_can_change: FileTime::decode(reader)?,
+            password_must_change: FileTime::decode(reader)?,
+            ..Default::default()
+        };
+        let strings = (0..6)
+            .map(|_| reader.read_unicode_string_header())
+            .collect::<Result<Vec<_>, _>>()?;
+        info.logon_count = reader.read_u16()?;
+        info.bad_password_count = reader.read_u16()?;
+        info.user_id = reader.read_u32()?;
+        info.primary_group_id = reader.read_u32()?;
+        let group_count = reader.read_u32()?;
+        let group_ids = reader.read_pointer()?;
+        info.user_flags = reader.read_u32()?;
+        info.user_session_key = reader.read_bytes(16)?.try_into().unwrap();
+        let logon_server = reader.read_unicode_string_header()?;
+        let logon_domain_name = reader.read_unicode_string_header()?;
+        let logon_domain_id = reader.read_pointer()?;
+        reader.read_u32()?;
+        reader.read_u32()?;
+        info.user_account_control = reader.read_u32()?;
+        info.sub_auth_status = reader.read_u32()?;
+        info.last_successful_i_logon = FileTime::decode(reader)?;
+        info.last_failed_i_logon = FileTime::decode(reader)?;
+        info.failed_i_logon_count = reader.read_u32()?;
+        reader.read_u32()?;
+        let sid_count = reader.read_u32()?;
+        let extra_sids = reader.read_pointer()?;
+        let resource_group_domain_sid = reader.read_pointer()?;
+        let resource_group_count = reader.read_u32()?;
+        let resource_group_ids = reader.read_pointer()?;
+
+        let mut strings = strings
+            .iter()
+            .map(|header| reader.read_unicode_string_body(header))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+        info.effective_name = strings.next().unwrap();
+        info.full_name = strings.next().unwrap();
+        info.logon_script = strings.next().unwrap();
+        info.profile_path = strings.next().unwrap();
+        info.home_directory = strings.next().unwrap();
+        info.home_directory_drive = strings.next().unwrap();
+        info.group_ids = GroupMembership::decode_array(group_ids, group_count, reader)?;
+        info.logon_server = reader.read_unicode_string_body(&logon_server)?;
+        info.logon_domain_name = reader.read_unicode_string_body(&logon_domain_name)?;
+        if logon_domain_id {
+            info.logon_domain_id = Some(Sid::decode(reader)?);
+        }
+        if extra_sids {
+            if reader.read_count(8)? != sid_count as usize {
+                return Err(Ecode::KRB_AP_ERR_MODIFIED);
+            }
+            let attributes = (0..sid_count)
+                .map(|_| Ok((reader.read_pointer()?, reader.read_u32()?)))
+                .collect::<Result<Vec<_>, _>>()?;
+            info.extra_sids = attributes
+                .into_iter()
+                .map(|(present, attributes)| {
+                    if !present {
+                        return Err(Ecode::KRB_AP_ERR_MODIFIED);
+                    }
+                    Ok(SidAndAttributes {
+                        sid: Sid::decode(reader)?,
+                        attributes,
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if resource_group_domain_sid {
+            info.resource_group_domain_sid = Some(Sid::decode(reader)?);
+        }
+        info.resource_group_ids =
+            GroupMembership::decode_array(resource_group_ids, resource_group_count, reader)?;
+        Ok(info)
+    }
+}
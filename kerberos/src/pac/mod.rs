@@ -0,0 +1,491 @@
+use crate::authorization_data::{self, AuthorizationElement, SupportedChecksums};
+use crate::cryptographic_hash::CryptographicHash;
+use messages::basic_types::{
+    ADEntry, AuthorizationData, AuthorizationDataTypes, EncryptionKey, Int32, KerberosTime,
+    OctetString, PrincipalName, Realm,
+};
+use messages::{Decode, Ecode};
+
+pub use logon_info::{FileTime, GroupMembership, KerbValidationInfo, Sid, SidAndAttributes};
+
+mod logon_info;
+mod ndr;
+
+#[cfg(test)]
+mod tests;
+
+/// ad-type of a Privilege Attribute Certificate, carried in an AD-IF-RELEVANT container,
+/// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-pac/c38cc307-f3e6-4ed4-8c81-dc550d96223c
+pub const AD_WIN2K_PAC: Int32 = 128;
+
+const PAC_VERSION: u32 = 0;
+
+/// RID of the Domain Users group, the primary group of the accounts this KDC issues PACs to
+const DOMAIN_USERS_RID: u32 = 513;
+
+/// USER_NORMAL_ACCOUNT bit of the user account control
+const USER_NORMAL_ACCOUNT: u32 = 0x10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PacBufferType {
+    LogonInfo = 1,
+    ServerChecksum = 6,
+    PrivsvrChecksum = 7,
+    ClientInfo = 10,
+    UpnDnsInfo = 12,
+}
+
+impl PacBufferType {
+    fn is_signature(buffer_type: u32) -> bool {
+        buffer_type == PacBufferType::ServerChecksum as u32
+            || buffer_type == PacBufferType::PrivsvrChecksum as u32
+    }
+}
+
+/// PAC_INFO_BUFFER, where a buffer lies in the PAC
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PacInfoBuffer {
+    buffer_type: u32,
+    size: usize,
+    offset: usize,
+}
+
+/// Privilege Attribute Certificate, kept in its encoded form, which its signatures cover
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pac {
+    bytes: Vec<u8>,
+    buffers: Vec<PacInfoBuffer>,
+}
+
+fn align8(length: usize) -> usize {
+    length.div_ceil(8) * 8
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, Ecode> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|value| u16::from_le_bytes(value.try_into().unwrap()))
+        .ok_or(Ecode::KRB_AP_ERR_MODIFIED)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Ecode> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|value| u32::from_le_bytes(value.try_into().unwrap()))
+        .ok_or(Ecode::KRB_AP_ERR_MODIFIED)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, Ecode> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|value| u64::from_le_bytes(value.try_into().unwrap()))
+        .ok_or(Ecode::KRB_AP_ERR_MODIFIED)
+}
+
+fn read_utf16(bytes: &[u8], offset: usize, length: usize) -> Result<String, Ecode> {
+    if !length.is_multiple_of(2) {
+        return Err(Ecode::KRB_AP_ERR_MODIFIED);
+    }
+    let characters = (0..length / 2)
+        .map(|i| read_u16(bytes, offset + 2 * i))
+        .collect::<Result<Vec<_>, _>>()?;
+    String::from_utf16(&characters).map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)
+}
+
+fn utf16(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn principal_name_string(name: &PrincipalName) -> String {
+    name.name_string()
+        .iter()
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Pac {
+    /// Lay the buffers out after the PACTYPE header, each of them aligned on 8 bytes
+    fn from_buffers(buffers: Vec<(u32, Vec<u8>)>) -> Pac {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(buffers.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&PAC_VERSION.to_le_bytes());
+        let mut offset = align8(8 + 16 * buffers.len());
+        let buffers = buffers
+            .into_iter()
+            .map(|(buffer_type, data)| {
+                let info = PacInfoBuffer {
+                    buffer_type,
+                    size: data.len(),
+                    offset,
+                };
+                offset = align8(offset + data.len());
+                (info, data)
+            })
+            .collect::<Vec<_>>();
+        for (info, _) in &buffers {
+            bytes.extend_from_slice(&info.buffer_type.to_le_bytes());
+            bytes.extend_from_slice(&(info.size as u32).to_le_bytes());
+            bytes.extend_from_slice(&(info.offset as u64).to_le_bytes());
+        }
+        for (info, data) in &buffers {
+            bytes.resize(info.offset, 0);
+            bytes.extend_from_slice(data);
+        }
+        bytes.resize(align8(bytes.len()), 0);
+        Pac {
+            bytes,
+            buffers: buffers.into_iter().map(|(info, _)| info).collect(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Pac, Ecode> {
+        let count = read_u32(bytes, 0)? as usize;
+        if read_u32(bytes, 4)? != PAC_VERSION || count > bytes.len() / 16 {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        let buffers = (0..count)
+            .map(|i| {
+                let info = PacInfoBuffer {
+                    buffer_type: read_u32(bytes, 8 + 16 * i)?,
+                    size: read_u32(bytes, 12 + 16 * i)? as usize,
+                    offset: usize::try_from(read_u64(bytes, 16 + 16 * i)?)
+                        .map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)?,
+                };
+                if info
+                    .offset
+                    .checked_add(info.size)
+                    .is_none_or(|end| end > bytes.len())
+                {
+                    return Err(Ecode::KRB_AP_ERR_MODIFIED);
+                }
+                Ok(info)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Pac {
+            bytes: bytes.to_vec(),
+            buffers,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn buffer(&self, buffer_type: PacBufferType) -> Option<&[u8]> {
+        self.buffers
+            .iter()
+            .find(|info| info.buffer_type == buffer_type as u32)
+            .map(|info| &self.bytes[info.offset..info.offset + info.size])
+    }
+
+    pub fn logon_info(&self) -> Result<Option<KerbValidationInfo>, Ecode> {
+        self.buffer(PacBufferType::LogonInfo)
+            .map(KerbValidationInfo::from_ndr)
+            .transpose()
+    }
+
+    pub fn client_info(&self) -> Result<Option<PacClientInfo>, Ecode> {
+        self.buffer(PacBufferType::ClientInfo)
+            .map(PacClientInfo::from_bytes)
+            .transpose()
+    }
+
+    pub fn upn_dns_info(&self) -> Result<Option<UpnDnsInfo>, Ecode> {
+        self.buffer(PacBufferType::UpnDnsInfo)
+            .map(UpnDnsInfo::from_bytes)
+            .transpose()
+    }
+
+    /// Sign `buffers`: the server signature covers the whole PAC, its signature fields being
+    /// zeroed, the KDC signature covers the server signature,
+    /// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-pac/a194aa34-81bd-46a0-a931-2e05b87d1098
+    fn sign(
+        mut buffers: Vec<(u32, Vec<u8>)>,
+        checksums: &SupportedChecksums,
+        server_key: &EncryptionKey,
+        kdc_key: &EncryptionKey,
+    ) -> Result<Pac, Ecode> {
+        let hash = checksums.first().ok_or(Ecode::KDC_ERR_SUMTYPE_NOSUPP)?;
+        let mut zeroed = hash.get_checksum_type().to_le_bytes().to_vec();
+        zeroed.resize(4 + hash.keyed_digest(&[], &[]).len(), 0);
+        buffers.push((PacBufferType::ServerChecksum as u32, zeroed.clone()));
+        buffers.push((PacBufferType::PrivsvrChecksum as u32, zeroed));
+        let mut pac = Pac::from_buffers(buffers);
+        let server_signature = hash.keyed_digest(server_key.keyvalue().as_bytes(), &pac.bytes);
+        pac.set_signature(PacBufferType::ServerChecksum, &server_signature);
+        let kdc_signature = hash.keyed_digest(kdc_key.keyvalue().as_bytes(), &server_signature);
+        pac.set_signature(PacBufferType::PrivsvrChecksum, &kdc_signature);
+        Ok(pac)
+    }
+
+    fn set_signature(&mut self, buffer_type: PacBufferType, signature: &[u8]) {
+        let info = self
+            .buffers
+            .iter()
+            .find(|info| info.buffer_type == buffer_type as u32)
+            .expect("The signature buffers are laid out before signing");
+        self.bytes[info.offset + 4..info.offset + 4 + signature.len()].copy_from_slice(signature);
+    }
+
+    /// PAC_SIGNATURE_DATA of the given type, as its checksum type and signature
+    fn signature(&self, buffer_type: PacBufferType) -> Result<(Int32, &[u8]), Ecode> {
+        let data = self.buffer(buffer_type).ok_or(Ecode::KRB_AP_ERR_MODIFIED)?;
+        let signature_type = read_u32(data, 0)? as Int32;
+        Ok((signature_type, &data[4..]))
+    }
+
+    fn find_hash(
+        checksums: &SupportedChecksums,
+        signature_type: Int32,
+    ) -> Result<&dyn CryptographicHash, Ecode> {
+        checksums
+            .iter()
+            .find(|hash| hash.get_checksum_type() == signature_type)
+            .map(|hash| hash.as_ref() as &dyn CryptographicHash)
+            .ok_or(Ecode::KDC_ERR_SUMTYPE_NOSUPP)
+    }
+
+    /// Check the PAC was signed for the service of key `key`
+    pub fn verify_server_signature(
+        &self,
+        checksums: &SupportedChecksums,
+        key: &EncryptionKey,
+    ) -> Result<(), Ecode> {
+        let (signature_type, signature) = self.signature(PacBufferType::ServerChecksum)?;
+        let hash = Self::find_hash(checksums, signature_type)?;
+        let mut data = self.bytes.clone();
+        self.buffers
+            .iter()
+            .filter(|info| PacBufferType::is_signature(info.buffer_type))
+            .filter(|info| info.size > 4)
+            .for_each(|info| data[info.offset + 4..info.offset + info.size].fill(0));
+        if hash.keyed_digest(key.keyvalue().as_bytes(), &data) != signature {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        Ok(())
+    }
+
+    /// Check the PAC was issued by the KDC of key `key`
+    pub fn verify_kdc_signature(
+        &self,
+        checksums: &SupportedChecksums,
+        key: &EncryptionKey,
+    ) -> Result<(), Ecode> {
+        let (_, server_signature) = self.signature(PacBufferType::ServerChecksum)?;
+        let (signature_type, signature) = self.signature(PacBufferType::PrivsvrChecksum)?;
+        let hash = Self::find_hash(checksums, signature_type)?;
+        if hash.keyed_digest(key.keyvalue().as_bytes(), server_signature) != signature {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        Ok(())
+    }
+
+    /// The same PAC, signed for another service, e.g. when the TGS copies the PAC of a TGT
+    /// into a service ticket
+    pub fn resign(
+        &self,
+        checksums: &SupportedChecksums,
+        server_key: &EncryptionKey,
+        kdc_key: &EncryptionKey,
+    ) -> Result<Pac, Ecode> {
+        let buffers = self
+            .buffers
+            .iter()
+            .filter(|info| !PacBufferType::is_signature(info.buffer_type))
+            .map(|info| {
+                let data = self.bytes[info.offset..info.offset + info.size].to_vec();
+                (info.buffer_type, data)
+            })
+            .collect();
+        Self::sign(buffers, checksums, server_key, kdc_key)
+    }
+
+    /// PAC of a ticket the KDC `kdc_name` issues to `cname`, authenticated at `authtime`.
+    /// The principal database holds no security identifier, so the logon information only
+    /// names the account and its realm
+    #[allow(clippy::too_many_arguments)]
+    pub fn issue(
+        checksums: &SupportedChecksums,
+        server_key: &EncryptionKey,
+        kdc_key: &EncryptionKey,
+        cname: &PrincipalName,
+        crealm: &Realm,
+        authtime: KerberosTime,
+        kdc_name: &PrincipalName,
+    ) -> Result<Pac, Ecode> {
+        let name = principal_name_string(cname);
+        let logon_info = KerbValidationInfo {
+            logon_time: authtime.into(),
+            logoff_time: FileTime::NEVER,
+            kick_off_time: FileTime::NEVER,
+            password_must_change: FileTime::NEVER,
+            effective_name: name.clone(),
+            primary_group_id: DOMAIN_USERS_RID,
+            logon_server: principal_name_string(kdc_name),
+            logon_domain_name: crealm.as_str().to_string(),
+            user_account_control: USER_NORMAL_ACCOUNT,
+            ..Default::default()
+        };
+        let client_info = PacClientInfo {
+            client_id: authtime.into(),
+            name: name.clone(),
+        };
+        let upn_dns_info = UpnDnsInfo {
+            upn: format!("{}@{}", name, crealm.as_str()),
+            dns_domain_name: crealm.as_str().to_string(),
+            flags: UpnDnsInfo::UPN_CONSTRUCTED,
+        };
+        let buffers = vec![
+            (PacBufferType::LogonInfo as u32, logon_info.to_ndr()),
+            (PacBufferType::ClientInfo as u32, client_info.to_bytes()),
+            (PacBufferType::UpnDnsInfo as u32, upn_dns_info.to_bytes()),
+        ];
+        Self::sign(buffers, checksums, server_key, kdc_key)
+    }
+
+    /// Check the PAC was issued to `cname` at `authtime`, as the ticket holding it was
+    pub fn verify_client(
+        &self,
+        cname: &PrincipalName,
+        authtime: KerberosTime,
+    ) -> Result<(), Ecode> {
+        let client_info = self.client_info()?.ok_or(Ecode::KRB_AP_ERR_MODIFIED)?;
+        if client_info.name != principal_name_string(cname)
+            || client_info.client_id != FileTime::from(authtime)
+        {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        Ok(())
+    }
+
+    /// AD-IF-RELEVANT container holding the PAC, as it is put in a ticket
+    pub fn to_authorization_data(&self) -> Result<ADEntry, Ecode> {
+        let entry = ADEntry::new(
+            AD_WIN2K_PAC,
+            OctetString::new(self.bytes.clone()).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+        );
+        authorization_data::make_entry(AuthorizationDataTypes::IfRelevant, &vec![entry])
+    }
+
+    /// PAC among the elements of the authorization data of a ticket
+    pub fn from_elements(elements: &[AuthorizationElement]) -> Result<Option<Pac>, Ecode> {
+        elements
+            .iter()
+            .find(|element| *element.entry.ad_type() == AD_WIN2K_PAC)
+            .map(|element| Pac::from_bytes(element.entry.ad_data().as_bytes()))
+            .transpose()
+    }
+}
+
+/// Whether `authorization_data` holds a PAC, at its top level or in AD-IF-RELEVANT containers
+pub fn contains_pac(authorization_data: &AuthorizationData) -> bool {
+    authorization_data.iter().any(|entry| {
+        *entry.ad_type() == AD_WIN2K_PAC
+            || (*entry.ad_type() == AuthorizationDataTypes::IfRelevant as Int32
+                && AuthorizationData::from_der(entry.ad_data().as_bytes())
+                    .is_ok_and(|elements| contains_pac(&elements)))
+    })
+}
+
+/// Replace each PAC of `authorization_data` by the one `map` makes of it
+pub fn map_pacs(
+    authorization_data: &AuthorizationData,
+    map: &mut impl FnMut(Pac) -> Result<Pac, Ecode>,
+) -> Result<AuthorizationData, Ecode> {
+    authorization_data
+        .iter()
+        .map(|entry| match *entry.ad_type() {
+            AD_WIN2K_PAC => {
+                let pac = map(Pac::from_bytes(entry.ad_data().as_bytes())?)?;
+                Ok(ADEntry::new(
+                    AD_WIN2K_PAC,
+                    OctetString::new(pac.bytes).map_err(|_| Ecode::KRB_ERR_GENERIC)?,
+                ))
+            }
+            ad_type if ad_type == AuthorizationDataTypes::IfRelevant as Int32 => {
+                let elements = AuthorizationData::from_der(entry.ad_data().as_bytes())
+                    .map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)?;
+                authorization_data::make_entry(
+                    AuthorizationDataTypes::IfRelevant,
+                    &map_pacs(&elements, map)?,
+                )
+            }
+            _ => Ok(entry.clone()),
+        })
+        .collect()
+}
+
+/// PAC_CLIENT_INFO, the client a PAC was issued to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PacClientInfo {
+    /// Authentication time of the client
+    pub client_id: FileTime,
+    pub name: String,
+}
+
+impl PacClientInfo {
+    fn to_bytes(&self) -> Vec<u8> {
+        let name = utf16(&self.name);
+        let mut bytes = self.client_id.0.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend(name);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Ecode> {
+        let name_length = read_u16(bytes, 8)? as usize;
+        Ok(PacClientInfo {
+            client_id: FileTime(read_u64(bytes, 0)?),
+            name: read_utf16(bytes, 10, name_length)?,
+        })
+    }
+}
+
+/// UPN_DNS_INFO, the user principal name and DNS domain of the client
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpnDnsInfo {
+    pub upn: String,
+    pub dns_domain_name: String,
+    pub flags: u32,
+}
+
+impl UpnDnsInfo {
+    /// The account has no UPN of its own, `upn` was built from its name and realm
+    pub const UPN_CONSTRUCTED: u32 = 0x1;
+
+    /// Both names follow the 12 bytes of the header, each of them aligned on 8 bytes
+    fn to_bytes(&self) -> Vec<u8> {
+        let upn = utf16(&self.upn);
+        let dns_domain_name = utf16(&self.dns_domain_name);
+        let upn_offset = align8(12);
+        let dns_domain_name_offset = align8(upn_offset + upn.len());
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(upn.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(upn_offset as u16).to_le_bytes());
+        bytes.extend_from_slice(&(dns_domain_name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(dns_domain_name_offset as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.flags.to_le_bytes());
+        bytes.resize(upn_offset, 0);
+        bytes.extend(upn);
+        bytes.resize(dns_domain_name_offset, 0);
+        bytes.extend(dns_domain_name);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Ecode> {
+        Ok(UpnDnsInfo {
+            upn: read_utf16(
+                bytes,
+                read_u16(bytes, 2)? as usize,
+                read_u16(bytes, 0)? as usize,
+            )?,
+            dns_domain_name: read_utf16(
+                bytes,
+                read_u16(bytes, 6)? as usize,
+                read_u16(bytes, 4)? as usize,
+            )?,
+            flags: read_u32(bytes, 8)?,
+        })
+    }
+}
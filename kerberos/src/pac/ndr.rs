@@ -0,0 +1,198 @@
+use messages::Ecode;
+
+/// Referent ID of the first pointer of a serialized object, the following ones being
+/// incremented by 4
+const FIRST_REFERENT_ID: u32 = 0x0002_0000;
+
+/// Common type header of the RPC type serialization version 1, little-endian,
+/// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-rpce/6d75d40e-e2d2-4420-b9e9-8508a726a9ae
+const COMMON_TYPE_HEADER: [u8; 8] = [0x01, 0x10, 0x08, 0x00, 0xcc, 0xcc, 0xcc, 0xcc];
+
+const PRIVATE_HEADER_LENGTH: usize = 8;
+
+/// Writer of the Network Data Representation of a structure, each primitive being aligned
+/// on its own size, https://pubs.opengroup.org/onlinepubs/9629399/chap14.htm
+pub(crate) struct NdrWriter {
+    bytes: Vec<u8>,
+    referent_id: u32,
+}
+
+impl NdrWriter {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            referent_id: FIRST_REFERENT_ID,
+        }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let padding = (alignment - self.bytes.len() % alignment) % alignment;
+        self.bytes.extend(std::iter::repeat_n(0, padding));
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.align(2);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.align(4);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Unique pointer, whose referent is written once the structure holding it is
+    pub fn write_pointer(&mut self, present: bool) {
+        if present {
+            let referent_id = self.referent_id;
+            self.referent_id += 4;
+            self.write_u32(referent_id);
+        } else {
+            self.write_u32(0);
+        }
+    }
+
+    /// Inline part of a RPC_UNICODE_STRING, its characters being deferred
+    pub fn write_unicode_string_header(&mut self, value: &str) {
+        let length = (value.encode_utf16().count() * 2) as u16;
+        self.write_u16(length);
+        self.write_u16(length);
+        self.write_pointer(!value.is_empty());
+    }
+
+    /// Conformant varying array of the characters of a RPC_UNICODE_STRING
+    pub fn write_unicode_string_body(&mut self, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        let characters: Vec<u16> = value.encode_utf16().collect();
+        self.write_u32(characters.len() as u32);
+        self.write_u32(0);
+        self.write_u32(characters.len() as u32);
+        characters.iter().for_each(|c| self.write_u16(*c));
+    }
+}
+
+pub(crate) struct NdrReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+/// Characters a RPC_UNICODE_STRING announces, read once its inline part is
+pub(crate) struct UnicodeStringHeader {
+    length: u16,
+    present: bool,
+}
+
+impl<'a> NdrReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        self.position += (alignment - self.position % alignment) % alignment;
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Ecode> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or(Ecode::KRB_AP_ERR_MODIFIED)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Ecode> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Ecode> {
+        self.align(2);
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Ecode> {
+        self.align(4);
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Whether a unique pointer has a referent, to be read after the structure holding it
+    pub fn read_pointer(&mut self) -> Result<bool, Ecode> {
+        Ok(self.read_u32()? != 0)
+    }
+
+    /// Conformance of an array, i.e. its number of elements, each of at least `element_size`
+    /// bytes, which cannot exceed what is left to read
+    pub fn read_count(&mut self, element_size: usize) -> Result<usize, Ecode> {
+        let count = self.read_u32()? as usize;
+        if count * element_size > self.bytes.len().saturating_sub(self.position) {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        Ok(count)
+    }
+
+    pub fn read_unicode_string_header(&mut self) -> Result<UnicodeStringHeader, Ecode> {
+        let length = self.read_u16()?;
+        let maximum_length = self.read_u16()?;
+        if length % 2 != 0 || length > maximum_length {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        let present = self.read_pointer()?;
+        Ok(UnicodeStringHeader { length, present })
+    }
+
+    pub fn read_unicode_string_body(
+        &mut self,
+        header: &UnicodeStringHeader,
+    ) -> Result<String, Ecode> {
+        if !header.present {
+            return Ok(String::new());
+        }
+        self.read_count(0)?;
+        let offset = self.read_u32()?;
+        let actual_count = self.read_count(2)?;
+        if offset != 0 || actual_count * 2 != header.length as usize {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        let characters = (0..actual_count)
+            .map(|_| self.read_u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        String::from_utf16(&characters).map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)
+    }
+}
+
+/// Serialize the object `encode` writes, as a top-level structure preceded by the
+/// common and private headers of the type serialization
+pub(crate) fn serialize(encode: impl FnOnce(&mut NdrWriter)) -> Vec<u8> {
+    let mut writer = NdrWriter::new();
+    encode(&mut writer);
+    writer.align(8);
+    let mut bytes = COMMON_TYPE_HEADER.to_vec();
+    bytes.extend_from_slice(&(writer.bytes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend(writer.bytes);
+    bytes
+}
+
+pub(crate) fn deserialize<T>(
+    bytes: &[u8],
+    decode: impl FnOnce(&mut NdrReader) -> Result<T, Ecode>,
+) -> Result<T, Ecode> {
+    let header_length = COMMON_TYPE_HEADER.len() + PRIVATE_HEADER_LENGTH;
+    // Only the little-endian representation is supported, the filler is not checked
+    if bytes.len() < header_length || bytes[..4] != COMMON_TYPE_HEADER[..4] {
+        return Err(Ecode::KRB_AP_ERR_MODIFIED);
+    }
+    let object_length = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+    let object = bytes
+        .get(header_length..header_length + object_length)
+        .ok_or(Ecode::KRB_AP_ERR_MODIFIED)?;
+    decode(&mut NdrReader::new(object))
+}
@@ -0,0 +1,229 @@
+use crate::authorization_data::{ticket_elements, SupportedChecksums};
+use crate::pac::{
+    contains_pac, map_pacs, FileTime, GroupMembership, KerbValidationInfo, Pac, PacClientInfo, Sid,
+    SidAndAttributes, UpnDnsInfo,
+};
+use crate::tests_common::mocked::MockedHasher;
+use messages::basic_types::{
+    EncryptionKey, KerberosString, KerberosTime, NameTypes, OctetString, PrincipalName, Realm,
+};
+use messages::Ecode;
+
+fn checksums() -> Vec<Box<dyn crate::cryptographic_hash::CryptographicHash + Send + Sync>> {
+    vec![Box::new(MockedHasher)]
+}
+
+fn key(value: u8) -> EncryptionKey {
+    EncryptionKey::new(1, OctetString::new(vec![value; 16]).unwrap())
+}
+
+fn principal_name(name: &str) -> PrincipalName {
+    PrincipalName::new(
+        NameTypes::NtPrincipal,
+        vec![KerberosString::new(name).unwrap()],
+    )
+    .unwrap()
+}
+
+fn make_pac(checksums: &SupportedChecksums, authtime: KerberosTime) -> Pac {
+    Pac::issue(
+        checksums,
+        &key(1),
+        &key(2),
+        &principal_name("alice"),
+        &Realm::new("EXAMPLE.COM").unwrap(),
+        authtime,
+        &principal_name("krbtgt"),
+    )
+    .unwrap()
+}
+
+fn domain_sid() -> Sid {
+    Sid {
+        revision: 1,
+        identifier_authority: [0, 0, 0, 0, 0, 5],
+        sub_authorities: vec![21, 1004336348, 1177238915, 682003330],
+    }
+}
+
+#[test]
+fn logon_info_should_be_serialized_with_type_serialization_headers() {
+    let bytes = KerbValidationInfo::default().to_ndr();
+
+    assert_eq!(
+        &bytes[..8],
+        &[0x01, 0x10, 0x08, 0x00, 0xcc, 0xcc, 0xcc, 0xcc]
+    );
+    let object_length = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+    assert_eq!(object_length % 8, 0);
+    assert_eq!(bytes.len(), 16 + object_length);
+    // Top-level pointer to the structure
+    assert_eq!(&bytes[16..20], &0x0002_0000u32.to_le_bytes());
+}
+
+#[test]
+fn logon_info_should_survive_ndr_round_trip() {
+    let info = KerbValidationInfo {
+        logon_time: FileTime(133_000_000_000_000_000),
+        logoff_time: FileTime::NEVER,
+        kick_off_time: FileTime::NEVER,
+        effective_name: "alice".to_string(),
+        full_name: "Alice Liddell".to_string(),
+        logon_count: 12,
+        user_id: 1105,
+        primary_group_id: 513,
+        group_ids: vec![
+            GroupMembership {
+                relative_id: 513,
+                attributes: 7,
+            },
+            GroupMembership {
+                relative_id: 1110,
+                attributes: 7,
+            },
+        ],
+        user_session_key: [0xab; 16],
+        logon_server: "DC01".to_string(),
+        logon_domain_name: "EXAMPLE".to_string(),
+        logon_domain_id: Some(domain_sid()),
+        user_account_control: 0x10,
+        extra_sids: vec![SidAndAttributes {
+            sid: Sid {
+                revision: 1,
+                identifier_authority: [0, 0, 0, 0, 0, 18],
+                sub_authorities: vec![1],
+            },
+            attributes: 7,
+        }],
+        resource_group_domain_sid: Some(domain_sid()),
+        resource_group_ids: vec![GroupMembership {
+            relative_id: 1200,
+            attributes: 0x20000007,
+        }],
+        ..Default::default()
+    };
+
+    let decoded = KerbValidationInfo::from_ndr(&info.to_ndr()).unwrap();
+
+    assert_eq!(decoded, info);
+}
+
+#[test]
+fn truncated_logon_info_should_be_rejected() {
+    let info = KerbValidationInfo {
+        effective_name: "alice".to_string(),
+        ..Default::default()
+    };
+    let bytes = info.to_ndr();
+
+    assert_eq!(
+        KerbValidationInfo::from_ndr(&bytes[..bytes.len() - 16]),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+}
+
+#[test]
+fn issued_pac_should_describe_the_client() {
+    let authtime = KerberosTime::now();
+    let pac = Pac::from_bytes(make_pac(&checksums(), authtime).as_bytes()).unwrap();
+
+    let logon_info = pac.logon_info().unwrap().unwrap();
+    assert_eq!(logon_info.effective_name, "alice");
+    assert_eq!(logon_info.logon_domain_name, "EXAMPLE.COM");
+    assert_eq!(logon_info.logon_server, "krbtgt");
+    assert_eq!(logon_info.logon_time, FileTime::from(authtime));
+    assert_eq!(logon_info.logoff_time, FileTime::NEVER);
+    assert_eq!(
+        pac.client_info().unwrap(),
+        Some(PacClientInfo {
+            client_id: FileTime::from(authtime),
+            name: "alice".to_string(),
+        })
+    );
+    assert_eq!(
+        pac.upn_dns_info().unwrap(),
+        Some(UpnDnsInfo {
+            upn: "alice@EXAMPLE.COM".to_string(),
+            dns_domain_name: "EXAMPLE.COM".to_string(),
+            flags: UpnDnsInfo::UPN_CONSTRUCTED,
+        })
+    );
+    assert!(pac
+        .verify_client(&principal_name("alice"), authtime)
+        .is_ok());
+    assert_eq!(
+        pac.verify_client(&principal_name("bob"), authtime),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+}
+
+#[test]
+fn signatures_should_be_verified_with_the_keys_they_were_made_with() {
+    let checksums = checksums();
+    let pac = make_pac(&checksums, KerberosTime::now());
+
+    assert!(pac.verify_server_signature(&checksums, &key(1)).is_ok());
+    assert!(pac.verify_kdc_signature(&checksums, &key(2)).is_ok());
+    assert_eq!(
+        pac.verify_server_signature(&checksums, &key(2)),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+    assert_eq!(
+        pac.verify_kdc_signature(&checksums, &key(1)),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+}
+
+#[test]
+fn modified_pac_should_fail_server_signature() {
+    let checksums = checksums();
+    let mut bytes = make_pac(&checksums, KerberosTime::now())
+        .as_bytes()
+        .to_vec();
+    // Somewhere in the logon information, the first buffer
+    let offset = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+    bytes[offset + 40] ^= 0xff;
+    let pac = Pac::from_bytes(&bytes).unwrap();
+
+    assert_eq!(
+        pac.verify_server_signature(&checksums, &key(1)),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+}
+
+#[test]
+fn resigned_pac_should_keep_its_buffers() {
+    let checksums = checksums();
+    let pac = make_pac(&checksums, KerberosTime::now());
+
+    let resigned = pac.resign(&checksums, &key(3), &key(2)).unwrap();
+
+    assert!(resigned
+        .verify_server_signature(&checksums, &key(3))
+        .is_ok());
+    assert!(resigned.verify_kdc_signature(&checksums, &key(2)).is_ok());
+    assert_eq!(
+        resigned.verify_server_signature(&checksums, &key(1)),
+        Err(Ecode::KRB_AP_ERR_MODIFIED)
+    );
+    assert_eq!(resigned.logon_info(), pac.logon_info());
+    assert_eq!(resigned.client_info(), pac.client_info());
+}
+
+#[test]
+fn pac_should_be_found_in_authorization_data() {
+    let checksums = checksums();
+    let pac = make_pac(&checksums, KerberosTime::now());
+    let authorization_data = vec![pac.to_authorization_data().unwrap()];
+    assert!(contains_pac(&authorization_data));
+
+    let resigned = map_pacs(&authorization_data, &mut |pac| {
+        pac.resign(&checksums, &key(3), &key(2))
+    })
+    .unwrap();
+
+    let elements = ticket_elements(&checksums, &key(0), &resigned).unwrap();
+    let found = Pac::from_elements(&elements).unwrap().unwrap();
+    assert!(found.verify_server_signature(&checksums, &key(3)).is_ok());
+    assert!(!contains_pac(&vec![]));
+}
@@ -1,11 +1,61 @@
+use crate::authorization_data::AuthorizationElement;
 use async_trait::async_trait;
 use der::Sequence;
 use messages::basic_types::{
-    EncryptionKey, HostAddress, Int32, KerberosTime, Microseconds, PrincipalName, Realm, UInt32,
+    EncryptionKey, HostAddress, Int32, KerberosString, KerberosTime, Microseconds, OctetString,
+    PrincipalName, Realm, SequenceOf, UInt32,
 };
-use messages::{ApReq, LastReq};
+use messages::{ApReq, LastReq, TicketFlags};
 use std::time::Duration;
 
+/// Attribute of a principal, valued as the bit of the attributes of MIT krb5 principals it
+/// corresponds to, e.g. `-allow_forwardable` sets `DisallowForwardable`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrincipalAttribute {
+    /// `-allow_postdated`, no postdated ticket is issued to or for the principal
+    DisallowPostdated = 0x1,
+    /// `-allow_forwardable`
+    DisallowForwardable = 0x2,
+    /// `-allow_tgs_req`, tickets to the service cannot be obtained with a TGT
+    DisallowTgtBased = 0x4,
+    /// `-allow_renewable`
+    DisallowRenewable = 0x8,
+    /// `-allow_proxiable`
+    DisallowProxiable = 0x10,
+    /// `-allow_dup_skey`, no user-to-user ticket is issued to the service
+    DisallowDupSkey = 0x20,
+    /// `-allow_tix`, the principal is locked out
+    DisallowAllTix = 0x40,
+    /// `+requires_preauth`, the client must prove it knows its key in the AS request
+    RequiresPreAuth = 0x80,
+    /// `-allow_svr`, tickets to the principal can only be user-to-user ones
+    DisallowSvr = 0x1000,
+    /// `+ok_as_delegate`, clients may delegate their credentials to the service
+    OkAsDelegate = 0x10_0000,
+}
+
+/// Set of the attributes of a principal, none being set by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrincipalAttributes(u32);
+
+impl PrincipalAttributes {
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn with(self, attribute: PrincipalAttribute) -> Self {
+        Self(self.0 | attribute as u32)
+    }
+
+    pub fn has(&self, attribute: PrincipalAttribute) -> bool {
+        self.0 & attribute as u32 != 0
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PrincipalDatabaseRecord {
     pub max_renewable_life: Duration,
@@ -13,6 +63,16 @@ pub struct PrincipalDatabaseRecord {
     pub key: EncryptionKey,
     pub p_kvno: Option<UInt32>,
     pub supported_encryption_types: Vec<Int32>,
+    /// Services this principal may obtain tickets to on behalf of a user (S4U2Proxy)
+    pub allowed_to_delegate_to: Vec<PrincipalName>,
+    pub attributes: PrincipalAttributes,
+}
+
+/// Group a principal belongs to, with the roles its members are granted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrincipalGroup {
+    pub name: KerberosString,
+    pub roles: Vec<KerberosString>,
 }
 
 #[async_trait]
@@ -22,6 +82,15 @@ pub trait PrincipalDatabase {
         principal_name: &PrincipalName,
         realm: &Realm,
     ) -> Option<PrincipalDatabaseRecord>;
+
+    /// Groups of the principal, issued as claims into its tickets
+    async fn get_groups(
+        &self,
+        _principal_name: &PrincipalName,
+        _realm: &Realm,
+    ) -> Vec<PrincipalGroup> {
+        vec![]
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Sequence)]
@@ -35,7 +104,12 @@ pub struct ReplayCacheEntry {
 #[async_trait]
 pub trait ReplayCache {
     type ReplayCacheError;
-    async fn store(&self, entry: &ReplayCacheEntry) -> Result<(), Self::ReplayCacheError>;
+    /// Remember `entry` for `lifetime`, after which `contain` must no longer report it.
+    async fn store(
+        &self,
+        entry: &ReplayCacheEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ReplayCacheError>;
     async fn contain(&self, entry: &ReplayCacheEntry) -> Result<bool, Self::ReplayCacheError>;
 }
 
@@ -46,21 +120,47 @@ pub trait TicketHotList {
     async fn contain(&self, ticket: &[u8]) -> Result<bool, Self::TicketHotListError>;
 }
 
+/// Authenticator seen by an application server. Two authenticators sent within the same
+/// microsecond are told apart by the hash of their ciphertext,
+/// https://www.rfc-editor.org/rfc/rfc4120#section-3.2.3
 #[derive(Debug, Clone, PartialEq, Eq, Sequence)]
 pub struct ApReplayEntry {
     pub ctime: KerberosTime,
     pub cusec: Microseconds,
     pub cname: PrincipalName,
     pub crealm: Realm,
+    pub sname: PrincipalName,
+    pub authenticator_hash: OctetString,
 }
 
 #[async_trait]
 pub trait ApReplayCache {
     type ApReplayCacheError;
-    async fn store(&self, entry: &ApReplayEntry) -> Result<(), Self::ApReplayCacheError>;
+    /// Remember `entry` for `lifetime`, after which `contain` must no longer report it.
+    async fn store(
+        &self,
+        entry: &ApReplayEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ApReplayCacheError>;
     async fn contain(&self, entry: &ApReplayEntry) -> Result<bool, Self::ApReplayCacheError>;
 }
 
+/// Time a replay cache entry may be forgotten at. Caches with a single TTL for all their
+/// entries keep it as the value of each entry, which lives for its own lifetime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayExpiry(pub KerberosTime);
+
+impl ReplayExpiry {
+    /// Expiry of an entry stored now for `lifetime`
+    pub fn after(lifetime: Duration) -> Self {
+        Self(KerberosTime::now() + lifetime)
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.0 > KerberosTime::now()
+    }
+}
+
 #[async_trait]
 pub trait ClientAddressStorage: Sync + Send {
     type Error;
@@ -87,7 +187,31 @@ pub struct UserSessionEntry {
     pub crealm: Realm,
     pub session_key: EncryptionKey,
     pub sequence_number: Int32,
+    /// Realms the client's ticket went through on its way from the client's realm
+    pub transited: SequenceOf<Realm>,
+    /// Authorization data of the client's ticket
+    pub authorization_data: Vec<AuthorizationElement>,
+    /// Key the server chose for the rest of the session, when mutual authentication was required
+    pub subkey: Option<EncryptionKey>,
+    /// Initial sequence number of the messages sent by the server, the client's being
+    /// `sequence_number`
+    pub server_sequence_number: Option<UInt32>,
+    /// Flags of the client's ticket
+    pub flags: TicketFlags,
+    pub authtime: KerberosTime,
+    /// Start time of the client's ticket, its authentication time unless it was postdated
+    pub starttime: KerberosTime,
+    pub endtime: KerberosTime,
+    pub renew_till: Option<KerberosTime>,
+}
+
+impl UserSessionEntry {
+    /// Key the KRB-SAFE and KRB-PRIV messages of the session are protected with
+    pub fn message_key(&self) -> &EncryptionKey {
+        self.subkey.as_ref().unwrap_or(&self.session_key)
+    }
 }
+
 #[async_trait]
 pub trait UserSessionStorage: Send + Sync {
     type Error;
@@ -98,3 +222,17 @@ pub trait UserSessionStorage: Send + Sync {
     ) -> Result<Option<UserSessionEntry>, Self::Error>;
     async fn store_session(&self, session: &UserSessionEntry) -> Result<(), Self::Error>;
 }
+
+/// Long-term keys of the services an application server accepts tickets for
+#[async_trait]
+pub trait ServiceKeyStore: Send + Sync {
+    /// Key of `sname` in `realm` for `etype`, of version `kvno` or the latest one when the
+    /// ticket does not say which
+    async fn get_key(
+        &self,
+        sname: &PrincipalName,
+        realm: &Realm,
+        etype: Int32,
+        kvno: Option<UInt32>,
+    ) -> Option<EncryptionKey>;
+}
@@ -5,7 +5,7 @@ use crate::cryptography::Cryptography;
 use crate::cryptography_error::CryptographyError;
 use crate::service_traits::{
     ApReplayCache, ApReplayEntry, ClientAddressStorage, LastReqDatabase, LastReqEntry,
-    PrincipalDatabase, PrincipalDatabaseRecord, ReplayCache, ReplayCacheEntry, UserSessionEntry,
+    PrincipalDatabase, PrincipalDatabaseRecord, PrincipalGroup, ReplayCache, ReplayCacheEntry, UserSessionEntry,
     UserSessionStorage,
 };
 use async_trait::async_trait;
@@ -16,7 +16,7 @@ use messages::basic_types::{
 use messages::{ApReq, AsRep, AsReq, Decode, EncAsRepPart, EncTgsRepPart, LastReq, TgsRep};
 use std::cell::RefCell;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub(crate) struct MockedCrypto;
 
@@ -45,15 +45,24 @@ impl Cryptography for MockedCrypto {
 
 pub(crate) struct MockedPrincipalDb {
     data: Arc<Mutex<Vec<(PrincipalName, Realm, PrincipalDatabaseRecord)>>>,
+    groups: Arc<Mutex<Vec<(PrincipalName, Realm, PrincipalGroup)>>>,
 }
 
 impl MockedPrincipalDb {
     pub fn new() -> MockedPrincipalDb {
         MockedPrincipalDb {
             data: Arc::new(Mutex::new(Vec::new())),
+            groups: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
+    pub fn add_group(&self, principal_name: PrincipalName, realm: Realm, group: PrincipalGroup) {
+        self.groups
+            .lock()
+            .unwrap()
+            .push((principal_name, realm, group));
+    }
+
     pub fn add_principal(
         &self,
         principal_name: PrincipalName,
@@ -78,14 +87,28 @@ impl PrincipalDatabase for MockedPrincipalDb {
             .lock()
             .unwrap()
             .iter()
-            .find_map(|(name, realm, record)| {
-                if name == principal_name && realm == realm {
+            .find_map(|(name, principal_realm, record)| {
+                if name == principal_name && principal_realm == realm {
                     Some(record.clone())
                 } else {
                     None
                 }
             })
     }
+
+    async fn get_groups(
+        &self,
+        principal_name: &PrincipalName,
+        realm: &Realm,
+    ) -> Vec<PrincipalGroup> {
+        self.groups
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, group_realm, _)| name == principal_name && group_realm == realm)
+            .map(|(_, _, group)| group.clone())
+            .collect()
+    }
 }
 
 pub(crate) struct MockClientEnv {
@@ -242,7 +265,7 @@ impl ClientEnv for MockClientEnv {
 }
 
 pub(crate) struct MockedReplayCache {
-    entries: Arc<Mutex<Vec<ReplayCacheEntry>>>,
+    entries: Arc<Mutex<Vec<(ReplayCacheEntry, Instant)>>>,
 }
 
 impl MockedReplayCache {
@@ -257,13 +280,25 @@ impl MockedReplayCache {
 impl ReplayCache for MockedReplayCache {
     type ReplayCacheError = ();
 
-    async fn store(&self, entry: &ReplayCacheEntry) -> Result<(), Self::ReplayCacheError> {
-        self.entries.lock().unwrap().push(entry.to_owned());
+    async fn store(
+        &self,
+        entry: &ReplayCacheEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ReplayCacheError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .push((entry.to_owned(), Instant::now() + lifetime));
         Ok(())
     }
 
     async fn contain(&self, entry: &ReplayCacheEntry) -> Result<bool, Self::ReplayCacheError> {
-        Ok(self.entries.lock().unwrap().iter().any(|e| e == entry))
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(e, expiry)| e == entry && *expiry > Instant::now()))
     }
 }
 
@@ -274,8 +309,13 @@ impl CryptographicHash for MockedHasher {
         1
     }
 
+    /// Fixed-size like a real digest, which the PAC signatures rely on
     fn digest(&self, data: &[u8]) -> Vec<u8> {
-        data.iter().rev().cloned().collect()
+        let mut digest = vec![0; 20];
+        data.iter()
+            .enumerate()
+            .for_each(|(i, byte)| digest[i % 20] ^= byte.rotate_left((i / 20) as u32));
+        digest
     }
 }
 
@@ -309,7 +349,7 @@ impl LastReqDatabase for MockedLastReqDb {
 }
 
 pub struct MockedApReplayCache {
-    entries: Arc<Mutex<Vec<ApReplayEntry>>>,
+    entries: Arc<Mutex<Vec<(ApReplayEntry, Instant)>>>,
 }
 
 impl MockedApReplayCache {
@@ -324,13 +364,25 @@ impl MockedApReplayCache {
 impl ApReplayCache for MockedApReplayCache {
     type ApReplayCacheError = ();
 
-    async fn store(&self, entry: &ApReplayEntry) -> Result<(), Self::ApReplayCacheError> {
-        self.entries.lock().unwrap().push(entry.to_owned());
+    async fn store(
+        &self,
+        entry: &ApReplayEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ApReplayCacheError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .push((entry.to_owned(), Instant::now() + lifetime));
         Ok(())
     }
 
     async fn contain(&self, entry: &ApReplayEntry) -> Result<bool, Self::ApReplayCacheError> {
-        Ok(self.entries.lock().unwrap().iter().any(|e| e == entry))
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(e, expiry)| e == entry && *expiry > Instant::now()))
     }
 }
 
@@ -394,7 +446,9 @@ impl UserSessionStorage for MockedUserSessionStorage {
     }
 
     async fn store_session(&self, session: &UserSessionEntry) -> Result<(), Self::Error> {
-        self.sessions.lock().unwrap().push(session.clone());
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|s| s.cname != session.cname || s.crealm != session.crealm);
+        sessions.push(session.clone());
         Ok(())
     }
 }
@@ -1,14 +1,20 @@
 #[cfg(test)]
 mod tests;
 
+use crate::authorization_data::{self, PrincipalClaims};
 use crate::cryptographic_hash::CryptographicHash;
 use crate::cryptography::Cryptography;
-use crate::service_traits::{LastReqDatabase, PrincipalDatabase, ReplayCache};
+use crate::pac::{self, Pac};
+use crate::service_traits::{
+    LastReqDatabase, PrincipalAttribute, PrincipalAttributes, PrincipalDatabase, ReplayCache,
+    ReplayCacheEntry,
+};
+use crate::transit_policy::TransitPolicy;
 use chrono::Local;
-use derive_builder::Builder;
+use derive_builder::{Builder, UninitializedFieldError};
 use messages::basic_types::{
     AuthorizationData, Checksum, EncryptedData, EncryptionKey, Int32, KerberosTime, OctetString,
-    PrincipalName, Realm,
+    PaData, PaDataTypes, PaForUser, PaS4uX509User, PrincipalName, Realm,
 };
 use messages::flags::{KdcOptionsFlag, TicketFlag};
 use messages::{
@@ -16,6 +22,7 @@ use messages::{
     Encode, KrbErrorMsg, KrbErrorMsgBuilder, LastReq, TgsRep, TgsReq, Ticket, TicketFlags,
 };
 use std::cmp::min;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum ServerError {
@@ -35,10 +42,21 @@ where
     supported_checksum: Vec<Box<dyn CryptographicHash + Sync + Send>>,
     supported_crypto: Vec<Box<dyn Cryptography + Sync + Send>>,
     principal_db: &'a T,
+    /// Name of this ticket-granting service, `krbtgt/<realm>` unless set
+    #[builder(
+        default = "PrincipalName::krbtgt(self.realm.as_ref().ok_or(UninitializedFieldError::new(\"realm\"))?)"
+    )]
     name: PrincipalName,
     realm: Realm,
-    #[allow(dead_code)] // TODO: use this to detect replay attacks
     replay_cache: &'a C,
+    #[builder(default = "Duration::from_secs(60 * 5)")]
+    allowable_clock_skew: Duration,
+    #[builder(default)]
+    transit_policy: TransitPolicy,
+    /// Whether the tickets issued through S4U2Self carry a PAC, the PACs of other tickets
+    /// being copied from the TGT
+    #[builder(default)]
+    issue_pac: bool,
     last_req_db: &'a (dyn LastReqDatabase + Sync + Send),
 }
 
@@ -56,9 +74,8 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             .to_owned()
     }
 
-    fn is_tgt_local_realm(&self, _ticket: &Ticket) -> bool {
-        // TODO: Implement this
-        true
+    fn is_tgt_local_realm(&self, ticket: &Ticket) -> bool {
+        ticket.realm() == &self.realm
     }
 
     fn verify_padata(&self, tgs_req: &TgsReq) -> Result<ApReq, Ecode> {
@@ -73,15 +90,227 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
         Ok(ap_req)
     }
 
-    fn get_tgt_realm(&self, enc_ticket_part: &EncTicketPart) -> Realm {
-        enc_ticket_part.crealm().clone()
+    /// Decrypt the TGT carried in the additional tickets of a user-to-user request,
+    /// which must have been issued by this KDC and still be valid
+    async fn decrypt_additional_tgt(&self, ticket: &Ticket) -> Result<EncTicketPart, Ecode> {
+        if !self.is_tgt_local_realm(ticket) || ticket.sname() != &self.name {
+            return Err(Ecode::KDC_ERR_BADOPTION);
+        }
+        let key = self
+            .principal_db
+            .get_principal(ticket.sname(), ticket.realm())
+            .await
+            .ok_or(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN)?
+            .key;
+        let data = self
+            .supported_crypto
+            .iter()
+            .find(|crypto| crypto.get_etype() == *ticket.enc_part().etype())
+            .ok_or(Ecode::KDC_ERR_ETYPE_NOSUPP)?
+            .decrypt(
+                ticket.enc_part().cipher().as_bytes(),
+                key.keyvalue().as_bytes(),
+            )
+            .map_err(|_| Ecode::KRB_AP_ERR_BAD_INTEGRITY)?;
+        let enc_part =
+            EncTicketPart::from_der(&data).map_err(|_| Ecode::KRB_AP_ERR_BAD_INTEGRITY)?;
+        if enc_part.endtime() < KerberosTime::now() {
+            return Err(Ecode::KRB_AP_ERR_TKT_EXPIRED);
+        }
+        Ok(enc_part)
     }
 
-    fn compute_checksum(&self, data: &[u8], checksum_type: Int32) -> Option<Vec<u8>> {
+    fn find_padata<'r>(&self, tgs_req: &'r TgsReq, padata_type: PaDataTypes) -> Option<&'r PaData> {
+        tgs_req
+            .padata()
+            .iter()
+            .find_map(|padata| {
+                padata
+                    .iter()
+                    .find(|padata| *padata.padata_type() == padata_type as i32)
+            })
+    }
+
+    /// The S4U user data is protected by a checksum keyed with the TGT session key
+    fn verify_s4u_checksum(
+        &self,
+        tgt: &EncTicketPart,
+        data: &[u8],
+        checksum: &Checksum,
+    ) -> Result<(), Ecode> {
+        let expected = self
+            .supported_checksum
+            .iter()
+            .find(|c| c.get_checksum_type() == *checksum.cksumtype())
+            .map(|c| c.keyed_digest(tgt.key().keyvalue().as_bytes(), data))
+            .ok_or(Ecode::KDC_ERR_SUMTYPE_NOSUPP)?;
+        if expected != checksum.checksum().as_bytes() {
+            return Err(Ecode::KRB_AP_ERR_MODIFIED);
+        }
+        Ok(())
+    }
+
+    /// The user a service asks a ticket to itself for (S4U2Self), named either in
+    /// PA-S4U-X509-USER or in PA-FOR-USER,
+    /// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-sfu
+    fn s4u2self_user(
+        &self,
+        tgs_req: &TgsReq,
+        tgt: &EncTicketPart,
+    ) -> Result<Option<(PrincipalName, Realm)>, Ecode> {
+        if let Some(padata) = self.find_padata(tgs_req, PaDataTypes::PaS4uX509User) {
+            let pa_s4u_x509_user = PaS4uX509User::from_der(padata.padata_value().as_bytes())
+                .map_err(|_| Ecode::KDC_ERR_PADATA_TYPE_NOSUPP)?;
+            let user_id = pa_s4u_x509_user.user_id();
+            self.verify_s4u_checksum(
+                tgt,
+                &user_id.to_der().map_err(|_| Ecode::KRB_AP_ERR_MODIFIED)?,
+                pa_s4u_x509_user.checksum(),
+            )?;
+            if user_id.nonce() != *tgs_req.req_body().nonce() {
+                return Err(Ecode::KRB_AP_ERR_MODIFIED);
+            }
+            // Users cannot be looked up by certificate
+            let cname = user_id.cname().ok_or(Ecode::KDC_ERR_C_PRINCIPAL_UNKNOWN)?;
+            return Ok(Some((cname.clone(), user_id.crealm().clone())));
+        }
+        if let Some(padata) = self.find_padata(tgs_req, PaDataTypes::PaForUser) {
+            let pa_for_user = PaForUser::from_der(padata.padata_value().as_bytes())
+                .map_err(|_| Ecode::KDC_ERR_PADATA_TYPE_NOSUPP)?;
+            self.verify_s4u_checksum(
+                tgt,
+                &PaForUser::checksum_data(
+                    pa_for_user.user_name(),
+                    pa_for_user.user_realm(),
+                    pa_for_user.auth_package(),
+                ),
+                pa_for_user.cksum(),
+            )?;
+            return Ok(Some((
+                pa_for_user.user_name().clone(),
+                pa_for_user.user_realm().clone(),
+            )));
+        }
+        Ok(None)
+    }
+
+    /// Check a S4U2Self request of the service `tgt` was issued to, telling whether the
+    /// ticket may be forwardable, i.e. usable as evidence for S4U2Proxy
+    async fn check_s4u2self(
+        &self,
+        tgt: &EncTicketPart,
+        sname: &PrincipalName,
+        (user_name, user_realm): &(PrincipalName, Realm),
+    ) -> Result<bool, Ecode> {
+        // The service can only impersonate users to itself
+        if tgt.cname() != sname || tgt.crealm() != &self.realm {
+            return Err(Ecode::KDC_ERR_BADOPTION);
+        }
+        if user_realm != &self.realm {
+            return Err(Ecode::KDC_ERR_WRONG_REALM);
+        }
+        self.principal_db
+            .get_principal(user_name, user_realm)
+            .await
+            .ok_or(Ecode::KDC_ERR_C_PRINCIPAL_UNKNOWN)?;
+        let service = self
+            .principal_db
+            .get_principal(tgt.cname(), tgt.crealm())
+            .await
+            .ok_or(Ecode::KDC_ERR_C_PRINCIPAL_UNKNOWN)?;
+        Ok(!service.allowed_to_delegate_to.is_empty())
+    }
+
+    /// Decrypt the evidence ticket of a S4U2Proxy request, a forwardable ticket to the
+    /// service `tgt` was issued to, and check the service may delegate to `sname`
+    async fn decrypt_evidence_ticket(
+        &self,
+        tgs_req: &TgsReq,
+        tgt: &EncTicketPart,
+        sname: &PrincipalName,
+    ) -> Result<EncTicketPart, Ecode> {
+        let ticket = tgs_req
+            .req_body()
+            .additional_tickets()
+            .and_then(|tickets| tickets.first())
+            .ok_or(Ecode::KDC_ERR_BADOPTION)?;
+        if ticket.sname() != tgt.cname() || ticket.realm() != &self.realm {
+            return Err(Ecode::KDC_ERR_BADOPTION);
+        }
+        let service = self
+            .principal_db
+            .get_principal(tgt.cname(), &self.realm)
+            .await
+            .ok_or(Ecode::KDC_ERR_C_PRINCIPAL_UNKNOWN)?;
+        // Delegation targets are matched by their components, whatever the name type the
+        // service chose for them
+        if !service
+            .allowed_to_delegate_to
+            .iter()
+            .any(|target| target.name_string() == sname.name_string())
+        {
+            return Err(Ecode::KDC_ERR_BADOPTION);
+        }
+        let data = self
+            .supported_crypto
+            .iter()
+            .find(|crypto| crypto.get_etype() == *ticket.enc_part().etype())
+            .ok_or(Ecode::KDC_ERR_ETYPE_NOSUPP)?
+            .decrypt(
+                ticket.enc_part().cipher().as_bytes(),
+                service.key.keyvalue().as_bytes(),
+            )
+            .map_err(|_| Ecode::KRB_AP_ERR_BAD_INTEGRITY)?;
+        let evidence =
+            EncTicketPart::from_der(&data).map_err(|_| Ecode::KRB_AP_ERR_BAD_INTEGRITY)?;
+        if !evidence.flags().is_set(TicketFlag::FORWARDABLE as usize) {
+            return Err(Ecode::KDC_ERR_BADOPTION);
+        }
+        if evidence.endtime() < KerberosTime::now() {
+            return Err(Ecode::KRB_AP_ERR_TKT_EXPIRED);
+        }
+        Ok(evidence)
+    }
+
+    /// Key of this KDC, which signs the PACs it issues
+    async fn kdc_key(&self) -> Result<EncryptionKey, Ecode> {
+        self.principal_db
+            .get_principal(&self.name, &self.realm)
+            .await
+            .map(|record| record.key)
+            .ok_or(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN)
+    }
+
+    /// Sign the PACs of `authorization_data` for the server of key `server_key`, once checked
+    /// they were signed for the TGT of key `tgt_key`, when they come from a TGT, and issued
+    /// by this KDC, when `issued_here`
+    async fn resign_pacs(
+        &self,
+        authorization_data: &AuthorizationData,
+        tgt_key: Option<&EncryptionKey>,
+        issued_here: bool,
+        server_key: &EncryptionKey,
+    ) -> Result<AuthorizationData, Ecode> {
+        let kdc_key = self.kdc_key().await?;
+        pac::map_pacs(authorization_data, &mut |pac| {
+            if let Some(tgt_key) = tgt_key {
+                pac.verify_server_signature(&self.supported_checksum, tgt_key)?;
+            }
+            if issued_here {
+                pac.verify_kdc_signature(&self.supported_checksum, &kdc_key)?;
+            }
+            pac.resign(&self.supported_checksum, server_key, &kdc_key)
+        })
+    }
+
+    fn find_checksum(
+        &self,
+        checksum_type: Int32,
+    ) -> Option<&(dyn CryptographicHash + Sync + Send)> {
         self.supported_checksum
             .iter()
             .find(|c| c.get_checksum_type() == checksum_type)
-            .map(|c| c.digest(data))
+            .map(|c| c.as_ref())
     }
 
     fn generate_random_session_key(&self) -> Result<EncryptionKey, ServerError> {
@@ -96,25 +325,70 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
         ))
     }
 
-    fn replay_detected(&self, _ticket: &EncTicketPart) -> bool {
-        // TODO: Implement request replay detection, https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3.1
-        // This is a dummy implementation
-        false
+    /// Look the authenticator up in the replay cache, then remember it for the clock skew
+    /// window so that a second request carrying it is refused,
+    /// https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3.1
+    async fn replay_detected(&self, entry: &ReplayCacheEntry) -> TGSResult<bool> {
+        if self
+            .replay_cache
+            .contain(entry)
+            .await
+            .map_err(|_| ServerError::Internal)?
+        {
+            return Ok(true);
+        }
+        self.replay_cache
+            .store(entry, self.allowable_clock_skew)
+            .await
+            .map_err(|_| ServerError::Internal)?;
+        Ok(false)
+    }
+
+    /// Check the ticket of the authentication header is currently valid and was presented
+    /// by its owner, https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3.1
+    fn check_header_ticket(
+        &self,
+        tgt: &EncTicketPart,
+        authenticator: &Authenticator,
+        validate: bool,
+    ) -> Result<(), Ecode> {
+        let now = KerberosTime::now();
+        if authenticator.cname() != tgt.cname() || authenticator.crealm() != tgt.crealm() {
+            return Err(Ecode::KRB_AP_ERR_BADMATCH);
+        }
+        if authenticator.ctime().abs_diff(&now) > self.allowable_clock_skew {
+            return Err(Ecode::KRB_AP_ERR_SKEW);
+        }
+        // An invalid ticket can only be presented for validation, which checks its start time
+        if !validate {
+            if tgt.flags().is_set(TicketFlag::INVALID as usize) {
+                return Err(Ecode::KRB_AP_ERR_TKT_NYV);
+            }
+            if tgt.starttime().unwrap_or(tgt.authtime()) > now + self.allowable_clock_skew {
+                return Err(Ecode::KRB_AP_ERR_TKT_NYV);
+            }
+        }
+        if tgt.endtime() + self.allowable_clock_skew < now {
+            return Err(Ecode::KRB_AP_ERR_TKT_EXPIRED);
+        }
+        Ok(())
     }
 
-    fn is_checksum_supported(&self, _checksum: &Checksum) -> bool {
-        // TODO: Implement this
-        true
+    fn is_checksum_supported(&self, checksum: &Checksum) -> bool {
+        self.find_checksum(*checksum.cksumtype()).is_some()
     }
 
-    fn is_checksum_keyed(&self, _checksum: &Checksum) -> bool {
-        // TODO: Implement this
-        true
+    /// Every supported checksum is verified as an HMAC keyed with the session key, see
+    /// `CryptographicHash::keyed_digest`
+    fn is_checksum_keyed(&self, checksum: &Checksum) -> bool {
+        self.is_checksum_supported(checksum)
     }
 
-    fn is_checksum_collision_proof(&self, _checksum: &Checksum) -> bool {
-        // TODO: Implement this
-        true
+    /// A checksum shorter than the digest of its type was truncated, which makes collisions
+    /// easier to find
+    fn is_checksum_collision_proof(&self, checksum: &Checksum) -> bool {
+        self.find_checksum(*checksum.cksumtype())
+            .is_some_and(|hash| hash.digest(&[]).len() <= checksum.checksum().as_bytes().len())
     }
 
     pub async fn handle_tgs_req(&self, tgs_req: &TgsReq) -> TGSResult<TgsRep> {
@@ -134,47 +408,124 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             .verify_padata(tgs_req)
             .map_err(&mut build_protocol_error)?;
 
-        let server = self
-            .principal_db
-            .get_principal(
-                tgs_req
-                    .req_body()
-                    .sname()
-                    .ok_or(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN))?,
-                tgs_req.req_body().realm(),
+        let kdc_options = tgs_req.req_body().kdc_options();
+
+        // The additional ticket is either the TGT of a user-to-user server or S4U2Proxy evidence
+        if kdc_options.is_set(KdcOptionsFlag::ENC_TKT_IN_SKEY as usize)
+            && kdc_options.is_set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize)
+        {
+            return Err(build_protocol_error(Ecode::KDC_ERR_BADOPTION));
+        }
+
+        // User-to-user: the server only holds a TGT, the new ticket is encrypted in its
+        // session key rather than in a long-term key,
+        // https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3
+        let additional_tgt = if kdc_options.is_set(KdcOptionsFlag::ENC_TKT_IN_SKEY as usize) {
+            let ticket = tgs_req
+                .req_body()
+                .additional_tickets()
+                .and_then(|tickets| tickets.first())
+                .ok_or(build_protocol_error(Ecode::KDC_ERR_BADOPTION))?;
+            Some(
+                self.decrypt_additional_tgt(ticket)
+                    .await
+                    .map_err(&mut build_protocol_error)?,
             )
-            .await
-            .ok_or(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN))?;
+        } else {
+            None
+        };
+
+        // Renewal and validation replace the presented ticket with a new one for the same
+        // server, which only the KDC of the ticket's realm can do
+        let renew = kdc_options.is_set(KdcOptionsFlag::RENEW as usize);
+        let validate = kdc_options.is_set(KdcOptionsFlag::VALIDATE as usize);
+        let replaces_ticket = renew || validate;
+        if replaces_ticket && !self.is_tgt_local_realm(ap_req.ticket()) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_BADOPTION));
+        }
+
+        let sname = match (&additional_tgt, tgs_req.req_body().sname()) {
+            _ if replaces_ticket => ap_req.ticket().sname().clone(),
+            (Some(additional_tgt), requested_sname) => {
+                if requested_sname.is_some_and(|sname| sname != additional_tgt.cname()) {
+                    return Err(build_protocol_error(Ecode::KDC_ERR_SERVER_NOMATCH));
+                }
+                additional_tgt.cname().clone()
+            }
+            // A service of a foreign realm can only be reached through a krbtgt/REMOTE@LOCAL
+            // ticket, encrypted with the inter-realm key this realm shares with the remote one
+            (None, Some(_)) if tgs_req.req_body().realm() != &self.realm => {
+                PrincipalName::krbtgt(tgs_req.req_body().realm())
+            }
+            (None, Some(requested_sname)) => requested_sname.clone(),
+            (None, None) => return Err(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN)),
+        };
+
+        // The server of a user-to-user ticket is known by its TGT, it may have no record
+        let server = self.principal_db.get_principal(&sname, &self.realm).await;
+        let server_key = match (&additional_tgt, &server) {
+            (Some(additional_tgt), _) => additional_tgt.key().clone(),
+            (None, Some(server)) => server.key.clone(),
+            (None, None) => return Err(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN)),
+        };
+        let server_attributes = server.map(|server| server.attributes).unwrap_or_default();
+        if server_attributes.has(PrincipalAttribute::DisallowAllTix) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN));
+        }
+        if server_attributes.has(PrincipalAttribute::DisallowTgtBased) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_POLICY));
+        }
+        match &additional_tgt {
+            None if server_attributes.has(PrincipalAttribute::DisallowSvr) => {
+                return Err(build_protocol_error(Ecode::KDC_ERR_MUST_USE_USER2USER));
+            }
+            Some(_) if server_attributes.has(PrincipalAttribute::DisallowDupSkey) => {
+                return Err(build_protocol_error(Ecode::KDC_ERR_POLICY));
+            }
+            _ => {}
+        }
 
-        let kdc_options = tgs_req.req_body().kdc_options();
         let auth_header = ap_req;
         let tgt = auth_header.ticket();
 
-        if !self.is_tgt_local_realm(tgt) && tgs_req.req_body().sname() != tgt.sname().into() {
+        // A TGT issued by another realm must be a krbtgt/LOCAL@REMOTE ticket
+        if !self.is_tgt_local_realm(tgt) && tgt.sname().krbtgt_realm() != Some(&self.realm) {
+            return Err(build_protocol_error(Ecode::KRB_AP_ERR_NOT_US));
+        }
+        // A local ticket must be our own TGT, unless it is a service ticket being renewed
+        // or validated
+        if self.is_tgt_local_realm(tgt) && !replaces_ticket && tgt.sname() != &self.name {
             return Err(build_protocol_error(Ecode::KRB_AP_ERR_NOT_US));
         }
 
+        // Local TGTs are encrypted with our own key, cross-realm TGTs with the inter-realm
+        // key shared with their issuer, both are registered under the ticket's server name
+        let tgs_key = self
+            .principal_db
+            .get_principal(tgt.sname(), tgt.realm())
+            .await
+            .ok_or(build_protocol_error(Ecode::KRB_AP_ERR_NOT_US))?
+            .key;
+
         let tgt = find_crypto_for_etype(*tgt.enc_part().etype())
             .ok_or(build_protocol_error(Ecode::KDC_ERR_ETYPE_NOSUPP))?
             .decrypt(
                 tgt.enc_part().cipher().as_bytes(),
-                server.key.keyvalue().as_bytes(),
+                tgs_key.keyvalue().as_bytes(),
             )
-            .map_err(|_| ServerError::Internal)
+            .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_BAD_INTEGRITY))
             .and_then(|data| {
                 EncTicketPart::from_der(data.as_slice())
                     .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_BAD_INTEGRITY))
             })?;
 
-        let realm = self.get_tgt_realm(&tgt);
-
         let authenticator = find_crypto_for_etype(*tgt.key().keytype())
             .ok_or(build_protocol_error(Ecode::KDC_ERR_ETYPE_NOSUPP))?
             .decrypt(
                 auth_header.authenticator().cipher().as_bytes(),
                 tgt.key().keyvalue().as_bytes(),
             )
-            .map_err(|_| ServerError::Internal)
+            .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_BAD_INTEGRITY))
             .and_then(|data| {
                 Authenticator::from_der(data.as_slice())
                     .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_BAD_INTEGRITY))
@@ -196,18 +547,79 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
                 Ok(t)
             })
             .and_then(|c| {
-                let checksum = self
-                    .compute_checksum(
-                        tgs_req.req_body().to_der().unwrap().as_slice(),
-                        *c.cksumtype(),
-                    )
+                // The request body is bound to the authenticator by a checksum keyed with the
+                // TGT session key, some clients using the authenticator subkey instead
+                let hash = self
+                    .find_checksum(*c.cksumtype())
                     .ok_or(build_protocol_error(Ecode::KDC_ERR_SUMTYPE_NOSUPP))?;
-                if checksum != c.checksum().as_bytes() {
+                let req_body = tgs_req.req_body().to_der().unwrap();
+                let matches = |key: &EncryptionKey| {
+                    hash.keyed_digest(key.keyvalue().as_bytes(), &req_body)
+                        == c.checksum().as_bytes()
+                };
+                if !(matches(tgt.key()) || authenticator.subkey().is_some_and(matches)) {
                     return Err(build_protocol_error(Ecode::KRB_AP_ERR_MODIFIED));
                 }
                 Ok(c)
             })?;
 
+        self.check_header_ticket(&tgt, &authenticator, validate)
+            .map_err(&mut build_protocol_error)?;
+
+        let replay_entry = ReplayCacheEntry {
+            server_name: auth_header.ticket().sname().clone(),
+            client_name: authenticator.cname().clone(),
+            time: authenticator.ctime(),
+            microseconds: authenticator.cusec(),
+        };
+        if self.replay_detected(&replay_entry).await? {
+            return Err(build_protocol_error(Ecode::KRB_AP_ERR_REPEAT));
+        }
+
+        // A local client may have been locked out or restricted since its TGT was issued
+        let client_attributes = if tgt.crealm() == &self.realm {
+            self.principal_db
+                .get_principal(tgt.cname(), tgt.crealm())
+                .await
+                .map(|client| client.attributes)
+                .unwrap_or_default()
+        } else {
+            PrincipalAttributes::default()
+        };
+        if client_attributes.has(PrincipalAttribute::DisallowAllTix) {
+            return Err(build_protocol_error(Ecode::KDC_ERR_CLIENT_REVOKED));
+        }
+
+        // With S4U2Self and S4U2Proxy, the ticket is issued to the user on whose behalf
+        // the service acts rather than to the service itself
+        let mut may_forward = true;
+        let s4u2self_user = self
+            .s4u2self_user(tgs_req, &tgt)
+            .map_err(&mut build_protocol_error)?;
+        if let Some(user) = &s4u2self_user {
+            may_forward = self
+                .check_s4u2self(&tgt, &sname, user)
+                .await
+                .map_err(&mut build_protocol_error)?;
+        }
+        let evidence = if kdc_options.is_set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize) {
+            Some(
+                self.decrypt_evidence_ticket(tgs_req, &tgt, &sname)
+                    .await
+                    .map_err(&mut build_protocol_error)?,
+            )
+        } else {
+            None
+        };
+        let (cname, crealm) = match (&s4u2self_user, &evidence) {
+            (Some(user), _) => user.clone(),
+            (None, Some(evidence)) => (evidence.cname().clone(), evidence.crealm().clone()),
+            (None, None) => (authenticator.cname().clone(), tgt.crealm().clone()),
+        };
+        let authtime = evidence
+            .as_ref()
+            .map_or(tgt.authtime(), |evidence| evidence.authtime());
+
         let session_key = self.generate_random_session_key()?;
 
         let use_etype = tgs_req
@@ -225,85 +637,135 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             new_ticket_enc_part.caddr(caddr.clone());
         }
 
-        let mut new_ticket_flags = TicketFlags::builder();
+        // A renewed or validated ticket keeps the flags of the one it replaces
+        let mut new_ticket_flags = if replaces_ticket {
+            tgt.flags().to_builder()
+        } else {
+            TicketFlags::builder()
+        };
 
-        // Check if the flag is set in the tgs request, if not return a protocol error
-        let mut check_tgs_req_flag = |flag: KdcOptionsFlag, err: Ecode| {
-            if !tgs_req.req_body().kdc_options().is_set(flag as usize) {
+        // Check if the flag is set in the TGT, if not return a protocol error: a ticket can only
+        // be granted the capabilities of the ticket it was obtained with
+        let mut check_tgt_flag = |flag: TicketFlag, err: Ecode| {
+            if !tgt.flags().is_set(flag as usize) {
                 return Err(build_protocol_error(err));
             }
             Ok(())
         };
 
-        if kdc_options.is_set(KdcOptionsFlag::FORWARDABLE as usize) {
-            check_tgs_req_flag(KdcOptionsFlag::FORWARDABLE, Ecode::KDC_ERR_BADOPTION)?;
-            new_ticket_flags.set(TicketFlag::FORWARDABLE as usize);
-        }
+        // Capabilities the server is not allowed are left out of the ticket
+        if !replaces_ticket {
+            if kdc_options.is_set(KdcOptionsFlag::FORWARDABLE as usize)
+                && may_forward
+                && !server_attributes.has(PrincipalAttribute::DisallowForwardable)
+            {
+                check_tgt_flag(TicketFlag::FORWARDABLE, Ecode::KDC_ERR_BADOPTION)?;
+                new_ticket_flags.set(TicketFlag::FORWARDABLE as usize);
+            }
 
-        if kdc_options.is_set(KdcOptionsFlag::FORWARDED as usize) {
-            check_tgs_req_flag(KdcOptionsFlag::FORWARDED, Ecode::KDC_ERR_BADOPTION)?;
-            new_ticket_flags.set(TicketFlag::FORWARDED as usize);
-            if let Some(caddr) = tgt.caddr() {
-                new_ticket_enc_part.caddr(caddr.clone());
-                tgt_rep.caddr(caddr.clone());
+            if kdc_options.is_set(KdcOptionsFlag::FORWARDED as usize) {
+                check_tgt_flag(TicketFlag::FORWARDABLE, Ecode::KDC_ERR_BADOPTION)?;
+                new_ticket_flags.set(TicketFlag::FORWARDED as usize);
+                if let Some(caddr) = tgt.caddr() {
+                    new_ticket_enc_part.caddr(caddr.clone());
+                    tgt_rep.caddr(caddr.clone());
+                }
             }
-        }
 
-        if tgt.flags().is_set(TicketFlag::FORWARDED as usize) {
-            new_ticket_flags.set(TicketFlag::FORWARDED as usize);
-        }
+            if tgt.flags().is_set(TicketFlag::FORWARDED as usize) {
+                new_ticket_flags.set(TicketFlag::FORWARDED as usize);
+            }
 
-        if kdc_options.is_set(KdcOptionsFlag::PROXIABLE as usize) {
-            check_tgs_req_flag(KdcOptionsFlag::PROXIABLE, Ecode::KDC_ERR_BADOPTION)?;
-            new_ticket_flags.set(TicketFlag::PROXIABLE as usize);
-        }
+            if kdc_options.is_set(KdcOptionsFlag::PROXIABLE as usize)
+                && !server_attributes.has(PrincipalAttribute::DisallowProxiable)
+            {
+                check_tgt_flag(TicketFlag::PROXIABLE, Ecode::KDC_ERR_BADOPTION)?;
+                new_ticket_flags.set(TicketFlag::PROXIABLE as usize);
+            }
 
-        if kdc_options.is_set(KdcOptionsFlag::PROXY as usize) {
-            check_tgs_req_flag(KdcOptionsFlag::PROXY, Ecode::KDC_ERR_BADOPTION)?;
-            new_ticket_flags.set(TicketFlag::PROXY as usize);
-            if let Some(caddr) = tgt.caddr() {
-                new_ticket_enc_part.caddr(caddr.clone());
-                tgt_rep.caddr(caddr.clone());
+            if server_attributes.has(PrincipalAttribute::OkAsDelegate) {
+                new_ticket_flags.set(TicketFlag::OK_AS_DELEGATE as usize);
+            }
+
+            if kdc_options.is_set(KdcOptionsFlag::PROXY as usize) {
+                check_tgt_flag(TicketFlag::PROXIABLE, Ecode::KDC_ERR_BADOPTION)?;
+                new_ticket_flags.set(TicketFlag::PROXY as usize);
+                if let Some(caddr) = tgt.caddr() {
+                    new_ticket_enc_part.caddr(caddr.clone());
+                    tgt_rep.caddr(caddr.clone());
+                }
+            }
+
+            if kdc_options.is_set(KdcOptionsFlag::RENEWABLE as usize) {
+                check_tgt_flag(TicketFlag::RENEWABLE, Ecode::KDC_ERR_BADOPTION)?;
+            }
+
+            if kdc_options.is_set(KdcOptionsFlag::ALLOW_POSTDATE as usize) {
+                check_tgt_flag(TicketFlag::MAY_POSTDATE, Ecode::KDC_ERR_BADOPTION)?;
+                new_ticket_flags.set(TicketFlag::MAY_POSTDATE as usize);
+            }
+
+            // A postdated ticket is issued invalid, to be validated once its start time has come
+            if kdc_options.is_set(KdcOptionsFlag::POSTDATED as usize) {
+                check_tgt_flag(TicketFlag::MAY_POSTDATE, Ecode::KDC_ERR_BADOPTION)?;
+                if client_attributes.has(PrincipalAttribute::DisallowPostdated)
+                    || server_attributes.has(PrincipalAttribute::DisallowPostdated)
+                {
+                    return Err(build_protocol_error(Ecode::KDC_ERR_CANNOT_POSTDATE));
+                }
+                new_ticket_flags.set(TicketFlag::POSTDATED as usize);
+                new_ticket_flags.set(TicketFlag::INVALID as usize);
             }
         }
 
-        // TODO: Check postdate flags
+        let kdc_time = KerberosTime::now();
 
-        if kdc_options.is_set(KdcOptionsFlag::VALIDATE as usize) {
-            check_tgs_req_flag(KdcOptionsFlag::INVALID, Ecode::KDC_ERR_POLICY)?;
-            if tgt.starttime().unwrap_or(KerberosTime::zero()) > KerberosTime::now() {
-                return Err(build_protocol_error(Ecode::KRB_AP_ERR_TKT_NYV));
+        // A postdated ticket is issued invalid, and validated once its start time has come,
+        // https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3
+        if validate {
+            if !tgt.flags().is_set(TicketFlag::INVALID as usize) {
+                return Err(build_protocol_error(Ecode::KDC_ERR_BADOPTION));
             }
-            if self.replay_detected(&tgt) {
-                return Err(build_protocol_error(Ecode::KRB_AP_ERR_REPEAT));
+            if tgt.starttime().unwrap_or(tgt.authtime()) > kdc_time {
+                return Err(build_protocol_error(Ecode::KRB_AP_ERR_TKT_NYV));
             }
-            new_ticket_flags.set(TicketFlag::INVALID as usize);
+            new_ticket_flags.unset(TicketFlag::INVALID as usize);
         }
 
         // TODO: more flag verification
 
-        new_ticket_enc_part.authtime(tgt.authtime());
-
-        let kdc_time = KerberosTime::now();
+        new_ticket_enc_part.authtime(authtime);
 
         let mut rtime = None;
 
-        if kdc_options.is_set(KdcOptionsFlag::RENEW as usize) {
+        if renew {
+            // The renewed ticket keeps the lifetime of the original one, up to its renew-till
             if !tgt.flags().is_set(TicketFlag::RENEWABLE as usize) {
                 return Err(build_protocol_error(Ecode::KDC_ERR_BADOPTION));
             }
-            if tgt.renew_till().unwrap_or(KerberosTime::zero()) >= kdc_time {
+            let renew_till = tgt
+                .renew_till()
+                .ok_or(build_protocol_error(Ecode::KDC_ERR_BADOPTION))?;
+            if renew_till < kdc_time {
                 return Err(build_protocol_error(Ecode::KRB_AP_ERR_TKT_EXPIRED));
             }
-            new_ticket_enc_part.starttime(kdc_time);
             let old_life = tgt.endtime() - tgt.starttime().unwrap_or(tgt.authtime());
-            new_ticket_enc_part.endtime(min(
-                kdc_time + old_life,
-                tgt.renew_till().unwrap_or(KerberosTime::max()),
-            ));
             new_ticket_enc_part.starttime(kdc_time);
+            new_ticket_enc_part.endtime(min(kdc_time + old_life, renew_till));
+            new_ticket_enc_part.renew_till(renew_till);
+        } else if validate {
+            new_ticket_enc_part.starttime(tgt.starttime().unwrap_or(tgt.authtime()));
+            new_ticket_enc_part.endtime(tgt.endtime());
+            if let Some(renew_till) = tgt.renew_till() {
+                new_ticket_enc_part.renew_till(renew_till);
+            }
         } else {
-            new_ticket_enc_part.starttime(kdc_time);
+            let starttime = if kdc_options.is_set(KdcOptionsFlag::POSTDATED as usize) {
+                tgs_req.req_body().from().copied().unwrap_or(kdc_time)
+            } else {
+                kdc_time
+            };
+            new_ticket_enc_part.starttime(starttime);
             let till = if tgs_req.req_body().till() == &KerberosTime::zero() {
                 KerberosTime::max()
             } else {
@@ -320,6 +782,10 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             .iter()
             .min()
             .unwrap();
+            // A delegated ticket cannot outlive the evidence of the user's authentication
+            let new_tkt_endtime = evidence
+                .as_ref()
+                .map_or(new_tkt_endtime, |evidence| min(new_tkt_endtime, evidence.endtime()));
 
             new_ticket_enc_part.endtime(new_tkt_endtime);
 
@@ -342,7 +808,10 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             .filter(|&t| t != KerberosTime::zero())
             .unwrap_or(KerberosTime::infinity());
 
-        if kdc_options.is_set(KdcOptionsFlag::RENEWABLE as usize) {
+        if kdc_options.is_set(KdcOptionsFlag::RENEWABLE as usize)
+            && !replaces_ticket
+            && !server_attributes.has(PrincipalAttribute::DisallowRenewable)
+        {
             new_ticket_flags.set(TicketFlag::RENEWABLE as usize);
             new_ticket_enc_part.renew_till(
                 *[
@@ -357,77 +826,139 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             );
         }
 
-        let decrypted_auth = tgs_req
-            .req_body()
-            .enc_authorization_data()
-            .map(|auth_data| {
-                let key = authenticator
-                    .subkey()
-                    .expect("enc_authorization_data must be decrypted with subkey");
-                find_crypto_for_etype(*key.keytype())
-                    .ok_or(build_protocol_error(Ecode::KDC_ERR_ETYPE_NOSUPP))
-                    .and_then(|crypto| {
-                        crypto
-                            .decrypt(auth_data.cipher().as_bytes(), key.keyvalue().as_bytes())
-                            .map_err(|_| ServerError::Internal)
-                            .and_then(|data| {
-                                AuthorizationData::from_der(data.as_slice())
-                                    .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_MODIFIED))
-                            })
-                    })
-            });
-
-        let auth_data = decrypted_auth.map(|decrypted_auth| {
-            let auth_data = authenticator
-                .authorization_data()
-                .expect("authorization data should be present in authenticator")
-                .iter()
-                .cloned()
-                .chain(decrypted_auth?)
-                .collect::<AuthorizationData>();
-            Ok(auth_data)
-        });
-        if let Some(auth_data) = auth_data {
-            new_ticket_enc_part.authorization_data(auth_data?);
+        // The authorization data of the TGT, or of the evidence ticket with S4U2Proxy, is
+        // copied into the new ticket, followed by the one the client encrypted in the request
+        // with its subkey, or else the TGT session key,
+        // https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3. The TGT of a S4U2Self
+        // request belongs to the service, so the PAC and claims of the user are issued instead
+        let source = evidence.as_ref().unwrap_or(&tgt);
+        let mut authorization_data = match (&s4u2self_user, source.authorization_data()) {
+            (Some((user, realm)), _) => {
+                let mut authorization_data = vec![];
+                if self.issue_pac {
+                    let kdc_key = self.kdc_key().await.map_err(&mut build_protocol_error)?;
+                    authorization_data.push(
+                        Pac::issue(
+                            &self.supported_checksum,
+                            &server_key,
+                            &kdc_key,
+                            user,
+                            realm,
+                            authtime,
+                            &self.name,
+                        )
+                        .and_then(|pac| pac.to_authorization_data())
+                        .map_err(&mut build_protocol_error)?,
+                    );
+                }
+                let groups = if realm == &self.realm {
+                    self.principal_db.get_groups(user, realm).await
+                } else {
+                    vec![]
+                };
+                authorization_data.extend(
+                    authorization_data::issue_claims(
+                        &self.supported_checksum,
+                        &session_key,
+                        &PrincipalClaims::from_groups(&groups),
+                        &self.realm,
+                        &self.name,
+                    )
+                    .map_err(&mut build_protocol_error)?,
+                );
+                authorization_data
+            }
+            (None, Some(auth_data)) => {
+                let authorization_data = authorization_data::copy_from_tgt(
+                    &self.supported_checksum,
+                    source.key(),
+                    &session_key,
+                    auth_data,
+                    &self.realm,
+                    &self.name,
+                )
+                .map_err(&mut build_protocol_error)?;
+                if pac::contains_pac(&authorization_data) {
+                    self.resign_pacs(
+                        &authorization_data,
+                        evidence.is_none().then_some(&tgs_key),
+                        evidence.is_some() || self.is_tgt_local_realm(auth_header.ticket()),
+                        &server_key,
+                    )
+                    .await
+                    .map_err(&mut build_protocol_error)?
+                } else {
+                    authorization_data
+                }
+            }
+            (None, None) => vec![],
+        };
+        if let Some(enc_auth_data) = tgs_req.req_body().enc_authorization_data() {
+            let key = authenticator.subkey().unwrap_or(tgt.key());
+            let requested = find_crypto_for_etype(*key.keytype())
+                .ok_or(build_protocol_error(Ecode::KDC_ERR_ETYPE_NOSUPP))?
+                .decrypt(enc_auth_data.cipher().as_bytes(), key.keyvalue().as_bytes())
+                .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_BAD_INTEGRITY))
+                .and_then(|data| {
+                    AuthorizationData::from_der(data.as_slice())
+                        .map_err(|_| build_protocol_error(Ecode::KRB_AP_ERR_MODIFIED))
+                })?;
+            authorization_data::check_requested(&requested).map_err(&mut build_protocol_error)?;
+            authorization_data.extend(requested);
+        }
+        if !authorization_data.is_empty() {
+            new_ticket_enc_part.authorization_data(authorization_data);
         }
 
-        new_ticket_enc_part.key(session_key.clone());
-        new_ticket_enc_part.crealm(tgt.crealm().clone());
-        new_ticket_enc_part.cname(authenticator.cname().clone());
-        new_ticket_enc_part.flags(new_ticket_flags.build().unwrap());
+        // The realm which issued a cross-realm TGT has been transited, unless it is the
+        // client's own realm, https://www.rfc-editor.org/rfc/rfc4120#section-3.3.3.2
+        let issuing_realm = auth_header.ticket().realm();
+        let transited =
+            if !self.is_tgt_local_realm(auth_header.ticket()) && issuing_realm != tgt.crealm() {
+                tgt.transited().with_realm(issuing_realm)
+            } else {
+                tgt.transited().clone()
+            };
 
-        if self.get_tgt_realm(&tgt) == realm {
-            new_ticket_enc_part.transited(tgt.transited().clone());
-        } else {
-            todo!("Inter-realm is currently not supported");
+        // Nothing was transited by a client of this realm, so there is nothing to check
+        let has_transited = tgt.crealm() != &self.realm || !transited.contents().is_empty();
+        if has_transited && !kdc_options.is_set(KdcOptionsFlag::DISABLE_TRANSITED_CHECK as usize) {
+            let transited_realms = transited
+                .realms(tgt.crealm(), &self.realm)
+                .map_err(|_| build_protocol_error(Ecode::KDC_ERR_TRTYPE_NOSUPP))?;
+            if !self
+                .transit_policy
+                .is_path_allowed(tgt.crealm(), &self.realm, &transited_realms)
+            {
+                return Err(build_protocol_error(Ecode::KDC_ERR_POLICY));
+            }
+            new_ticket_flags.set(TicketFlag::TRANSITED_POLICY_CHECKED as usize);
         }
+        new_ticket_enc_part.transited(transited);
+
+        new_ticket_enc_part.key(session_key.clone());
+        new_ticket_enc_part.crealm(crealm.clone());
+        new_ticket_enc_part.cname(cname.clone());
+        new_ticket_enc_part.flags(new_ticket_flags.build().unwrap());
 
         let ticket = new_ticket_enc_part.build().expect("ticket should be built");
         // Only encrypt case where server is specified
         let encrypted_ticket = self
             .supported_crypto
             .iter()
-            .find(|crypto| crypto.get_etype() == *server.key.keytype())
+            .find(|crypto| crypto.get_etype() == *server_key.keytype())
             .ok_or(build_protocol_error(Ecode::KDC_ERR_ETYPE_NOSUPP))?
             .encrypt(
                 &ticket.to_der().expect("ticket should be encoded"),
-                server.key.keyvalue().as_bytes(),
+                server_key.keyvalue().as_bytes(),
             )
             .map(|data| {
-                EncryptedData::new(*server.key.keytype(), None, OctetString::new(data).unwrap())
+                EncryptedData::new(*server_key.keytype(), None, OctetString::new(data).unwrap())
             })
             .map_err(|_| ServerError::Internal)
             .unwrap();
 
-        let new_ticket = Ticket::new(
-            realm.clone(),
-            tgs_req
-                .req_body()
-                .sname()
-                .expect("sname should be present in tgs_req")
-                .clone(),
-            encrypted_ticket,
-        );
+        let new_ticket = Ticket::new(self.realm.clone(), sname.clone(), encrypted_ticket);
 
         tgt_rep.key(session_key.clone());
         let last_req = self
@@ -438,16 +969,14 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
         }
         tgt_rep.nonce(*tgs_req.req_body().nonce());
         tgt_rep.flags(new_ticket_flags.build().unwrap());
-        tgt_rep.authtime(tgt.authtime());
-        if let Some(starttime) = tgt.starttime() {
+        tgt_rep.authtime(authtime);
+        if let Some(starttime) = ticket.starttime() {
             tgt_rep.starttime(starttime);
         }
         tgt_rep.endtime(ticket.endtime());
 
-        if let Some(sname) = tgs_req.req_body().sname() {
-            tgt_rep.sname(sname.clone());
-        }
-        tgt_rep.srealm(realm.clone());
+        tgt_rep.sname(sname);
+        tgt_rep.srealm(self.realm.clone());
         if ticket.flags().is_set(TicketFlag::RENEWABLE as usize) {
             if let Some(renew_till) = ticket.renew_till() {
                 tgt_rep.renew_till(renew_till);
@@ -488,13 +1017,7 @@ impl<'a, T: PrincipalDatabase + Sync + Send, C: ReplayCache + Sync + Send>
             )
         };
 
-        Ok(TgsRep::new(
-            None,
-            tgt.crealm().clone(),
-            tgt.cname().clone(),
-            new_ticket,
-            tgs_rep,
-        ))
+        Ok(TgsRep::new(None, crealm, cname, new_ticket, tgs_rep))
     }
 
     async fn fetch_last_request_info(
@@ -1,15 +1,27 @@
+use crate::authorization_data::{
+    sign_kdc_issued, ticket_elements, AuthorizationElement, PrincipalClaims,
+};
 use crate::cryptographic_hash::CryptographicHash;
 use crate::cryptography::Cryptography;
-use crate::service_traits::{PrincipalDatabase, PrincipalDatabaseRecord, ReplayCache};
+use crate::pac::Pac;
+use crate::service_traits::{
+    PrincipalAttribute, PrincipalAttributes, PrincipalDatabase, PrincipalDatabaseRecord,
+    PrincipalGroup, ReplayCache,
+};
 use crate::tests_common::mocked::{MockedCrypto, MockedHasher, MockedLastReqDb, MockedPrincipalDb, MockedReplayCache};
-use crate::ticket_granting_service::{TicketGrantingService, TicketGrantingServiceBuilder};
+use crate::transit_policy::TransitPolicy;
+use crate::ticket_granting_service::{
+    ServerError, TicketGrantingService, TicketGrantingServiceBuilder,
+};
 use messages::basic_types::{
-    Checksum, EncryptedData, EncryptionKey, KerberosFlags, KerberosString, KerberosTime, NameTypes,
-    OctetString, PaData, PrincipalName, SequenceOf,
+    ADEntry, AuthorizationData, AuthorizationDataTypes, Checksum, EncryptedData, EncryptionKey,
+    KerberosFlags, KerberosString, KerberosTime, NameTypes, OctetString, PaData, PaDataTypes,
+    PaForUser, PrincipalName, SequenceOf,
 };
+use messages::flags::{KdcOptionsFlag, TicketFlag};
 use messages::{
-    APOptions, ApReq, AuthenticatorBuilder, Decode, EncTicketPart,
-    Encode, KdcReqBody, KdcReqBodyBuilder, TgsReq, Ticket, TransitedEncoding,
+    APOptions, ApReq, AuthenticatorBuilder, Decode, Ecode, EncTicketPart,
+    Encode, KdcReqBody, KdcReqBodyBuilder, TgsRep, TgsReq, Ticket, TransitedEncoding,
 };
 use std::sync::LazyLock;
 use std::time::Duration;
@@ -28,7 +40,21 @@ static SERVER_KEY: LazyLock<EncryptionKey> = LazyLock::new(|| {
     )
 });
 
-static  SESSION_KEY: LazyLock<EncryptionKey> = LazyLock::new(|| {
+static TGS_KEY: LazyLock<EncryptionKey> = LazyLock::new(|| {
+    EncryptionKey::new(
+        1,
+        OctetString::new(vec![0x4; 16]).unwrap(), // Mocked key
+    )
+});
+
+static INTER_REALM_KEY: LazyLock<EncryptionKey> = LazyLock::new(|| {
+    EncryptionKey::new(
+        1,
+        OctetString::new(vec![0x5; 16]).unwrap(), // Mocked key
+    )
+});
+
+static SESSION_KEY: LazyLock<EncryptionKey> = LazyLock::new(|| {
     EncryptionKey::new(
         1,
         OctetString::new(vec![0x3; 16]).unwrap(), // Mocked key
@@ -44,6 +70,21 @@ fn make_principal_name_unsafe(name: &str) -> PrincipalName {
 }
 
 fn make_principal_db() -> MockedPrincipalDb {
+    make_principal_db_with_delegation(vec![])
+}
+
+/// Principal database where "service" may delegate to `allowed_to_delegate_to`
+fn make_principal_db_with_delegation(
+    allowed_to_delegate_to: Vec<PrincipalName>,
+) -> MockedPrincipalDb {
+    make_principal_db_with(allowed_to_delegate_to, PrincipalAttributes::default())
+}
+
+/// Same as `make_principal_db_with_delegation`, "service" having `service_attributes`
+fn make_principal_db_with(
+    allowed_to_delegate_to: Vec<PrincipalName>,
+    service_attributes: PrincipalAttributes,
+) -> MockedPrincipalDb {
     let principal_database = MockedPrincipalDb::new();
     principal_database.add_principal(
         make_principal_name_unsafe("host"),
@@ -57,6 +98,21 @@ fn make_principal_db() -> MockedPrincipalDb {
             ),
             p_kvno: None,
             supported_encryption_types: vec![1, 3, 23, 18],
+            allowed_to_delegate_to: vec![],
+            attributes: PrincipalAttributes::default(),
+        },
+    );
+    principal_database.add_principal(
+        make_principal_name_unsafe("tgs"),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        PrincipalDatabaseRecord {
+            max_renewable_life: Duration::from_secs(3600 * 24),
+            max_lifetime: Duration::from_secs(3600 * 24),
+            key: TGS_KEY.clone(),
+            p_kvno: None,
+            supported_encryption_types: vec![1, 3, 23, 18],
+            allowed_to_delegate_to: vec![],
+            attributes: PrincipalAttributes::default(),
         },
     );
     principal_database.add_principal(
@@ -68,6 +124,8 @@ fn make_principal_db() -> MockedPrincipalDb {
             key: SERVER_KEY.clone(),
             p_kvno: None,
             supported_encryption_types: vec![1, 3, 23, 18],
+            allowed_to_delegate_to,
+            attributes: service_attributes,
         },
     );
     principal_database
@@ -99,9 +157,24 @@ fn make_basic_tgs_request(
     realm: &str,
     cname: &str,
     pa_data: Option<fn(&KdcReqBody) -> SequenceOf<PaData>>,
+) -> TgsReq {
+    make_tgs_request_with_options(
+        sname,
+        realm,
+        cname,
+        KerberosFlags::builder().build().unwrap(),
+        pa_data,
+    )
+}
+
+fn make_tgs_request_with_options(
+    sname: &str,
+    realm: &str,
+    cname: &str,
+    kdc_options: KerberosFlags,
+    pa_data: Option<fn(&KdcReqBody) -> SequenceOf<PaData>>,
 ) -> TgsReq {
     let till = KerberosTime::now() + Duration::from_secs(3600 * 24);
-    let kdc_options = KerberosFlags::builder().build().unwrap();
     let kdc_body = KdcReqBodyBuilder::default()
         .sname(make_principal_name_unsafe(sname))
         .realm(KerberosString::new(realm).unwrap())
@@ -116,12 +189,30 @@ fn make_basic_tgs_request(
 }
 
 fn make_pa_data(kdc_req: &KdcReqBody) -> SequenceOf<PaData> {
+    make_pa_data_with_tgt(
+        kdc_req,
+        "EXAMPLE.COM",
+        make_principal_name_unsafe("tgs"),
+        &TGS_KEY,
+        "EXAMPLE.COM",
+        "user",
+    )
+}
+
+fn make_pa_data_with_tgt(
+    kdc_req: &KdcReqBody,
+    tgt_realm: &str,
+    tgt_sname: PrincipalName,
+    tgt_key: &EncryptionKey,
+    crealm: &str,
+    cname: &str,
+) -> SequenceOf<PaData> {
     let enc_ticket = EncTicketPart::builder()
         .transited(TransitedEncoding::new(1, OctetString::new(vec![]).unwrap()))
         .key(SESSION_KEY.clone())
-        .cname(make_principal_name_unsafe("user"))
-        .crealm(KerberosString::new("EXAMPLE.COM").unwrap())
-        .flags(KerberosFlags::builder().build().unwrap())
+        .cname(make_principal_name_unsafe(cname))
+        .crealm(KerberosString::new(crealm).unwrap())
+        .flags(ticket_flags([TicketFlag::FORWARDABLE]))
         .authtime(KerberosTime::now())
         .starttime(KerberosTime::now())
         .endtime(KerberosTime::now() + Duration::from_secs(3600 * 24))
@@ -132,24 +223,52 @@ fn make_pa_data(kdc_req: &KdcReqBody) -> SequenceOf<PaData> {
     let enc_ticket = MockedCrypto
         .encrypt(
             &enc_ticket.to_der().unwrap(),
-            SERVER_KEY.keyvalue().as_bytes(),
+            tgt_key.keyvalue().as_bytes(),
         )
         .unwrap();
     let enc_ticket = EncryptedData::new(1, None, OctetString::new(enc_ticket).unwrap());
-    let ticket = Ticket::new(
-        KerberosString::new("EXAMPLE.COM").unwrap(),
-        make_principal_name_unsafe("host"),
-        enc_ticket,
-    );
+    let ticket = Ticket::new(KerberosString::new(tgt_realm).unwrap(), tgt_sname, enc_ticket);
+    make_pa_data_with_ticket(kdc_req, ticket, crealm, cname)
+}
+
+/// Authentication header presenting `ticket`, whose session key must be `SESSION_KEY`
+fn make_pa_data_with_ticket(
+    kdc_req: &KdcReqBody,
+    ticket: Ticket,
+    crealm: &str,
+    cname: &str,
+) -> SequenceOf<PaData> {
+    make_pa_data_with_authenticator(kdc_req, ticket, crealm, cname, KerberosTime::now())
+}
 
-    let checksum = MockedHasher.digest(&kdc_req.to_der().unwrap());
+fn make_pa_data_with_authenticator(
+    kdc_req: &KdcReqBody,
+    ticket: Ticket,
+    crealm: &str,
+    cname: &str,
+    ctime: KerberosTime,
+) -> SequenceOf<PaData> {
+    let checksum = MockedHasher.keyed_digest(
+        SESSION_KEY.keyvalue().as_bytes(),
+        &kdc_req.to_der().unwrap(),
+    );
     let checksum = Checksum::new(1, OctetString::new(checksum).unwrap());
+    make_pa_data_with_checksum(ticket, crealm, cname, ctime, checksum)
+}
 
+/// Authentication header whose authenticator carries `checksum` as that of the request body
+fn make_pa_data_with_checksum(
+    ticket: Ticket,
+    crealm: &str,
+    cname: &str,
+    ctime: KerberosTime,
+    checksum: Checksum,
+) -> SequenceOf<PaData> {
     let authenticator = AuthenticatorBuilder::default()
-        .cname(make_principal_name_unsafe("user"))
-        .crealm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .cname(make_principal_name_unsafe(cname))
+        .crealm(KerberosString::new(crealm).unwrap())
         .cusec(0)
-        .ctime(KerberosTime::now())
+        .ctime(ctime)
         .cksum(checksum)
         .seq_number(0)
         .build()
@@ -235,3 +354,1588 @@ async fn test_basic_request_processing() {
         &make_principal_name_unsafe("user")
     );
 }
+
+#[tokio::test]
+async fn test_replayed_authenticator_is_rejected() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_basic_tgs_request("service", "EXAMPLE.COM", "user", Some(make_pa_data));
+    tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("First request should succeed");
+
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_REPEAT)
+        }
+        other => panic!("Replayed request should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_replay_cache_entry_expires() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = TicketGrantingServiceBuilder::default()
+        .name(make_principal_name_unsafe("tgs"))
+        .realm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .principal_db(&principal_db)
+        .replay_cache(&replay_cache)
+        .allowable_clock_skew(Duration::ZERO)
+        .supported_crypto(vec![Box::new(MockedCrypto)])
+        .last_req_db(&mocked_last_req_db)
+        .supported_checksum(vec![Box::new(MockedHasher)])
+        .build()
+        .unwrap();
+
+    let tgs_req = make_basic_tgs_request("service", "EXAMPLE.COM", "user", Some(make_pa_data));
+    tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("First request should succeed");
+    tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("Replay cache entry should have expired");
+}
+
+#[tokio::test]
+async fn test_foreign_service_realm_gets_cross_realm_tgt() {
+    let principal_db = make_principal_db();
+    principal_db.add_principal(
+        PrincipalName::krbtgt(&KerberosString::new("REMOTE.COM").unwrap()),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        PrincipalDatabaseRecord {
+            max_renewable_life: Duration::from_secs(3600 * 24),
+            max_lifetime: Duration::from_secs(3600 * 24),
+            key: INTER_REALM_KEY.clone(),
+            p_kvno: None,
+            supported_encryption_types: vec![1],
+            allowed_to_delegate_to: vec![],
+            attributes: PrincipalAttributes::default(),
+        },
+    );
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_basic_tgs_request("service", "REMOTE.COM", "user", Some(make_pa_data));
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("Should be referred to the remote realm");
+
+    let ticket = tgs_rep.ticket();
+    assert_eq!(ticket.realm().as_str(), "EXAMPLE.COM");
+    assert_eq!(
+        ticket.sname().krbtgt_realm().map(|realm| realm.as_str()),
+        Some("REMOTE.COM")
+    );
+    let enc_ticket_part = MockedCrypto
+        .decrypt(
+            ticket.enc_part().cipher().as_ref(),
+            INTER_REALM_KEY.keyvalue().as_bytes(),
+        )
+        .map(|data| EncTicketPart::from_der(&data).unwrap())
+        .unwrap();
+    assert_eq!(enc_ticket_part.crealm().as_str(), "EXAMPLE.COM");
+}
+
+#[tokio::test]
+async fn test_foreign_service_realm_without_trust_is_rejected() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_basic_tgs_request("service", "REMOTE.COM", "user", Some(make_pa_data));
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+/// Trust krbtgt/EXAMPLE.COM@MIDDLE.COM tickets, issued to clients of CLIENT.COM
+fn add_middle_realm_trust(principal_db: &MockedPrincipalDb) {
+    principal_db.add_principal(
+        PrincipalName::krbtgt(&KerberosString::new("EXAMPLE.COM").unwrap()),
+        KerberosString::new("MIDDLE.COM").unwrap(),
+        PrincipalDatabaseRecord {
+            max_renewable_life: Duration::from_secs(3600 * 24),
+            max_lifetime: Duration::from_secs(3600 * 24),
+            key: INTER_REALM_KEY.clone(),
+            p_kvno: None,
+            supported_encryption_types: vec![1],
+            allowed_to_delegate_to: vec![],
+            attributes: PrincipalAttributes::default(),
+        },
+    );
+}
+
+fn make_middle_realm_pa_data(kdc_req: &KdcReqBody) -> SequenceOf<PaData> {
+    make_pa_data_with_tgt(
+        kdc_req,
+        "MIDDLE.COM",
+        PrincipalName::krbtgt(&KerberosString::new("EXAMPLE.COM").unwrap()),
+        &INTER_REALM_KEY,
+        "CLIENT.COM",
+        "user",
+    )
+}
+
+fn decrypt_service_ticket(tgs_rep: &TgsRep) -> EncTicketPart {
+    MockedCrypto
+        .decrypt(
+            tgs_rep.ticket().enc_part().cipher().as_ref(),
+            SERVER_KEY.keyvalue().as_bytes(),
+        )
+        .map(|data| EncTicketPart::from_der(&data).unwrap())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_cross_realm_tgt_is_accepted_and_transited_updated() {
+    let principal_db = make_principal_db();
+    add_middle_realm_trust(&principal_db);
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = TicketGrantingServiceBuilder::default()
+        .name(make_principal_name_unsafe("tgs"))
+        .realm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .principal_db(&principal_db)
+        .replay_cache(&replay_cache)
+        .transit_policy(TransitPolicy::default().with_capath(
+            &KerberosString::new("CLIENT.COM").unwrap(),
+            &KerberosString::new("EXAMPLE.COM").unwrap(),
+            vec![KerberosString::new("MIDDLE.COM").unwrap()],
+        ))
+        .supported_crypto(vec![Box::new(MockedCrypto)])
+        .last_req_db(&mocked_last_req_db)
+        .supported_checksum(vec![Box::new(MockedHasher)])
+        .build()
+        .unwrap();
+
+    let tgs_req = make_basic_tgs_request(
+        "service",
+        "EXAMPLE.COM",
+        "user",
+        Some(make_middle_realm_pa_data),
+    );
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("Cross-realm TGT should be accepted");
+
+    let enc_ticket_part = decrypt_service_ticket(&tgs_rep);
+    assert_eq!(enc_ticket_part.crealm().as_str(), "CLIENT.COM");
+    assert_eq!(
+        enc_ticket_part.transited().contents().as_bytes(),
+        "MIDDLE.COM".as_bytes()
+    );
+    assert!(enc_ticket_part
+        .flags()
+        .is_set(TicketFlag::TRANSITED_POLICY_CHECKED as usize));
+}
+
+#[tokio::test]
+async fn test_transit_outside_policy_is_rejected() {
+    let principal_db = make_principal_db();
+    add_middle_realm_trust(&principal_db);
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    // MIDDLE.COM is not on the hierarchical path from CLIENT.COM to EXAMPLE.COM
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_basic_tgs_request(
+        "service",
+        "EXAMPLE.COM",
+        "user",
+        Some(make_middle_realm_pa_data),
+    );
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KDC_ERR_POLICY)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_disabled_transited_check_is_honored() {
+    let principal_db = make_principal_db();
+    add_middle_realm_trust(&principal_db);
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_tgs_request_with_options(
+        "service",
+        "EXAMPLE.COM",
+        "user",
+        KerberosFlags::builder()
+            .set(KdcOptionsFlag::DISABLE_TRANSITED_CHECK as usize)
+            .build()
+            .unwrap(),
+        Some(make_middle_realm_pa_data),
+    );
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("Transited path should not be checked");
+
+    let enc_ticket_part = decrypt_service_ticket(&tgs_rep);
+    assert!(!enc_ticket_part
+        .flags()
+        .is_set(TicketFlag::TRANSITED_POLICY_CHECKED as usize));
+}
+
+#[tokio::test]
+async fn test_foreign_tgt_for_another_realm_is_rejected() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_basic_tgs_request(
+        "service",
+        "EXAMPLE.COM",
+        "user",
+        Some(|kdc_req| {
+            make_pa_data_with_tgt(
+                kdc_req,
+                "MIDDLE.COM",
+                PrincipalName::krbtgt(&KerberosString::new("OTHER.COM").unwrap()),
+                &INTER_REALM_KEY,
+                "MIDDLE.COM",
+                "user",
+            )
+        }),
+    );
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_NOT_US)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_service_ticket_as_tgt_is_rejected() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_basic_tgs_request(
+        "host",
+        "EXAMPLE.COM",
+        "user",
+        Some(|kdc_req| {
+            make_pa_data_with_tgt(
+                kdc_req,
+                "EXAMPLE.COM",
+                make_principal_name_unsafe("service"),
+                &SERVER_KEY,
+                "EXAMPLE.COM",
+                "user",
+            )
+        }),
+    );
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_NOT_US)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+static PEER_SESSION_KEY: LazyLock<EncryptionKey> = LazyLock::new(|| {
+    EncryptionKey::new(
+        1,
+        OctetString::new(vec![0x6; 16]).unwrap(), // Mocked key
+    )
+});
+
+/// TGT this KDC issued to "peer", which only holds the session key of it
+fn make_peer_tgt() -> Ticket {
+    let enc_ticket = EncTicketPart::builder()
+        .transited(TransitedEncoding::new(1, OctetString::new(vec![]).unwrap()))
+        .key(PEER_SESSION_KEY.clone())
+        .cname(make_principal_name_unsafe("peer"))
+        .crealm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .flags(KerberosFlags::builder().build().unwrap())
+        .authtime(KerberosTime::now())
+        .starttime(KerberosTime::now())
+        .endtime(KerberosTime::now() + Duration::from_secs(3600 * 24))
+        .build()
+        .unwrap();
+    let enc_ticket = MockedCrypto
+        .encrypt(&enc_ticket.to_der().unwrap(), TGS_KEY.keyvalue().as_bytes())
+        .unwrap();
+    Ticket::new(
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        make_principal_name_unsafe("tgs"),
+        EncryptedData::new(1, None, OctetString::new(enc_ticket).unwrap()),
+    )
+}
+
+fn make_user_to_user_tgs_request(sname: &str, additional_tickets: Vec<Ticket>) -> TgsReq {
+    let kdc_body = KdcReqBodyBuilder::default()
+        .sname(make_principal_name_unsafe(sname))
+        .realm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .till(KerberosTime::now() + Duration::from_secs(3600 * 24))
+        .cname(make_principal_name_unsafe("user"))
+        .nonce(309346u32)
+        .etype(vec![1])
+        .kdc_options(
+            KerberosFlags::builder()
+                .set(KdcOptionsFlag::ENC_TKT_IN_SKEY as usize)
+                .build()
+                .unwrap(),
+        )
+        .additional_tickets(additional_tickets)
+        .build()
+        .unwrap();
+    TgsReq::new(make_pa_data(&kdc_body), kdc_body)
+}
+
+#[tokio::test]
+async fn test_user_to_user_ticket_is_encrypted_in_peer_session_key() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    // "peer" has no entry in the principal database, only its TGT
+    let tgs_req = make_user_to_user_tgs_request("peer", vec![make_peer_tgt()]);
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("User-to-user request should be accepted");
+
+    assert_eq!(tgs_rep.ticket().sname(), &make_principal_name_unsafe("peer"));
+    let enc_ticket_part = MockedCrypto
+        .decrypt(
+            tgs_rep.ticket().enc_part().cipher().as_ref(),
+            PEER_SESSION_KEY.keyvalue().as_bytes(),
+        )
+        .map(|data| EncTicketPart::from_der(&data).unwrap())
+        .expect("Ticket should be encrypted in the peer's session key");
+    assert_eq!(enc_ticket_part.cname(), &make_principal_name_unsafe("user"));
+}
+
+#[tokio::test]
+async fn test_user_to_user_without_additional_ticket_is_rejected() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_user_to_user_tgs_request("peer", vec![]);
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KDC_ERR_BADOPTION)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_user_to_user_server_must_own_additional_ticket() {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_user_to_user_tgs_request("service", vec![make_peer_tgt()]);
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KDC_ERR_SERVER_NOMATCH)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+fn add_user(principal_db: &MockedPrincipalDb) {
+    add_user_with(principal_db, PrincipalAttributes::default());
+}
+
+/// Same as `add_user`, "user" having `attributes`
+fn add_user_with(principal_db: &MockedPrincipalDb, attributes: PrincipalAttributes) {
+    principal_db.add_principal(
+        make_principal_name_unsafe("user"),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        PrincipalDatabaseRecord {
+            max_renewable_life: Duration::from_secs(3600 * 24),
+            max_lifetime: Duration::from_secs(3600 * 24),
+            key: CLIENT_KEY.clone(),
+            p_kvno: None,
+            supported_encryption_types: vec![1],
+            allowed_to_delegate_to: vec![],
+            attributes,
+        },
+    );
+}
+
+/// Principal database where "service" may delegate to "host" and "user" is a user
+fn make_delegation_principal_db() -> MockedPrincipalDb {
+    let principal_db = make_principal_db_with_delegation(vec![make_principal_name_unsafe("host")]);
+    add_user(&principal_db);
+    principal_db
+}
+
+fn make_pa_for_user(user: &str, cksum: Vec<u8>) -> PaData {
+    let pa_for_user = PaForUser::new(
+        make_principal_name_unsafe(user),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        Checksum::new(1, OctetString::new(cksum).unwrap()),
+    );
+    PaData::new(
+        PaDataTypes::PaForUser as i32,
+        OctetString::new(pa_for_user.to_der().unwrap()).unwrap(),
+    )
+}
+
+fn make_valid_pa_for_user(user: &str) -> PaData {
+    let cksum = MockedHasher.keyed_digest(
+        SESSION_KEY.keyvalue().as_bytes(),
+        &PaForUser::checksum_data(
+            &make_principal_name_unsafe(user),
+            &KerberosString::new("EXAMPLE.COM").unwrap(),
+            &KerberosString::new(PaForUser::AUTH_PACKAGE).unwrap(),
+        ),
+    );
+    make_pa_for_user(user, cksum)
+}
+
+/// Request of "service", authenticated with its TGT
+fn make_s4u_tgs_request(
+    sname: &str,
+    kdc_options: KerberosFlags,
+    additional_tickets: Vec<Ticket>,
+    padata: Vec<PaData>,
+) -> TgsReq {
+    let kdc_body = KdcReqBodyBuilder::default()
+        .sname(make_principal_name_unsafe(sname))
+        .realm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .till(KerberosTime::now() + Duration::from_secs(3600 * 24))
+        .cname(make_principal_name_unsafe("service"))
+        .nonce(309346u32)
+        .etype(vec![1])
+        .kdc_options(kdc_options)
+        .additional_tickets(additional_tickets)
+        .build()
+        .unwrap();
+    let mut pa_data = make_pa_data_with_tgt(
+        &kdc_body,
+        "EXAMPLE.COM",
+        make_principal_name_unsafe("tgs"),
+        &TGS_KEY,
+        "EXAMPLE.COM",
+        "service",
+    );
+    pa_data.extend(padata);
+    TgsReq::new(pa_data, kdc_body)
+}
+
+fn forwardable() -> KerberosFlags {
+    KerberosFlags::builder()
+        .set(KdcOptionsFlag::FORWARDABLE as usize)
+        .build()
+        .unwrap()
+}
+
+/// Ticket of "user" to "service", as obtained through S4U2Self
+fn make_evidence_ticket(flags: KerberosFlags) -> Ticket {
+    let enc_ticket = EncTicketPart::builder()
+        .transited(TransitedEncoding::new(1, OctetString::new(vec![]).unwrap()))
+        .key(SESSION_KEY.clone())
+        .cname(make_principal_name_unsafe("user"))
+        .crealm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .flags(flags)
+        .authtime(KerberosTime::now())
+        .starttime(KerberosTime::now())
+        .endtime(KerberosTime::now() + Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    let enc_ticket = MockedCrypto
+        .encrypt(&enc_ticket.to_der().unwrap(), SERVER_KEY.keyvalue().as_bytes())
+        .unwrap();
+    Ticket::new(
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        make_principal_name_unsafe("service"),
+        EncryptedData::new(1, None, OctetString::new(enc_ticket).unwrap()),
+    )
+}
+
+#[tokio::test]
+async fn test_s4u2self_issues_ticket_for_user() {
+    let principal_db = make_delegation_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "service",
+        forwardable(),
+        vec![],
+        vec![make_valid_pa_for_user("user")],
+    );
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("S4U2Self request should be accepted");
+
+    assert_eq!(tgs_rep.cname(), &make_principal_name_unsafe("user"));
+    let enc_ticket_part = decrypt_service_ticket(&tgs_rep);
+    assert_eq!(enc_ticket_part.cname(), &make_principal_name_unsafe("user"));
+    assert!(enc_ticket_part
+        .flags()
+        .is_set(TicketFlag::FORWARDABLE as usize));
+}
+
+#[tokio::test]
+async fn test_s4u2self_ticket_is_not_forwardable_without_delegation() {
+    // "service" has no delegation rights in this database
+    let principal_db = make_principal_db();
+    add_user(&principal_db);
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "service",
+        forwardable(),
+        vec![],
+        vec![make_valid_pa_for_user("user")],
+    );
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("S4U2Self request should be accepted");
+
+    let enc_ticket_part = decrypt_service_ticket(&tgs_rep);
+    assert_eq!(enc_ticket_part.cname(), &make_principal_name_unsafe("user"));
+    assert!(!enc_ticket_part
+        .flags()
+        .is_set(TicketFlag::FORWARDABLE as usize));
+}
+
+#[tokio::test]
+async fn test_s4u2self_with_modified_user_is_rejected() {
+    let principal_db = make_delegation_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let cksum = MockedHasher.keyed_digest(
+        SESSION_KEY.keyvalue().as_bytes(),
+        &PaForUser::checksum_data(
+            &make_principal_name_unsafe("someone"),
+            &KerberosString::new("EXAMPLE.COM").unwrap(),
+            &KerberosString::new(PaForUser::AUTH_PACKAGE).unwrap(),
+        ),
+    );
+    let tgs_req = make_s4u_tgs_request(
+        "service",
+        forwardable(),
+        vec![],
+        vec![make_pa_for_user("user", cksum)],
+    );
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KRB_AP_ERR_MODIFIED)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_s4u2proxy_issues_ticket_to_allowed_service() {
+    let principal_db = make_delegation_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "host",
+        KerberosFlags::builder()
+            .set(KdcOptionsFlag::FORWARDABLE as usize)
+            .set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize)
+            .build()
+            .unwrap(),
+        vec![make_evidence_ticket(forwardable())],
+        vec![],
+    );
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("S4U2Proxy request should be accepted");
+
+    assert_eq!(tgs_rep.ticket().sname(), &make_principal_name_unsafe("host"));
+    assert_eq!(tgs_rep.cname(), &make_principal_name_unsafe("user"));
+    let enc_ticket_part = MockedCrypto
+        .decrypt(
+            tgs_rep.ticket().enc_part().cipher().as_ref(),
+            &[0xa; 16],
+        )
+        .map(|data| EncTicketPart::from_der(&data).unwrap())
+        .unwrap();
+    assert_eq!(enc_ticket_part.cname(), &make_principal_name_unsafe("user"));
+}
+
+#[tokio::test]
+async fn test_s4u2proxy_target_matches_whatever_its_name_type() {
+    let principal_db = make_principal_db_with_delegation(vec![PrincipalName::new(
+        NameTypes::NtSrvHst,
+        vec![KerberosString::new("host").unwrap()],
+    )
+    .unwrap()]);
+    add_user(&principal_db);
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "host",
+        KerberosFlags::builder()
+            .set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize)
+            .build()
+            .unwrap(),
+        vec![make_evidence_ticket(forwardable())],
+        vec![],
+    );
+    tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("S4U2Proxy request should be accepted");
+}
+
+#[tokio::test]
+async fn test_s4u2proxy_to_service_not_allowed_is_rejected() {
+    let principal_db = make_delegation_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "tgs",
+        KerberosFlags::builder()
+            .set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize)
+            .build()
+            .unwrap(),
+        vec![make_evidence_ticket(forwardable())],
+        vec![],
+    );
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KDC_ERR_BADOPTION)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_s4u2proxy_with_non_forwardable_evidence_is_rejected() {
+    let principal_db = make_delegation_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "host",
+        KerberosFlags::builder()
+            .set(KdcOptionsFlag::CNAME_IN_ADDL_TKT as usize)
+            .build()
+            .unwrap(),
+        vec![make_evidence_ticket(KerberosFlags::builder().build().unwrap())],
+        vec![],
+    );
+    match tgs_service.handle_tgs_req(&tgs_req).await {
+        Err(ServerError::ProtocolError(err)) => {
+            assert_eq!(err.error_code(), Ecode::KDC_ERR_BADOPTION)
+        }
+        other => panic!("Request should be rejected, got {:?}", other),
+    }
+}
+
+fn ticket_flags<const N: usize>(flags: [TicketFlag; N]) -> KerberosFlags {
+    let mut builder = KerberosFlags::builder();
+    for flag in flags {
+        builder.set(flag as usize);
+    }
+    builder.build().unwrap()
+}
+
+fn make_local_tgt(
+    flags: KerberosFlags,
+    authtime: KerberosTime,
+    starttime: KerberosTime,
+    endtime: KerberosTime,
+    renew_till: Option<KerberosTime>,
+) -> EncTicketPart {
+    let mut enc_ticket = EncTicketPart::builder();
+    enc_ticket
+        .transited(TransitedEncoding::new(1, OctetString::new(vec![]).unwrap()))
+        .key(SESSION_KEY.clone())
+        .cname(make_principal_name_unsafe("user"))
+        .crealm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .flags(flags)
+        .authtime(authtime)
+        .starttime(starttime)
+        .endtime(endtime);
+    if let Some(renew_till) = renew_till {
+        enc_ticket.renew_till(renew_till);
+    }
+    enc_ticket.build().unwrap()
+}
+
+/// Request presenting `tgt` for renewal or validation, depending on `option`
+fn make_tgt_replacement_request(option: KdcOptionsFlag, tgt: &EncTicketPart) -> TgsReq {
+    make_request_with_tgt(
+        "tgs",
+        KerberosFlags::builder().set(option as usize).build().unwrap(),
+        tgt,
+        |kdc_body, ticket| make_pa_data_with_ticket(kdc_body, ticket, "EXAMPLE.COM", "user"),
+    )
+}
+
+/// Request of "user" to `sname`, authenticated by `make_pa_data` with the encrypted `tgt`
+fn make_request_with_tgt(
+    sname: &str,
+    kdc_options: KerberosFlags,
+    tgt: &EncTicketPart,
+    make_pa_data: impl Fn(&KdcReqBody, Ticket) -> SequenceOf<PaData>,
+) -> TgsReq {
+    make_request_with_authorization_data(sname, kdc_options, tgt, None, make_pa_data)
+}
+
+/// Same as `make_request_with_tgt`, `authorization_data` being encrypted in the TGT session key
+fn make_request_with_authorization_data(
+    sname: &str,
+    kdc_options: KerberosFlags,
+    tgt: &EncTicketPart,
+    authorization_data: Option<&AuthorizationData>,
+    make_pa_data: impl Fn(&KdcReqBody, Ticket) -> SequenceOf<PaData>,
+) -> TgsReq {
+    let mut kdc_body = KdcReqBodyBuilder::default();
+    if let Some(authorization_data) = authorization_data {
+        let encrypted = MockedCrypto
+            .encrypt(
+                &authorization_data.to_der().unwrap(),
+                SESSION_KEY.keyvalue().as_bytes(),
+            )
+            .unwrap();
+        kdc_body.enc_authorization_data(EncryptedData::new(
+            1,
+            None,
+            OctetString::new(encrypted).unwrap(),
+        ));
+    }
+    let kdc_body = kdc_body
+        .sname(make_principal_name_unsafe(sname))
+        .realm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .till(KerberosTime::zero())
+        .cname(make_principal_name_unsafe("user"))
+        .nonce(309346u32)
+        .etype(vec![1])
+        .kdc_options(kdc_options)
+        .build()
+        .unwrap();
+    let enc_ticket = MockedCrypto
+        .encrypt(&tgt.to_der().unwrap(), TGS_KEY.keyvalue().as_bytes())
+        .unwrap();
+    let ticket = Ticket::new(
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        make_principal_name_unsafe("tgs"),
+        EncryptedData::new(1, None, OctetString::new(enc_ticket).unwrap()),
+    );
+    TgsReq::new(make_pa_data(&kdc_body, ticket), kdc_body)
+}
+
+async fn handle_with_basic_service(tgs_req: &TgsReq) -> Result<TgsRep, ServerError> {
+    let principal_db = make_principal_db();
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+    tgs_service.handle_tgs_req(tgs_req).await
+}
+
+fn decrypt_tgt(tgs_rep: &TgsRep) -> EncTicketPart {
+    MockedCrypto
+        .decrypt(
+            tgs_rep.ticket().enc_part().cipher().as_ref(),
+            TGS_KEY.keyvalue().as_bytes(),
+        )
+        .map(|data| EncTicketPart::from_der(&data).unwrap())
+        .unwrap()
+}
+
+fn assert_protocol_error(result: Result<TgsRep, ServerError>, ecode: Ecode) {
+    match result {
+        Err(ServerError::ProtocolError(err)) => assert_eq!(err.error_code(), ecode),
+        other => panic!("Request should be rejected with {:?}, got {:?}", ecode, other),
+    }
+}
+
+#[tokio::test]
+async fn test_renewal_keeps_authtime_and_lifetime() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([TicketFlag::RENEWABLE, TicketFlag::FORWARDABLE]),
+        now - Duration::from_secs(3 * 3600),
+        now - Duration::from_secs(3600),
+        now + Duration::from_secs(3600),
+        Some(now + Duration::from_secs(10 * 3600)),
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::RENEW, &tgt);
+    let tgs_rep = handle_with_basic_service(&tgs_req)
+        .await
+        .expect("Renewable ticket should be renewed");
+
+    assert_eq!(tgs_rep.ticket().sname(), &make_principal_name_unsafe("tgs"));
+    let renewed = decrypt_tgt(&tgs_rep);
+    assert_eq!(renewed.authtime(), tgt.authtime());
+    assert_eq!(renewed.cname(), tgt.cname());
+    assert_eq!(renewed.renew_till(), tgt.renew_till());
+    assert!(renewed.flags().is_set(TicketFlag::RENEWABLE as usize));
+    assert!(renewed.flags().is_set(TicketFlag::FORWARDABLE as usize));
+    let starttime = renewed.starttime().unwrap();
+    assert!(starttime.abs_diff(&KerberosTime::now()) <= Duration::from_secs(1));
+    assert_eq!(renewed.endtime() - starttime, Duration::from_secs(2 * 3600));
+}
+
+#[tokio::test]
+async fn test_renewal_is_capped_by_renew_till() {
+    let now = KerberosTime::now();
+    let renew_till = now + Duration::from_secs(1800);
+    let tgt = make_local_tgt(
+        ticket_flags([TicketFlag::RENEWABLE]),
+        now - Duration::from_secs(3600),
+        now - Duration::from_secs(3600),
+        now + Duration::from_secs(600),
+        Some(renew_till),
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::RENEW, &tgt);
+    let tgs_rep = handle_with_basic_service(&tgs_req)
+        .await
+        .expect("Renewable ticket should be renewed");
+
+    let renewed = decrypt_tgt(&tgs_rep);
+    assert_eq!(renewed.endtime(), renew_till);
+}
+
+#[tokio::test]
+async fn test_renewal_of_non_renewable_ticket_is_rejected() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([]),
+        now,
+        now,
+        now + Duration::from_secs(3600),
+        Some(now + Duration::from_secs(10 * 3600)),
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::RENEW, &tgt);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KDC_ERR_BADOPTION,
+    );
+}
+
+#[tokio::test]
+async fn test_renewal_after_renew_till_is_rejected() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([TicketFlag::RENEWABLE]),
+        now - Duration::from_secs(3600),
+        now - Duration::from_secs(3600),
+        now + Duration::from_secs(3600),
+        Some(now - Duration::from_secs(60)),
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::RENEW, &tgt);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_TKT_EXPIRED,
+    );
+}
+
+#[tokio::test]
+async fn test_validation_clears_invalid_flag() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([TicketFlag::INVALID, TicketFlag::POSTDATED]),
+        now - Duration::from_secs(3600),
+        now - Duration::from_secs(60),
+        now + Duration::from_secs(3600),
+        None,
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::VALIDATE, &tgt);
+    let tgs_rep = handle_with_basic_service(&tgs_req)
+        .await
+        .expect("Postdated ticket should be validated");
+
+    let validated = decrypt_tgt(&tgs_rep);
+    assert!(!validated.flags().is_set(TicketFlag::INVALID as usize));
+    assert!(validated.flags().is_set(TicketFlag::POSTDATED as usize));
+    assert_eq!(validated.authtime(), tgt.authtime());
+    assert_eq!(validated.starttime(), tgt.starttime());
+    assert_eq!(validated.endtime(), tgt.endtime());
+}
+
+#[tokio::test]
+async fn test_validation_before_starttime_is_rejected() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([TicketFlag::INVALID, TicketFlag::POSTDATED]),
+        now,
+        now + Duration::from_secs(3600),
+        now + Duration::from_secs(2 * 3600),
+        None,
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::VALIDATE, &tgt);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_TKT_NYV,
+    );
+}
+
+#[tokio::test]
+async fn test_validation_of_valid_ticket_is_rejected() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([]),
+        now,
+        now,
+        now + Duration::from_secs(3600),
+        None,
+    );
+    let tgs_req = make_tgt_replacement_request(KdcOptionsFlag::VALIDATE, &tgt);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KDC_ERR_BADOPTION,
+    );
+}
+
+/// Service request of "user", authenticated with `tgt` and an authenticator made at `ctime`
+fn make_service_request_with_tgt(
+    kdc_options: KerberosFlags,
+    tgt: &EncTicketPart,
+    cname: &'static str,
+    ctime: KerberosTime,
+) -> TgsReq {
+    make_request_with_tgt("service", kdc_options, tgt, move |kdc_body, ticket| {
+        make_pa_data_with_authenticator(kdc_body, ticket, "EXAMPLE.COM", cname, ctime)
+    })
+}
+
+fn make_current_tgt(flags: KerberosFlags) -> EncTicketPart {
+    let now = KerberosTime::now();
+    make_local_tgt(flags, now, now, now + Duration::from_secs(3600), None)
+}
+
+#[tokio::test]
+async fn test_expired_tgt_is_rejected() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([]),
+        now - Duration::from_secs(2 * 3600),
+        now - Duration::from_secs(2 * 3600),
+        now - Duration::from_secs(3600),
+        None,
+    );
+    let tgs_req = make_service_request_with_tgt(ticket_flags([]), &tgt, "user", now);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_TKT_EXPIRED,
+    );
+}
+
+#[tokio::test]
+async fn test_tgt_expired_within_clock_skew_is_accepted() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([]),
+        now - Duration::from_secs(3600),
+        now - Duration::from_secs(3600),
+        now - Duration::from_secs(60),
+        None,
+    );
+    let tgs_req = make_service_request_with_tgt(ticket_flags([]), &tgt, "user", now);
+    handle_with_basic_service(&tgs_req)
+        .await
+        .expect("TGT expired within the clock skew should be accepted");
+}
+
+#[tokio::test]
+async fn test_tgt_not_yet_valid_is_rejected() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([]),
+        now,
+        now + Duration::from_secs(3600),
+        now + Duration::from_secs(2 * 3600),
+        None,
+    );
+    let tgs_req = make_service_request_with_tgt(ticket_flags([]), &tgt, "user", now);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_TKT_NYV,
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_tgt_is_rejected() {
+    let tgt = make_current_tgt(ticket_flags([TicketFlag::INVALID]));
+    let tgs_req =
+        make_service_request_with_tgt(ticket_flags([]), &tgt, "user", KerberosTime::now());
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_TKT_NYV,
+    );
+}
+
+#[tokio::test]
+async fn test_authenticator_outside_clock_skew_is_rejected() {
+    let tgt = make_current_tgt(ticket_flags([]));
+    let ctime = KerberosTime::now() - Duration::from_secs(10 * 60);
+    let tgs_req = make_service_request_with_tgt(ticket_flags([]), &tgt, "user", ctime);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_SKEW,
+    );
+}
+
+#[tokio::test]
+async fn test_authenticator_of_another_client_is_rejected() {
+    let tgt = make_current_tgt(ticket_flags([]));
+    let tgs_req =
+        make_service_request_with_tgt(ticket_flags([]), &tgt, "someone", KerberosTime::now());
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_BADMATCH,
+    );
+}
+
+#[tokio::test]
+async fn test_request_body_checksum_must_be_keyed_with_the_session_key() {
+    let tgt = make_current_tgt(ticket_flags([]));
+    let testcases: [(fn(&[u8]) -> Checksum, Ecode); 4] = [
+        (
+            |body| Checksum::new(1, OctetString::new(MockedHasher.digest(body)).unwrap()),
+            Ecode::KRB_AP_ERR_MODIFIED,
+        ),
+        (
+            |body| {
+                let checksum = MockedHasher.keyed_digest(TGS_KEY.keyvalue().as_bytes(), body);
+                Checksum::new(1, OctetString::new(checksum).unwrap())
+            },
+            Ecode::KRB_AP_ERR_MODIFIED,
+        ),
+        (
+            |body| {
+                let checksum = MockedHasher.keyed_digest(SESSION_KEY.keyvalue().as_bytes(), body);
+                Checksum::new(1, OctetString::new(&checksum[..8]).unwrap())
+            },
+            Ecode::KDC_ERR_SUMTYPE_NOSUPP,
+        ),
+        (
+            |body| {
+                let checksum = MockedHasher.keyed_digest(SESSION_KEY.keyvalue().as_bytes(), body);
+                Checksum::new(2, OctetString::new(checksum).unwrap())
+            },
+            Ecode::KDC_ERR_SUMTYPE_NOSUPP,
+        ),
+    ];
+    for (make_checksum, ecode) in testcases {
+        let tgs_req =
+            make_request_with_tgt("service", ticket_flags([]), &tgt, |kdc_body, ticket| {
+                let checksum = make_checksum(&kdc_body.to_der().unwrap());
+                make_pa_data_with_checksum(
+                    ticket,
+                    "EXAMPLE.COM",
+                    "user",
+                    KerberosTime::now(),
+                    checksum,
+                )
+            });
+        assert_protocol_error(handle_with_basic_service(&tgs_req).await, ecode);
+    }
+}
+
+#[tokio::test]
+async fn test_options_need_the_matching_tgt_flags() {
+    let testcases = [
+        (KdcOptionsFlag::FORWARDABLE, TicketFlag::FORWARDABLE),
+        (KdcOptionsFlag::FORWARDED, TicketFlag::FORWARDABLE),
+        (KdcOptionsFlag::PROXIABLE, TicketFlag::PROXIABLE),
+        (KdcOptionsFlag::PROXY, TicketFlag::PROXIABLE),
+        (KdcOptionsFlag::RENEWABLE, TicketFlag::RENEWABLE),
+        (KdcOptionsFlag::ALLOW_POSTDATE, TicketFlag::MAY_POSTDATE),
+        (KdcOptionsFlag::POSTDATED, TicketFlag::MAY_POSTDATE),
+    ];
+    for (option, flag) in testcases {
+        let kdc_options = KerberosFlags::builder().set(option as usize).build().unwrap();
+        let now = KerberosTime::now();
+
+        let tgt = make_current_tgt(ticket_flags([]));
+        let tgs_req = make_service_request_with_tgt(kdc_options.clone(), &tgt, "user", now);
+        assert_protocol_error(
+            handle_with_basic_service(&tgs_req).await,
+            Ecode::KDC_ERR_BADOPTION,
+        );
+
+        let tgt = make_local_tgt(
+            ticket_flags([flag]),
+            now,
+            now,
+            now + Duration::from_secs(3600),
+            Some(now + Duration::from_secs(3600)),
+        );
+        let tgs_req = make_service_request_with_tgt(kdc_options, &tgt, "user", now);
+        handle_with_basic_service(&tgs_req)
+            .await
+            .expect("Option allowed by the TGT should be honored");
+    }
+}
+
+#[tokio::test]
+async fn test_postdated_ticket_is_issued_invalid() {
+    let now = KerberosTime::now();
+    let tgt = make_current_tgt(ticket_flags([TicketFlag::MAY_POSTDATE]));
+    let kdc_options = KerberosFlags::builder()
+        .set(KdcOptionsFlag::POSTDATED as usize)
+        .build()
+        .unwrap();
+    let tgs_req = make_service_request_with_tgt(kdc_options, &tgt, "user", now);
+
+    let tgs_rep = handle_with_basic_service(&tgs_req)
+        .await
+        .expect("Postdating allowed by the TGT should be honored");
+
+    let flags = decrypt_service_ticket_flags(&tgs_rep);
+    assert!(flags.is_set(TicketFlag::POSTDATED as usize));
+    assert!(flags.is_set(TicketFlag::INVALID as usize));
+}
+
+fn make_ad_entry(ad_type: i32, data: &[u8]) -> ADEntry {
+    ADEntry::new(ad_type, OctetString::new(data).unwrap())
+}
+
+fn make_ad_container(ad_type: AuthorizationDataTypes, elements: &AuthorizationData) -> ADEntry {
+    make_ad_entry(ad_type as i32, &elements.to_der().unwrap())
+}
+
+fn make_tgt_with_authorization_data(authorization_data: AuthorizationData) -> EncTicketPart {
+    let now = KerberosTime::now();
+    EncTicketPart::builder()
+        .transited(TransitedEncoding::new(1, OctetString::new(vec![]).unwrap()))
+        .key(SESSION_KEY.clone())
+        .cname(make_principal_name_unsafe("user"))
+        .crealm(KerberosString::new("EXAMPLE.COM").unwrap())
+        .flags(ticket_flags([]))
+        .authtime(now)
+        .starttime(now)
+        .endtime(now + Duration::from_secs(3600))
+        .authorization_data(authorization_data)
+        .build()
+        .unwrap()
+}
+
+fn make_authorization_data_request(
+    tgt: &EncTicketPart,
+    requested: Option<&AuthorizationData>,
+) -> TgsReq {
+    make_request_with_authorization_data(
+        "service",
+        ticket_flags([]),
+        tgt,
+        requested,
+        |kdc_body, ticket| make_pa_data_with_ticket(kdc_body, ticket, "EXAMPLE.COM", "user"),
+    )
+}
+
+#[tokio::test]
+async fn test_authorization_data_is_copied_and_kdc_issued_signed_again() {
+    let kdc_issued = sign_kdc_issued(
+        &[Box::new(MockedHasher)],
+        &SESSION_KEY,
+        vec![make_ad_entry(-1, b"admins")],
+        &KerberosString::new("EXAMPLE.COM").unwrap(),
+        &make_principal_name_unsafe("tgs"),
+    )
+    .unwrap();
+    let tgt = make_tgt_with_authorization_data(vec![
+        kdc_issued,
+        make_ad_container(
+            AuthorizationDataTypes::IfRelevant,
+            &vec![make_ad_entry(-2, b"hint")],
+        ),
+    ]);
+    let requested = vec![make_ad_entry(-3, b"restriction")];
+    let tgs_req = make_authorization_data_request(&tgt, Some(&requested));
+    let tgs_rep = handle_with_basic_service(&tgs_req)
+        .await
+        .expect("Authorization data should be accepted");
+
+    let ticket = decrypt_service_ticket(&tgs_rep);
+    let elements = ticket_elements(
+        &[Box::new(MockedHasher)],
+        ticket.key(),
+        ticket.authorization_data().unwrap(),
+    )
+    .expect("KDC-issued container should be signed with the new session key");
+    let element = |ad_type, data: &[u8], kdc_issued| AuthorizationElement {
+        entry: make_ad_entry(ad_type, data),
+        kdc_issued,
+    };
+    assert_eq!(
+        elements,
+        vec![
+            element(-1, b"admins", true),
+            element(-2, b"hint", false),
+            element(-3, b"restriction", false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_modified_kdc_issued_authorization_data_is_rejected() {
+    let kdc_issued = sign_kdc_issued(
+        &[Box::new(MockedHasher)],
+        &CLIENT_KEY,
+        vec![make_ad_entry(-1, b"admins")],
+        &KerberosString::new("EXAMPLE.COM").unwrap(),
+        &make_principal_name_unsafe("tgs"),
+    )
+    .unwrap();
+    let tgt = make_tgt_with_authorization_data(vec![kdc_issued]);
+    let tgs_req = make_authorization_data_request(&tgt, None);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_MODIFIED,
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_mandatory_for_kdc_authorization_data_is_rejected() {
+    let mandatory = make_ad_container(
+        AuthorizationDataTypes::MandatoryForKdc,
+        &vec![make_ad_entry(-1, b"unknown")],
+    );
+    let tgt = make_tgt_with_authorization_data(vec![mandatory.clone()]);
+    let tgs_req = make_authorization_data_request(&tgt, None);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KDC_ERR_POLICY,
+    );
+
+    let tgt = make_tgt_with_authorization_data(vec![make_ad_entry(-2, b"hint")]);
+    let tgs_req = make_authorization_data_request(&tgt, Some(&vec![mandatory]));
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KDC_ERR_POLICY,
+    );
+}
+
+#[tokio::test]
+async fn test_kdc_issued_authorization_data_requested_by_client_is_rejected() {
+    let forged = sign_kdc_issued(
+        &[Box::new(MockedHasher)],
+        &SESSION_KEY,
+        vec![make_ad_entry(-1, b"admins")],
+        &KerberosString::new("EXAMPLE.COM").unwrap(),
+        &make_principal_name_unsafe("tgs"),
+    )
+    .unwrap();
+    let tgt = make_tgt_with_authorization_data(vec![make_ad_entry(-2, b"hint")]);
+    let tgs_req = make_authorization_data_request(&tgt, Some(&vec![forged]));
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KDC_ERR_POLICY,
+    );
+}
+
+#[tokio::test]
+async fn test_s4u2self_ticket_carries_claims_of_the_user() {
+    let principal_db = make_delegation_principal_db();
+    principal_db.add_group(
+        make_principal_name_unsafe("user"),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        PrincipalGroup {
+            name: KerberosString::new("admins").unwrap(),
+            roles: vec![KerberosString::new("admin").unwrap()],
+        },
+    );
+    principal_db.add_group(
+        make_principal_name_unsafe("service"),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        PrincipalGroup {
+            name: KerberosString::new("services").unwrap(),
+            roles: vec![],
+        },
+    );
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_s4u_tgs_request(
+        "service",
+        forwardable(),
+        vec![],
+        vec![make_valid_pa_for_user("user")],
+    );
+    let tgs_rep = tgs_service
+        .handle_tgs_req(&tgs_req)
+        .await
+        .expect("S4U2Self request should be accepted");
+
+    let ticket = decrypt_service_ticket(&tgs_rep);
+    let elements = ticket_elements(
+        &[Box::new(MockedHasher)],
+        ticket.key(),
+        ticket.authorization_data().unwrap(),
+    )
+    .unwrap();
+    let claims = PrincipalClaims::from_elements(&elements).unwrap().unwrap();
+    assert_eq!(claims.groups, vec![KerberosString::new("admins").unwrap()]);
+    assert!(claims.has_role("admin"));
+}
+
+/// TGT of "user" carrying a PAC signed with `server_key` for the TGT, and `kdc_key`
+fn make_tgt_with_pac(server_key: &EncryptionKey, kdc_key: &EncryptionKey) -> EncTicketPart {
+    let authtime = KerberosTime::now();
+    let pac = Pac::issue(
+        &[Box::new(MockedHasher)],
+        server_key,
+        kdc_key,
+        &make_principal_name_unsafe("user"),
+        &KerberosString::new("EXAMPLE.COM").unwrap(),
+        authtime,
+        &make_principal_name_unsafe("tgs"),
+    )
+    .unwrap();
+    make_tgt_with_authorization_data(vec![pac.to_authorization_data().unwrap()])
+}
+
+#[tokio::test]
+async fn test_pac_of_tgt_is_signed_again_for_the_service() {
+    let tgt = make_tgt_with_pac(&TGS_KEY, &TGS_KEY);
+    let tgs_req = make_authorization_data_request(&tgt, None);
+    let tgs_rep = handle_with_basic_service(&tgs_req)
+        .await
+        .expect("PAC of the TGT should be accepted");
+
+    let ticket = decrypt_service_ticket(&tgs_rep);
+    let elements = ticket_elements(
+        &[Box::new(MockedHasher)],
+        ticket.key(),
+        ticket.authorization_data().unwrap(),
+    )
+    .unwrap();
+    let pac = Pac::from_elements(&elements).unwrap().unwrap();
+    assert!(pac
+        .verify_server_signature(&[Box::new(MockedHasher)], &SERVER_KEY)
+        .is_ok());
+    assert!(pac
+        .verify_kdc_signature(&[Box::new(MockedHasher)], &TGS_KEY)
+        .is_ok());
+    assert!(pac
+        .verify_client(&make_principal_name_unsafe("user"), tgt.authtime())
+        .is_ok());
+}
+
+#[tokio::test]
+async fn test_pac_not_signed_for_the_tgt_is_rejected() {
+    let tgt = make_tgt_with_pac(&SERVER_KEY, &TGS_KEY);
+    let tgs_req = make_authorization_data_request(&tgt, None);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_MODIFIED,
+    );
+
+    let tgt = make_tgt_with_pac(&TGS_KEY, &SERVER_KEY);
+    let tgs_req = make_authorization_data_request(&tgt, None);
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KRB_AP_ERR_MODIFIED,
+    );
+}
+
+#[tokio::test]
+async fn test_pac_requested_by_client_is_rejected() {
+    let tgt = make_tgt_with_authorization_data(vec![make_ad_entry(-2, b"hint")]);
+    let forged = make_tgt_with_pac(&TGS_KEY, &TGS_KEY)
+        .authorization_data()
+        .unwrap()
+        .clone();
+    let tgs_req = make_authorization_data_request(&tgt, Some(&forged));
+    assert_protocol_error(
+        handle_with_basic_service(&tgs_req).await,
+        Ecode::KDC_ERR_POLICY,
+    );
+}
+
+async fn handle_with_service_attributes(
+    tgs_req: &TgsReq,
+    service_attributes: PrincipalAttributes,
+) -> Result<TgsRep, ServerError> {
+    let principal_db = make_principal_db_with(vec![], service_attributes);
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+    tgs_service.handle_tgs_req(tgs_req).await
+}
+
+fn decrypt_service_ticket_flags(tgs_rep: &TgsRep) -> KerberosFlags {
+    decrypt_service_ticket(tgs_rep).flags().clone()
+}
+
+#[tokio::test]
+async fn test_capabilities_disallowed_to_the_service_are_left_out() {
+    let now = KerberosTime::now();
+    let tgt = make_local_tgt(
+        ticket_flags([
+            TicketFlag::FORWARDABLE,
+            TicketFlag::PROXIABLE,
+            TicketFlag::RENEWABLE,
+        ]),
+        now,
+        now,
+        now + Duration::from_secs(3600),
+        Some(now + Duration::from_secs(3600)),
+    );
+    let mut kdc_options = KerberosFlags::builder();
+    kdc_options
+        .set(KdcOptionsFlag::FORWARDABLE as usize)
+        .set(KdcOptionsFlag::PROXIABLE as usize)
+        .set(KdcOptionsFlag::RENEWABLE as usize);
+    let tgs_req = make_service_request_with_tgt(kdc_options.build().unwrap(), &tgt, "user", now);
+    let disallowed = PrincipalAttributes::default()
+        .with(PrincipalAttribute::DisallowForwardable)
+        .with(PrincipalAttribute::DisallowProxiable)
+        .with(PrincipalAttribute::DisallowRenewable);
+
+    let testcases = [(PrincipalAttributes::default(), true), (disallowed, false)];
+    for (service_attributes, allowed) in testcases {
+        let tgs_rep = handle_with_service_attributes(&tgs_req, service_attributes)
+            .await
+            .expect("Request should be accepted");
+
+        let flags = decrypt_service_ticket_flags(&tgs_rep);
+        for flag in [
+            TicketFlag::FORWARDABLE,
+            TicketFlag::PROXIABLE,
+            TicketFlag::RENEWABLE,
+        ] {
+            assert_eq!(flags.is_set(flag as usize), allowed);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ticket_to_service_ok_as_delegate_says_so() {
+    let now = KerberosTime::now();
+    let tgt = make_current_tgt(ticket_flags([]));
+    let tgs_req = make_service_request_with_tgt(ticket_flags([]), &tgt, "user", now);
+
+    let tgs_rep = handle_with_service_attributes(
+        &tgs_req,
+        PrincipalAttributes::default().with(PrincipalAttribute::OkAsDelegate),
+    )
+    .await
+    .expect("Request should be accepted");
+
+    assert!(decrypt_service_ticket_flags(&tgs_rep).is_set(TicketFlag::OK_AS_DELEGATE as usize));
+}
+
+#[tokio::test]
+async fn test_service_attributes_can_refuse_tickets() {
+    let testcases = [
+        (PrincipalAttribute::DisallowAllTix, Ecode::KDC_ERR_S_PRINCIPAL_UNKNOWN),
+        (PrincipalAttribute::DisallowTgtBased, Ecode::KDC_ERR_POLICY),
+        (PrincipalAttribute::DisallowSvr, Ecode::KDC_ERR_MUST_USE_USER2USER),
+    ];
+    for (attribute, ecode) in testcases {
+        let now = KerberosTime::now();
+        let tgt = make_current_tgt(ticket_flags([]));
+        let tgs_req = make_service_request_with_tgt(ticket_flags([]), &tgt, "user", now);
+
+        let result = handle_with_service_attributes(
+            &tgs_req,
+            PrincipalAttributes::default().with(attribute),
+        )
+        .await;
+
+        assert_protocol_error(result, ecode);
+    }
+}
+
+#[tokio::test]
+async fn test_user_to_user_ticket_to_peer_disallowing_it_is_rejected() {
+    let principal_db = make_principal_db();
+    principal_db.add_principal(
+        make_principal_name_unsafe("peer"),
+        KerberosString::new("EXAMPLE.COM").unwrap(),
+        PrincipalDatabaseRecord {
+            max_renewable_life: Duration::from_secs(3600 * 24),
+            max_lifetime: Duration::from_secs(3600 * 24),
+            key: CLIENT_KEY.clone(),
+            p_kvno: None,
+            supported_encryption_types: vec![1],
+            allowed_to_delegate_to: vec![],
+            attributes: PrincipalAttributes::default().with(PrincipalAttribute::DisallowDupSkey),
+        },
+    );
+    let replay_cache = MockedReplayCache::new();
+    let mocked_last_req_db = MockedLastReqDb::new();
+    let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+    let tgs_req = make_user_to_user_tgs_request("peer", vec![make_peer_tgt()]);
+
+    assert_protocol_error(
+        tgs_service.handle_tgs_req(&tgs_req).await,
+        Ecode::KDC_ERR_POLICY,
+    );
+}
+
+#[tokio::test]
+async fn test_client_attributes_can_refuse_tickets() {
+    let now = KerberosTime::now();
+    let tgt = make_current_tgt(ticket_flags([TicketFlag::MAY_POSTDATE]));
+    let postdated = KerberosFlags::builder()
+        .set(KdcOptionsFlag::POSTDATED as usize)
+        .build()
+        .unwrap();
+    let testcases = [
+        (
+            PrincipalAttribute::DisallowAllTix,
+            ticket_flags([]),
+            Ecode::KDC_ERR_CLIENT_REVOKED,
+        ),
+        (
+            PrincipalAttribute::DisallowPostdated,
+            postdated,
+            Ecode::KDC_ERR_CANNOT_POSTDATE,
+        ),
+    ];
+    for (attribute, kdc_options, ecode) in testcases {
+        let principal_db = make_principal_db();
+        add_user_with(
+            &principal_db,
+            PrincipalAttributes::default().with(attribute),
+        );
+        let replay_cache = MockedReplayCache::new();
+        let mocked_last_req_db = MockedLastReqDb::new();
+        let tgs_service = make_basic_tgs_service(&principal_db, &replay_cache, &mocked_last_req_db);
+
+        let tgs_req = make_service_request_with_tgt(kdc_options, &tgt, "user", now);
+        assert_protocol_error(tgs_service.handle_tgs_req(&tgs_req).await, ecode);
+    }
+}
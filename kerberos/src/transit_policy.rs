@@ -0,0 +1,40 @@
+use messages::basic_types::Realm;
+use messages::TransitedEncoding;
+use std::collections::HashMap;
+
+/// Realms a cross-realm authentication path may go through, in the spirit of MIT's `[capaths]`.
+/// Without an explicit path for a pair of realms, only the realms on the hierarchical path
+/// between them may be transited, https://www.rfc-editor.org/rfc/rfc4120#section-2.7
+#[derive(Clone, Debug, Default)]
+pub struct TransitPolicy {
+    capaths: HashMap<(String, String), Vec<Realm>>,
+}
+
+impl TransitPolicy {
+    /// Let clients of `client_realm` reach `server_realm` through the realms of `path`,
+    /// an empty path meaning the two realms must trust each other directly
+    pub fn with_capath(
+        mut self,
+        client_realm: &Realm,
+        server_realm: &Realm,
+        path: Vec<Realm>,
+    ) -> Self {
+        self.capaths
+            .insert((client_realm.to_string(), server_realm.to_string()), path);
+        self
+    }
+
+    pub fn is_path_allowed(
+        &self,
+        client_realm: &Realm,
+        server_realm: &Realm,
+        transited: &[Realm],
+    ) -> bool {
+        let allowed = self
+            .capaths
+            .get(&(client_realm.to_string(), server_realm.to_string()))
+            .cloned()
+            .unwrap_or_else(|| TransitedEncoding::hierarchical_path(client_realm, server_realm));
+        transited.iter().all(|realm| allowed.contains(realm))
+    }
+}
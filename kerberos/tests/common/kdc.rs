@@ -0,0 +1,42 @@
+use kerberos::service_traits::{PrincipalDatabase, ReplayCache};
+use kerberos::ticket_granting_service::{ServerError, TicketGrantingService};
+use messages::{Decode, Encode, KrbErrorMsg, TgsRep, TgsReq};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// RFC4120 7.2.2, every message sent over TCP is preceded by its length
+async fn read_message(stream: &mut TcpStream) -> Vec<u8> {
+    let length = stream.read_u32().await.unwrap();
+    let mut message = vec![0; length as usize];
+    stream.read_exact(&mut message).await.unwrap();
+    message
+}
+
+async fn write_message(stream: &mut TcpStream, message: &[u8]) {
+    stream.write_u32(message.len() as u32).await.unwrap();
+    stream.write_all(message).await.unwrap();
+}
+
+/// Answer a single TGS request sent to `listener`
+pub async fn serve_tgs_req<P, C>(listener: &TcpListener, tgs: &TicketGrantingService<'_, P, C>)
+where
+    P: PrincipalDatabase + Sync + Send,
+    C: ReplayCache + Sync + Send,
+{
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let tgs_req = TgsReq::from_der(&read_message(&mut stream).await).unwrap();
+    let reply = match tgs.handle_tgs_req(&tgs_req).await {
+        Ok(tgs_rep) => tgs_rep.to_der().unwrap(),
+        Err(ServerError::ProtocolError(error)) => error.to_der().unwrap(),
+        Err(ServerError::Internal) => panic!("KDC failed to process the request"),
+    };
+    write_message(&mut stream, &reply).await;
+}
+
+pub async fn send_tgs_req(addr: SocketAddr, tgs_req: &TgsReq) -> Result<TgsRep, KrbErrorMsg> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    write_message(&mut stream, &tgs_req.to_der().unwrap()).await;
+    let reply = read_message(&mut stream).await;
+    TgsRep::from_der(&reply).map_err(|_| KrbErrorMsg::from_der(&reply).unwrap())
+}
@@ -6,17 +6,17 @@ use kerberos::cryptography::Cryptography;
 use kerberos::cryptography_error::CryptographyError;
 use kerberos::service_traits::{
     ApReplayCache, ApReplayEntry, ClientAddressStorage, LastReqDatabase, LastReqEntry,
-    PrincipalDatabase, PrincipalDatabaseRecord, ReplayCache, ReplayCacheEntry, UserSessionEntry,
-    UserSessionStorage,
+    PrincipalAttributes, PrincipalDatabase, PrincipalDatabaseRecord, PrincipalGroup, ReplayCache,
+    ReplayCacheEntry, UserSessionEntry, UserSessionStorage,
 };
 use messages::basic_types::{
-    EncryptionKey, HostAddress, Int32, KerberosFlags, KerberosString, OctetString, PrincipalName,
-    Realm,
+    EncryptionKey, HostAddress, Int32, KerberosFlags, KerberosString, NameTypes, OctetString,
+    PrincipalName, Realm,
 };
 use messages::{ApReq, AsRep, AsReq, EncAsRepPart, EncTgsRepPart, LastReq, TgsRep};
 use std::cell::RefCell;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub(crate) struct MockedCrypto;
 
@@ -57,11 +57,96 @@ impl PrincipalDatabase for MockedPrincipalDb {
             p_kvno: Some(1),
             max_renewable_life: Duration::from_secs(5 * 60),
             supported_encryption_types: vec![1, 2, 3],
+            allowed_to_delegate_to: vec![],
+            attributes: PrincipalAttributes::default(),
             max_lifetime: Duration::from_secs(24 * 60 * 60),
         })
     }
 }
 
+/// Principal database holding a distinct key per principal, e.g. the KDC database
+/// of one realm when several realms are involved in an exchange
+pub(crate) struct MockedKeyedPrincipalDb {
+    data: Arc<Mutex<Vec<(PrincipalName, Realm, EncryptionKey)>>>,
+    delegations: Arc<Mutex<Vec<(PrincipalName, PrincipalName)>>>,
+    groups: Arc<Mutex<Vec<(PrincipalName, PrincipalGroup)>>>,
+}
+
+impl MockedKeyedPrincipalDb {
+    pub(crate) fn new() -> MockedKeyedPrincipalDb {
+        MockedKeyedPrincipalDb {
+            data: Arc::new(Mutex::new(Vec::new())),
+            delegations: Arc::new(Mutex::new(Vec::new())),
+            groups: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub(crate) fn add_principal(&self, principal_name: PrincipalName, realm: Realm, key: Vec<u8>) {
+        self.data.lock().unwrap().push((
+            principal_name,
+            realm,
+            EncryptionKey::new(1, OctetString::new(key).unwrap()),
+        ));
+    }
+
+    pub(crate) fn allow_delegation(&self, principal_name: PrincipalName, target: PrincipalName) {
+        self.delegations
+            .lock()
+            .unwrap()
+            .push((principal_name, target));
+    }
+
+    pub(crate) fn add_group(&self, principal_name: PrincipalName, group: PrincipalGroup) {
+        self.groups.lock().unwrap().push((principal_name, group));
+    }
+}
+
+#[async_trait]
+impl PrincipalDatabase for MockedKeyedPrincipalDb {
+    async fn get_principal(
+        &self,
+        principal_name: &PrincipalName,
+        realm: &Realm,
+    ) -> Option<PrincipalDatabaseRecord> {
+        let allowed_to_delegate_to = self
+            .delegations
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == principal_name)
+            .map(|(_, target)| target.clone())
+            .collect();
+        self.data
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(name, principal_realm, _)| name == principal_name && principal_realm == realm)
+            .map(|(_, _, key)| PrincipalDatabaseRecord {
+                key: key.clone(),
+                p_kvno: Some(1),
+                max_renewable_life: Duration::from_secs(5 * 60),
+                supported_encryption_types: vec![1],
+                allowed_to_delegate_to,
+                attributes: PrincipalAttributes::default(),
+                max_lifetime: Duration::from_secs(24 * 60 * 60),
+            })
+    }
+
+    async fn get_groups(
+        &self,
+        principal_name: &PrincipalName,
+        _realm: &Realm,
+    ) -> Vec<PrincipalGroup> {
+        self.groups
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == principal_name)
+            .map(|(_, group)| group.clone())
+            .collect()
+    }
+}
+
 pub struct MockClientEnv {
     pub as_req: RefCell<Option<AsReq>>,
     pub as_rep: RefCell<Option<AsRep>>,
@@ -72,6 +157,11 @@ pub struct MockClientEnv {
     pub subkey: RefCell<Option<EncryptionKey>>,
     pub seq_number: RefCell<Option<u32>>,
     pub kdc_options: RefCell<Option<KerberosFlags>>,
+    pub client_name: RefCell<Option<KerberosString>>,
+    pub server_name: RefCell<Option<KerberosString>>,
+    /// Name of the service, when it cannot be told by `server_name` alone
+    pub server_principal: RefCell<Option<PrincipalName>>,
+    pub server_realm: RefCell<Option<KerberosString>>,
 }
 
 impl MockClientEnv {
@@ -86,13 +176,21 @@ impl MockClientEnv {
             subkey: RefCell::new(None),
             seq_number: RefCell::new(None),
             kdc_options: RefCell::new(None),
+            client_name: RefCell::new(None),
+            server_name: RefCell::new(None),
+            server_principal: RefCell::new(None),
+            server_realm: RefCell::new(None),
         }
     }
 }
 
 impl ClientEnv for MockClientEnv {
     fn get_client_name(&self) -> Result<KerberosString, ClientEnvError> {
-        Ok(KerberosString::new("client".as_bytes()).unwrap())
+        Ok(self
+            .client_name
+            .borrow()
+            .clone()
+            .unwrap_or(KerberosString::new("client".as_bytes()).unwrap()))
     }
 
     fn get_client_realm(&self) -> Result<KerberosString, ClientEnvError> {
@@ -100,11 +198,28 @@ impl ClientEnv for MockClientEnv {
     }
 
     fn get_server_name(&self) -> Result<KerberosString, ClientEnvError> {
-        Ok(KerberosString::new("server".as_bytes()).unwrap())
+        Ok(self
+            .server_name
+            .borrow()
+            .clone()
+            .unwrap_or(KerberosString::new("server".as_bytes()).unwrap()))
     }
 
     fn get_server_realm(&self) -> Result<KerberosString, ClientEnvError> {
-        Ok(KerberosString::new("realm".as_bytes()).unwrap())
+        Ok(self
+            .server_realm
+            .borrow()
+            .clone()
+            .unwrap_or(KerberosString::new("realm".as_bytes()).unwrap()))
+    }
+
+    fn get_server_principal(&self) -> Result<PrincipalName, ClientEnvError> {
+        match self.server_principal.borrow().clone() {
+            Some(server_principal) => Ok(server_principal),
+            None => Ok(
+                PrincipalName::new(NameTypes::NtPrincipal, vec![self.get_server_name()?]).unwrap(),
+            ),
+        }
     }
 
     fn get_kdc_options(&self) -> Result<KerberosFlags, ClientEnvError> {
@@ -216,7 +331,7 @@ impl ClientEnv for MockClientEnv {
 }
 
 pub(crate) struct MockedReplayCache {
-    entries: Arc<Mutex<Vec<ReplayCacheEntry>>>,
+    entries: Arc<Mutex<Vec<(ReplayCacheEntry, Instant)>>>,
 }
 
 impl MockedReplayCache {
@@ -231,13 +346,25 @@ impl MockedReplayCache {
 impl ReplayCache for MockedReplayCache {
     type ReplayCacheError = ();
 
-    async fn store(&self, entry: &ReplayCacheEntry) -> Result<(), Self::ReplayCacheError> {
-        self.entries.lock().unwrap().push(entry.to_owned());
+    async fn store(
+        &self,
+        entry: &ReplayCacheEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ReplayCacheError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .push((entry.to_owned(), Instant::now() + lifetime));
         Ok(())
     }
 
     async fn contain(&self, entry: &ReplayCacheEntry) -> Result<bool, Self::ReplayCacheError> {
-        Ok(self.entries.lock().unwrap().iter().any(|e| e == entry))
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(e, expiry)| e == entry && *expiry > Instant::now()))
     }
 }
 
@@ -248,8 +375,13 @@ impl CryptographicHash for MockedHasher {
         1
     }
 
+    /// Fixed-size like a real digest, which the PAC signatures rely on
     fn digest(&self, data: &[u8]) -> Vec<u8> {
-        data.iter().rev().cloned().collect()
+        let mut digest = vec![0; 20];
+        data.iter()
+            .enumerate()
+            .for_each(|(i, byte)| digest[i % 20] ^= byte.rotate_left((i / 20) as u32));
+        digest
     }
 }
 
@@ -283,7 +415,7 @@ impl LastReqDatabase for MockedLastReqDb {
 }
 
 pub struct MockedApReplayCache {
-    entries: Arc<Mutex<Vec<ApReplayEntry>>>,
+    entries: Arc<Mutex<Vec<(ApReplayEntry, Instant)>>>,
 }
 
 impl MockedApReplayCache {
@@ -298,13 +430,25 @@ impl MockedApReplayCache {
 impl ApReplayCache for MockedApReplayCache {
     type ApReplayCacheError = ();
 
-    async fn store(&self, entry: &ApReplayEntry) -> Result<(), Self::ApReplayCacheError> {
-        self.entries.lock().unwrap().push(entry.to_owned());
+    async fn store(
+        &self,
+        entry: &ApReplayEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ApReplayCacheError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .push((entry.to_owned(), Instant::now() + lifetime));
         Ok(())
     }
 
     async fn contain(&self, entry: &ApReplayEntry) -> Result<bool, Self::ApReplayCacheError> {
-        Ok(self.entries.lock().unwrap().iter().any(|e| e == entry))
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(e, expiry)| e == entry && *expiry > Instant::now()))
     }
 }
 
@@ -368,7 +512,9 @@ impl UserSessionStorage for MockedUserSessionStorage {
     }
 
     async fn store_session(&self, session: &UserSessionEntry) -> Result<(), Self::Error> {
-        self.sessions.lock().unwrap().push(session.clone());
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|s| s.cname != session.cname || s.crealm != session.crealm);
+        sessions.push(session.clone());
         Ok(())
     }
 }
@@ -1 +1,2 @@
-pub mod mocked;
\ No newline at end of file
+pub mod kdc;
+pub mod mocked;
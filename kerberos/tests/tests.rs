@@ -1,27 +1,52 @@
 pub mod common;
 
 mod tests {
+    use crate::common::kdc::{send_tgs_req, serve_tgs_req};
     use crate::common::mocked::{
         MockClientEnv, MockedApReplayCache, MockedClientAddressStorage, MockedCrypto, MockedHasher,
-        MockedLastReqDb, MockedPrincipalDb, MockedReplayCache, MockedUserSessionStorage,
+        MockedKeyedPrincipalDb, MockedLastReqDb, MockedPrincipalDb, MockedReplayCache,
+        MockedUserSessionStorage,
     };
     use kerberos::application_authentication_service::{
         ApplicationAuthenticationService, ApplicationAuthenticationServiceBuilder,
     };
     use kerberos::authentication_service::{AuthenticationService, AuthenticationServiceBuilder};
-    use kerberos::client::ap_exchange::prepare_ap_request;
+    use kerberos::application_messages::MessageContext;
+    use kerberos::client::ap_exchange::{
+        prepare_ap_request, prepare_krb_cred, prepare_mutual_ap_request, receive_ap_reply,
+    };
+    use kerberos::client::ccache::{CredentialCache, FileCredentialCache};
     use kerberos::client::as_exchange::{prepare_as_request, receive_as_response};
-    use kerberos::client::tgs_exchange::{prepare_tgs_request, receive_tgs_response};
+    use kerberos::client::tgs_exchange::{
+        prepare_cross_realm_tgs_request, prepare_forwarded_tgt_request, prepare_s4u2proxy_request,
+        prepare_s4u2self_request, prepare_tgs_request, prepare_user_to_user_tgs_request,
+        receive_forwarded_tgt, receive_tgs_response,
+    };
+    use kerberos::client::user_to_user::{
+        decrypt_user_to_user_ticket, prepare_tgt_reply, prepare_tgt_request,
+    };
+    use kerberos::authorization_data::PrincipalClaims;
+    use kerberos::gssapi::{
+        accept_sec_context, accept_spnego_context, error_token, init_sec_context,
+        init_spnego_context, read_spnego_ap_req, reject_spnego_token, ChannelBindings,
+        ContextFlag, ContextFlags, GssError,
+    };
+    use kerberos::keytab::{Keytab, KeytabEntry};
     use kerberos::service_traits::{
-        ApReplayCache, LastReqDatabase, PrincipalDatabase, ReplayCache,
+        ApReplayCache, LastReqDatabase, PrincipalDatabase, PrincipalGroup, ReplayCache,
+        UserSessionStorage,
     };
     use kerberos::ticket_granting_service::{TicketGrantingService, TicketGrantingServiceBuilder};
     use messages::basic_types::{
-        AddressTypes, EncryptionKey, HostAddress, KerberosString, NameTypes, OctetString,
-        PrincipalName, Realm,
+        AddressTypes, EncryptionKey, HostAddress, KerberosFlags, KerberosString, KerberosTime,
+        NameTypes, OctetString, PrincipalName, Realm,
     };
+    use messages::flags::{KdcOptionsFlag, TicketFlag};
+    use messages::Ecode;
     use std::net::Ipv4Addr;
+    use std::sync::LazyLock;
     use std::time::Duration;
+    use tokio::net::TcpListener;
 
     fn get_auth_service<P>(db: &P, pre_auth: bool) -> AuthenticationService<P>
     where
@@ -30,15 +55,9 @@ mod tests {
         AuthenticationServiceBuilder::default()
             .principal_db(db)
             .realm(Realm::new("realm".as_bytes()).unwrap())
-            .sname(
-                PrincipalName::new(
-                    NameTypes::NtPrincipal,
-                    [KerberosString::new("server").unwrap()],
-                )
-                .unwrap(),
-            )
             .require_pre_authenticate(pre_auth)
             .supported_crypto_systems(vec![Box::new(MockedCrypto)])
+            .supported_checksum(vec![Box::new(MockedHasher)])
             .build()
             .unwrap()
     }
@@ -48,6 +67,20 @@ mod tests {
         replay_cache: &'a C,
         mocked_last_req_db: &'a L,
     ) -> TicketGrantingService<'a, P, C>
+    where
+        P: PrincipalDatabase + Send + Sync,
+        C: ReplayCache + Send + Sync,
+        L: LastReqDatabase + Send + Sync,
+    {
+        get_realm_tgs_service("realm", db, replay_cache, mocked_last_req_db)
+    }
+
+    fn get_realm_tgs_service<'a, P, C, L>(
+        realm: &str,
+        db: &'a P,
+        replay_cache: &'a C,
+        mocked_last_req_db: &'a L,
+    ) -> TicketGrantingService<'a, P, C>
     where
         P: PrincipalDatabase + Send + Sync,
         C: ReplayCache + Send + Sync,
@@ -55,14 +88,7 @@ mod tests {
     {
         TicketGrantingServiceBuilder::default()
             .principal_db(db)
-            .realm(Realm::new("realm".as_bytes()).unwrap())
-            .name(
-                PrincipalName::new(
-                    NameTypes::NtPrincipal,
-                    [KerberosString::new("server").unwrap()],
-                )
-                .unwrap(),
-            )
+            .realm(Realm::new(realm).unwrap())
             .replay_cache(replay_cache)
             .supported_crypto(vec![Box::new(MockedCrypto)])
             .supported_checksum(vec![Box::new(MockedHasher)])
@@ -71,11 +97,41 @@ mod tests {
             .unwrap()
     }
 
+    /// Keys of the services the application server runs
+    static SERVICE_KEYTAB: LazyLock<Keytab> = LazyLock::new(|| {
+        let realm = Realm::new("realm").unwrap();
+        let services = [
+            vec![KerberosString::new("server").unwrap()],
+            vec![
+                KerberosString::new("HTTP").unwrap(),
+                KerberosString::new("web01.corp.example").unwrap(),
+            ],
+        ];
+        Keytab::new(
+            services
+                .into_iter()
+                .map(|name| KeytabEntry {
+                    principal: PrincipalName::new(NameTypes::NtPrincipal, name).unwrap(),
+                    realm: realm.clone(),
+                    timestamp: KerberosTime::now(),
+                    kvno: 1,
+                    key: EncryptionKey::new(1, OctetString::new(vec![1; 8]).unwrap()),
+                })
+                .collect(),
+        )
+    });
+
     fn get_ap_service<'a, C>(
         replay_cache: &'a C,
         address_storage: &'a MockedClientAddressStorage,
         session_storage: &'a MockedUserSessionStorage,
-    ) -> ApplicationAuthenticationService<'a, C, MockedUserSessionStorage, MockedClientAddressStorage>
+    ) -> ApplicationAuthenticationService<
+        'a,
+        C,
+        MockedUserSessionStorage,
+        MockedClientAddressStorage,
+        Keytab,
+    >
     where
         C: ApReplayCache + Sync + Send,
     {
@@ -90,10 +146,11 @@ mod tests {
             )
             .replay_cache(replay_cache)
             .session_storage(session_storage)
-            .service_key(EncryptionKey::new(1, OctetString::new(vec![1; 8]).unwrap()))
+            .key_store(&*SERVICE_KEYTAB)
             .accept_empty_address_ticket(true)
             .ticket_allowable_clock_skew(Duration::from_secs(60 * 10))
             .crypto(vec![Box::new(MockedCrypto)])
+            .supported_checksum(vec![Box::new(MockedHasher)])
             .address_storage(address_storage)
             .build()
             .unwrap()
@@ -108,6 +165,10 @@ mod tests {
         let as_rep = auth_service.handle_krb_as_req(&as_req).await;
         assert!(as_rep.is_ok());
         let as_rep = as_rep.unwrap();
+        assert_eq!(
+            as_rep.ticket().sname(),
+            &PrincipalName::krbtgt(&Realm::new("realm").unwrap())
+        );
         assert!(receive_as_response(&mock_client_env, &as_req, &as_rep).is_ok());
     }
 
@@ -187,4 +248,606 @@ mod tests {
         let ap_rep = ap_service.handle_krb_ap_req(ap_req).await;
         ap_rep.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_cross_realm_exchange() {
+        let local_realm = Realm::new("realm").unwrap();
+        let remote_realm = Realm::new("remote").unwrap();
+        let server = PrincipalName::new(
+            NameTypes::NtPrincipal,
+            [KerberosString::new("server").unwrap()],
+        )
+        .unwrap();
+        let inter_realm_key = vec![7; 8];
+
+        let local_db = MockedKeyedPrincipalDb::new();
+        let client = PrincipalName::new(
+            NameTypes::NtPrincipal,
+            [KerberosString::new("client").unwrap()],
+        )
+        .unwrap();
+        local_db.add_principal(client, local_realm.clone(), vec![1; 8]);
+        local_db.add_principal(server.clone(), local_realm.clone(), vec![2; 8]);
+        local_db.add_principal(
+            PrincipalName::krbtgt(&local_realm),
+            local_realm.clone(),
+            vec![4; 8],
+        );
+        local_db.add_principal(
+            PrincipalName::krbtgt(&remote_realm),
+            local_realm.clone(),
+            inter_realm_key.clone(),
+        );
+
+        let remote_db = MockedKeyedPrincipalDb::new();
+        remote_db.add_principal(
+            PrincipalName::krbtgt(&remote_realm),
+            local_realm.clone(),
+            inter_realm_key,
+        );
+        remote_db.add_principal(server.clone(), remote_realm.clone(), vec![3; 8]);
+
+        let (local_replay_cache, local_last_req_db) =
+            (MockedReplayCache::new(), MockedLastReqDb::new());
+        let local_tgs = get_realm_tgs_service(
+            "realm",
+            &local_db,
+            &local_replay_cache,
+            &local_last_req_db,
+        );
+        let (remote_replay_cache, remote_last_req_db) =
+            (MockedReplayCache::new(), MockedLastReqDb::new());
+        let remote_tgs = get_realm_tgs_service(
+            "remote",
+            &remote_db,
+            &remote_replay_cache,
+            &remote_last_req_db,
+        );
+        let local_kdc = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote_kdc = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let mock_client_env = MockClientEnv::new();
+        let as_req = prepare_as_request(&mock_client_env, None, None, None)
+            .expect("Failed to prepare AS request");
+        let as_rep = get_auth_service(&local_db, false)
+            .handle_krb_as_req(&as_req)
+            .await
+            .expect("Failed to get a TGT from the local realm");
+        receive_as_response(&mock_client_env, &as_req, &as_rep).unwrap();
+
+        // The local KDC refers the client to the remote realm
+        mock_client_env.server_realm.replace(Some(remote_realm.clone()));
+        let tgs_req = prepare_tgs_request(&mock_client_env).unwrap();
+        let (_, tgs_rep) = tokio::join!(
+            serve_tgs_req(&local_kdc, &local_tgs),
+            send_tgs_req(local_kdc.local_addr().unwrap(), &tgs_req)
+        );
+        let tgs_rep = tgs_rep.expect("Local KDC should issue a cross-realm TGT");
+        assert_eq!(tgs_rep.ticket().realm(), &local_realm);
+        assert_eq!(tgs_rep.ticket().sname(), &PrincipalName::krbtgt(&remote_realm));
+        receive_tgs_response(&tgs_req, &tgs_rep, &mock_client_env).unwrap();
+
+        // The remote KDC accepts the cross-realm TGT and issues the service ticket
+        let tgs_req = prepare_cross_realm_tgs_request(&mock_client_env).unwrap();
+        let (_, tgs_rep) = tokio::join!(
+            serve_tgs_req(&remote_kdc, &remote_tgs),
+            send_tgs_req(remote_kdc.local_addr().unwrap(), &tgs_req)
+        );
+        let tgs_rep = tgs_rep.expect("Remote KDC should issue the service ticket");
+        assert_eq!(tgs_rep.ticket().realm(), &remote_realm);
+        assert_eq!(tgs_rep.ticket().sname(), &server);
+        assert_eq!(tgs_rep.crealm(), &local_realm);
+        receive_tgs_response(&tgs_req, &tgs_rep, &mock_client_env).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_user_to_user_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let client = PrincipalName::new(
+            NameTypes::NtPrincipal,
+            [KerberosString::new("client").unwrap()],
+        )
+        .unwrap();
+        let peer = PrincipalName::new(
+            NameTypes::NtPrincipal,
+            [KerberosString::new("server").unwrap()],
+        )
+        .unwrap();
+        let db = MockedKeyedPrincipalDb::new();
+        // The mocked client environment logs in with the same key whoever the client is
+        db.add_principal(client.clone(), realm.clone(), vec![1; 8]);
+        db.add_principal(peer.clone(), realm.clone(), vec![1; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        // Both the client and its peer log in, the peer only keeps the session key of its TGT
+        let client_env = MockClientEnv::new();
+        let peer_env = MockClientEnv::new();
+        peer_env.client_name.replace(Some(peer.name_string()[0].clone()));
+        for env in [&client_env, &peer_env] {
+            let as_req = prepare_as_request(env, None, None, None).unwrap();
+            let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+            receive_as_response(env, &as_req, &as_rep).unwrap();
+        }
+
+        let tgt_req = prepare_tgt_request(&client_env).unwrap();
+        let tgt_rep = prepare_tgt_reply(&peer_env, &tgt_req).unwrap();
+
+        let tgs_req = prepare_user_to_user_tgs_request(&client_env, &tgt_rep).unwrap();
+        let tgs_rep = tgs_service
+            .handle_tgs_req(&tgs_req)
+            .await
+            .expect("KDC should issue a user-to-user ticket");
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+
+        let ticket = decrypt_user_to_user_ticket(&peer_env, tgs_rep.ticket())
+            .expect("Peer should decrypt the ticket with its TGT session key");
+        assert_eq!(ticket.cname(), &client);
+        let enc_tgs_rep_part = client_env.enc_tgs_rep_part.borrow().clone().unwrap();
+        assert_eq!(ticket.key(), enc_tgs_rep_part.key());
+    }
+
+    #[tokio::test]
+    async fn test_constrained_delegation_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let principal = |name: &str| {
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new(name).unwrap()])
+                .unwrap()
+        };
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(principal("client"), realm.clone(), vec![1; 8]);
+        db.add_principal(principal("alice"), realm.clone(), vec![2; 8]);
+        db.add_principal(principal("backend"), realm.clone(), vec![3; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![4; 8]);
+        db.allow_delegation(principal("client"), principal("backend"));
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        // The service logs in, then obtains a ticket to itself on behalf of alice
+        let client_env = MockClientEnv::new();
+        client_env.kdc_options.replace(Some(
+            KerberosFlags::builder()
+                .set(KdcOptionsFlag::FORWARDABLE as usize)
+                .build()
+                .unwrap(),
+        ));
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+
+        let tgs_req = prepare_s4u2self_request(&client_env, &principal("alice"), &realm).unwrap();
+        let tgs_rep = tgs_service
+            .handle_tgs_req(&tgs_req)
+            .await
+            .expect("KDC should issue a ticket on behalf of the user");
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+        assert_eq!(tgs_rep.cname(), &principal("alice"));
+        let enc_tgs_rep_part = client_env.enc_tgs_rep_part.borrow().clone().unwrap();
+        assert!(enc_tgs_rep_part
+            .flags()
+            .is_set(TicketFlag::FORWARDABLE as usize));
+
+        // The evidence ticket is then used to reach the backend as alice
+        client_env
+            .server_name
+            .replace(Some(KerberosString::new("backend").unwrap()));
+        let tgs_req = prepare_s4u2proxy_request(&client_env).unwrap();
+        let tgs_rep = tgs_service
+            .handle_tgs_req(&tgs_req)
+            .await
+            .expect("KDC should issue a ticket to the backend on behalf of the user");
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+        assert_eq!(tgs_rep.cname(), &principal("alice"));
+        assert_eq!(tgs_rep.ticket().sname(), &principal("backend"));
+    }
+
+    #[tokio::test]
+    async fn test_claims_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let principal = |name: &str| {
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new(name).unwrap()])
+                .unwrap()
+        };
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(principal("client"), realm.clone(), vec![1; 8]);
+        db.add_principal(principal("server"), realm.clone(), vec![1; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        db.add_group(
+            principal("client"),
+            PrincipalGroup {
+                name: KerberosString::new("admins").unwrap(),
+                roles: vec![KerberosString::new("admin").unwrap()],
+            },
+        );
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        // The claims issued into the TGT are carried over to the service ticket
+        let client_env = MockClientEnv::new();
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+        let tgs_req = prepare_tgs_request(&client_env).unwrap();
+        let tgs_rep = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+
+        let (ap_cache, address_storage, session_storage) = (
+            MockedApReplayCache::new(),
+            MockedClientAddressStorage::new(),
+            MockedUserSessionStorage::new(),
+        );
+        let ap_service = get_ap_service(&ap_cache, &address_storage, &session_storage);
+        let ap_req = prepare_ap_request(&client_env, false, None).unwrap();
+        ap_service.handle_krb_ap_req(ap_req).await.unwrap();
+
+        let session = session_storage
+            .get_session(&principal("client"), &realm)
+            .await
+            .unwrap()
+            .expect("Session should be stored");
+        let claims = PrincipalClaims::from_elements(&session.authorization_data)
+            .unwrap()
+            .expect("Service ticket should carry the claims of the client");
+        assert!(claims.is_member_of("admins"));
+        assert!(claims.has_role("admin"));
+    }
+
+    #[tokio::test]
+    async fn test_host_based_service_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let service = PrincipalName::new(
+            NameTypes::NtSrvHst,
+            [
+                KerberosString::new("HTTP").unwrap(),
+                KerberosString::new("web01.corp.example").unwrap(),
+            ],
+        )
+        .unwrap();
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new("client").unwrap()])
+                .unwrap(),
+            realm.clone(),
+            vec![1; 8],
+        );
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        db.add_principal(service.clone(), realm.clone(), vec![1; 8]);
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        let client_env = MockClientEnv::new();
+        client_env.server_principal.replace(Some(service.clone()));
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+        let tgs_req = prepare_tgs_request(&client_env).unwrap();
+        let tgs_rep = tgs_service
+            .handle_tgs_req(&tgs_req)
+            .await
+            .expect("KDC should issue a ticket to the host-based service");
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+        assert_eq!(tgs_rep.ticket().sname(), &service);
+
+        let (ap_cache, address_storage, session_storage) = (
+            MockedApReplayCache::new(),
+            MockedClientAddressStorage::new(),
+            MockedUserSessionStorage::new(),
+        );
+        let ap_service = get_ap_service(&ap_cache, &address_storage, &session_storage);
+        let ap_req = prepare_ap_request(&client_env, false, None).unwrap();
+        ap_service
+            .handle_krb_ap_req(ap_req)
+            .await
+            .expect("Service should accept its ticket");
+    }
+
+    #[tokio::test]
+    async fn test_mutual_authentication_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let principal = |name: &str| {
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new(name).unwrap()])
+                .unwrap()
+        };
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(principal("client"), realm.clone(), vec![1; 8]);
+        db.add_principal(principal("server"), realm.clone(), vec![1; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        let client_env = MockClientEnv::new();
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+        let tgs_req = prepare_tgs_request(&client_env).unwrap();
+        let tgs_rep = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+
+        let (ap_cache, address_storage, session_storage) = (
+            MockedApReplayCache::new(),
+            MockedClientAddressStorage::new(),
+            MockedUserSessionStorage::new(),
+        );
+        let ap_service = get_ap_service(&ap_cache, &address_storage, &session_storage);
+
+        // No reply is sent unless the client asks for one
+        let ap_req = prepare_ap_request(&client_env, false, None).unwrap();
+        assert!(ap_service.handle_krb_ap_req(ap_req).await.unwrap().is_none());
+
+        let (ap_req, authenticator) = prepare_mutual_ap_request(&client_env, None).unwrap();
+        let ap_rep = ap_service
+            .handle_krb_ap_req(ap_req)
+            .await
+            .unwrap()
+            .expect("Server should reply when mutual authentication is required");
+        receive_ap_reply(&client_env, ap_rep, authenticator.clone())
+            .expect("Client should accept the reply of the server");
+
+        // Both ends agree on the negotiated context
+        let session = session_storage
+            .get_session(&principal("client"), &realm)
+            .await
+            .unwrap()
+            .filter(|session| session.sequence_number == authenticator.seq_number().unwrap())
+            .expect("Session should be stored");
+        assert!(session.subkey.is_some());
+        assert_eq!(*client_env.subkey.borrow(), session.subkey);
+        assert_eq!(*client_env.seq_number.borrow(), session.server_sequence_number);
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_tgt_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let principal = |name: &str| {
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new(name).unwrap()])
+                .unwrap()
+        };
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(principal("client"), realm.clone(), vec![1; 8]);
+        db.add_principal(principal("server"), realm.clone(), vec![1; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        let client_env = MockClientEnv::new();
+        client_env.kdc_options.replace(Some(
+            KerberosFlags::builder()
+                .set(KdcOptionsFlag::FORWARDABLE as usize)
+                .build()
+                .unwrap(),
+        ));
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+        let tgs_req = prepare_tgs_request(&client_env).unwrap();
+        let tgs_rep = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+
+        // The client gets a TGT to forward, keeping the ticket of the server it is sent to
+        let tgs_req = prepare_forwarded_tgt_request(&client_env).unwrap();
+        let forwarded_rep = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        let tgt = receive_forwarded_tgt(&tgs_req, &forwarded_rep, &client_env)
+            .expect("Client should accept the forwarded TGT");
+        assert!(tgt.is_tgt() && tgt.flags.is_set(TicketFlag::FORWARDED as usize));
+        assert_eq!(*client_env.tgs_rep.borrow(), Some(tgs_rep));
+
+        let (ap_cache, address_storage, session_storage) = (
+            MockedApReplayCache::new(),
+            MockedClientAddressStorage::new(),
+            MockedUserSessionStorage::new(),
+        );
+        let ap_service = get_ap_service(&ap_cache, &address_storage, &session_storage);
+        let ap_req = prepare_ap_request(&client_env, false, None).unwrap();
+        ap_service.handle_krb_ap_req(ap_req).await.unwrap();
+        let krb_cred = prepare_krb_cred(&client_env, std::slice::from_ref(&tgt)).unwrap();
+
+        // The server reads it with the session key, then acts as the client with its TGT
+        let session = session_storage
+            .get_session(&principal("client"), &realm)
+            .await
+            .unwrap()
+            .expect("Session should be stored");
+        let mut context = MessageContext::builder()
+            .key(session.session_key.clone())
+            .local_address(
+                HostAddress::new(
+                    AddressTypes::Ipv4,
+                    OctetString::new(Ipv4Addr::new(192, 168, 1, 2).octets().as_slice()).unwrap(),
+                )
+                .unwrap(),
+            )
+            .crypto(vec![Box::new(MockedCrypto)])
+            .build()
+            .unwrap();
+        let credentials = context.read_krb_cred(&krb_cred).unwrap();
+        assert_eq!(credentials, std::slice::from_ref(&tgt));
+
+        let path = std::env::temp_dir().join(format!("krb5cc-forwarded-{}", std::process::id()));
+        let ccache = FileCredentialCache::new(&path);
+        ccache.store_delegated(credentials).unwrap();
+        let (default_principal, retrieved) = (
+            ccache.default_principal().unwrap(),
+            ccache.retrieve_tgt().unwrap(),
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(default_principal, (principal("client"), realm));
+        assert_eq!(retrieved, Some(tgt));
+    }
+
+    #[tokio::test]
+    async fn test_gssapi_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let principal = |name: &str| {
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new(name).unwrap()])
+                .unwrap()
+        };
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(principal("client"), realm.clone(), vec![1; 8]);
+        db.add_principal(principal("server"), realm.clone(), vec![1; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        let client_env = MockClientEnv::new();
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+        let tgs_req = prepare_tgs_request(&client_env).unwrap();
+        let tgs_rep = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+
+        let (ap_cache, address_storage, session_storage) = (
+            MockedApReplayCache::new(),
+            MockedClientAddressStorage::new(),
+            MockedUserSessionStorage::new(),
+        );
+        let ap_service = get_ap_service(&ap_cache, &address_storage, &session_storage);
+        let bindings = ChannelBindings::new(b"tls-server-end-point:certificate hash".to_vec());
+        let flags = ContextFlags::default()
+            .with(ContextFlag::Mutual)
+            .with(ContextFlag::Replay)
+            .with(ContextFlag::Sequence);
+
+        // The acceptor only accepts contexts bound to its channel
+        let (_, token) = init_sec_context(
+            &client_env,
+            flags,
+            Some(&ChannelBindings::new(b"other".to_vec())),
+        )
+        .unwrap();
+        assert!(matches!(
+            accept_sec_context(&ap_service, &token, Some(&bindings)).await,
+            Err(GssError::BadBindings)
+        ));
+
+        let (mut initiator, token) = init_sec_context(&client_env, flags, Some(&bindings)).unwrap();
+        assert!(!initiator.is_established());
+        let (mut acceptor, reply) = accept_sec_context(&ap_service, &token, Some(&bindings))
+            .await
+            .expect("Acceptor should accept the context");
+        assert_eq!(acceptor.peer_name(), (&principal("client"), &realm));
+        assert!(acceptor.flags().has(ContextFlag::Sequence));
+
+        // The AP-REQ is not accepted twice
+        match accept_sec_context(&ap_service, &token, Some(&bindings)).await {
+            Err(GssError::Rejected(error)) => {
+                let mut other = init_sec_context(&client_env, flags, None).unwrap().0;
+                assert!(matches!(
+                    other.continue_init_sec_context(&client_env, &error_token(&error).unwrap()),
+                    Err(GssError::Rejected(_))
+                ));
+            }
+            _ => panic!("Replayed AP-REQ should be rejected"),
+        }
+
+        assert!(matches!(initiator.get_mic(b"early"), Err(GssError::NoContext)));
+        initiator
+            .continue_init_sec_context(&client_env, &reply.expect("Mutual authentication"))
+            .expect("Initiator should accept the AP-REP");
+        assert!(initiator.is_established());
+        assert_eq!(initiator.peer_name(), (&principal("server"), &realm));
+
+        let mic = initiator.get_mic(b"request").unwrap();
+        acceptor.verify_mic(b"request", &mic).unwrap();
+        assert!(matches!(
+            acceptor.verify_mic(b"request", &mic),
+            Err(GssError::DuplicateToken)
+        ));
+        let token = initiator.wrap(b"secret request", true).unwrap();
+        assert_eq!(
+            acceptor.unwrap(&token).unwrap(),
+            (b"secret request".to_vec(), true)
+        );
+        let token = acceptor.wrap(b"response", false).unwrap();
+        assert_eq!(
+            initiator.unwrap(&token).unwrap(),
+            (b"response".to_vec(), false)
+        );
+        let mic = acceptor.get_mic(b"response").unwrap();
+        initiator.verify_mic(b"response", &mic).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spnego_exchange() {
+        let realm = Realm::new("realm").unwrap();
+        let principal = |name: &str| {
+            PrincipalName::new(NameTypes::NtPrincipal, [KerberosString::new(name).unwrap()])
+                .unwrap()
+        };
+        let db = MockedKeyedPrincipalDb::new();
+        db.add_principal(principal("client"), realm.clone(), vec![1; 8]);
+        db.add_principal(principal("server"), realm.clone(), vec![1; 8]);
+        db.add_principal(PrincipalName::krbtgt(&realm), realm.clone(), vec![2; 8]);
+        let (replay_cache, last_req_db) = (MockedReplayCache::new(), MockedLastReqDb::new());
+        let tgs_service = get_tgs_service(&db, &replay_cache, &last_req_db);
+        let as_service = get_auth_service(&db, false);
+
+        let client_env = MockClientEnv::new();
+        let as_req = prepare_as_request(&client_env, None, None, None).unwrap();
+        let as_rep = as_service.handle_krb_as_req(&as_req).await.unwrap();
+        receive_as_response(&client_env, &as_req, &as_rep).unwrap();
+        let tgs_req = prepare_tgs_request(&client_env).unwrap();
+        let tgs_rep = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        receive_tgs_response(&tgs_req, &tgs_rep, &client_env).unwrap();
+
+        let (ap_cache, address_storage, session_storage) = (
+            MockedApReplayCache::new(),
+            MockedClientAddressStorage::new(),
+            MockedUserSessionStorage::new(),
+        );
+        let ap_service = get_ap_service(&ap_cache, &address_storage, &session_storage);
+        let flags = ContextFlags::default().with(ContextFlag::Mutual);
+
+        // The Kerberos V5 token alone is not a SPNEGO one
+        let (_, token) = init_sec_context(&client_env, flags, None).unwrap();
+        assert!(matches!(
+            accept_spnego_context(&ap_service, &token, None).await,
+            Err(GssError::DefectiveToken)
+        ));
+
+        let (mut initiator, token) = init_spnego_context(&client_env, flags, None).unwrap();
+        let ap_req = read_spnego_ap_req(&token).unwrap();
+        assert_eq!(ap_req.ticket(), tgs_rep.ticket());
+        let (mut acceptor, reply) = accept_spnego_context(&ap_service, &token, None)
+            .await
+            .expect("Acceptor should accept the SPNEGO token");
+        assert_eq!(acceptor.peer_name(), (&principal("client"), &realm));
+        // The session tells the acceptor what the ticket of the client says
+        let session = acceptor.session().expect("Acceptor should keep the session");
+        let enc_part = client_env.enc_tgs_rep_part.borrow().clone().unwrap();
+        assert_eq!(&session.flags, enc_part.flags());
+        assert_eq!(&session.authtime, enc_part.authtime());
+        assert_eq!(&session.endtime, enc_part.endtime());
+
+        // A replayed token is rejected with the KRB-ERROR of the acceptor
+        let error = accept_spnego_context(&ap_service, &token, None)
+            .await
+            .err()
+            .expect("Replayed token should be rejected");
+        let mut other = init_spnego_context(&client_env, flags, None).unwrap().0;
+        match other.continue_spnego_context(&client_env, &reject_spnego_token(&error).unwrap()) {
+            Err(GssError::Rejected(error)) => {
+                assert_eq!(error.error_code(), Ecode::KRB_AP_ERR_REPEAT)
+            }
+            _ => panic!("Initiator should be told the token was replayed"),
+        }
+
+        initiator
+            .continue_spnego_context(&client_env, &reply)
+            .expect("Initiator should accept the negotiation token");
+        assert!(initiator.is_established());
+        let token = acceptor.wrap(b"response", true).unwrap();
+        assert_eq!(
+            initiator.unwrap(&token).unwrap(),
+            (b"response".to_vec(), true)
+        );
+    }
 }
@@ -1,29 +1,11 @@
-use actix_web::{dev::ConnectionInfo, web, HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder};
 use chrono::{DateTime, NaiveDate};
-use der::{Decode, Encode};
-use hex::FromHex;
 use kerberos_infra::server::database::{postgres::PostgresDb, Database};
-use messages::{
-    basic_types::{
-        AddressTypes, HostAddress, KerberosString, NameTypes, OctetString, PrincipalName, Realm,
-    },
-    ApReq,
-};
-use serde::{Deserialize, Serialize};
+use messages::basic_types::{KerberosString, NameTypes, PrincipalName};
+use serde::Serialize;
 use sqlx::{Executor, Row};
 
-use crate::{
-    client_address_storage::AppServerClientStorage,
-    replay_cache::AppServerReplayCache,
-    session_storage::ApplicationSessionStorage,
-    utils::{create_service, AuthenticationServiceConfig},
-};
-
-#[derive(Deserialize)]
-pub struct UserProfileQuery {
-    pub realm: String,
-    pub sequence: i32,
-}
+use crate::{middleware::AuthenticatedPrincipal, utils::AuthenticationServiceConfig};
 
 #[derive(Serialize, Debug, Clone)]
 pub struct UserProfileResponse {
@@ -37,19 +19,13 @@ pub struct UserProfileResponse {
     pub updated_at: DateTime<chrono::Utc>,
 }
 
-#[derive(Deserialize)]
-pub struct UserAuthenticateCommand {
-    pub ticket: String,
-}
-
-#[actix_web::get("/users/{username}")]
+/// Profile of the authenticated user, registered under a scope wrapped in
+/// [KerberosAuthentication](crate::middleware::KerberosAuthentication)
+#[actix_web::get("/{username}")]
 async fn handle_get(
     db: web::Data<PostgresDb>,
     auth_service_config: web::Data<AuthenticationServiceConfig>,
-    replay_cache: web::Data<AppServerReplayCache>,
-    session_cache: web::Data<ApplicationSessionStorage>,
-    address_cache: web::Data<AppServerClientStorage>,
-    query: web::Query<UserProfileQuery>,
+    principal: AuthenticatedPrincipal,
     username: web::Path<String>,
 ) -> actix_web::Result<impl Responder> {
     println!("Getting user info: {}", username);
@@ -64,26 +40,10 @@ async fn handle_get(
     }
     .map_err(|_| actix_web::error::ErrorBadRequest("Failed to create PrincipalName".to_string()))?;
 
-    let sequence_number = query.sequence;
-
-    let realm = Realm::new(query.realm.as_bytes())
-        .map_err(|_| actix_web::error::ErrorBadRequest("Failed to create Realm".to_string()))?;
-
-    let auth_service = create_service(
-        auth_service_config.as_ref(),
-        replay_cache.as_ref(),
-        session_cache.as_ref(),
-        address_cache.as_ref(),
-    );
-
-    println!("before");
-    if !auth_service
-        .is_user_authenticated(&username, &realm, sequence_number)
-        .await
-    {
-        return Ok(HttpResponse::Unauthorized().finish());
+    // Users only read their own profile, principals of trusted realms having none here
+    if principal.principal != username || principal.realm != auth_service_config.realm {
+        return Ok(HttpResponse::Forbidden().finish());
     }
-    println!("after");
 
     let username = username.name_string().first().map(|o| o.as_str()).ok_or(
         actix_web::error::ErrorBadRequest("Failed to get username from PrincipalName".to_string()),
@@ -132,55 +92,3 @@ async fn handle_get(
 
     Ok(HttpResponse::Ok().json(body))
 }
-
-#[actix_web::post("/authenticate")]
-async fn handle_post(
-    connection: ConnectionInfo,
-    auth_service_config: web::Data<AuthenticationServiceConfig>,
-    replay_cache: web::Data<AppServerReplayCache>,
-    session_cache: web::Data<ApplicationSessionStorage>,
-    address_cache: web::Data<AppServerClientStorage>,
-    body: web::Json<UserAuthenticateCommand>,
-) -> actix_web::Result<impl Responder> {
-    println!("Handling");
-    let auth_service = create_service(
-        auth_service_config.as_ref(),
-        replay_cache.as_ref(),
-        session_cache.as_ref(),
-        address_cache.as_ref(),
-    );
-
-    let address = connection
-        .peer_addr()
-        .map(|addr| HostAddress::new(AddressTypes::Ipv4, OctetString::new(addr).unwrap()));
-
-    let ap_req = {
-        let bytes = Vec::from_hex(&body.ticket)
-            .map_err(|_| actix_web::error::ErrorBadRequest("Your ticket is invalid".to_string()))?;
-        ApReq::from_der(&bytes)
-            .map_err(|_| actix_web::error::ErrorBadRequest("Failed to decode AP-REQ".to_string()))?
-    };
-
-    if let Some(inner) = address {
-        if let Ok(address) = inner {
-            address_cache.store(&ap_req, &address).await.map_err(|_| {
-                actix_web::error::ErrorInternalServerError(
-                    "Failed to store client address".to_string(),
-                )
-            })?;
-        }
-    } else {
-        return Ok(HttpResponse::Unauthorized().finish());
-    }
-
-    let reply = auth_service.handle_krb_ap_req(ap_req).await;
-    println!("Reply: {:?}", reply);
-
-    if let Ok(ap_rep) = reply {
-        Ok(HttpResponse::Ok().body(ap_rep.to_der().map_err(|_| {
-            actix_web::error::ErrorInternalServerError("Failed to encode AP-REP".to_string())
-        })?))
-    } else {
-        Ok(HttpResponse::Unauthorized().finish())
-    }
-}
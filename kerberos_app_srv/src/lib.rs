@@ -3,4 +3,6 @@ pub mod session_storage;
 pub mod client_address_storage;
 pub mod database;
 pub mod handlers;
+pub mod middleware;
+pub mod negotiate;
 pub mod utils;
\ No newline at end of file
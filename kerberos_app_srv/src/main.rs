@@ -6,20 +6,22 @@ use actix_web::{
 };
 use config::Config;
 use der::asn1::OctetString;
+use kerberos::keytab::{Keytab, KeytabEntry};
 use kerberos_app_srv::{
     client_address_storage::AppServerClientStorage, replay_cache::AppServerReplayCache,
     session_storage::ApplicationSessionStorage,
 };
 use kerberos_app_srv::{
     database::AppDbSchema,
-    handlers::{handle_get, handle_post},
+    handlers::handle_get,
+    middleware::KerberosAuthentication,
     utils::AuthenticationServiceConfig,
 };
 use kerberos_infra::server::database::{
     postgres::{PgDbSettings, PostgresDb},
     DbSettings, Migration,
 };
-use messages::basic_types::{EncryptionKey, KerberosString, NameTypes, PrincipalName, Realm};
+use messages::basic_types::{EncryptionKey, KerberosTime, Realm};
 use secrecy::{ExposeSecret, SecretBox};
 use serde::Deserialize;
 
@@ -36,21 +38,28 @@ async fn main() -> std::io::Result<()> {
     postgres.migrate_then_seed().await.unwrap();
 
     HttpServer::new(move || {
-        let replay_cache = AppServerReplayCache::new();
-        let session_cache = ApplicationSessionStorage::new();
-        let address_cache = AppServerClientStorage::new();
+        let replay_cache = web::Data::new(AppServerReplayCache::new());
+        let session_cache = web::Data::new(ApplicationSessionStorage::new());
+        let address_cache = web::Data::new(AppServerClientStorage::new());
         let app_config = AppSrvConfig::load_from("server");
-        let auth_service_config = AuthenticationServiceConfig::from(app_config);
+        let auth_service_config = web::Data::new(AuthenticationServiceConfig::from(app_config));
         println!("Application server started at 127.0.0.1:8080!");
         App::new()
-            .app_data(web::Data::new(replay_cache))
-            .app_data(web::Data::new(session_cache))
-            .app_data(web::Data::new(address_cache))
+            .app_data(replay_cache.clone())
+            .app_data(session_cache.clone())
+            .app_data(address_cache.clone())
             .app_data(web::Data::new(postgres.clone()))
-            .app_data(web::Data::new(auth_service_config))
-            .service(handle_get)
-            .service(handle_post)
-            
+            .app_data(auth_service_config.clone())
+            .service(
+                web::scope("/users")
+                    .wrap(KerberosAuthentication::new(
+                        auth_service_config,
+                        replay_cache,
+                        session_cache,
+                        address_cache,
+                    ))
+                    .service(handle_get),
+            )
     })
     .workers(1)
     .bind(("127.0.0.1", 8080))?
@@ -62,11 +71,22 @@ async fn main() -> std::io::Result<()> {
 pub struct AppSrvConfig {
     pub realm: SecretBox<String>,
     pub sname: SecretBox<String>,
-    pub service_key: SecretBox<String>,
+    /// Keytab file of the services, e.g. exported with `ktutil`
+    pub keytab: Option<String>,
+    #[serde(default)]
+    pub keys: Vec<ServiceKeyConfig>,
     pub accept_empty_address_ticket: bool,
     pub ticket_allowable_clock_skew: u64,
 }
 
+/// Key of a service the server runs, several versions being listed while it rotates
+#[derive(Debug, Deserialize)]
+pub struct ServiceKeyConfig {
+    pub principal: String,
+    pub kvno: u32,
+    pub key: SecretBox<String>,
+}
+
 impl AppSrvConfig {
     pub fn load_from(dir: &str) -> Self {
         let base_path = std::env::current_dir().expect("Fail to read the base directory");
@@ -85,15 +105,28 @@ impl From<AppSrvConfig> for AuthenticationServiceConfig {
     fn from(value: AppSrvConfig) -> Self {
         AuthenticationServiceConfig {
             realm: Realm::new(value.realm.expose_secret()).unwrap(),
-            sname: PrincipalName::new(
-                NameTypes::NtEnterprise,
-                vec![KerberosString::new(value.sname.expose_secret()).unwrap()],
-            )
-            .unwrap(),
-            service_key: EncryptionKey::new(
-                1,
-                OctetString::new(value.service_key.expose_secret().clone()).unwrap(),
-            ),
+            // e.g. `HTTP/localhost`, the service on a host
+            sname: value.sname.expose_secret().parse().unwrap(),
+            keytab: {
+                let mut keytab = value
+                    .keytab
+                    .as_ref()
+                    .map(|path| Keytab::load(path).expect("Failed to load the keytab"))
+                    .unwrap_or_default();
+                value.keys.iter().for_each(|key| {
+                    keytab.add(KeytabEntry {
+                        principal: key.principal.parse().unwrap(),
+                        realm: Realm::new(value.realm.expose_secret()).unwrap(),
+                        timestamp: KerberosTime::now(),
+                        kvno: key.kvno,
+                        key: EncryptionKey::new(
+                            1,
+                            OctetString::new(key.key.expose_secret().clone()).unwrap(),
+                        ),
+                    })
+                });
+                keytab
+            },
             accept_empty_address_ticket: value.accept_empty_address_ticket,
             ticket_allowable_clock_skew: Duration::from_secs(value.ticket_allowable_clock_skew),
         }
@@ -0,0 +1,185 @@
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{HeaderValue, WWW_AUTHENTICATE},
+    web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use kerberos::{authorization_data::AuthorizationElement, service_traits::UserSessionEntry};
+use messages::{
+    basic_types::{KerberosTime, PrincipalName, Realm},
+    TicketFlags,
+};
+
+use crate::{
+    client_address_storage::AppServerClientStorage,
+    negotiate::{authenticate, challenge, negotiate_token},
+    replay_cache::AppServerReplayCache,
+    session_storage::ApplicationSessionStorage,
+    utils::{create_service, AuthenticationServiceConfig},
+};
+
+/// Client a request was authenticated as by [KerberosAuthentication], which handlers of the
+/// services it wraps take as an argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedPrincipal {
+    pub principal: PrincipalName,
+    pub realm: Realm,
+    pub authtime: KerberosTime,
+    /// Start time of the client's ticket, its authentication time unless it was postdated
+    pub starttime: KerberosTime,
+    pub endtime: KerberosTime,
+    pub renew_till: Option<KerberosTime>,
+    pub flags: TicketFlags,
+    /// Authorization data of the client's ticket, e.g. its groups
+    pub authorization_data: Vec<AuthorizationElement>,
+}
+
+impl From<UserSessionEntry> for AuthenticatedPrincipal {
+    fn from(session: UserSessionEntry) -> Self {
+        Self {
+            principal: session.cname,
+            realm: session.crealm,
+            authtime: session.authtime,
+            starttime: session.starttime,
+            endtime: session.endtime,
+            renew_till: session.renew_till,
+            flags: session.flags,
+            authorization_data: session.authorization_data,
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedPrincipal {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // Requests reaching handlers the middleware does not wrap are asked to authenticate
+        ready(
+            req.extensions()
+                .get::<AuthenticatedPrincipal>()
+                .cloned()
+                .ok_or_else(|| InternalError::from_response("", challenge(None)).into()),
+        )
+    }
+}
+
+/// Middleware authenticating the requests of the services it wraps with HTTP Negotiate,
+/// https://www.rfc-editor.org/rfc/rfc4559. The others are answered with a challenge, and the
+/// authenticated ones are given the [AuthenticatedPrincipal] of their client:
+///
+/// ```ignore
+/// App::new().service(
+///     web::scope("/users")
+///         .wrap(KerberosAuthentication::new(config, replay_cache, session_cache, address_cache))
+///         .route("/me", web::get().to(|principal: AuthenticatedPrincipal| async move {
+///             principal.principal.to_string()
+///         })),
+/// )
+/// ```
+#[derive(Clone)]
+pub struct KerberosAuthentication {
+    config: web::Data<AuthenticationServiceConfig>,
+    replay_cache: web::Data<AppServerReplayCache>,
+    session_cache: web::Data<ApplicationSessionStorage>,
+    address_cache: web::Data<AppServerClientStorage>,
+}
+
+impl KerberosAuthentication {
+    /// Middleware sharing its caches with the handlers taking them as app data, so that an
+    /// authenticator is not accepted twice whichever way it is sent
+    pub fn new(
+        config: web::Data<AuthenticationServiceConfig>,
+        replay_cache: web::Data<AppServerReplayCache>,
+        session_cache: web::Data<ApplicationSessionStorage>,
+        address_cache: web::Data<AppServerClientStorage>,
+    ) -> Self {
+        Self {
+            config,
+            replay_cache,
+            session_cache,
+            address_cache,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for KerberosAuthentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = KerberosAuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(KerberosAuthenticationMiddleware {
+            service: Rc::new(service),
+            auth: self.clone(),
+        }))
+    }
+}
+
+pub struct KerberosAuthenticationMiddleware<S> {
+    service: Rc<S>,
+    auth: KerberosAuthentication,
+}
+
+impl<S, B> Service<ServiceRequest> for KerberosAuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let auth = self.auth.clone();
+
+        Box::pin(async move {
+            let reject = |req: ServiceRequest, response: HttpResponse| {
+                Ok(req.into_response(response).map_into_right_body())
+            };
+            let token = match negotiate_token(req.request()) {
+                Some(Ok(token)) => token,
+                Some(Err(_)) => return reject(req, HttpResponse::BadRequest().finish()),
+                None => return reject(req, challenge(None)),
+            };
+
+            let auth_service = create_service(
+                auth.config.as_ref(),
+                auth.replay_cache.as_ref(),
+                auth.session_cache.as_ref(),
+                auth.address_cache.as_ref(),
+            );
+            let authenticated =
+                authenticate(req.request(), &token, &auth_service, &auth.address_cache).await;
+            let (session, completion) = match authenticated {
+                Ok(authenticated) => authenticated,
+                Err(response) => return reject(req, response),
+            };
+            req.extensions_mut()
+                .insert(AuthenticatedPrincipal::from(session));
+
+            // The completion carries the AP-REP, which mutually authenticating clients check
+            let mut response = service.call(req).await?;
+            let completion = HeaderValue::from_str(&completion)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            response.headers_mut().insert(WWW_AUTHENTICATE, completion);
+            Ok(response.map_into_left_body())
+        })
+    }
+}
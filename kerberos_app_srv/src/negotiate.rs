@@ -0,0 +1,90 @@
+use std::net::IpAddr;
+
+use actix_web::{
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use kerberos::{
+    application_authentication_service::ApplicationAuthenticationService,
+    gssapi::{accept_spnego_context, read_spnego_ap_req, reject_spnego_token},
+    keytab::Keytab,
+    service_traits::UserSessionEntry,
+};
+use messages::basic_types::{AddressTypes, HostAddress, OctetString};
+
+use crate::{
+    client_address_storage::AppServerClientStorage, replay_cache::AppServerReplayCache,
+    session_storage::ApplicationSessionStorage,
+};
+
+/// Scheme of the HTTP Negotiate authentication, https://www.rfc-editor.org/rfc/rfc4559
+pub const NEGOTIATE: &str = "Negotiate";
+
+type AppAuthenticationService<'a> = ApplicationAuthenticationService<
+    'a,
+    AppServerReplayCache,
+    ApplicationSessionStorage,
+    AppServerClientStorage,
+    Keytab,
+>;
+
+/// SPNEGO token of the `Authorization: Negotiate` header of `req`, none if the client did not
+/// use the scheme
+pub fn negotiate_token(req: &HttpRequest) -> Option<Result<Vec<u8>, base64::DecodeError>> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case(NEGOTIATE)
+        .then(|| STANDARD.decode(token.trim()))
+}
+
+/// Response asking the client to authenticate with the Negotiate scheme, along with the
+/// token rejecting its last attempt if any
+pub fn challenge(token: Option<&[u8]>) -> HttpResponse {
+    let value = match token {
+        Some(token) => format!("{} {}", NEGOTIATE, STANDARD.encode(token)),
+        None => NEGOTIATE.to_string(),
+    };
+    HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, value))
+        .finish()
+}
+
+/// Authenticate the client of `req` with its SPNEGO `token`, giving back the session it
+/// negotiated and the `WWW-Authenticate` value completing the authentication, which carries
+/// the AP-REP when the client asked for mutual authentication. Otherwise gives back the
+/// response to send instead
+pub async fn authenticate(
+    req: &HttpRequest,
+    token: &[u8],
+    auth_service: &AppAuthenticationService<'_>,
+    address_cache: &AppServerClientStorage,
+) -> Result<(UserSessionEntry, String), HttpResponse> {
+    let ap_req = read_spnego_ap_req(token)
+        .map_err(|e| challenge(reject_spnego_token(&e).ok().as_deref()))?;
+    // Tickets issued for given addresses are only accepted from them
+    if let Some(address) = req.peer_addr().and_then(|addr| host_address(addr.ip())) {
+        address_cache
+            .store(&ap_req, &address)
+            .await
+            .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    }
+
+    let (context, reply) = accept_spnego_context(auth_service, token, None)
+        .await
+        .map_err(|e| challenge(reject_spnego_token(&e).ok().as_deref()))?;
+    let session = context
+        .session()
+        .cloned()
+        .ok_or(HttpResponse::InternalServerError().finish())?;
+    Ok((session, format!("{} {}", NEGOTIATE, STANDARD.encode(reply))))
+}
+
+fn host_address(ip: IpAddr) -> Option<HostAddress> {
+    let (addr_type, octets) = match ip {
+        IpAddr::V4(ip) => (AddressTypes::Ipv4, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (AddressTypes::Ipv6, ip.octets().to_vec()),
+    };
+    HostAddress::new(addr_type, OctetString::new(octets).ok()?).ok()
+}
@@ -1,12 +1,13 @@
-use std::marker::PhantomData;
+use std::time::Duration;
 
 use crate::utils::SrvCacheError;
 use async_trait::async_trait;
-use kerberos::service_traits::{ApReplayCache, ApReplayEntry};
+use kerberos::service_traits::{ApReplayCache, ApReplayEntry, ReplayExpiry};
 use kerberos_infra::server::cache::{Cache, CacheSettings, Cacheable};
 use messages::Encode;
 
-pub struct AppServerReplayCache(Cache<Vec<u8>, PhantomData<bool>>);
+/// Authenticators keyed by their DER encoding, with the time they may be forgotten at
+pub struct AppServerReplayCache(Cache<Vec<u8>, ReplayExpiry>);
 
 impl AppServerReplayCache {
     pub fn new() -> AppServerReplayCache {
@@ -21,10 +22,16 @@ impl AppServerReplayCache {
 impl ApReplayCache for AppServerReplayCache {
     type ApReplayCacheError = SrvCacheError;
 
-    async fn store(&self, authenticator: &ApReplayEntry) -> Result<(), Self::ApReplayCacheError> {
-        self.0
-            .put(authenticator.to_der().unwrap(), PhantomData)
-            .await?;
+    async fn store(
+        &self,
+        authenticator: &ApReplayEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ApReplayCacheError> {
+        let key = authenticator
+            .to_der()
+            .map_err(|_| SrvCacheError::Internal)?;
+
+        self.0.put(key, ReplayExpiry::after(lifetime)).await?;
 
         Ok(())
     }
@@ -37,8 +44,10 @@ impl ApReplayCache for AppServerReplayCache {
             .to_der()
             .map_err(|_| SrvCacheError::Internal)?;
 
-        self.0.get(&key).await?;
-
-        Ok(true)
+        match self.0.get(&key).await.map_err(SrvCacheError::from) {
+            Ok(expiry) => Ok(expiry.is_live()),
+            Err(SrvCacheError::MissingKey | SrvCacheError::Expired) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 }
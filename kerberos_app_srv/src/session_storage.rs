@@ -7,11 +7,11 @@ use async_trait::async_trait;
 use der::Sequence;
 use kerberos::service_traits::{UserSessionEntry, UserSessionStorage};
 use messages::{
-    basic_types::{EncryptionKey, PrincipalName, Realm},
+    basic_types::{PrincipalName, Realm},
     Encode,
 };
 
-pub struct ApplicationSessionStorage(Cache<Vec<u8>, (EncryptionKey, i32)>);
+pub struct ApplicationSessionStorage(Cache<Vec<u8>, UserSessionEntry>);
 
 #[derive(Debug, Clone, PartialEq, Eq, Sequence)]
 pub struct AppServerSessionRequest {
@@ -44,17 +44,7 @@ impl UserSessionStorage for ApplicationSessionStorage {
         .to_der()
         .map_err(|_| SrvCacheError::Internal)?;
 
-        let session = self.0.get(&key).await.ok();
-
-        match session {
-            Some((key, sequence_number)) => Ok(Some(UserSessionEntry {
-                cname: cname.clone(),
-                crealm: crealm.clone(),
-                sequence_number,
-                session_key: key.clone(),
-            })),
-            None => Ok(None),
-        }
+        Ok(self.0.get(&key).await.ok())
     }
 
     async fn store_session(&self, session: &UserSessionEntry) -> Result<(), Self::Error> {
@@ -65,9 +55,7 @@ impl UserSessionStorage for ApplicationSessionStorage {
         .to_der()
         .map_err(|_| SrvCacheError::Internal)?;
 
-        self.0
-            .put(key, (session.session_key.clone(), session.sequence_number))
-            .await?;
+        self.0.put(key, session.clone()).await?;
 
         Ok(())
     }
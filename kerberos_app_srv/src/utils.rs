@@ -2,7 +2,8 @@ use std::time::Duration;
 
 use kerberos::application_authentication_service::ApplicationAuthenticationServiceBuilder;
 use kerberos_infra::server::cache::CacheErr;
-use messages::basic_types::{EncryptionKey, PrincipalName, Realm};
+use kerberos::keytab::Keytab;
+use messages::basic_types::{PrincipalName, Realm};
 
 use crate::{
     client_address_storage::AppServerClientStorage, replay_cache::AppServerReplayCache,
@@ -28,7 +29,8 @@ impl From<CacheErr> for SrvCacheError {
 pub struct AuthenticationServiceConfig {
     pub realm: Realm,
     pub sname: PrincipalName,
-    pub service_key: EncryptionKey,
+    /// Keys of the services the server accepts tickets for
+    pub keytab: Keytab,
     pub accept_empty_address_ticket: bool,
     pub ticket_allowable_clock_skew: Duration,
 }
@@ -43,17 +45,19 @@ pub fn create_service<'a>(
     AppServerReplayCache,
     ApplicationSessionStorage,
     AppServerClientStorage,
+    Keytab,
 > {
     ApplicationAuthenticationServiceBuilder::default()
         .realm(auth_service_config.realm.clone())
         .sname(auth_service_config.sname.clone())
-        .service_key(auth_service_config.service_key.clone())
+        .key_store(&auth_service_config.keytab)
         .accept_empty_address_ticket(auth_service_config.accept_empty_address_ticket)
         .ticket_allowable_clock_skew(auth_service_config.ticket_allowable_clock_skew)
         .replay_cache(replay_cache)
         .session_storage(session_cache)
         .address_storage(address_cache)
         .crypto(vec![Box::new(kerberos::AesGcm::new())])
+        .supported_checksum(vec![Box::new(kerberos::Sha1::new())])
         .build()
         .expect("Failed to build authentication service")
 }
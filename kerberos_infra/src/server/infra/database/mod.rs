@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use config::Config;
-use view::PrincipalComplexView;
+use view::{GroupView, PrincipalComplexView};
 
 use crate::server::utils::Environment;
 
@@ -86,6 +86,14 @@ pub trait KrbV5Queryable {
     ) -> DatabaseResult<Option<PrincipalComplexView>> {
         Err(DatabaseError::InternalError)
     }
+
+    async fn get_groups(
+        &self,
+        _principal_name: &str,
+        _realm: &str,
+    ) -> DatabaseResult<Vec<GroupView>> {
+        Err(DatabaseError::InternalError)
+    }
 }
 
 #[derive(Debug)]
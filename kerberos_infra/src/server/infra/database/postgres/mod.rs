@@ -8,7 +8,8 @@ use sqlx::{postgres::PgPoolOptions, Executor, PgPool, Row};
 use crate::server::infra::KrbDbSchema;
 
 use super::{
-    view::PrincipalComplexView, Database, DatabaseError, DatabaseResult, KrbV5Queryable, Migration,
+    view::{GroupView, PrincipalComplexView, PrincipalNameView},
+    Database, DatabaseError, DatabaseResult, KrbV5Queryable, Migration,
 };
 
 pub use schemas::Krb5DbSchemaV1;
@@ -130,10 +131,25 @@ impl KrbV5Queryable for Box<dyn Database<Inner = PgPool>> {
                     k.knvno,
                     k.etype,
                     tp.maximum_ticket_lifetime as maximum_lifetime,
-                    tp.maximum_renewable_lifetime as maximum_renewable_life
+                    tp.maximum_renewable_lifetime as maximum_renewable_life,
+                    ARRAY(
+                        SELECT d.target_principal_name::TEXT
+                        FROM "{0}".Delegation d
+                        WHERE d.principal_name = p.principal_name
+                        ORDER BY d.target_principal_name
+                    ) as allowed_to_delegate_to,
+                    ARRAY(
+                        SELECT COALESCE(t.name_type, 1)
+                        FROM "{0}".Delegation d
+                            LEFT JOIN "{0}".Principal t ON d.target_principal_name = t.principal_name
+                        WHERE d.principal_name = p.principal_name
+                        ORDER BY d.target_principal_name
+                    ) as allowed_to_delegate_to_name_types,
+                    p.name_type,
+                    p.flags
                 FROM
                     (
-                        SELECT principal_name, realm, expire
+                        SELECT principal_name, name_type, realm, flags, expire
                         FROM "{0}".Principal
                         WHERE principal_name = '{1}' AND realm = '{2}'
                     ) AS p
@@ -154,10 +170,29 @@ impl KrbV5Queryable for Box<dyn Database<Inner = PgPool>> {
             supported_enctypes: vec![row.get(4)],
             max_lifetime: row.get(5),
             max_renewable_life: row.get(6),
+            allowed_to_delegate_to: row
+                .get::<Vec<String>, _>(7)
+                .into_iter()
+                .zip(row.get::<Vec<i32>, _>(8))
+                .map(|(principal_name, name_type)| PrincipalNameView {
+                    principal_name,
+                    name_type,
+                })
+                .collect(),
+            name_type: row.get(9),
+            flags: row.get(10),
         });
 
         Ok(result)
     }
+
+    async fn get_groups(
+        &self,
+        principal_name: &str,
+        realm: &str,
+    ) -> DatabaseResult<Vec<GroupView>> {
+        query_groups(self.as_ref(), principal_name, realm).await
+    }
 }
 
 #[async_trait]
@@ -178,10 +213,25 @@ impl KrbV5Queryable for &dyn Database<Inner = PgPool> {
                     k.knvno,
                     k.etype,
                     tp.maximum_ticket_lifetime as maximum_lifetime,
-                    tp.maximum_renewable_lifetime as maximum_renewable_life
+                    tp.maximum_renewable_lifetime as maximum_renewable_life,
+                    ARRAY(
+                        SELECT d.target_principal_name::TEXT
+                        FROM "{0}".Delegation d
+                        WHERE d.principal_name = p.principal_name
+                        ORDER BY d.target_principal_name
+                    ) as allowed_to_delegate_to,
+                    ARRAY(
+                        SELECT COALESCE(t.name_type, 1)
+                        FROM "{0}".Delegation d
+                            LEFT JOIN "{0}".Principal t ON d.target_principal_name = t.principal_name
+                        WHERE d.principal_name = p.principal_name
+                        ORDER BY d.target_principal_name
+                    ) as allowed_to_delegate_to_name_types,
+                    p.name_type,
+                    p.flags
                 FROM
                     (
-                        SELECT principal_name, realm, expire
+                        SELECT principal_name, name_type, realm, flags, expire
                         FROM "{0}".Principal
                         WHERE principal_name = '{1}' AND realm = '{2}'
                     ) AS p
@@ -202,10 +252,70 @@ impl KrbV5Queryable for &dyn Database<Inner = PgPool> {
             supported_enctypes: vec![row.get(4)],
             max_lifetime: row.get(5),
             max_renewable_life: row.get(6),
+            allowed_to_delegate_to: row
+                .get::<Vec<String>, _>(7)
+                .into_iter()
+                .zip(row.get::<Vec<i32>, _>(8))
+                .map(|(principal_name, name_type)| PrincipalNameView {
+                    principal_name,
+                    name_type,
+                })
+                .collect(),
+            name_type: row.get(9),
+            flags: row.get(10),
         });
 
         Ok(result)
     }
+
+    async fn get_groups(
+        &self,
+        principal_name: &str,
+        realm: &str,
+    ) -> DatabaseResult<Vec<GroupView>> {
+        query_groups(*self, principal_name, realm).await
+    }
+}
+
+/// Groups of a principal along with their roles, the names coming from requests being bound
+/// rather than written into the query
+async fn query_groups(
+    db: &dyn Database<Inner = PgPool>,
+    principal_name: &str,
+    realm: &str,
+) -> DatabaseResult<Vec<GroupView>> {
+    let schema = db.get_schema().schema_name();
+
+    let query = format!(r#"
+        SELECT
+            m.group_name,
+            ARRAY(
+                SELECT r.role_name::TEXT
+                FROM "{0}".GroupRole r
+                WHERE r.group_name = m.group_name
+                ORDER BY r.role_name
+            ) as roles
+        FROM
+            "{0}".GroupMember m
+            JOIN
+                "{0}".Principal p ON m.principal_name = p.principal_name
+        WHERE p.principal_name = $1 AND p.realm = $2
+        ORDER BY m.group_name;
+    "#, schema);
+
+    let result = sqlx::query(&query)
+        .bind(principal_name)
+        .bind(realm)
+        .fetch_all(db.inner())
+        .await?
+        .into_iter()
+        .map(|row| GroupView {
+            group_name: row.get(0),
+            roles: row.get(1),
+        })
+        .collect();
+
+    Ok(result)
 }
 
 impl From<sqlx::Error> for DatabaseError {
@@ -36,9 +36,10 @@ impl Schema for Krb5DbSchemaV1 {
                     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
                 );
 
-                -- Create Principal table
+                -- Create Principal table, the components of a name being separated by '/', e.g. HTTP/web01.corp.example
                 CREATE TABLE "{0}".Principal (
                     principal_name VARCHAR(255) PRIMARY KEY,
+                    name_type INT NOT NULL DEFAULT 1 CHECK (name_type >= 0),
                     realm VARCHAR(255) REFERENCES "{0}".TicketPolicy(realm),
                     flags INT NOT NULL CHECK (flags >= 0 AND flags <= 0xFFFFFFFF),
                     expire TIMESTAMP NOT NULL,
@@ -75,6 +76,44 @@ impl Schema for Krb5DbSchemaV1 {
                     FOREIGN KEY (etype) REFERENCES "{0}".Encrypt(etype)
                 );
 
+                -- Create Delegation table, the services a principal may obtain tickets to on behalf of users
+                CREATE TABLE "{0}".Delegation (
+                    principal_name VARCHAR(255),
+                    target_principal_name VARCHAR(255) NOT NULL,
+                    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (principal_name, target_principal_name),
+                    FOREIGN KEY (principal_name) REFERENCES "{0}".Principal(principal_name)
+                );
+
+                -- Create PrincipalGroup table, the groups issued as claims into the tickets of their members
+                CREATE TABLE "{0}".PrincipalGroup (
+                    group_name VARCHAR(255) PRIMARY KEY,
+                    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                );
+
+                -- Create GroupMember table
+                CREATE TABLE "{0}".GroupMember (
+                    principal_name VARCHAR(255),
+                    group_name VARCHAR(255),
+                    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (principal_name, group_name),
+                    FOREIGN KEY (principal_name) REFERENCES "{0}".Principal(principal_name),
+                    FOREIGN KEY (group_name) REFERENCES "{0}".PrincipalGroup(group_name)
+                );
+
+                -- Create GroupRole table, the roles granted to the members of a group
+                CREATE TABLE "{0}".GroupRole (
+                    group_name VARCHAR(255),
+                    role_name VARCHAR(255) NOT NULL,
+                    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (group_name, role_name),
+                    FOREIGN KEY (group_name) REFERENCES "{0}".PrincipalGroup(group_name)
+                );
+
             COMMIT;
         "#,
             schema
@@ -94,11 +133,15 @@ impl Schema for Krb5DbSchemaV1 {
 
                     INSERT INTO "{0}".Principal (principal_name, realm, flags, expire)
                     VALUES ('toney', 'MYREALM.COM', 0, '2025-12-31 23:59:59'),
-                           ('MYREALM.COM', 'MYREALM.COM', 0, '2025-12-31 23:59:59'),
                            ('steve', 'EXAMPLE.COM', 0, '2025-12-31 23:59:59'),
                            ('janice', 'MYREALM.COM', 0, '2025-12-31 23:59:59'),
                            ('david', 'EXAMPLE.ORG', 0, '2025-12-31 23:59:59');
 
+                    -- Services, NT-SRV-INST (2) and NT-SRV-HST (3) names
+                    INSERT INTO "{0}".Principal (principal_name, name_type, realm, flags, expire)
+                    VALUES ('krbtgt/MYREALM.COM', 2, 'MYREALM.COM', 0, '2025-12-31 23:59:59'),
+                           ('HTTP/localhost', 3, 'MYREALM.COM', 0, '2025-12-31 23:59:59');
+
                     -- Expired principals
                     INSERT INTO "{0}".Principal (principal_name, realm, flags, expire, created_at, updated_at)
                     VALUES ('donald', 'EXAMPLE.COM', 0, '2023-12-31 23:59:59', '2021-02-01 00:00:00', '2021-02-01 00:00:00'),
@@ -114,13 +157,29 @@ impl Schema for Krb5DbSchemaV1 {
 
                     INSERT INTO "{0}".Key (principal_name, etype, secret_key, knvno, created_at, updated_at)
                     VALUES ('toney', 1, 'uJV4sOr09XwCdIIjKjB7CV3zZdBmWVRt', 1, '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
-                           ('MYREALM.COM', 1, 'M4rYnBn0kOQC5vM1ddnAHXcKc0hhe16d', 1, '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('krbtgt/MYREALM.COM', 1, 'M4rYnBn0kOQC5vM1ddnAHXcKc0hhe16d', 1, '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('HTTP/localhost', 1, 'Hq3v9LrT0cWx5ZbN8mYe2KpA7sDf4JgU', 1, '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
                            ('steve', 1, 'VwTyeYkpChVj63Qg3KK4VbGyvi9ZwOaA', 1, '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
                            ('janice', 1, 'kBgzBnJ9gO81twZT39Kxu3or8ngHyVM7', 1, '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
                            ('david', 1, '22IzIa3qlwgRU1R7YOiRv9yamdN05sOK', 2, '2022-08-25 00:00:00', '2022-08-25 00:00:00'),
                            ('donald', 2, 'G0kL9hKLD7B4WogLFPInyglRtnCbTrJA', 3, '2022-08-25 00:00:00', '2022-08-25 00:00:00'),
                            ('benjamin', 3, 'u8YNSG06O8ENHJH9Hhunc81gBXHSgn0g', 5, '2022-08-25 00:00:00', '2022-08-25 00:00:00');
 
+                    INSERT INTO "{0}".PrincipalGroup (group_name, created_at, updated_at)
+                    VALUES ('admins', '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('developers', '2023-01-01 00:00:00', '2023-01-01 00:00:00');
+
+                    INSERT INTO "{0}".GroupMember (principal_name, group_name, created_at, updated_at)
+                    VALUES ('toney', 'admins', '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('toney', 'developers', '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('janice', 'developers', '2023-01-01 00:00:00', '2023-01-01 00:00:00');
+
+                    INSERT INTO "{0}".GroupRole (group_name, role_name, created_at, updated_at)
+                    VALUES ('admins', 'admin', '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('admins', 'deploy', '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('developers', 'deploy', '2023-01-01 00:00:00', '2023-01-01 00:00:00'),
+                           ('developers', 'read', '2023-01-01 00:00:00', '2023-01-01 00:00:00');
+
             COMMIT;
         "#,
             schema
@@ -68,6 +68,8 @@ async fn query_principal_should_return_principal_when_principal_exists() {
 
     assert_eq!(principal.principal_name, "toney");
 
+    assert_eq!(principal.name_type, 1);
+
     assert_eq!(principal.realm, "MYREALM.COM");
 
     assert_eq!(principal.supported_enctypes, vec![17]);
@@ -82,4 +84,90 @@ async fn query_principal_should_return_principal_when_principal_exists() {
     assert_eq!(principal.max_lifetime, 7200);
 
     assert_eq!(principal.max_renewable_life, 6000);
+
+    assert!(principal.allowed_to_delegate_to.is_empty());
+
+    assert_eq!(principal.flags, 0);
+}
+
+#[tokio::test]
+async fn query_principal_should_return_ticket_granting_service_of_realm() {
+    let config = {
+        let mut initial = PgDbSettings::load("src/server/infra/database/server/config/database");
+        initial.name = Uuid::new_v4().to_string().into();
+        initial
+    };
+
+    let schema = Krb5DbSchemaV1::boxed();
+
+    let mut db = PostgresDb::boxed(config, schema);
+
+    let result = db.migrate_then_seed().await;
+
+    assert!(
+        result.is_ok(),
+        "Failed to migrate and seed database {:?}",
+        result
+    );
+
+    let principal = db
+        .get_principal("krbtgt/MYREALM.COM", "MYREALM.COM")
+        .await
+        .unwrap();
+
+    assert!(principal.is_some(), "Principal not found {:?}", principal);
+
+    let principal = principal.unwrap();
+
+    assert_eq!(principal.name_type, 2);
+
+    assert_eq!(
+        principal.key.expose_secret(),
+        "M4rYnBn0kOQC5vM1ddnAHXcKc0hhe16d"
+    );
+
+    let principal = db.get_principal("MYREALM.COM", "MYREALM.COM").await.unwrap();
+
+    assert!(principal.is_none(), "Realm should not be a principal");
+}
+
+#[tokio::test]
+async fn query_groups_should_return_groups_of_principal_with_their_roles() {
+    let config = {
+        let mut initial = PgDbSettings::load("src/server/infra/database/server/config/database");
+        initial.name = Uuid::new_v4().to_string().into();
+        initial
+    };
+
+    let schema = Krb5DbSchemaV1::boxed();
+
+    let mut db = PostgresDb::boxed(config, schema);
+
+    let result = db.migrate_then_seed().await;
+
+    assert!(
+        result.is_ok(),
+        "Failed to migrate and seed database {:?}",
+        result
+    );
+
+    let groups = db.get_groups("toney", "MYREALM.COM").await;
+
+    assert!(groups.is_ok(), "Failed to get groups {:?}", groups);
+
+    let groups = groups.unwrap();
+
+    assert_eq!(groups.len(), 2);
+
+    assert_eq!(groups[0].group_name, "admins");
+
+    assert_eq!(groups[0].roles, vec!["admin", "deploy"]);
+
+    assert_eq!(groups[1].group_name, "developers");
+
+    assert_eq!(groups[1].roles, vec!["deploy", "read"]);
+
+    let groups = db.get_groups("steve", "EXAMPLE.COM").await.unwrap();
+
+    assert!(groups.is_empty());
 }
@@ -0,0 +1,5 @@
+#[derive(Debug)]
+pub struct GroupView {
+    pub group_name: String,
+    pub roles: Vec<String>,
+}
@@ -1,3 +1,5 @@
+mod group;
 mod principal;
 
+pub use group::*;
 pub use principal::*;
@@ -1,12 +1,23 @@
 use secrecy::Secret;
 
+/// Name of a principal as stored, its components being separated by `/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrincipalNameView {
+    pub principal_name: String,
+    pub name_type: i32,
+}
+
 #[derive(Debug)]
 pub struct PrincipalComplexView {
     pub principal_name: String,
+    pub name_type: i32,
     pub realm: String,
     pub max_renewable_life: i64,
     pub max_lifetime: i64,
     pub key: Secret<String>,
     pub p_kvno: i32,
     pub supported_enctypes: Vec<i32>,
+    pub allowed_to_delegate_to: Vec<PrincipalNameView>,
+    /// Attribute bits of the principal, e.g. `0x80` for requiring pre-authentication
+    pub flags: i32,
 }
\ No newline at end of file
@@ -4,24 +4,20 @@ pub struct AuthenticationServiceConfig {
     pub realm: Realm,
     pub sname: PrincipalName,
     pub require_preauth: bool,
+    /// Whether tickets carry a Privilege Attribute Certificate
+    pub issue_pac: bool,
 }
 
 impl AuthenticationServiceConfig {
     pub fn local(require_preauth: bool) -> Self {
-        use messages::basic_types::{KerberosString, NameTypes};
-
         let realm = Realm::try_from("MYREALM.COM").unwrap();
-
-        let sname = PrincipalName::new(
-            NameTypes::NtEnterprise,
-            vec![KerberosString::try_from("MYREALM.COM").unwrap()],
-        )
-        .unwrap();
+        let sname = PrincipalName::krbtgt(&realm);
 
         Self {
             realm,
             sname,
             require_preauth,
+            issue_pac: true,
         }
     }
 }
@@ -29,20 +25,19 @@ impl AuthenticationServiceConfig {
 pub struct TicketGrantingServiceConfig {
     pub realm: Realm,
     pub sname: PrincipalName,
+    /// Whether the tickets issued through S4U2Self carry a Privilege Attribute Certificate
+    pub issue_pac: bool,
 }
 
 impl TicketGrantingServiceConfig {
     pub fn local() -> Self {
-        use messages::basic_types::{KerberosString, NameTypes};
-
         let realm = Realm::try_from("MYREALM.COM").unwrap();
+        let sname = PrincipalName::krbtgt(&realm);
 
-        let sname = PrincipalName::new(
-            NameTypes::NtEnterprise,
-            vec![KerberosString::try_from("MYREALM.COM").unwrap()],
-        )
-        .unwrap();
-
-        Self { realm, sname }
+        Self {
+            realm,
+            sname,
+            issue_pac: true,
+        }
     }
 }
@@ -11,6 +11,7 @@ use kerberos::{
     algo::{AesGcm, Sha1},
     authentication_service,
     authentication_service::AuthenticationServiceBuilder,
+    ticket_granting_service,
     ticket_granting_service::TicketGrantingServiceBuilder,
 };
 
@@ -46,7 +47,9 @@ impl AsyncReceiver for NpglAsReqHandler {
             .realm(self.0.realm.clone())
             .sname(self.0.sname.clone())
             .require_pre_authenticate(self.0.require_preauth)
+            .issue_pac(self.0.issue_pac)
             .supported_crypto_systems(vec![Box::new(AesGcm::new())])
+            .supported_checksum(vec![Box::new(Sha1::new())])
             .principal_db(&npgl_db_view)
             .build()
             .expect("Failed to build authentication service");
@@ -107,15 +110,28 @@ impl AsyncReceiver for NpglTgsReqHandler {
             .name(self.0.sname.clone())
             .supported_crypto(vec![Box::new(AesGcm::new())])
             .supported_checksum(vec![Box::new(Sha1::new())])
+            .issue_pac(self.0.issue_pac)
             .principal_db(&npgl_db_view)
             .replay_cache(&npgl_cache_view)
             .last_req_db(&npgl_cache_view)
             .build()
             .expect("Failed to build ticket granting service");
 
-        let tgs_req = TgsReq::from_der(bytes).unwrap();
+        let tgs_req = TgsReq::from_der(bytes).map_err(|_| HostError::Ignorable)?;
 
-        let reply = tgs_service.handle_tgs_req(&tgs_req).await.unwrap();
+        let reply = tgs_service
+            .handle_tgs_req(&tgs_req)
+            .await
+            .map_err(|e| match e {
+                ticket_granting_service::ServerError::ProtocolError(reply) => {
+                    HostError::Actionable {
+                        reply: reply.to_der().unwrap(),
+                    }
+                }
+                ticket_granting_service::ServerError::Internal => {
+                    HostError::Aborted { cause: None }
+                }
+            })?;
 
         Ok(reply.to_der().unwrap())
     }
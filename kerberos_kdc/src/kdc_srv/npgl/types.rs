@@ -2,21 +2,45 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use kerberos_infra::server::{
-    cache::{CacheResultType, Cacheable},
+    cache::{CacheErr, CacheResultType, Cacheable},
     database::{Database, ExposeSecret, KrbV5Queryable},
 };
 use messages::der::{Decode, Encode, Sequence};
 use messages::{
-    basic_types::{EncryptionKey, OctetString, PrincipalName, Realm},
+    basic_types::{
+        EncryptionKey, KerberosString, KerberosTime, NameTypes, OctetString, PrincipalName, Realm,
+    },
     LastReq,
 };
 use sqlx::PgPool;
 
 use kerberos::service_traits::{
-    LastReqDatabase, LastReqEntry, PrincipalDatabase, PrincipalDatabaseRecord, ReplayCache,
-    ReplayCacheEntry,
+    LastReqDatabase, LastReqEntry, PrincipalAttributes, PrincipalDatabase,
+    PrincipalDatabaseRecord, PrincipalGroup, ReplayCache, ReplayCacheEntry, ReplayExpiry,
 };
 
+/// Principals are stored under their components separated by `/`, e.g. `krbtgt/MYREALM.COM`
+fn stored_name(principal_name: &PrincipalName) -> String {
+    principal_name
+        .name_string()
+        .iter()
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn parse_stored_name(principal_name: &str, name_type: i32) -> Option<PrincipalName> {
+    PrincipalName::new(
+        NameTypes::try_from(name_type).ok()?,
+        principal_name
+            .split('/')
+            .map(KerberosString::new)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?,
+    )
+    .ok()
+}
+
 pub struct NpglKdcDbView<'a>(&'a dyn Database<Inner = PgPool>);
 
 impl<'a> NpglKdcDbView<'a> {
@@ -34,11 +58,11 @@ impl PrincipalDatabase for NpglKdcDbView<'_> {
     ) -> Option<PrincipalDatabaseRecord> {
         let realm = realm.as_str();
 
-        let principal_name = principal_name.name_string().first()?.as_str();
+        let principal_name = stored_name(principal_name);
 
         let principal = self
             .0
-            .get_principal(principal_name, realm)
+            .get_principal(&principal_name, realm)
             .await
             .ok()?
             .map(|view| {
@@ -51,11 +75,42 @@ impl PrincipalDatabase for NpglKdcDbView<'_> {
                     key: EncryptionKey::new(1, keyvalue),
                     p_kvno: Some(view.p_kvno as u32),
                     supported_encryption_types: view.supported_enctypes,
+                    allowed_to_delegate_to: view
+                        .allowed_to_delegate_to
+                        .iter()
+                        .filter_map(|target| {
+                            parse_stored_name(&target.principal_name, target.name_type)
+                        })
+                        .collect(),
+                    attributes: PrincipalAttributes::from_bits(view.flags as u32),
                 }
             });
 
         principal
     }
+
+    async fn get_groups(
+        &self,
+        principal_name: &PrincipalName,
+        realm: &Realm,
+    ) -> Vec<PrincipalGroup> {
+        self.0
+            .get_groups(&stored_name(principal_name), realm.as_str())
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|view| {
+                Some(PrincipalGroup {
+                    name: KerberosString::new(&view.group_name).ok()?,
+                    roles: view
+                        .roles
+                        .iter()
+                        .filter_map(|role| KerberosString::new(role).ok())
+                        .collect(),
+                })
+            })
+            .collect()
+    }
 }
 
 pub struct NpglKdcCacheView<'a>(&'a mut dyn Cacheable<Vec<u8>, CacheResultType>);
@@ -85,23 +140,38 @@ impl<'a> NpglKdcCacheView<'a> {
 impl ReplayCache for NpglKdcCacheView<'_> {
     type ReplayCacheError = String;
 
-    async fn store(&self, entry: &ReplayCacheEntry) -> Result<(), Self::ReplayCacheError> {
-        let key = entry.to_der().expect("Failed to encode");
+    async fn store(
+        &self,
+        entry: &ReplayCacheEntry,
+        lifetime: Duration,
+    ) -> Result<(), Self::ReplayCacheError> {
+        let key = entry.to_der().map_err(|e| e.to_string())?;
+
+        let expiry = ReplayExpiry::after(lifetime)
+            .0
+            .to_der()
+            .map_err(|e| e.to_string())?;
 
         self.0
-            .put(key.clone(), CacheResultType::None)
+            .put(key, CacheResultType::DerBytes(expiry))
             .await
-            .unwrap();
+            .map_err(|e| format!("{:?}", e))?;
 
         Ok(())
     }
 
     async fn contain(&self, entry: &ReplayCacheEntry) -> Result<bool, Self::ReplayCacheError> {
-        let key = entry.to_der().expect("Failed to encode");
-
-        let result = self.0.get(&key).await;
+        let key = entry.to_der().map_err(|e| e.to_string())?;
 
-        Ok(result.is_ok())
+        match self.0.get(&key).await {
+            Ok(CacheResultType::DerBytes(bytes)) => {
+                let expiry = KerberosTime::from_der(&bytes).map_err(|e| e.to_string())?;
+                Ok(ReplayExpiry(expiry).is_live())
+            }
+            Ok(CacheResultType::None) => Ok(true),
+            Err(CacheErr::MissingKey | CacheErr::ValueExpired) => Ok(false),
+            Err(e) => Err(format!("{:?}", e)),
+        }
     }
 }
 
@@ -3,24 +3,24 @@ extern crate messages;
 
 use kerberos_kdc::kdc_srv::{AuthenticationServiceConfig, Listen, TicketGrantingServiceConfig};
 use kerberos_kdc::kdc_srv::npgl::NpglKdcSrv;
-use messages::basic_types::{KerberosString, NameTypes, PrincipalName, Realm};
+use messages::basic_types::{PrincipalName, Realm};
 
 #[tokio::main]
 async fn main() {
     let realm = Realm::try_from("MYREALM.COM").unwrap();
-
-    let sname = PrincipalName::new(
-        NameTypes::NtEnterprise,
-        vec![KerberosString::try_from("MYREALM.COM").unwrap()],
-    )
-    .unwrap();
+    let sname = PrincipalName::krbtgt(&realm);
     let as_config = AuthenticationServiceConfig {
         realm: realm.clone(),
         sname: sname.clone(),
         require_preauth: false,
+        issue_pac: true,
     };
 
-    let tgs_config = TicketGrantingServiceConfig { realm, sname };
+    let tgs_config = TicketGrantingServiceConfig {
+        realm,
+        sname,
+        issue_pac: true,
+    };
 
     let mut kdc =
         NpglKdcSrv::load_from("config", as_config, tgs_config).expect("Failed to load KDC");
@@ -6,7 +6,7 @@ pub enum NameTypes {
     NtUnknown = 0,
     NtPrincipal = 1,
     NtSrvInst = 2,
-    NtSrcHst = 3,
+    NtSrvHst = 3,
     NtSrvXhst = 4,
     NtUid = 5,
     NtX500Principal = 6,
@@ -48,6 +48,8 @@ pub enum PaDataTypes {
     PaPwSalt = 3,
     PaEtypeInfo = 11,
     PaEtypeInfo2 = 19,
+    PaForUser = 129,
+    PaS4uX509User = 130,
 }
 }
 
@@ -68,6 +70,7 @@ pub mod flags {
         OPT_HARDWARE_AUTH = 11,       // Bit 11
         UNUSED12 = 12,                // Bit 12
         UNUSED13 = 13,                // Bit 13
+        CNAME_IN_ADDL_TKT = 14,       // Bit 14, MS-SFU constrained delegation
         UNUSED15 = 15,                // Bit 15
         DISABLE_TRANSITED_CHECK = 26, // Bit 26
         RENEWABLE_OK = 27,            // Bit 27
@@ -114,10 +117,8 @@ pub(crate) mod application_tags {
     pub const TGS_REP: u8 = 13;
     pub const AP_REQ: u8 = 14;
     pub const AP_REP: u8 = 15;
-    #[allow(dead_code)]
-    pub const RESERVED16: u8 = 16; // TGT-REQ (for user-to-user)
-    #[allow(dead_code)]
-    pub const RESERVED17: u8 = 17; // TGT-REP (for user-to-user)
+    pub const TGT_REQ: u8 = 16; // for user-to-user
+    pub const TGT_REP: u8 = 17; // for user-to-user
 
     // 18-19 unused
     pub const KRB_SAFE: u8 = 20;
@@ -21,6 +21,13 @@ impl KerberosFlags {
             .get(idx)
             .map_or(false, |byte| byte & (1 << shift) != 0)
     }
+
+    /// Builder starting from the flags currently set
+    pub fn to_builder(&self) -> KerberosFlagsBuilder {
+        let mut inner = self.inner.raw_bytes().to_vec();
+        inner.resize(inner.len().max(4), 0);
+        KerberosFlagsBuilder { inner }
+    }
 }
 
 impl FixedTag for KerberosFlags {
@@ -66,6 +73,13 @@ impl KerberosFlagsBuilder {
         self
     }
 
+    pub fn unset(&mut self, bit_pos: usize) -> &mut Self {
+        if let Some(byte) = self.inner.get_mut(bit_pos / 8) {
+            *byte &= !(1 << (7 - bit_pos % 8));
+        }
+        self
+    }
+
     pub fn build(&mut self) -> Result<KerberosFlags, &'static str> {
         let inner = BitString::new(0, self.inner.clone()).map_err(|_| "Invalid bit string")?;
         Ok(KerberosFlags { inner })
@@ -27,6 +27,9 @@ pub use kerberos_string::KerberosString;
 // RFC4120 5.2.3
 pub use kerberos_time::KerberosTime;
 
+// RFC1964 2.1.1
+pub use principal_string::{PrincipalNameParseError, QualifiedPrincipalName};
+
 // RFC4120 5.2.2
 pub type Realm = KerberosString;
 
@@ -41,6 +44,8 @@ pub struct PrincipalName {
 }
 
 impl PrincipalName {
+    const KRBTGT: &'static str = "krbtgt";
+
     pub fn new<K: Into<SequenceOf<KerberosString>>>(
         nt_code: NameTypes,
         components: K,
@@ -72,6 +77,27 @@ impl PrincipalName {
     pub fn name_string(&self) -> &SequenceOf<KerberosString> {
         &self.name_string
     }
+
+    // RFC4120 7.3
+    /// Name of the ticket-granting service of `realm`, `krbtgt/<realm>`
+    pub fn krbtgt(realm: &Realm) -> Self {
+        Self {
+            name_type: NameTypes::NtSrvInst as i32,
+            name_string: vec![
+                KerberosString::new(Self::KRBTGT).expect("krbtgt is a valid IA5String"),
+                realm.clone(),
+            ],
+        }
+    }
+
+    /// The realm whose ticket-granting service this name designates,
+    /// if the name has the form `krbtgt/<realm>`
+    pub fn krbtgt_realm(&self) -> Option<&Realm> {
+        match self.name_string.as_slice() {
+            [service, realm] if service.as_str() == Self::KRBTGT => Some(realm),
+            _ => None,
+        }
+    }
 }
 
 // RFC4120 5.2.5
@@ -366,6 +392,8 @@ pub enum PaDataRegisteredType {
     PwSalt(OctetString),    // salt (not ASN.1 encoded)
     ETypeInfo(ETypeInfo),   // DER encoding of ETYPE-INFO
     ETypeInfo2(ETypeInfo2), // DER encoding of ETYPE-INFO2
+    ForUser(PaForUser),     // DER encoding of PA-FOR-USER
+    S4uX509User(PaS4uX509User), // DER encoding of PA-S4U-X509-USER
 }
 
 impl PaDataRegisteredType {
@@ -422,6 +450,18 @@ impl PaDataRegisteredType {
                     .map_err(|e| to_meaningful_error(padata_type, "ETYPE-INFO2", e))?;
                 PaDataRegisteredType::ETypeInfo2(decoded)
             }
+            PaDataTypes::PaForUser => {
+                let decoded = octet_str_ref
+                    .decode_into::<PaForUser>()
+                    .map_err(|e| to_meaningful_error(padata_type, "PA-FOR-USER", e))?;
+                PaDataRegisteredType::ForUser(decoded)
+            }
+            PaDataTypes::PaS4uX509User => {
+                let decoded = octet_str_ref
+                    .decode_into::<PaS4uX509User>()
+                    .map_err(|e| to_meaningful_error(padata_type, "PA-S4U-X509-USER", e))?;
+                PaDataRegisteredType::S4uX509User(decoded)
+            }
         };
 
         Ok(value)
@@ -553,6 +593,143 @@ pub type ETypeInfo2 = SequenceOf<ETypeInfo2Entry>;
 
 impl CipherText for ETypeInfo2 {}
 
+// MS-SFU 2.2.1
+// Sent by a service in a TGS-REQ to obtain a ticket to itself on behalf of the user (S4U2Self)
+#[derive(Sequence, PartialEq, Eq, Clone, Debug)]
+pub struct PaForUser {
+    #[asn1(context_specific = "0")]
+    user_name: PrincipalName,
+    #[asn1(context_specific = "1")]
+    user_realm: Realm,
+    #[asn1(context_specific = "2")]
+    cksum: Checksum,
+    #[asn1(context_specific = "3")]
+    auth_package: KerberosString,
+}
+
+impl PaForUser {
+    pub const AUTH_PACKAGE: &'static str = "Kerberos";
+
+    pub fn new(user_name: PrincipalName, user_realm: Realm, cksum: Checksum) -> Self {
+        Self {
+            user_name,
+            user_realm,
+            cksum,
+            auth_package: KerberosString::new(Self::AUTH_PACKAGE).expect("Valid IA5 string"),
+        }
+    }
+
+    /// S4UByteArray the checksum is computed over: the name type as a little-endian
+    /// 32-bit integer, followed by the name components, the realm and the auth package
+    pub fn checksum_data(
+        user_name: &PrincipalName,
+        user_realm: &Realm,
+        auth_package: &KerberosString,
+    ) -> Vec<u8> {
+        let mut data = user_name.name_type().to_le_bytes().to_vec();
+        for component in user_name.name_string() {
+            data.extend_from_slice(component.as_str().as_bytes());
+        }
+        data.extend_from_slice(user_realm.as_str().as_bytes());
+        data.extend_from_slice(auth_package.as_str().as_bytes());
+        data
+    }
+
+    pub fn user_name(&self) -> &PrincipalName {
+        &self.user_name
+    }
+
+    pub fn user_realm(&self) -> &Realm {
+        &self.user_realm
+    }
+
+    pub fn cksum(&self) -> &Checksum {
+        &self.cksum
+    }
+
+    pub fn auth_package(&self) -> &KerberosString {
+        &self.auth_package
+    }
+}
+
+// MS-SFU 2.2.2
+#[derive(Sequence, PartialEq, Eq, Clone, Debug)]
+pub struct S4uUserId {
+    #[asn1(context_specific = "0")]
+    nonce: UInt32, // the nonce of the request body
+    #[asn1(context_specific = "1", optional = "true")]
+    cname: Option<PrincipalName>,
+    #[asn1(context_specific = "2")]
+    crealm: Realm,
+    #[asn1(context_specific = "3", optional = "true")]
+    subject_certificate: Option<OctetString>,
+    #[asn1(context_specific = "4", optional = "true")]
+    options: Option<KerberosFlags>,
+}
+
+impl S4uUserId {
+    pub fn new(
+        nonce: UInt32,
+        cname: Option<PrincipalName>,
+        crealm: Realm,
+        subject_certificate: Option<OctetString>,
+        options: Option<KerberosFlags>,
+    ) -> Self {
+        Self {
+            nonce,
+            cname,
+            crealm,
+            subject_certificate,
+            options,
+        }
+    }
+
+    pub fn nonce(&self) -> UInt32 {
+        self.nonce
+    }
+
+    pub fn cname(&self) -> Option<&PrincipalName> {
+        self.cname.as_ref()
+    }
+
+    pub fn crealm(&self) -> &Realm {
+        &self.crealm
+    }
+
+    pub fn subject_certificate(&self) -> Option<&OctetString> {
+        self.subject_certificate.as_ref()
+    }
+
+    pub fn options(&self) -> Option<&KerberosFlags> {
+        self.options.as_ref()
+    }
+}
+
+// MS-SFU 2.2.2
+// S4U2Self request identifying the user by name or by X.509 certificate,
+// the checksum is computed over the DER encoding of the user id
+#[derive(Sequence, PartialEq, Eq, Clone, Debug)]
+pub struct PaS4uX509User {
+    #[asn1(context_specific = "0")]
+    user_id: S4uUserId,
+    #[asn1(context_specific = "1")]
+    checksum: Checksum,
+}
+
+impl PaS4uX509User {
+    pub fn new(user_id: S4uUserId, checksum: Checksum) -> Self {
+        Self { user_id, checksum }
+    }
+
+    pub fn user_id(&self) -> &S4uUserId {
+        &self.user_id
+    }
+
+    pub fn checksum(&self) -> &Checksum {
+        &self.checksum
+    }
+}
+
 // RFC4120 5.2.9
 #[derive(Sequence, PartialEq, Eq, Clone, Debug)]
 pub struct EncryptedData {
@@ -645,6 +822,7 @@ impl Checksum {
 mod kerberos_flags;
 mod kerberos_string;
 mod kerberos_time;
+mod principal_string;
 #[cfg(test)]
 mod test;
 pub use kerberos_flags::KerberosFlags;
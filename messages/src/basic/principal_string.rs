@@ -0,0 +1,264 @@
+// Text form of principal names, `component/component@REALM`, where `/`, `@`, `\` and
+// control characters are escaped with a backslash, RFC1964 2.1.1
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use super::{KerberosString, NameTypes, PrincipalName, Realm};
+
+const COMPONENT_SEPARATOR: char = '/';
+const REALM_SEPARATOR: char = '@';
+const ESCAPE: char = '\\';
+
+/// Why the text form of a principal name was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrincipalNameParseError {
+    /// Nothing to name, e.g. an empty string or `@REALM`
+    EmptyName,
+    /// An `@` not followed by a realm
+    EmptyRealm,
+    /// A principal name of `PrincipalName` was expected, without realm
+    UnexpectedRealm,
+    /// A principal name with realm was expected
+    MissingRealm,
+    /// A second unescaped `@`
+    UnescapedRealmSeparator,
+    /// A backslash at the end of the text
+    TrailingEscape,
+    /// A backslash followed by a character which is not escapable
+    InvalidEscape(char),
+    /// A character which is not in the IA5 set a `KerberosString` is made of
+    InvalidCharacter(char),
+}
+
+impl Display for PrincipalNameParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyName => f.write_str("principal name is empty"),
+            Self::EmptyRealm => f.write_str("realm is empty"),
+            Self::UnexpectedRealm => f.write_str("principal name should not have a realm"),
+            Self::MissingRealm => f.write_str("principal name should have a realm"),
+            Self::UnescapedRealmSeparator => f.write_str("realm contains an unescaped '@'"),
+            Self::TrailingEscape => f.write_str("principal name ends with an escape"),
+            Self::InvalidEscape(c) => write!(f, "'\\{}' is not a valid escape", c.escape_debug()),
+            Self::InvalidCharacter(c) => {
+                write!(f, "'{}' is not an IA5 character", c.escape_debug())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrincipalNameParseError {}
+
+/// Principal name split into its unescaped components and realm
+struct ParsedPrincipal {
+    components: Vec<String>,
+    realm: Option<String>,
+}
+
+fn unescape(c: char) -> Result<char, PrincipalNameParseError> {
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'b' => Ok('\u{8}'),
+        '0' => Ok('\0'),
+        COMPONENT_SEPARATOR | REALM_SEPARATOR | ESCAPE => Ok(c),
+        _ => Err(PrincipalNameParseError::InvalidEscape(c)),
+    }
+}
+
+/// Byte offset of the last `@` which is not escaped
+fn last_realm_separator(input: &str) -> Option<usize> {
+    let mut escaped = false;
+    let mut last = None;
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            ESCAPE => escaped = true,
+            REALM_SEPARATOR => last = Some(i),
+            _ => {}
+        }
+    }
+    last
+}
+
+/// Split `input` into its components and realm. An enterprise name is a single component in
+/// which `/` and every `@` but the one before the realm stand for themselves
+fn parse(input: &str, enterprise: bool) -> Result<ParsedPrincipal, PrincipalNameParseError> {
+    let realm_separator = last_realm_separator(input).filter(|_| enterprise);
+    let mut components = vec![String::new()];
+    let mut realm: Option<String> = None;
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if !c.is_ascii() {
+            return Err(PrincipalNameParseError::InvalidCharacter(c));
+        }
+        let in_realm = realm.is_some();
+        let current = match realm.as_mut() {
+            Some(realm) => realm,
+            None => components.last_mut().expect("There is always a component"),
+        };
+        match c {
+            ESCAPE => {
+                let (_, escaped) = chars
+                    .next()
+                    .ok_or(PrincipalNameParseError::TrailingEscape)?;
+                current.push(unescape(escaped)?);
+            }
+            REALM_SEPARATOR if in_realm => {
+                return Err(PrincipalNameParseError::UnescapedRealmSeparator)
+            }
+            REALM_SEPARATOR if enterprise && realm_separator != Some(i) => current.push(c),
+            REALM_SEPARATOR => realm = Some(String::new()),
+            // Only the name is made of components, a realm may contain a `/`
+            COMPONENT_SEPARATOR if !in_realm && !enterprise => components.push(String::new()),
+            _ => current.push(c),
+        }
+    }
+    if components.len() == 1 && components[0].is_empty() {
+        return Err(PrincipalNameParseError::EmptyName);
+    }
+    if realm.as_ref().is_some_and(|realm| realm.is_empty()) {
+        return Err(PrincipalNameParseError::EmptyRealm);
+    }
+    Ok(ParsedPrincipal { components, realm })
+}
+
+fn kerberos_string(value: &str) -> Result<KerberosString, PrincipalNameParseError> {
+    KerberosString::new(value).map_err(|_| {
+        PrincipalNameParseError::InvalidCharacter(
+            value.chars().find(|c| !c.is_ascii()).unwrap_or_default(),
+        )
+    })
+}
+
+/// Name type of a parsed name as `krb5_parse_name` gives it without flags: `krbtgt/<realm>`
+/// is the ticket-granting service instance and any other name is a principal, e.g.
+/// `alice/admin` or `alice\@corp.example`. Enterprise names are parsed by
+/// `QualifiedPrincipalName::parse_enterprise`
+fn infer_name_type(components: &[String]) -> NameTypes {
+    match components {
+        [service, _] if service == PrincipalName::KRBTGT => NameTypes::NtSrvInst,
+        _ => NameTypes::NtPrincipal,
+    }
+}
+
+fn to_principal_name(components: &[String]) -> Result<PrincipalName, PrincipalNameParseError> {
+    let name_string = components
+        .iter()
+        .map(|component| kerberos_string(component))
+        .collect::<Result<Vec<_>, _>>()?;
+    PrincipalName::new(infer_name_type(components), name_string)
+        .map_err(|_| PrincipalNameParseError::EmptyName)
+}
+
+fn write_escaped(f: &mut Formatter<'_>, value: &str, escape_separator: bool) -> std::fmt::Result {
+    for c in value.chars() {
+        match c {
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\0' => f.write_str("\\0")?,
+            ESCAPE | REALM_SEPARATOR => write!(f, "{ESCAPE}{c}")?,
+            COMPONENT_SEPARATOR if escape_separator => write!(f, "{ESCAPE}{c}")?,
+            _ => write!(f, "{c}")?,
+        }
+    }
+    Ok(())
+}
+
+impl PrincipalName {
+    /// Enterprise name of a user, e.g. `alice@corp.example`, to be resolved by the KDC
+    /// of the realm it is sent to, https://www.rfc-editor.org/rfc/rfc6806#section-5
+    pub fn enterprise(name: &str) -> Result<Self, PrincipalNameParseError> {
+        if name.is_empty() {
+            return Err(PrincipalNameParseError::EmptyName);
+        }
+        PrincipalName::new(NameTypes::NtEnterprise, vec![kerberos_string(name)?])
+            .map_err(|_| PrincipalNameParseError::EmptyName)
+    }
+}
+
+/// Components separated by `/`, the realm separator `@` being escaped
+impl Display for PrincipalName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, component) in self.name_string().iter().enumerate() {
+            if i > 0 {
+                write!(f, "{COMPONENT_SEPARATOR}")?;
+            }
+            write_escaped(f, component.as_str(), true)?;
+        }
+        Ok(())
+    }
+}
+
+/// Name without realm, e.g. `HTTP/web01.corp.example`. The name type is inferred from the
+/// components, see `PrincipalName::new` for another one
+impl FromStr for PrincipalName {
+    type Err = PrincipalNameParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = parse(s, false)?;
+        if parsed.realm.is_some() {
+            return Err(PrincipalNameParseError::UnexpectedRealm);
+        }
+        to_principal_name(&parsed.components)
+    }
+}
+
+/// Principal name qualified by the realm it belongs to, e.g.
+/// `HTTP/web01.corp.example@MYREALM.COM`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct QualifiedPrincipalName {
+    name: PrincipalName,
+    realm: Realm,
+}
+
+impl QualifiedPrincipalName {
+    pub fn new(name: PrincipalName, realm: Realm) -> Self {
+        Self { name, realm }
+    }
+
+    pub fn name(&self) -> &PrincipalName {
+        &self.name
+    }
+
+    pub fn realm(&self) -> &Realm {
+        &self.realm
+    }
+
+    pub fn into_parts(self) -> (PrincipalName, Realm) {
+        (self.name, self.realm)
+    }
+
+    /// Name whose part before its last unescaped `@` is an enterprise name, e.g.
+    /// `alice@corp.example@MYREALM.COM`
+    pub fn parse_enterprise(s: &str) -> Result<Self, PrincipalNameParseError> {
+        let parsed = parse(s, true)?;
+        let realm = parsed.realm.ok_or(PrincipalNameParseError::MissingRealm)?;
+        Ok(Self {
+            name: PrincipalName::enterprise(&parsed.components[0])?,
+            realm: kerberos_string(&realm)?,
+        })
+    }
+}
+
+impl Display for QualifiedPrincipalName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{REALM_SEPARATOR}", self.name)?;
+        write_escaped(f, self.realm.as_str(), false)
+    }
+}
+
+impl FromStr for QualifiedPrincipalName {
+    type Err = PrincipalNameParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = parse(s, false)?;
+        let realm = parsed.realm.ok_or(PrincipalNameParseError::MissingRealm)?;
+        Ok(Self {
+            name: to_principal_name(&parsed.components)?,
+            realm: kerberos_string(&realm)?,
+        })
+    }
+}
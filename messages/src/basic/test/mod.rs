@@ -12,12 +12,13 @@ use utils::{
 
 use crate::basic::{
     ADRegisteredEntry, AdAndOr, AdIfRelevant, AdMandatoryForKdc, ETypeInfo, ETypeInfo2,
-    PaDataRegisteredType, PaEncTimestamp, PaEncTsEnc,
+    PaDataRegisteredType, PaEncTimestamp, PaEncTsEnc, PaForUser, PaS4uX509User, S4uUserId,
 };
 
 use super::{
     flags, ADEntry, AdKdcIssued, Checksum, EncryptedData, EncryptionKey, HostAddress,
-    KerberosFlags, PaData, PrincipalName,
+    KerberosFlags, KerberosString, NameTypes, OctetString, PaData, PaDataTypes, PrincipalName,
+    PrincipalNameParseError, QualifiedPrincipalName,
 };
 
 mod utils;
@@ -104,6 +105,256 @@ fn encode_decode_for_principal_name_works_fine() {
     }
 }
 
+#[test]
+fn krbtgt_principal_name_designates_its_realm() {
+    let realm = KerberosString::new("EXAMPLE.COM").unwrap();
+    let krbtgt = PrincipalName::krbtgt(&realm);
+    assert!(krbtgt.has_name_type_of(NameTypes::NtSrvInst));
+    assert_eq!(krbtgt.krbtgt_realm(), Some(&realm));
+
+    let host = PrincipalName::new(
+        NameTypes::NtSrvHst,
+        vec![KerberosString::new("host").unwrap(), realm.clone()],
+    )
+    .unwrap();
+    assert_eq!(host.krbtgt_realm(), None);
+}
+
+fn name_of(name_type: NameTypes, components: &[&str]) -> PrincipalName {
+    PrincipalName::new(
+        name_type,
+        components
+            .iter()
+            .map(|component| KerberosString::new(component).unwrap())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn principal_name_should_be_parsed_from_its_text_form() {
+    let testcases: Vec<(&str, NameTypes, Vec<&str>)> = vec![
+        ("alice", NameTypes::NtPrincipal, vec!["alice"]),
+        ("with space", NameTypes::NtPrincipal, vec!["with space"]),
+        ("krbtgt", NameTypes::NtPrincipal, vec!["krbtgt"]),
+        (
+            "krbtgt/MYREALM.COM",
+            NameTypes::NtSrvInst,
+            vec!["krbtgt", "MYREALM.COM"],
+        ),
+        ("krbtgt/A/B", NameTypes::NtPrincipal, vec!["krbtgt", "A", "B"]),
+        ("alice/admin", NameTypes::NtPrincipal, vec!["alice", "admin"]),
+        (
+            "HTTP/web01.corp.example",
+            NameTypes::NtPrincipal,
+            vec!["HTTP", "web01.corp.example"],
+        ),
+        ("a/b/c", NameTypes::NtPrincipal, vec!["a", "b", "c"]),
+        ("host/", NameTypes::NtPrincipal, vec!["host", ""]),
+        ("/", NameTypes::NtPrincipal, vec!["", ""]),
+        (r"a\/b", NameTypes::NtPrincipal, vec!["a/b"]),
+        (r"a\\/b", NameTypes::NtPrincipal, vec![r"a\", "b"]),
+        (r"a\\\/b", NameTypes::NtPrincipal, vec![r"a\/b"]),
+        (r"a\\", NameTypes::NtPrincipal, vec![r"a\"]),
+        (
+            r"alice\@corp.example",
+            NameTypes::NtPrincipal,
+            vec!["alice@corp.example"],
+        ),
+        (r"tab\tthere", NameTypes::NtPrincipal, vec!["tab\tthere"]),
+        (r"\t\n\b\0", NameTypes::NtPrincipal, vec!["\t\n\u{8}\0"]),
+    ];
+
+    for (text, name_type, components) in testcases {
+        let parsed = text.parse::<PrincipalName>();
+        assert_eq!(
+            parsed,
+            Ok(name_of(name_type, &components)),
+            "Failed to parse {:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn invalid_principal_names_should_be_rejected() {
+    let testcases = vec![
+        ("", PrincipalNameParseError::EmptyName),
+        (
+            "alice@MYREALM.COM",
+            PrincipalNameParseError::UnexpectedRealm,
+        ),
+        (r"alice\", PrincipalNameParseError::TrailingEscape),
+        (r"al\ice", PrincipalNameParseError::InvalidEscape('i')),
+        (r"al\.ice", PrincipalNameParseError::InvalidEscape('.')),
+        ("alicé", PrincipalNameParseError::InvalidCharacter('é')),
+        ("HTTP/wéb01", PrincipalNameParseError::InvalidCharacter('é')),
+    ];
+
+    for (text, expected) in testcases {
+        assert_eq!(
+            text.parse::<PrincipalName>(),
+            Err(expected),
+            "Accepted {:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn qualified_principal_name_should_be_parsed_from_its_text_form() {
+    let realm = |name: &str| KerberosString::new(name).unwrap();
+    let testcases = vec![
+        (
+            "alice@MYREALM.COM",
+            name_of(NameTypes::NtPrincipal, &["alice"]),
+            realm("MYREALM.COM"),
+        ),
+        (
+            "HTTP/web01.corp.example@MYREALM.COM",
+            name_of(NameTypes::NtPrincipal, &["HTTP", "web01.corp.example"]),
+            realm("MYREALM.COM"),
+        ),
+        (
+            "krbtgt/OTHER.COM@MYREALM.COM",
+            name_of(NameTypes::NtSrvInst, &["krbtgt", "OTHER.COM"]),
+            realm("MYREALM.COM"),
+        ),
+        (
+            r"alice\@corp.example@MYREALM.COM",
+            name_of(NameTypes::NtPrincipal, &["alice@corp.example"]),
+            realm("MYREALM.COM"),
+        ),
+        (
+            "alice@MY/REALM",
+            name_of(NameTypes::NtPrincipal, &["alice"]),
+            realm("MY/REALM"),
+        ),
+        (
+            r"alice@MY\@REALM",
+            name_of(NameTypes::NtPrincipal, &["alice"]),
+            realm("MY@REALM"),
+        ),
+    ];
+
+    for (text, name, realm) in testcases {
+        let parsed = text.parse::<QualifiedPrincipalName>();
+        assert_eq!(
+            parsed,
+            Ok(QualifiedPrincipalName::new(name, realm)),
+            "Failed to parse {:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn invalid_qualified_principal_names_should_be_rejected() {
+    let testcases = vec![
+        ("alice", PrincipalNameParseError::MissingRealm),
+        (r"alice\@MYREALM.COM", PrincipalNameParseError::MissingRealm),
+        ("@MYREALM.COM", PrincipalNameParseError::EmptyName),
+        ("alice@", PrincipalNameParseError::EmptyRealm),
+        (
+            "alice@corp.example@MYREALM.COM",
+            PrincipalNameParseError::UnescapedRealmSeparator,
+        ),
+        (
+            "alice@MYRÉALM",
+            PrincipalNameParseError::InvalidCharacter('É'),
+        ),
+        (r"alice@MYREALM\", PrincipalNameParseError::TrailingEscape),
+    ];
+
+    for (text, expected) in testcases {
+        assert_eq!(
+            text.parse::<QualifiedPrincipalName>(),
+            Err(expected),
+            "Accepted {:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn enterprise_principal_name_should_keep_its_separators() {
+    let testcases = vec![
+        ("alice@corp.example@MYREALM.COM", "alice@corp.example"),
+        (r"alice\@corp.example@MYREALM.COM", "alice@corp.example"),
+        ("a/b@c@d@MYREALM.COM", "a/b@c@d"),
+        ("alice@MYREALM.COM", "alice"),
+    ];
+
+    for (text, name) in testcases {
+        let parsed = QualifiedPrincipalName::parse_enterprise(text).unwrap();
+        assert_eq!(parsed.name(), &PrincipalName::enterprise(name).unwrap());
+        assert!(parsed.name().has_name_type_of(NameTypes::NtEnterprise));
+        assert_eq!(parsed.realm().as_str(), "MYREALM.COM");
+    }
+
+    assert_eq!(
+        QualifiedPrincipalName::parse_enterprise("alice"),
+        Err(PrincipalNameParseError::MissingRealm)
+    );
+    assert_eq!(
+        PrincipalName::enterprise(""),
+        Err(PrincipalNameParseError::EmptyName)
+    );
+}
+
+#[test]
+fn principal_name_should_be_displayed_with_escapes() {
+    let testcases = vec![
+        (name_of(NameTypes::NtPrincipal, &["alice"]), "alice"),
+        (
+            name_of(NameTypes::NtSrvHst, &["HTTP", "web01.corp.example"]),
+            "HTTP/web01.corp.example",
+        ),
+        (name_of(NameTypes::NtPrincipal, &["a/b"]), r"a\/b"),
+        (name_of(NameTypes::NtPrincipal, &[r"a\b"]), r"a\\b"),
+        (
+            name_of(NameTypes::NtEnterprise, &["alice@corp.example"]),
+            r"alice\@corp.example",
+        ),
+        (
+            name_of(NameTypes::NtPrincipal, &["\t\n\u{8}\0"]),
+            r"\t\n\b\0",
+        ),
+        (name_of(NameTypes::NtSrvHst, &["host", ""]), "host/"),
+    ];
+
+    for (name, expected) in testcases {
+        assert_eq!(name.to_string(), expected);
+    }
+
+    let qualified = QualifiedPrincipalName::new(
+        name_of(NameTypes::NtEnterprise, &["alice@corp.example"]),
+        KerberosString::new("MY@REALM/X").unwrap(),
+    );
+    assert_eq!(qualified.to_string(), r"alice\@corp.example@MY\@REALM/X");
+}
+
+#[test]
+fn principal_name_text_form_should_round_trip() {
+    let testcases = vec![
+        "alice@MYREALM.COM",
+        "krbtgt/MYREALM.COM@MYREALM.COM",
+        "HTTP/web01.corp.example@MYREALM.COM",
+        r"alice\@corp.example@MYREALM.COM",
+        r"a\/b/c\\d@MY\@REALM",
+        r"\t\n\b\0/x@MYREALM.COM",
+        "a/b/c/d@MYREALM.COM",
+    ];
+
+    for text in testcases {
+        let parsed = text.parse::<QualifiedPrincipalName>().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.to_string().parse(), Ok(parsed.clone()));
+        let (name, _) = parsed.into_parts();
+        assert_eq!(name.to_string().parse(), Ok(name));
+    }
+}
+
 ////////////////////////// HostAddress //////////////////////////
 #[test]
 fn getter_of_host_address_works_fine() {
@@ -422,6 +673,54 @@ fn encode_decode_for_etype_info2_works_fine() {
     }
 }
 
+#[test]
+fn pa_for_user_checksum_covers_the_s4u_byte_array() {
+    let user_name = PrincipalName::new(
+        NameTypes::NtEnterprise,
+        vec![KerberosString::new("alice").unwrap()],
+    )
+    .unwrap();
+    let user_realm = KerberosString::new("EXAMPLE.COM").unwrap();
+    let data = PaForUser::checksum_data(
+        &user_name,
+        &user_realm,
+        &KerberosString::new(PaForUser::AUTH_PACKAGE).unwrap(),
+    );
+    assert_eq!(data, b"\x0a\x00\x00\x00aliceEXAMPLE.COMKerberos".to_vec());
+}
+
+#[test]
+fn upgrade_s4u_pa_data_works_fine() {
+    let user_name = PrincipalName::new(
+        NameTypes::NtPrincipal,
+        vec![KerberosString::new("alice").unwrap()],
+    )
+    .unwrap();
+    let user_realm = KerberosString::new("EXAMPLE.COM").unwrap();
+    let checksum = Checksum::new(1, OctetString::new(b"checksum".to_vec()).unwrap());
+
+    let pa_for_user = PaForUser::new(user_name.clone(), user_realm.clone(), checksum.clone());
+    let pa_data = PaData::new(
+        PaDataTypes::PaForUser as i32,
+        OctetString::new(pa_for_user.to_der().unwrap()).unwrap(),
+    );
+    match PaDataRegisteredType::upgrade(&pa_data) {
+        Ok(PaDataRegisteredType::ForUser(decoded)) => assert_eq!(decoded, pa_for_user),
+        _ => panic!("PA-FOR-USER should be upgraded"),
+    }
+
+    let user_id = S4uUserId::new(42, Some(user_name), user_realm, None, None);
+    let pa_s4u_x509_user = PaS4uX509User::new(user_id, checksum);
+    let pa_data = PaData::new(
+        PaDataTypes::PaS4uX509User as i32,
+        OctetString::new(pa_s4u_x509_user.to_der().unwrap()).unwrap(),
+    );
+    match PaDataRegisteredType::upgrade(&pa_data) {
+        Ok(PaDataRegisteredType::S4uX509User(decoded)) => assert_eq!(decoded, pa_s4u_x509_user),
+        _ => panic!("PA-S4U-X509-USER should be upgraded"),
+    }
+}
+
 #[test]
 #[ignore = "This test is ignored because the interface of TgsReq is not yet implemented in the library"]
 fn upgrade_registered_pa_data_should_return_ok_when_given_predefined_code() {
@@ -505,6 +804,22 @@ fn kerberos_flags_should_correctly_identify_its_options() {
     }
 }
 
+#[test]
+fn unset_kerberos_flags_works_fine() {
+    let flags = KerberosFlags::builder()
+        .set(flags::TicketFlag::INVALID as usize)
+        .set(flags::TicketFlag::RENEWABLE as usize)
+        .build()
+        .unwrap();
+    let flags = flags
+        .to_builder()
+        .unset(flags::TicketFlag::INVALID as usize)
+        .build()
+        .unwrap();
+    assert!(!flags.is_set(flags::TicketFlag::INVALID as usize));
+    assert!(flags.is_set(flags::TicketFlag::RENEWABLE as usize));
+}
+
 #[test]
 fn encode_decode_kerberos_flags_works_fine() {
     let testcases: Vec<KerberosFlags> = vec![
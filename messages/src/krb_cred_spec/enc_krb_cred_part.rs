@@ -6,8 +6,8 @@ use der::{
 use crate::basic::{application_tags, HostAddress, KerberosTime, Microseconds, SequenceOf, UInt32};
 use crate::krb_cred_spec::krb_cred_info::KrbCredInfo;
 
-#[derive(Sequence, Eq, PartialEq, Debug)]
-pub struct EncKrbCredPartInner {
+#[derive(Sequence, Eq, PartialEq, Debug, Clone)]
+struct EncKrbCredPartInner {
     #[asn1(context_specific = "0")]
     ticket_info: SequenceOf<KrbCredInfo>,
 
@@ -27,7 +27,7 @@ pub struct EncKrbCredPartInner {
     r_address: Option<HostAddress>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct EncKrbCredPart(EncKrbCredPartInner);
 
 impl EncKrbCredPart {
@@ -129,4 +129,11 @@ mod tests {
             EncKrbCredPart::decode(&mut SliceReader::new(buf.as_mut_slice()).unwrap()).unwrap();
         assert_eq!(data, decoded);
     }
+
+    #[test]
+    fn should_be_tagged_application_29() {
+        let encoded = sample_data().to_der().unwrap();
+        assert_eq!(encoded[0], 0x7d);
+        assert_eq!(EncKrbCredPart::from_der(&encoded).unwrap(), sample_data());
+    }
 }
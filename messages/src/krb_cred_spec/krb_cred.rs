@@ -6,8 +6,8 @@ use der::{
 use crate::basic::{application_tags, EncryptedData, Int32, SequenceOf};
 use crate::tickets::Ticket;
 
-#[derive(Sequence, Eq, PartialEq, Debug)]
-pub struct KrbCredInner {
+#[derive(Sequence, Eq, PartialEq, Debug, Clone)]
+struct KrbCredInner {
     #[asn1(context_specific = "0")]
     pvno: Int32,
 
@@ -21,7 +21,7 @@ pub struct KrbCredInner {
     enc_part: EncryptedData,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct KrbCred(KrbCredInner);
 
 impl KrbCred {
@@ -118,4 +118,11 @@ mod tests {
         let decoded = KrbCred::decode(&mut SliceReader::new(buf.as_mut_slice()).unwrap()).unwrap();
         assert_eq!(data, decoded);
     }
+
+    #[test]
+    fn should_be_tagged_application_22() {
+        let encoded = sample_data().to_der().unwrap();
+        assert_eq!(encoded[0], 0x76);
+        assert_eq!(KrbCred::from_der(&encoded).unwrap(), sample_data());
+    }
 }
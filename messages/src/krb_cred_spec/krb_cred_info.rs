@@ -3,7 +3,7 @@ use der::Sequence;
 use crate::basic::{EncryptionKey, HostAddresses, KerberosTime, PrincipalName, Realm};
 use crate::tickets::TicketFlags;
 
-#[derive(Sequence, Eq, PartialEq, Debug)]
+#[derive(Sequence, Eq, PartialEq, Debug, Clone)]
 pub struct KrbCredInfo {
     #[asn1(context_specific = "0")]
     key: EncryptionKey,
@@ -1,6 +1,3 @@
-#![allow(dead_code)]
 pub mod enc_krb_cred_part;
-#[allow(dead_code)]
 pub mod krb_cred;
-#[allow(dead_code)]
-pub mod krb_cred_info;
\ No newline at end of file
+pub mod krb_cred_info;
@@ -6,7 +6,8 @@ use der::{
 use crate::basic::{
     application_tags, HostAddress, KerberosTime, Microseconds, OctetString, UInt32,
 };
-#[derive(Sequence)]
+// Tagged APPLICATION 28 by `EncKrbPrivPart`
+#[derive(Sequence, Eq, PartialEq, Debug, Clone)]
 struct EncKrbPrivPartInner {
     #[asn1(context_specific = "0")]
     user_data: OctetString,
@@ -27,6 +28,7 @@ struct EncKrbPrivPartInner {
     r_address: Option<HostAddress>,
 }
 
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub struct EncKrbPrivPart(EncKrbPrivPartInner);
 
 impl EncKrbPrivPart {
@@ -157,5 +159,12 @@ mod tests {
                 EncKrbPrivPart::decode(&mut SliceReader::new(buf.as_mut_slice()).unwrap()).unwrap();
             assert_eq!(decoded.header(), data.header());
         }
+
+        #[test]
+        fn should_be_tagged_application_28() {
+            let encoded = sample_data().to_der().unwrap();
+            assert_eq!(encoded[0], 0x7c);
+            assert_eq!(EncKrbPrivPart::from_der(&encoded).unwrap(), sample_data());
+        }
     }
 }
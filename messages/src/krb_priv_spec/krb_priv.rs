@@ -6,9 +6,9 @@ use der::{
 use crate::basic::Int32;
 use crate::basic::{application_tags, EncryptedData};
 
-#[derive(Sequence, Eq, PartialEq, Debug)]
-// Missing Application tag
-pub struct KrbPrivInner {
+// Tagged APPLICATION 21 by `KrbPriv`
+#[derive(Sequence, Eq, PartialEq, Debug, Clone)]
+struct KrbPrivInner {
     #[asn1(context_specific = "0")]
     pvno: Int32,
 
@@ -19,6 +19,7 @@ pub struct KrbPrivInner {
     enc_part: EncryptedData,
 }
 
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub struct KrbPriv(KrbPrivInner);
 
 impl KrbPriv {
@@ -110,4 +111,11 @@ mod tests {
         let decoded = KrbPriv::decode(&mut SliceReader::new(buf.as_mut_slice()).unwrap()).unwrap();
         assert_eq!(decoded.header(), data.header());
     }
+
+    #[test]
+    fn should_be_tagged_application_21() {
+        let encoded = sample_data().to_der().unwrap();
+        assert_eq!(encoded[0], 0x75);
+        assert_eq!(KrbPriv::from_der(&encoded).unwrap(), sample_data());
+    }
 }
@@ -1,4 +1,2 @@
-#[allow(dead_code)]
 pub mod enc_krb_priv_part;
-#[allow(dead_code)]
-pub mod krb_priv;
\ No newline at end of file
+pub mod krb_priv;
@@ -15,9 +15,42 @@ const KRB_SAFE_TAG: TagNumber = TagNumber::new(KRB_SAFE);
 pub struct KrbSafe(KrbSafeInner);
 
 impl KrbSafe {
+    pub fn new(safe_body: KrbSafeBody, cksum: Checksum) -> Self {
+        fn make_tag<T>(value: T, number: u8) -> ContextSpecific<T> {
+            ContextSpecific {
+                value,
+                tag_number: TagNumber::new(number),
+                tag_mode: TagMode::Explicit,
+            }
+        }
+        KrbSafe(KrbSafeInner {
+            pnvo: make_tag(5, 0),
+            msg_type: make_tag(KRB_SAFE, 1),
+            safe_body: make_tag(safe_body, 2),
+            cksum: make_tag(cksum, 3),
+        })
+    }
+
     pub fn builder() -> KrbSafeBuilder {
         KrbSafeBuilder::new()
     }
+
+    pub fn pvno(&self) -> u8 {
+        self.0.pnvo.value
+    }
+
+    pub fn msg_type(&self) -> u8 {
+        self.0.msg_type.value
+    }
+
+    pub fn safe_body(&self) -> &KrbSafeBody {
+        &self.0.safe_body.value
+    }
+
+    /// Keyed checksum over the DER encoding of the body
+    pub fn cksum(&self) -> &Checksum {
+        &self.0.cksum.value
+    }
 }
 
 impl EncodeValue for KrbSafe {
@@ -74,20 +107,38 @@ pub struct KrbSafeBody {
 }
 
 impl KrbSafeBody {
+    pub fn new(
+        user_data: OctetString,
+        timestamp: Option<KerberosTime>,
+        usec: Option<Microseconds>,
+        seq_number: Option<UInt32>,
+        s_address: HostAddress,
+        r_address: Option<HostAddress>,
+    ) -> Self {
+        Self {
+            user_data,
+            timestamp,
+            usec,
+            seq_number,
+            s_address,
+            r_address,
+        }
+    }
+
     pub fn user_data(&self) -> &OctetString {
         &self.user_data
     }
 
     pub fn timestamp(&self) -> Option<&KerberosTime> {
-        self.timestamp.as_ref().map(|timestamp| timestamp)
+        self.timestamp.as_ref()
     }
 
     pub fn usec(&self) -> Option<&Microseconds> {
-        self.usec.as_ref().map(|usec| usec)
+        self.usec.as_ref()
     }
 
     pub fn seq_number(&self) -> Option<&UInt32> {
-        self.seq_number.as_ref().map(|seq_number| seq_number)
+        self.seq_number.as_ref()
     }
 
     pub fn s_address(&self) -> &HostAddress {
@@ -95,7 +146,7 @@ impl KrbSafeBody {
     }
 
     pub fn r_address(&self) -> Option<&HostAddress> {
-        self.r_address.as_ref().map(|r_address| r_address)
+        self.r_address.as_ref()
     }
 }
 
@@ -171,28 +222,22 @@ impl KrbSafeBuilder {
     }
 
     pub fn build_unsafe(self) -> KrbSafe {
-        fn make_tag<T>(value: T, number: u8) -> ContextSpecific<T> {
-            ContextSpecific {
-                value,
-                tag_number: TagNumber::new(number),
-                tag_mode: TagMode::Explicit,
-            }
-        }
-        KrbSafe(KrbSafeInner {
-            pnvo: make_tag(5, 0),
-            msg_type: make_tag(KRB_SAFE, 1),
-            safe_body: make_tag(
-                KrbSafeBody {
-                    user_data: self.user_data.expect("user_data is required"),
-                    timestamp: self.timestamp,
-                    usec: self.usec,
-                    seq_number: self.seq_number,
-                    s_address: self.s_address.expect("s_address is required"),
-                    r_address: self.r_address,
-                },
-                2,
+        KrbSafe::new(
+            KrbSafeBody::new(
+                self.user_data.expect("user_data is required"),
+                self.timestamp,
+                self.usec,
+                self.seq_number,
+                self.s_address.expect("s_address is required"),
+                self.r_address,
             ),
-            cksum: make_tag(self.cksum.expect("cksum is required"), 3),
-        })
+            self.cksum.expect("cksum is required"),
+        )
+    }
+}
+
+impl Default for KrbSafeBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -20,9 +20,15 @@ mod krb_cred_spec;
 mod cs_message;
 mod krb_error_spec;
 
-#[allow(dead_code)]
+// Section 5.6
 mod krb_safe_spec;
 
+// User-to-user authentication, TGT-REQ and TGT-REP
+mod user_to_user;
+
+// SPNEGO negotiation tokens, RFC 4178
+mod spnego;
+
 pub mod basic_types {
     pub use crate::basic::constants::AddressTypes;
     pub use crate::basic::constants::AuthorizationDataTypes;
@@ -49,8 +55,13 @@ pub mod basic_types {
     pub use crate::basic::PaData;
     pub use crate::basic::PaEncTimestamp;
     pub use crate::basic::PaEncTsEnc;
+    pub use crate::basic::PaForUser;
+    pub use crate::basic::PaS4uX509User;
     pub use crate::basic::PrincipalName;
+    pub use crate::basic::PrincipalNameParseError;
+    pub use crate::basic::QualifiedPrincipalName;
     pub use crate::basic::Realm;
+    pub use crate::basic::S4uUserId;
     pub use crate::basic::SequenceOf;
     pub use crate::basic::UInt32;
     pub use crate::basic::PaDataRegisteredType;
@@ -88,21 +99,34 @@ pub use spec_as_tgs_exchange::last_req::LastReqEntry;
 pub use spec_as_tgs_exchange::tgs_rep::TgsRep;
 pub use spec_as_tgs_exchange::tgs_req::TgsReq;
 
-// pub use krb_priv_spec::enc_krb_priv_part::EncKrbPrivPart;
-// pub use krb_priv_spec::krb_priv::KrbPriv;
-//
-// pub use krb_cred_spec::enc_krb_cred_part::EncKrbCredPart;
-// pub use krb_cred_spec::krb_cred::KrbCred;
-// pub use krb_cred_spec::krb_cred_info::KrbCredInfo;
+pub use user_to_user::TgtRep;
+pub use user_to_user::TgtReq;
+
+pub use krb_priv_spec::enc_krb_priv_part::EncKrbPrivPart;
+pub use krb_priv_spec::krb_priv::KrbPriv;
+
+pub use krb_cred_spec::enc_krb_cred_part::EncKrbCredPart;
+pub use krb_cred_spec::krb_cred::KrbCred;
+pub use krb_cred_spec::krb_cred_info::KrbCredInfo;
 
 pub use krb_error_spec::Ecode;
 pub use krb_error_spec::KrbErrorMsg;
 pub use krb_error_spec::KrbErrorMsgBuilder;
 pub use krb_error_spec::KrbErrorMsgBuilderError;
 
-// pub use krb_safe_spec::KrbSafe;
-// pub use krb_safe_spec::KrbSafeBody;
-// pub use krb_safe_spec::KrbSafeBuilder;
+pub use krb_safe_spec::KrbSafe;
+pub use krb_safe_spec::KrbSafeBody;
+pub use krb_safe_spec::KrbSafeBuilder;
+
+pub use spnego::InitialContextToken;
+pub use spnego::MechType;
+pub use spnego::NegState;
+pub use spnego::NegTokenInit;
+pub use spnego::NegTokenResp;
+pub use spnego::NegotiationToken;
+pub use spnego::KRB5_OID;
+pub use spnego::MS_KRB5_OID;
+pub use spnego::SPNEGO_OID;
 
 pub use der::Decode;
 pub use der::Encode;
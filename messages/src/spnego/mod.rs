@@ -0,0 +1,236 @@
+#[cfg(test)]
+mod test;
+
+use crate::basic::{BitString, OctetString};
+use der::asn1::{ContextSpecific, ContextSpecificRef, ObjectIdentifier};
+use der::{
+    Decode, DecodeValue, Encode, EncodeValue, Enumerated, FixedTag, Header, Length, Reader,
+    Sequence, Tag, TagMode, TagNumber, Writer,
+};
+
+/// Object identifier of a GSS-API mechanism
+pub type MechType = ObjectIdentifier;
+
+/// SPNEGO, the pseudo-mechanism negotiating the one to use
+pub const SPNEGO_OID: MechType = MechType::new_unwrap("1.3.6.1.5.5.2");
+
+/// Kerberos V5 mechanism
+pub const KRB5_OID: MechType = MechType::new_unwrap("1.2.840.113554.1.2.2");
+
+/// Kerberos V5 mechanism as Windows once misencoded it, which its clients still offer first
+pub const MS_KRB5_OID: MechType = MechType::new_unwrap("1.2.840.48018.1.2.2");
+
+const NEG_TOKEN_INIT: TagNumber = TagNumber::N0;
+const NEG_TOKEN_RESP: TagNumber = TagNumber::N1;
+
+/// First token of the initiator, its negotiation token framed with the SPNEGO mechanism,
+/// https://www.rfc-editor.org/rfc/rfc2743#section-3.1
+#[derive(Debug, PartialEq, Clone)]
+pub struct InitialContextToken {
+    this_mech: MechType,
+    inner_context_token: NegotiationToken,
+}
+
+impl InitialContextToken {
+    pub fn new(neg_token_init: NegTokenInit) -> Self {
+        Self {
+            this_mech: SPNEGO_OID,
+            inner_context_token: NegotiationToken::NegTokenInit(neg_token_init),
+        }
+    }
+
+    pub fn this_mech(&self) -> &MechType {
+        &self.this_mech
+    }
+
+    pub fn inner_context_token(&self) -> &NegotiationToken {
+        &self.inner_context_token
+    }
+}
+
+impl EncodeValue for InitialContextToken {
+    fn value_len(&self) -> der::Result<Length> {
+        self.this_mech.encoded_len()? + self.inner_context_token.encoded_len()?
+    }
+
+    fn encode_value(&self, encoder: &mut impl Writer) -> der::Result<()> {
+        self.this_mech.encode(encoder)?;
+        self.inner_context_token.encode(encoder)
+    }
+}
+
+impl<'a> DecodeValue<'a> for InitialContextToken {
+    fn decode_value<R: Reader<'a>>(reader: &mut R, _: Header) -> der::Result<Self> {
+        Ok(Self {
+            this_mech: MechType::decode(reader)?,
+            inner_context_token: NegotiationToken::decode(reader)?,
+        })
+    }
+}
+
+impl FixedTag for InitialContextToken {
+    const TAG: Tag = Tag::Application {
+        number: TagNumber::N0,
+        constructed: true,
+    };
+}
+
+/// Token of the SPNEGO negotiation, https://www.rfc-editor.org/rfc/rfc4178#section-4.2
+#[derive(Debug, PartialEq, Clone)]
+pub enum NegotiationToken {
+    NegTokenInit(NegTokenInit),
+    NegTokenResp(NegTokenResp),
+}
+
+impl Encode for NegotiationToken {
+    fn encoded_len(&self) -> der::Result<Length> {
+        match self {
+            Self::NegTokenInit(token) => explicit(NEG_TOKEN_INIT, token).encoded_len(),
+            Self::NegTokenResp(token) => explicit(NEG_TOKEN_RESP, token).encoded_len(),
+        }
+    }
+
+    fn encode(&self, encoder: &mut impl Writer) -> der::Result<()> {
+        match self {
+            Self::NegTokenInit(token) => explicit(NEG_TOKEN_INIT, token).encode(encoder),
+            Self::NegTokenResp(token) => explicit(NEG_TOKEN_RESP, token).encode(encoder),
+        }
+    }
+}
+
+impl<'a> Decode<'a> for NegotiationToken {
+    fn decode<R: Reader<'a>>(reader: &mut R) -> der::Result<Self> {
+        match reader.peek_tag()? {
+            Tag::ContextSpecific {
+                constructed: true,
+                number: NEG_TOKEN_INIT,
+            } => Ok(Self::NegTokenInit(
+                ContextSpecific::<NegTokenInit>::decode(reader)?.value,
+            )),
+            Tag::ContextSpecific {
+                constructed: true,
+                number: NEG_TOKEN_RESP,
+            } => Ok(Self::NegTokenResp(
+                ContextSpecific::<NegTokenResp>::decode(reader)?.value,
+            )),
+            tag => Err(tag.unexpected_error(None)),
+        }
+    }
+}
+
+fn explicit<T>(tag_number: TagNumber, value: &T) -> ContextSpecificRef<'_, T> {
+    ContextSpecificRef {
+        tag_number,
+        tag_mode: TagMode::Explicit,
+        value,
+    }
+}
+
+/// Mechanisms the initiator proposes, by order of preference, along with the first token of
+/// the one it prefers
+#[derive(Sequence, Debug, PartialEq, Clone)]
+pub struct NegTokenInit {
+    #[asn1(context_specific = "0")]
+    mech_types: Vec<MechType>,
+
+    #[asn1(context_specific = "1", optional = "true")]
+    req_flags: Option<BitString>,
+
+    #[asn1(context_specific = "2", optional = "true")]
+    mech_token: Option<OctetString>,
+
+    #[asn1(context_specific = "3", optional = "true")]
+    mech_list_mic: Option<OctetString>,
+}
+
+impl NegTokenInit {
+    pub fn new(
+        mech_types: Vec<MechType>,
+        mech_token: Option<OctetString>,
+        mech_list_mic: Option<OctetString>,
+    ) -> Self {
+        Self {
+            mech_types,
+            req_flags: None,
+            mech_token,
+            mech_list_mic,
+        }
+    }
+
+    pub fn mech_types(&self) -> &[MechType] {
+        &self.mech_types
+    }
+
+    /// Context flags, which the initiator should leave out as its mechanism token carries them
+    pub fn req_flags(&self) -> Option<&BitString> {
+        self.req_flags.as_ref()
+    }
+
+    /// First token of the first mechanism of `mech_types`
+    pub fn mech_token(&self) -> Option<&OctetString> {
+        self.mech_token.as_ref()
+    }
+
+    pub fn mech_list_mic(&self) -> Option<&OctetString> {
+        self.mech_list_mic.as_ref()
+    }
+}
+
+/// State of the negotiation after a token of the acceptor
+#[derive(Enumerated, Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+pub enum NegState {
+    AcceptCompleted = 0,
+    AcceptIncomplete = 1,
+    Reject = 2,
+    RequestMic = 3,
+}
+
+/// Token of the acceptor, and of the initiator after its first one
+#[derive(Sequence, Debug, PartialEq, Clone)]
+pub struct NegTokenResp {
+    #[asn1(context_specific = "0", optional = "true")]
+    neg_state: Option<NegState>,
+
+    #[asn1(context_specific = "1", optional = "true")]
+    supported_mech: Option<MechType>,
+
+    #[asn1(context_specific = "2", optional = "true")]
+    response_token: Option<OctetString>,
+
+    #[asn1(context_specific = "3", optional = "true")]
+    mech_list_mic: Option<OctetString>,
+}
+
+impl NegTokenResp {
+    pub fn new(
+        neg_state: Option<NegState>,
+        supported_mech: Option<MechType>,
+        response_token: Option<OctetString>,
+        mech_list_mic: Option<OctetString>,
+    ) -> Self {
+        Self {
+            neg_state,
+            supported_mech,
+            response_token,
+            mech_list_mic,
+        }
+    }
+
+    pub fn neg_state(&self) -> Option<NegState> {
+        self.neg_state
+    }
+
+    /// Mechanism the acceptor selected, in its first token only
+    pub fn supported_mech(&self) -> Option<&MechType> {
+        self.supported_mech.as_ref()
+    }
+
+    pub fn response_token(&self) -> Option<&OctetString> {
+        self.response_token.as_ref()
+    }
+
+    pub fn mech_list_mic(&self) -> Option<&OctetString> {
+        self.mech_list_mic.as_ref()
+    }
+}
@@ -0,0 +1,79 @@
+use crate::basic::OctetString;
+use crate::spnego::{
+    InitialContextToken, NegState, NegTokenInit, NegTokenResp, NegotiationToken, KRB5_OID,
+    MS_KRB5_OID, SPNEGO_OID,
+};
+use der::{Decode, Encode};
+
+#[test]
+fn initial_context_token_should_be_framed_with_spnego() {
+    #[rustfmt::skip]
+    let encoding = vec![
+        0x60, 0x2d, // [APPLICATION 0]
+            0x06, 0x06, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x02, // thisMech SPNEGO
+            0xa0, 0x23, 0x30, 0x21, // negTokenInit [0] SEQUENCE
+                0xa0, 0x18, 0x30, 0x16, // mechTypes [0] SEQUENCE OF
+                    0x06, 0x09, 0x2a, 0x86, 0x48, 0x82, 0xf7, 0x12, 0x01, 0x02, 0x02,
+                    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x02,
+                0xa2, 0x05, 0x04, 0x03, 0x01, 0x02, 0x03, // mechToken [2] OCTET STRING
+    ];
+
+    let token = InitialContextToken::from_der(&encoding).unwrap();
+    assert_eq!(token.this_mech(), &SPNEGO_OID);
+    let NegotiationToken::NegTokenInit(init) = token.inner_context_token() else {
+        panic!("Initial token should be a NegTokenInit");
+    };
+    assert_eq!(init.mech_types(), [MS_KRB5_OID, KRB5_OID]);
+    assert_eq!(init.mech_token().unwrap().as_bytes(), [1, 2, 3]);
+    assert_eq!(init.req_flags(), None);
+    assert_eq!(init.mech_list_mic(), None);
+
+    let rebuilt = InitialContextToken::new(NegTokenInit::new(
+        vec![MS_KRB5_OID, KRB5_OID],
+        Some(OctetString::new([1, 2, 3]).unwrap()),
+        None,
+    ));
+    assert_eq!(rebuilt.to_der().unwrap(), encoding);
+}
+
+#[test]
+fn neg_token_resp_should_be_tagged_context_1() {
+    let token = NegotiationToken::NegTokenResp(NegTokenResp::new(
+        Some(NegState::AcceptCompleted),
+        Some(KRB5_OID),
+        None,
+        None,
+    ));
+
+    #[rustfmt::skip]
+    let encoding = vec![
+        0xa1, 0x14, 0x30, 0x12, // negTokenResp [1] SEQUENCE
+            0xa0, 0x03, 0x0a, 0x01, 0x00, // negState [0] ENUMERATED
+            0xa1, 0x0b, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x02,
+    ];
+    assert_eq!(token.to_der().unwrap(), encoding);
+    assert_eq!(NegotiationToken::from_der(&encoding).unwrap(), token);
+}
+
+#[test]
+fn rejecting_neg_token_resp_should_carry_the_error_token() {
+    let token = NegotiationToken::NegTokenResp(NegTokenResp::new(
+        Some(NegState::Reject),
+        None,
+        Some(OctetString::new([0x60, 0x00]).unwrap()),
+        None,
+    ));
+
+    let decoded = NegotiationToken::from_der(&token.to_der().unwrap()).unwrap();
+    let NegotiationToken::NegTokenResp(resp) = decoded else {
+        panic!("Token should be a NegTokenResp");
+    };
+    assert_eq!(resp.neg_state(), Some(NegState::Reject));
+    assert_eq!(resp.supported_mech(), None);
+    assert_eq!(resp.response_token().unwrap().as_bytes(), [0x60, 0x00]);
+}
+
+#[test]
+fn other_tokens_should_not_be_negotiation_tokens() {
+    assert!(NegotiationToken::from_der(&[0xa2, 0x02, 0x30, 0x00]).is_err());
+}
@@ -158,3 +158,87 @@ fn encode_decode_for_enc_ticket_part_works_fine() {
     assert!(decoded.starttime().is_none());
     assert!(decoded.renew_till().is_none());
 }
+
+#[test]
+fn transited_encoding_accumulates_realms() {
+    let transited = TransitedEncoding::empty(0)
+        .with_realm(&Realm::new("EXAMPLE.COM").unwrap())
+        .with_realm(&Realm::new("EXAMPLE.ORG").unwrap());
+    assert_eq!(
+        transited.tr_type(),
+        &TransitedEncoding::DOMAIN_X500_COMPRESS
+    );
+    assert_eq!(
+        transited.contents().as_bytes(),
+        "EXAMPLE.COM,EXAMPLE.ORG".as_bytes()
+    );
+}
+
+fn realms(names: &[&str]) -> Vec<Realm> {
+    names.iter().map(|name| Realm::new(name).unwrap()).collect()
+}
+
+#[test]
+fn domain_x500_compress_encodes_rfc_examples() {
+    let testcases = [
+        (
+            realms(&[
+                "EDU",
+                "MIT.EDU",
+                "ATHENA.MIT.EDU",
+                "WASHINGTON.EDU",
+                "CS.WASHINGTON.EDU",
+            ]),
+            "EDU,MIT.,ATHENA.,WASHINGTON.EDU,CS.",
+        ),
+        (
+            realms(&["/COM", "/COM/HP", "/COM/HP/APOLLO", "/COM/DEC"]),
+            "/COM,/HP,/APOLLO, /COM/DEC",
+        ),
+    ];
+    let client_realm = Realm::new("CLIENT.ORG").unwrap();
+    let server_realm = Realm::new("SERVER.ORG").unwrap();
+    for (path, encoded) in testcases {
+        let transited = TransitedEncoding::from_realms(&path);
+        assert_eq!(transited.tr_type(), &TransitedEncoding::DOMAIN_X500_COMPRESS);
+        assert_eq!(transited.contents().as_bytes(), encoded.as_bytes());
+        assert_eq!(
+            transited.realms(&client_realm, &server_realm).unwrap(),
+            path
+        );
+    }
+}
+
+#[test]
+fn domain_x500_compress_expands_null_subfields() {
+    let client_realm = Realm::new("ATHENA.MIT.EDU").unwrap();
+    let server_realm = Realm::new("CS.WASHINGTON.EDU").unwrap();
+    let testcases = [
+        (",", vec!["MIT.EDU", "EDU", "WASHINGTON.EDU"]),
+        ("EDU,", vec!["EDU", "WASHINGTON.EDU"]),
+        (",EDU", vec!["MIT.EDU", "EDU"]),
+        ("MIT.EDU,,WASHINGTON.EDU", vec!["MIT.EDU", "EDU", "WASHINGTON.EDU"]),
+    ];
+    for (encoded, expected) in testcases {
+        let transited = TransitedEncoding::new(
+            TransitedEncoding::DOMAIN_X500_COMPRESS,
+            OctetString::new(encoded.as_bytes()).unwrap(),
+        );
+        assert_eq!(
+            transited.realms(&client_realm, &server_realm).unwrap(),
+            realms(&expected),
+            "Failed to decode {encoded:?}"
+        );
+    }
+}
+
+#[test]
+fn domain_x500_compress_rejects_other_encodings() {
+    let realm = Realm::new("EXAMPLE.COM").unwrap();
+    let transited = TransitedEncoding::new(5, OctetString::new("bytes".as_bytes()).unwrap());
+    assert!(transited.realms(&realm, &realm).is_err());
+    assert_eq!(
+        TransitedEncoding::empty(0).realms(&realm, &realm),
+        Ok(Vec::new())
+    );
+}
@@ -1,6 +1,6 @@
 use der::Sequence;
 
-use crate::basic::{Int32, OctetString};
+use crate::basic::{Int32, OctetString, Realm};
 
 #[derive(Sequence, PartialEq, Eq, Clone, Debug)]
 pub struct TransitedEncoding {
@@ -11,6 +11,9 @@ pub struct TransitedEncoding {
 }
 
 impl TransitedEncoding {
+    // RFC4120 3.3.3.2
+    pub const DOMAIN_X500_COMPRESS: Int32 = 1;
+
     pub fn empty(tr_type: Int32) -> Self {
         Self {
             tr_type,
@@ -28,4 +31,181 @@ impl TransitedEncoding {
     pub fn contents(&self) -> &OctetString {
         &self.contents
     }
+
+    /// DOMAIN-X500-COMPRESS encoding of `realms`, in the order they were transited.
+    /// Each realm is abbreviated relative to the one before it whenever possible
+    pub fn from_realms(realms: &[Realm]) -> Self {
+        let mut entries: Vec<String> = Vec::with_capacity(realms.len());
+        let mut prev: Option<&str> = None;
+        for realm in realms {
+            let name = realm.as_str();
+            let entry = match prev {
+                Some(prev) if !is_x500(prev) && name.ends_with(&format!(".{prev}")) => {
+                    name[..name.len() - prev.len()].to_string()
+                }
+                Some(prev) if is_x500(prev) && name.starts_with(&format!("{prev}/")) => {
+                    name[prev.len()..].to_string()
+                }
+                // A leading space keeps a full X.500 name from being read as abbreviated
+                Some(_) if is_x500(name) => format!(" {name}"),
+                _ => name.to_string(),
+            };
+            entries.push(escape(&entry));
+            prev = Some(name);
+        }
+        Self::encode_entries(&entries)
+    }
+
+    /// Record that the ticket went through `realm`, by appending it to the list of realms
+    pub fn with_realm(&self, realm: &Realm) -> Self {
+        if self.contents.as_bytes().is_empty() {
+            return Self::from_realms(std::slice::from_ref(realm));
+        }
+        let name = realm.as_str();
+        let entry = if is_x500(name) {
+            format!(" {name}")
+        } else {
+            name.to_string()
+        };
+        let contents = String::from_utf8_lossy(self.contents.as_bytes()).into_owned();
+        Self::encode_entries(&[contents, escape(&entry)])
+    }
+
+    /// Realms the ticket went through, in order. A null subfield stands for the realms on the
+    /// hierarchical path between its neighbours, the client's realm being considered to precede
+    /// the list and the server's realm to follow it
+    pub fn realms(
+        &self,
+        client_realm: &Realm,
+        server_realm: &Realm,
+    ) -> Result<Vec<Realm>, &'static str> {
+        if self.contents.as_bytes().is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.tr_type != Self::DOMAIN_X500_COMPRESS {
+            return Err("Unsupported transited encoding type");
+        }
+        let contents = std::str::from_utf8(self.contents.as_bytes())
+            .map_err(|_| "Transited realms must be IA5 strings")?;
+
+        // `None` marks a null subfield
+        let mut entries: Vec<Option<Realm>> = Vec::new();
+        let mut prev: Option<String> = None;
+        for entry in split_entries(contents) {
+            if entry.is_empty() {
+                entries.push(None);
+                continue;
+            }
+            let name = if let Some(full) = entry.strip_prefix(' ') {
+                full.to_string()
+            } else if entry.ends_with('.') {
+                let base = prev.clone().unwrap_or(client_realm.as_str().to_string());
+                format!("{entry}{base}")
+            } else if is_x500(&entry) {
+                match &prev {
+                    Some(prev) if is_x500(prev) => format!("{prev}{entry}"),
+                    _ => entry,
+                }
+            } else {
+                entry
+            };
+            entries.push(Some(
+                Realm::new(&name).map_err(|_| "Transited realms must be IA5 strings")?,
+            ));
+            prev = Some(name);
+        }
+
+        let mut realms = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            match entry {
+                Some(realm) => realms.push(realm.clone()),
+                None => {
+                    let from = realms.last().unwrap_or(client_realm);
+                    let to = entries[i + 1..]
+                        .iter()
+                        .find_map(|e| e.as_ref())
+                        .unwrap_or(server_realm);
+                    realms.extend(Self::hierarchical_path(from, to));
+                }
+            }
+        }
+        Ok(realms)
+    }
+
+    /// Realms strictly between `from` and `to` when climbing the realm hierarchy
+    /// up to their closest common ancestor and back down
+    pub fn hierarchical_path(from: &Realm, to: &Realm) -> Vec<Realm> {
+        let (from_name, to_name) = (from.as_str(), to.as_str());
+        if is_x500(from_name) != is_x500(to_name) {
+            return Vec::new();
+        }
+        let x500 = is_x500(from_name);
+        let (from_parts, to_parts) = (components(from_name), components(to_name));
+        let common = from_parts
+            .iter()
+            .zip(to_parts.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let up = (common.max(1)..from_parts.len())
+            .rev()
+            .map(|len| &from_parts[..len]);
+        let down = ((common + 1)..to_parts.len()).map(|len| &to_parts[..len]);
+        up.chain(down)
+            .map(|parts| render(parts, x500))
+            .filter(|name| name != from_name && name != to_name)
+            .filter_map(|name| Realm::new(&name).ok())
+            .collect()
+    }
+
+    fn encode_entries(entries: &[String]) -> Self {
+        Self {
+            tr_type: Self::DOMAIN_X500_COMPRESS,
+            contents: OctetString::new(entries.join(",").into_bytes())
+                .expect("Realm list is a valid octet string"),
+        }
+    }
+}
+
+fn is_x500(name: &str) -> bool {
+    name.starts_with('/')
+}
+
+/// Name components of a realm, from the root of the hierarchy down
+fn components(name: &str) -> Vec<&str> {
+    if is_x500(name) {
+        name.split('/').filter(|c| !c.is_empty()).collect()
+    } else {
+        name.split('.').filter(|c| !c.is_empty()).rev().collect()
+    }
+}
+
+fn render(parts: &[&str], x500: bool) -> String {
+    if x500 {
+        format!("/{}", parts.join("/"))
+    } else {
+        parts.iter().rev().cloned().collect::<Vec<_>>().join(".")
+    }
+}
+
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace(',', "\\,")
+}
+
+/// Split on the commas which are not escaped with a backslash
+fn split_entries(contents: &str) -> Vec<String> {
+    let mut entries = vec![String::new()];
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    entries.last_mut().unwrap().push(escaped);
+                }
+            }
+            ',' => entries.push(String::new()),
+            c => entries.last_mut().unwrap().push(c),
+        }
+    }
+    entries
 }
@@ -0,0 +1,5 @@
+mod tgt_rep;
+mod tgt_req;
+
+pub use tgt_rep::TgtRep;
+pub use tgt_req::TgtReq;
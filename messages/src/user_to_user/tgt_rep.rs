@@ -0,0 +1,93 @@
+use crate::basic::application_tags::TGT_REP;
+use crate::tickets::Ticket;
+use der::{
+    Decode, DecodeValue, Encode, EncodeValue, FixedTag, Length, Reader, Sequence, TagNumber, Writer,
+};
+
+/// KRB_TGT_REP message, carrying the TGT whose session key the user-to-user ticket
+/// is to be encrypted in
+#[derive(Debug, PartialEq, Clone)]
+pub struct TgtRep(KrbTgtRepInner);
+
+impl<'a> DecodeValue<'a> for TgtRep {
+    fn decode_value<R: Reader<'a>>(reader: &mut R, _header: der::Header) -> der::Result<Self> {
+        let inner = KrbTgtRepInner::decode(reader)?;
+        Ok(Self(inner))
+    }
+}
+
+impl FixedTag for TgtRep {
+    const TAG: der::Tag = der::Tag::Application {
+        number: TagNumber::new(TGT_REP),
+        constructed: true,
+    };
+}
+
+impl EncodeValue for TgtRep {
+    fn value_len(&self) -> der::Result<Length> {
+        self.0.encoded_len()
+    }
+
+    fn encode_value(&self, encoder: &mut impl Writer) -> der::Result<()> {
+        self.0.encode(encoder)
+    }
+}
+
+#[derive(Sequence, Debug, PartialEq, Clone)]
+struct KrbTgtRepInner {
+    #[asn1(context_specific = "0")]
+    pvno: u8,
+
+    #[asn1(context_specific = "1")]
+    msg_type: u8,
+
+    #[asn1(context_specific = "2")]
+    ticket: Ticket,
+}
+
+impl TgtRep {
+    pub fn new(ticket: Ticket) -> Self {
+        TgtRep(KrbTgtRepInner {
+            pvno: 5,
+            msg_type: TGT_REP,
+            ticket,
+        })
+    }
+
+    pub const fn pvno(&self) -> u8 {
+        self.0.pvno
+    }
+
+    pub const fn msg_type(&self) -> u8 {
+        self.0.msg_type
+    }
+
+    pub fn ticket(&self) -> &Ticket {
+        &self.0.ticket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::basic::{EncryptedData, KerberosString, OctetString, PrincipalName};
+    use crate::tickets::Ticket;
+    use crate::user_to_user::TgtRep;
+    use der::{Decode, Encode};
+
+    #[test]
+    fn encode_then_decode() {
+        let realm = KerberosString::new("EXAMPLE.COM").unwrap();
+        let ticket = Ticket::new(
+            realm.clone(),
+            PrincipalName::krbtgt(&realm),
+            EncryptedData::new(1, None, OctetString::new(b"encrypted".to_vec()).unwrap()),
+        );
+        let msg = TgtRep::new(ticket.clone());
+        let encoded_msg = msg.to_der().unwrap();
+        assert_eq!(encoded_msg[0], 0b0110_0000 + 17); // APPLICATION 17
+
+        let decoded_msg = TgtRep::from_der(&encoded_msg).unwrap();
+        assert_eq!(msg, decoded_msg);
+        assert_eq!(decoded_msg.ticket(), &ticket);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::basic::application_tags::TGT_REQ;
+use crate::basic::{PrincipalName, Realm};
+use der::{
+    Decode, DecodeValue, Encode, EncodeValue, FixedTag, Length, Reader, Sequence, TagNumber, Writer,
+};
+
+/// KRB_TGT_REQ message, sent to a peer to obtain its TGT for user-to-user authentication
+#[derive(Debug, PartialEq, Clone)]
+pub struct TgtReq(KrbTgtReqInner);
+
+impl<'a> DecodeValue<'a> for TgtReq {
+    fn decode_value<R: Reader<'a>>(reader: &mut R, _header: der::Header) -> der::Result<Self> {
+        let inner = KrbTgtReqInner::decode(reader)?;
+        Ok(Self(inner))
+    }
+}
+
+impl FixedTag for TgtReq {
+    const TAG: der::Tag = der::Tag::Application {
+        number: TagNumber::new(TGT_REQ),
+        constructed: true,
+    };
+}
+
+impl EncodeValue for TgtReq {
+    fn value_len(&self) -> der::Result<Length> {
+        self.0.encoded_len()
+    }
+
+    fn encode_value(&self, encoder: &mut impl Writer) -> der::Result<()> {
+        self.0.encode(encoder)
+    }
+}
+
+#[derive(Sequence, Debug, PartialEq, Clone)]
+struct KrbTgtReqInner {
+    #[asn1(context_specific = "0")]
+    pvno: u8,
+
+    #[asn1(context_specific = "1")]
+    msg_type: u8,
+
+    #[asn1(context_specific = "2", optional = "true")]
+    server_name: Option<PrincipalName>,
+
+    #[asn1(context_specific = "3", optional = "true")]
+    realm: Option<Realm>,
+}
+
+impl TgtReq {
+    pub fn new(server_name: Option<PrincipalName>, realm: Option<Realm>) -> Self {
+        TgtReq(KrbTgtReqInner {
+            pvno: 5,
+            msg_type: TGT_REQ,
+            server_name,
+            realm,
+        })
+    }
+
+    pub const fn pvno(&self) -> u8 {
+        self.0.pvno
+    }
+
+    pub const fn msg_type(&self) -> u8 {
+        self.0.msg_type
+    }
+
+    pub fn server_name(&self) -> Option<&PrincipalName> {
+        self.0.server_name.as_ref()
+    }
+
+    pub fn realm(&self) -> Option<&Realm> {
+        self.0.realm.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::basic::{KerberosString, NameTypes, PrincipalName};
+    use crate::user_to_user::TgtReq;
+    use der::{Decode, Encode};
+
+    #[test]
+    fn encode_then_decode() {
+        let msg = TgtReq::new(
+            Some(
+                PrincipalName::new(
+                    NameTypes::NtPrincipal,
+                    vec![KerberosString::new("peer").unwrap()],
+                )
+                .unwrap(),
+            ),
+            Some(KerberosString::new("EXAMPLE.COM").unwrap()),
+        );
+        let encoded_msg = msg.to_der().unwrap();
+        let decoded_msg = TgtReq::from_der(&encoded_msg).unwrap();
+        assert_eq!(msg, decoded_msg);
+    }
+
+    #[test]
+    fn correct_encode() {
+        let msg = TgtReq::new(None, None);
+
+        let encoded_msg = msg.to_der().unwrap();
+        #[rustfmt::skip]
+        let correct_encoding = vec![
+            112, 12, 48, 10, // APPLICATION 16 SEQUENCE
+                160, 3, 2, 1, 5, // pvno [0] INTEGER
+                161, 3, 2, 1, 16, // msg-type [1] INTEGER
+        ];
+
+        assert_eq!(encoded_msg, correct_encoding);
+    }
+}